@@ -0,0 +1,189 @@
+//! Benchmarks of the extraction pipeline on synthetic teehistorian files,
+//! run with `cargo bench`.
+
+mod synthetic;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ndarray::Array3;
+use std::{env, fs, hint::black_box, path::PathBuf};
+use synthetic::SyntheticFile;
+use teehistorian::{
+    chunks::{InputDiff, PlayerDiff},
+    Th,
+};
+use teehistorian_extractor::{
+    export::ExportConfig,
+    extractor::{Extractor, Sequence},
+    parser::{DDNetSequence, Parser, ParserConfig},
+    sink::write_features,
+    tick::{Tick, TickHistory},
+};
+
+/// (players, ticks) of the generated files
+const FILE_SIZES: [(i32, i32); 3] = [(4, 20_000), (16, 20_000), (64, 5_000)];
+
+fn parse_bytes(bytes: &[u8], config: &ParserConfig) -> Vec<DDNetSequence> {
+    let mut th = Th::parse(bytes).unwrap();
+    let mut parser = Parser::new(config.clone());
+    parser.parse_header(th.header().unwrap()).unwrap();
+    while let Ok(chunk) = th.next_chunk() {
+        parser.parse_chunk(chunk).unwrap();
+    }
+    parser.completed_sequences
+}
+
+/// completed sequences of a synthetic file, ready for conversion
+fn synthetic_ddnet_sequences(file: &SyntheticFile) -> Vec<DDNetSequence> {
+    let mut ddnet_sequences = parse_bytes(&file.generate(), &ParserConfig::default());
+    for ddnet_seq in ddnet_sequences.iter_mut() {
+        ddnet_seq.teehist_path = Some("synthetic".into());
+    }
+    ddnet_sequences
+}
+
+fn chunk_parsing(c: &mut Criterion) {
+    let config = ParserConfig::default();
+    let mut group = c.benchmark_group("chunk_parsing");
+    group.sample_size(10);
+    for (players, ticks) in FILE_SIZES {
+        let bytes = SyntheticFile::new(players, ticks).generate();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("memory", players), &bytes, |b, bytes| {
+            b.iter(|| parse_bytes(bytes, &config))
+        });
+    }
+    group.finish();
+}
+
+fn file_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("file_parsing");
+    group.sample_size(10);
+    for (players, ticks) in FILE_SIZES {
+        let path: PathBuf = env::temp_dir().join(format!(
+            "teehistorian_extractor_bench_{}_{}.teehistorian",
+            players, ticks
+        ));
+        let file = SyntheticFile::new(players, ticks);
+        file.write(&path).unwrap();
+        group.throughput(Throughput::Bytes(fs::metadata(&path).unwrap().len()));
+        for mmap in [false, true] {
+            let config = ParserConfig::builder().mmap(mmap).build().unwrap();
+            let name = if mmap { "mmap" } else { "buffered" };
+            group.bench_with_input(BenchmarkId::new(name, players), &path, |b, path| {
+                b.iter(|| Extractor::parse_file(path, &config).unwrap())
+            });
+        }
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
+fn tick_state(c: &mut Criterion) {
+    const PLAYERS: i32 = 64;
+    const TICKS: i32 = 1000;
+    let mut group = c.benchmark_group("tick_state");
+    group.throughput(Throughput::Elements((PLAYERS * TICKS) as u64));
+
+    let mut start = Tick::new();
+    for cid in 0..PLAYERS {
+        start.input_vectors.insert(cid, [0; 10]);
+        start.player_positions.insert(cid, (0, 0));
+    }
+
+    group.bench_function("apply_diffs", |b| {
+        b.iter(|| {
+            let mut tick = start.clone();
+            let mut history = TickHistory::new();
+            for tick_index in 0..TICKS {
+                for cid in 0..PLAYERS {
+                    tick.apply_position_diff(PlayerDiff { cid, dx: 1, dy: -1 })
+                        .unwrap();
+                    if (tick_index + cid) % 4 == 0 {
+                        let mut dinput = [0; 10];
+                        dinput[1] = 3;
+                        tick.apply_input_diff(InputDiff { cid, dinput });
+                    }
+                }
+                history.push(tick_index..tick_index + 1, &tick);
+            }
+            history
+        })
+    });
+
+    let mut tick = start.clone();
+    let mut history = TickHistory::new();
+    for tick_index in 0..TICKS {
+        for cid in 0..PLAYERS {
+            tick.apply_position_diff(PlayerDiff { cid, dx: 1, dy: -1 })
+                .unwrap();
+        }
+        history.push(tick_index..tick_index + 1, &tick);
+    }
+    group.bench_function("history_states", |b| {
+        b.iter(|| {
+            (0..PLAYERS)
+                .flat_map(|cid| history.states(cid, 0, TICKS))
+                .filter_map(|(_, position)| position)
+                .map(|(x, _)| x as i64)
+                .sum::<i64>()
+        })
+    });
+    group.finish();
+}
+
+fn sequence_conversion(c: &mut Criterion) {
+    let ddnet_sequences = synthetic_ddnet_sequences(&SyntheticFile::new(16, 20_000));
+    let ticks: usize = ddnet_sequences.iter().map(|s| s.input_vectors.len()).sum();
+
+    let mut group = c.benchmark_group("sequence_conversion");
+    group.throughput(Throughput::Elements(ticks as u64));
+    group.bench_function("from_ddnet_sequence", |b| {
+        b.iter(|| {
+            ddnet_sequences
+                .iter()
+                .map(|s| Sequence::from_ddnet_sequence(black_box(s)).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+}
+
+fn feature_assembly(c: &mut Criterion) {
+    let config = ExportConfig::builder()
+        .seq_length(1000)
+        .use_rel_target(true)
+        .build()
+        .unwrap();
+    // cleaned sequences hold the ticks needed for one exported sequence
+    let sequences: Vec<Sequence> = synthetic_ddnet_sequences(&SyntheticFile::new(16, 20_000))
+        .iter()
+        .map(|s| Sequence::from_ddnet_sequence(s).unwrap())
+        .filter(|s| s.tick_count >= config.source_ticks())
+        .collect();
+    let num_features = config.column_names().len();
+
+    let mut group = c.benchmark_group("feature_assembly");
+    group.throughput(Throughput::Elements(
+        (sequences.len() * config.seq_length) as u64,
+    ));
+    let mut batch = Array3::zeros((sequences.len(), config.seq_length, num_features));
+    group.bench_function("write_features", |b| {
+        b.iter(|| {
+            for (seq, out) in sequences.iter().zip(batch.outer_iter_mut()) {
+                write_features(seq, &config, out).unwrap();
+            }
+            black_box(&batch);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    chunk_parsing,
+    file_parsing,
+    tick_state,
+    sequence_conversion,
+    feature_assembly
+);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+//! Generator for synthetic teehistorian files.
+//! All players join at the start, move around randomly and respawn every life_ticks ticks,
+//! so each player yields about ticks / life_ticks sequences.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{fs, io, path::Path};
+use teehistorian::{
+    chunks::{InputDiff, InputNew, Join, NetMessage, PlayerDiff, PlayerNew, PlayerOld},
+    Chunk, ThWriter,
+};
+
+/// message id of ClStartInfo (20), packed with the system flag unset
+const CL_START_INFO: u8 = 20 << 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SyntheticFile {
+    /// amount of players, at most 64
+    pub players: i32,
+    /// length of the recording
+    pub ticks: i32,
+    /// ticks between respawns of a player
+    pub life_ticks: i32,
+    pub seed: u64,
+}
+
+impl SyntheticFile {
+    pub fn new(players: i32, ticks: i32) -> SyntheticFile {
+        SyntheticFile {
+            players,
+            ticks,
+            life_ticks: 1500,
+            seed: 0,
+        }
+    }
+
+    /// Uncompressed teehistorian file contents
+    pub fn generate(&self) -> Vec<u8> {
+        assert!(
+            (1..=64).contains(&self.players),
+            "players must be in 1..=64"
+        );
+        let header = r#"{"server_name":"synthetic","map_name":"Synthetic","start_time":"2024-10-01 18:23:05 +0200"}"#;
+        let mut writer = ThWriter::new(Vec::new(), header).expect("writing to a vec can't fail");
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let mut add = |chunk: Chunk| {
+            writer
+                .add_chunk(&chunk)
+                .expect("writing to a vec can't fail")
+        };
+
+        let start_infos: Vec<Vec<u8>> = (0..self.players)
+            .map(|cid| start_info(&format!("player{}", cid)))
+            .collect();
+        for cid in 0..self.players {
+            add(Chunk::Join(Join { cid }));
+            add(Chunk::NetMessage(NetMessage {
+                cid,
+                msg_size: start_infos[cid as usize].len() as i32,
+                msg: &start_infos[cid as usize],
+            }));
+            add(Chunk::InputNew(InputNew {
+                cid,
+                input: [0; 10],
+            }));
+        }
+
+        let mut alive = vec![false; self.players as usize];
+        for tick in 0..self.ticks {
+            for cid in 0..self.players {
+                // stagger respawns so not all players die in the same tick
+                let life_tick = (tick + cid * 37) % self.life_ticks;
+                let alive = &mut alive[cid as usize];
+                if !*alive {
+                    add(Chunk::PlayerNew(PlayerNew {
+                        cid,
+                        x: rng.gen_range(0..10_000),
+                        y: rng.gen_range(0..10_000),
+                    }));
+                    *alive = true;
+                } else if life_tick == 0 {
+                    add(Chunk::PlayerOld(PlayerOld { cid }));
+                    *alive = false;
+                } else {
+                    add(Chunk::PlayerDiff(PlayerDiff {
+                        cid,
+                        dx: rng.gen_range(-20..=20),
+                        dy: rng.gen_range(-20..=20),
+                    }));
+                }
+
+                // inputs change much less often than positions
+                if rng.gen_ratio(1, 4) {
+                    let mut dinput = [0; 10];
+                    dinput[1] = rng.gen_range(-50..=50);
+                    dinput[2] = rng.gen_range(-50..=50);
+                    add(Chunk::InputDiff(InputDiff { cid, dinput }));
+                }
+            }
+        }
+        add(Chunk::Eos);
+        writer.into_inner()
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.generate())
+    }
+}
+
+/// ClStartInfo net message: name, clan, country, skin, use_custom_color, color_body, color_feet
+fn start_info(name: &str) -> Vec<u8> {
+    let mut msg = vec![CL_START_INFO];
+    msg.extend_from_slice(name.as_bytes());
+    msg.extend_from_slice(&[0, 0, 0]); // name terminator, empty clan, country
+    msg.extend_from_slice(b"default\0");
+    msg.extend_from_slice(&[0, 0, 0]);
+    msg
+}
@@ -0,0 +1,57 @@
+/* C interface of teehistorian_extractor, built with `cargo build --release --features capi`.
+ * Link against libteehistorian_extractor. See src/capi.rs for details. */
+#ifndef TEEHISTORIAN_EXTRACTOR_H
+#define TEEHISTORIAN_EXTRACTOR_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct TeehistParserConfig {
+    bool cut_kill;
+    bool cut_rescue;
+    int32_t max_speed;
+} TeehistParserConfig;
+
+/* valid until the next call to teehist_sequence_iter_next or teehist_sequence_iter_free */
+typedef struct TeehistSequence {
+    size_t start_tick;
+    size_t tick_count;
+    const char *player_name;
+    const char *map_name;
+    const char *teehist_name;
+    const char *timeout_code; /* NULL if unknown */
+    bool has_finish_time;
+    int32_t finish_time;
+    const int32_t *pos_x;
+    const int32_t *pos_y;
+    const int32_t *move_dir;
+    const int32_t *target_x;
+    const int32_t *target_y;
+    const bool *jump;
+    const bool *fire;
+    const bool *hook;
+} TeehistSequence;
+
+typedef struct TeehistSequenceIter TeehistSequenceIter;
+
+/* config may be NULL for defaults, returns NULL if path is not valid UTF-8 */
+TeehistSequenceIter *teehist_sequence_iter_open(const char *path, const TeehistParserConfig *config);
+
+/* 1: sequence written to out, 0: end of file, -1: error */
+int teehist_sequence_iter_next(TeehistSequenceIter *iter, TeehistSequence *out);
+
+/* message of the last error or NULL */
+const char *teehist_sequence_iter_error(const TeehistSequenceIter *iter);
+
+void teehist_sequence_iter_free(TeehistSequenceIter *iter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,246 @@
+//! Alias detection: ranks pairs of player names by how likely they belong to the same person.
+//!
+//! Shared timeout codes are strong evidence, but players who changed their client have new
+//! codes. Behavioral fingerprints (input rate, aim speed, favorite maps) fill that gap.
+
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use crate::error::Result;
+use crate::extractor::{Extractor, Sequence};
+use crate::parser::ParserConfig;
+
+/// server ticks per second
+const TICK_RATE: usize = 50;
+
+/// upper bounds of the button input changes per second bins, the last bin is open
+const INPUT_RATE_BOUNDS: [u32; 8] = [1, 2, 3, 4, 6, 8, 12, 16];
+
+/// upper bounds of the aim angle change per tick bins in degrees, the last bin is open
+pub(crate) const AIM_SPEED_BOUNDS: [f64; 8] = [0.5, 1., 2., 4., 8., 16., 32., 64.];
+
+/// Behavior of a player, only active parts are counted: seconds without input changes and
+/// ticks without aim movement are skipped, so AFK stretches don't dominate.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerFingerprint {
+    pub ticks: u64,
+    pub timeout_codes: BTreeSet<String>,
+    /// histogram of button input changes per second
+    pub input_rate: [u64; INPUT_RATE_BOUNDS.len() + 1],
+    /// histogram of aim angle changes per tick
+    pub aim_speed: [u64; AIM_SPEED_BOUNDS.len() + 1],
+    /// map name -> ticks played
+    pub maps: HashMap<String, u64>,
+}
+
+impl PlayerFingerprint {
+    pub fn add_sequence(&mut self, sequence: &Sequence) {
+        self.ticks += sequence.tick_count as u64;
+        self.timeout_codes
+            .extend(sequence.timeout_code.iter().cloned());
+        *self.maps.entry(sequence.map_name.to_string()).or_insert(0) += sequence.tick_count as u64;
+
+        let changed = |i: usize| {
+            sequence.move_dir[i] != sequence.move_dir[i - 1]
+                || sequence.jump[i] != sequence.jump[i - 1]
+                || sequence.fire[i] != sequence.fire[i - 1]
+                || sequence.hook[i] != sequence.hook[i - 1]
+        };
+        for second in (1..sequence.tick_count).step_by(TICK_RATE) {
+            let end = (second + TICK_RATE).min(sequence.tick_count);
+            let changes = (second..end).filter(|&i| changed(i)).count() as u32;
+            if changes > 0 {
+                self.input_rate[bin(&INPUT_RATE_BOUNDS, changes)] += 1;
+            }
+        }
+
+        let angle = |i: usize| {
+            (sequence.target_y[i] as f64)
+                .atan2(sequence.target_x[i] as f64)
+                .to_degrees()
+        };
+        for i in 1..sequence.tick_count {
+            let delta = (angle(i) - angle(i - 1)).abs();
+            let delta = delta.min(360. - delta);
+            if delta > 0. {
+                self.aim_speed[bin(&AIM_SPEED_BOUNDS, delta)] += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: PlayerFingerprint) {
+        self.ticks += other.ticks;
+        self.timeout_codes.extend(other.timeout_codes);
+        for (bin, count) in self.input_rate.iter_mut().zip(other.input_rate) {
+            *bin += count;
+        }
+        for (bin, count) in self.aim_speed.iter_mut().zip(other.aim_speed) {
+            *bin += count;
+        }
+        for (map, ticks) in other.maps {
+            *self.maps.entry(map).or_insert(0) += ticks;
+        }
+    }
+}
+
+/// index of the first bin whose upper bound is at least value
+pub(crate) fn bin<T: PartialOrd>(bounds: &[T], value: T) -> usize {
+    bounds
+        .iter()
+        .position(|bound| value <= *bound)
+        .unwrap_or(bounds.len())
+}
+
+/// Weights of the signals in the alias score. Without timeout codes on either side, the
+/// score only consists of the behavioral signals.
+#[derive(Debug, Clone)]
+pub struct AliasWeights {
+    pub timeout_codes: f64,
+    pub input_rate: f64,
+    pub aim_speed: f64,
+    pub maps: f64,
+}
+
+impl Default for AliasWeights {
+    fn default() -> Self {
+        AliasWeights {
+            timeout_codes: 0.5,
+            input_rate: 0.2,
+            aim_speed: 0.2,
+            maps: 0.1,
+        }
+    }
+}
+
+/// a pair of players with their combined score and its individual signals, all in 0..=1
+#[derive(Debug, Clone)]
+pub struct AliasCandidate {
+    pub player: String,
+    pub alias: String,
+    pub score: f64,
+    /// jaccard index of the timeout codes, None if either player has none
+    pub timeout_codes: Option<f64>,
+    pub input_rate: f64,
+    pub aim_speed: f64,
+    pub maps: f64,
+}
+
+impl AliasCandidate {
+    pub fn new(
+        (player, a): (&str, &PlayerFingerprint),
+        (alias, b): (&str, &PlayerFingerprint),
+        weights: &AliasWeights,
+    ) -> AliasCandidate {
+        let timeout_codes = jaccard(&a.timeout_codes, &b.timeout_codes);
+        let input_rate = histogram_overlap(&a.input_rate, &b.input_rate);
+        let aim_speed = histogram_overlap(&a.aim_speed, &b.aim_speed);
+        let maps = cosine_similarity(&a.maps, &b.maps);
+
+        let mut weighted =
+            input_rate * weights.input_rate + aim_speed * weights.aim_speed + maps * weights.maps;
+        let mut total_weight = weights.input_rate + weights.aim_speed + weights.maps;
+        if let Some(timeout_codes) = timeout_codes {
+            weighted += timeout_codes * weights.timeout_codes;
+            total_weight += weights.timeout_codes;
+        }
+        AliasCandidate {
+            player: player.to_string(),
+            alias: alias.to_string(),
+            score: if total_weight > 0. {
+                weighted / total_weight
+            } else {
+                0.
+            },
+            timeout_codes,
+            input_rate,
+            aim_speed,
+            maps,
+        }
+    }
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let shared = a.intersection(b).count();
+    Some(shared as f64 / (a.len() + b.len() - shared) as f64)
+}
+
+/// shared probability mass of two histograms, 0 if either is empty
+fn histogram_overlap(a: &[u64], b: &[u64]) -> f64 {
+    let (total_a, total_b) = (a.iter().sum::<u64>(), b.iter().sum::<u64>());
+    if total_a == 0 || total_b == 0 {
+        return 0.;
+    }
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as f64 / total_a as f64).min(b as f64 / total_b as f64))
+        .sum()
+}
+
+fn cosine_similarity(a: &HashMap<String, u64>, b: &HashMap<String, u64>) -> f64 {
+    let norm = |v: &HashMap<String, u64>| v.values().map(|&x| (x as f64).powi(2)).sum::<f64>();
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(map, &x)| b.get(map).map(|&y| x as f64 * y as f64))
+        .sum();
+    let norms = (norm(a) * norm(b)).sqrt();
+    if norms > 0. {
+        dot / norms
+    } else {
+        0.
+    }
+}
+
+/// All pairs of players with at least min_ticks and a score of at least min_score, highest
+/// score first. Within a pair, the player with more ticks comes first.
+pub fn rank_aliases(
+    fingerprints: &HashMap<String, PlayerFingerprint>,
+    weights: &AliasWeights,
+    min_ticks: u64,
+    min_score: f64,
+) -> Vec<AliasCandidate> {
+    let mut players: Vec<(&str, &PlayerFingerprint)> = fingerprints
+        .iter()
+        .filter(|(_, fingerprint)| fingerprint.ticks >= min_ticks)
+        .map(|(name, fingerprint)| (name.as_str(), fingerprint))
+        .collect();
+    players.sort_by(|a, b| b.1.ticks.cmp(&a.1.ticks).then_with(|| a.0.cmp(b.0)));
+
+    let mut candidates: Vec<AliasCandidate> = (0..players.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let players = &players;
+            (i + 1..players.len())
+                .map(move |j| AliasCandidate::new(players[i], players[j], weights))
+                .filter(|candidate| candidate.score >= min_score)
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.player.cmp(&b.player))
+            .then_with(|| a.alias.cmp(&b.alias))
+    });
+    candidates
+}
+
+/// Fingerprints of all named players of a single file
+pub fn file_fingerprints(path: &Path) -> Result<HashMap<String, PlayerFingerprint>> {
+    let mut fingerprints: HashMap<String, PlayerFingerprint> = HashMap::new();
+    for ddnet_sequence in Extractor::get_ddnet_sequences(path, &ParserConfig::default())? {
+        if ddnet_sequence.player_name.is_none() {
+            continue;
+        }
+        let sequence = Sequence::from_ddnet_sequence(&ddnet_sequence)?;
+        fingerprints
+            .entry(sequence.player_name.to_string())
+            .or_default()
+            .add_sequence(&sequence);
+    }
+    Ok(fingerprints)
+}
@@ -0,0 +1,136 @@
+//! Weak labels for cheat detection: heuristics scoring how bot-like a sequence is.
+//!
+//! Unlike [`crate::bot_filter`], nothing is dropped. Each heuristic yields a score in 0..=1
+//! and the sequence score is the highest of them, so a single strong signal is enough.
+//! The scores are meant for ranking and sampling, not as proof of cheating.
+
+use crate::extractor::Sequence;
+
+/// sequences scoring at least this are flagged
+pub const FLAG_THRESHOLD: f32 = 0.5;
+
+/// consecutive ticks with an identical aim movement that count as linear aim
+const LINEAR_AIM_TICKS: usize = 4;
+
+/// minimum amount of ticks with aim movement before linear aim is judged
+const MIN_AIM_MOVES: usize = 50;
+
+/// ticks between direction reversals that are too short for a human reaction (60ms)
+const MIN_REACTION_TICKS: usize = 3;
+
+/// minimum amount of direction reversals before reaction times are judged
+const MIN_DIRECTION_REVERSALS: usize = 10;
+
+/// minimum amount of input changes before periodicity is judged
+const MIN_PERIODIC_CHANGES: usize = 10;
+
+/// Scores of the individual heuristics, all in 0..=1
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AnomalyScore {
+    /// share of aim movement that happens in perfectly linear steps, as interpolated by
+    /// aimbots instead of moved by hand
+    pub linear_aim: f32,
+    /// share of direction reversals following the previous one faster than humans react
+    pub reaction_time: f32,
+    /// share of input changes at the most common interval, scripted inputs repeat exactly
+    pub periodic_inputs: f32,
+}
+
+impl AnomalyScore {
+    pub fn new(sequence: &Sequence) -> AnomalyScore {
+        AnomalyScore {
+            linear_aim: linear_aim(sequence),
+            reaction_time: reaction_time(sequence),
+            periodic_inputs: periodic_inputs(sequence),
+        }
+    }
+
+    /// highest score of the heuristics
+    pub fn score(&self) -> f32 {
+        self.linear_aim
+            .max(self.reaction_time)
+            .max(self.periodic_inputs)
+    }
+
+    /// names of the heuristics scoring at least FLAG_THRESHOLD, joined by '|'
+    pub fn flags(&self) -> String {
+        [
+            ("linear_aim", self.linear_aim),
+            ("reaction_time", self.reaction_time),
+            ("periodic_inputs", self.periodic_inputs),
+        ]
+        .iter()
+        .filter(|(_, score)| *score >= FLAG_THRESHOLD)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join("|")
+    }
+}
+
+fn linear_aim(sequence: &Sequence) -> f32 {
+    let deltas: Vec<(i32, i32)> = (1..sequence.tick_count)
+        .map(|i| {
+            (
+                sequence.target_x[i] - sequence.target_x[i - 1],
+                sequence.target_y[i] - sequence.target_y[i - 1],
+            )
+        })
+        .collect();
+    let moves = deltas.iter().filter(|&&delta| delta != (0, 0)).count();
+    if moves < MIN_AIM_MOVES {
+        return 0.;
+    }
+
+    let mut linear = 0;
+    for run in deltas.chunk_by(|a, b| a == b) {
+        if run[0] != (0, 0) && run.len() >= LINEAR_AIM_TICKS {
+            linear += run.len();
+        }
+    }
+    linear as f32 / moves as f32
+}
+
+fn reaction_time(sequence: &Sequence) -> f32 {
+    // releasing a key and pressing the other one quickly is human, so only ticks starting a
+    // direction other than the last one count
+    let mut reversal_ticks = Vec::new();
+    let mut last_direction = 0;
+    for (i, &direction) in sequence.move_dir[..sequence.tick_count].iter().enumerate() {
+        if direction != 0 && direction != last_direction {
+            reversal_ticks.push(i);
+            last_direction = direction;
+        }
+    }
+    if reversal_ticks.len() < MIN_DIRECTION_REVERSALS {
+        return 0.;
+    }
+
+    let fast = reversal_ticks
+        .windows(2)
+        .filter(|w| w[1] - w[0] < MIN_REACTION_TICKS)
+        .count();
+    fast as f32 / (reversal_ticks.len() - 1) as f32
+}
+
+fn periodic_inputs(sequence: &Sequence) -> f32 {
+    let change_ticks: Vec<usize> = (1..sequence.tick_count)
+        .filter(|&i| {
+            sequence.move_dir[i] != sequence.move_dir[i - 1]
+                || sequence.jump[i] != sequence.jump[i - 1]
+                || sequence.fire[i] != sequence.fire[i - 1]
+                || sequence.hook[i] != sequence.hook[i - 1]
+        })
+        .collect();
+    if change_ticks.len() < MIN_PERIODIC_CHANGES {
+        return 0.;
+    }
+
+    let mut intervals: Vec<usize> = change_ticks.windows(2).map(|w| w[1] - w[0]).collect();
+    intervals.sort_unstable();
+    let most_common = intervals
+        .chunk_by(|a, b| a == b)
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    most_common as f32 / intervals.len() as f32
+}
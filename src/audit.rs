@@ -0,0 +1,189 @@
+//! Verification of the implicit tick rule of the parser.
+//!
+//! Most tick boundaries aren't stored in teehistorian files, the parser infers them from the
+//! cid order of player chunks (a player chunk with lower or equal cid starts a new tick). If
+//! that rule were wrong, the movement of a player would regularly land in a neighbouring tick.
+//! This is cross-checked against the continuity of movement: velocities change gradually, and
+//! abrupt changes from jumps, hooks, collisions or teleports aren't reverted in the next tick.
+//! A misplaced tick boundary instead shows up as a pair of spikes that cancel out, e.g. a
+//! player moving 10, 0, 20, 10 units in consecutive ticks.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::error::Result;
+use crate::extractor::Extractor;
+use crate::parser::{ParserConfig, ParserEvents};
+use crate::tick::Tick;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AuditConfig {
+    /// Minimum change of a player's movement between two ticks (units per tick) to count as a
+    /// spike. Players moving slower aren't checked, as a misplaced tick boundary can't be
+    /// told apart from acceleration.
+    pub spike_threshold: i32,
+
+    /// maximum difference between the combined movement of two spikes and the movement
+    /// before them, for the spikes to count as cancelling out
+    pub tolerance: i32,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            spike_threshold: 6,
+            tolerance: 3,
+        }
+    }
+}
+
+/// divergence statistics of a file, or multiple files merged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditReport {
+    /// parsed ticks
+    pub ticks: i64,
+
+    /// ticks of all players that moved fast enough to reveal a misplaced tick boundary
+    pub checked: u64,
+
+    /// pairs of ticks with movement spikes that cancel out
+    pub spikes: u64,
+
+    /// spikes where the player stood still and moved twice as far in the next tick
+    pub stalls: u64,
+
+    /// spikes where the player moved twice as far and stood still in the next tick
+    pub doubles: u64,
+
+    /// largest change of movement (units per tick) of any spike
+    pub max_deviation: i32,
+}
+
+impl AuditReport {
+    /// fraction of checked ticks with cancelling spikes, close to 0 for correct alignment
+    pub fn spike_rate(&self) -> f64 {
+        if self.checked == 0 {
+            0.
+        } else {
+            self.spikes as f64 / self.checked as f64
+        }
+    }
+
+    pub fn merge(&mut self, other: &AuditReport) {
+        self.ticks += other.ticks;
+        self.checked += other.checked;
+        self.spikes += other.spikes;
+        self.stalls += other.stalls;
+        self.doubles += other.doubles;
+        self.max_deviation = self.max_deviation.max(other.max_deviation);
+    }
+}
+
+/// position and most recent movements of a player
+struct Motion {
+    position: (i32, i32),
+    /// movements of the last ticks, oldest first
+    moves: Vec<(i32, i32)>,
+}
+
+/// [`ParserEvents`] that audits the tick alignment of player positions, see module docs
+pub struct TickAudit {
+    config: AuditConfig,
+    motions: HashMap<i32, Motion>,
+    pub report: AuditReport,
+}
+
+impl TickAudit {
+    pub fn new(config: AuditConfig) -> TickAudit {
+        TickAudit {
+            config,
+            motions: HashMap::new(),
+            report: AuditReport::default(),
+        }
+    }
+
+    /// check the movements of three consecutive ticks for spikes in the last two
+    fn check(&mut self, before: (i32, i32), first: (i32, i32), second: (i32, i32)) {
+        let threshold = self.config.spike_threshold;
+        if chebyshev(before) <= threshold {
+            return;
+        }
+        self.report.checked += 1;
+
+        // saturating, as corrupted files can hold any positions
+        let first_dev = (
+            first.0.saturating_sub(before.0),
+            first.1.saturating_sub(before.1),
+        );
+        let second_dev = (
+            second.0.saturating_sub(before.0),
+            second.1.saturating_sub(before.1),
+        );
+        let combined_dev = (
+            first_dev.0.saturating_add(second_dev.0),
+            first_dev.1.saturating_add(second_dev.1),
+        );
+        if chebyshev(first_dev) <= threshold
+            || chebyshev(second_dev) <= threshold
+            || chebyshev(combined_dev) > self.config.tolerance
+        {
+            return;
+        }
+
+        self.report.spikes += 1;
+        if first == (0, 0) {
+            self.report.stalls += 1;
+        } else if second == (0, 0) {
+            self.report.doubles += 1;
+        }
+        self.report.max_deviation = self
+            .report
+            .max_deviation
+            .max(chebyshev(first_dev))
+            .max(chebyshev(second_dev));
+    }
+}
+
+impl ParserEvents for TickAudit {
+    fn on_tick(&mut self, tick: i32, state: &Tick) {
+        self.report.ticks = tick as i64 + 1;
+
+        // movements across a respawn aren't related
+        self.motions
+            .retain(|cid, _| state.player_positions.contains_key(cid));
+
+        for (&cid, &position) in state.player_positions.iter() {
+            let Some(motion) = self.motions.get_mut(&cid) else {
+                self.motions.insert(
+                    cid,
+                    Motion {
+                        position,
+                        moves: Vec::with_capacity(3),
+                    },
+                );
+                continue;
+            };
+            motion.moves.push((
+                position.0.saturating_sub(motion.position.0),
+                position.1.saturating_sub(motion.position.1),
+            ));
+            motion.position = position;
+            if motion.moves.len() == 3 {
+                let (before, first, second) = (motion.moves[0], motion.moves[1], motion.moves[2]);
+                motion.moves.remove(0);
+                self.check(before, first, second);
+            }
+        }
+    }
+}
+
+fn chebyshev(v: (i32, i32)) -> i32 {
+    v.0.saturating_abs().max(v.1.saturating_abs())
+}
+
+/// Audit the tick alignment of a single teehistorian file.
+/// If parsing fails midway, the ticks until the error are reported.
+pub fn audit_file(path: &Path, config: &AuditConfig) -> Result<AuditReport> {
+    let mut audit = TickAudit::new(*config);
+    Extractor::parse_file_with_events(path, &ParserConfig::default(), &mut [&mut audit])?;
+    Ok(audit.report)
+}
@@ -0,0 +1,85 @@
+use glob::{MatchOptions, Pattern};
+use std::sync::OnceLock;
+
+use crate::extractor::Sequence;
+
+/// name globs of common server-side bots and dummies, matched case-insensitively
+const BOT_NAME_PATTERNS: &[&str] = &[
+    "nameless tee",
+    "brainless tee",
+    "bot",
+    "bot[0-9]*",
+    "*[[]bot[]]*",
+    "*(bot)*",
+    "dummy",
+    "dummy[0-9]*",
+];
+
+/// ticks without any aim change (20 seconds) that a human player practically never reaches
+const STATIC_AIM_TICKS: usize = 1000;
+
+/// minimum amount of input changes before periodicity is judged
+const MIN_PERIODIC_CHANGES: usize = 10;
+
+fn bot_name_patterns() -> &'static [Pattern] {
+    static PATTERNS: OnceLock<Vec<Pattern>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        BOT_NAME_PATTERNS
+            .iter()
+            .map(|p| Pattern::new(p).expect("invalid bot name pattern"))
+            .collect()
+    })
+}
+
+/// check player name against the built-in list of bot names
+pub fn is_bot_name(player_name: &str) -> bool {
+    let options = MatchOptions {
+        case_sensitive: false,
+        ..MatchOptions::new()
+    };
+    bot_name_patterns()
+        .iter()
+        .any(|pattern| pattern.matches_with(player_name, options))
+}
+
+/// longest stretch of consecutive ticks with an identical aim target
+pub fn longest_static_aim(sequence: &Sequence) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for i in 0..sequence.tick_count {
+        let unchanged = i > 0
+            && sequence.target_x[i] == sequence.target_x[i - 1]
+            && sequence.target_y[i] == sequence.target_y[i - 1];
+        current = if unchanged { current + 1 } else { 1 };
+        longest = longest.max(current);
+    }
+    longest
+}
+
+/// Whether all changes of the button inputs (move, jump, fire, hook) happen at exactly the
+/// same interval, which scripted inputs do and humans don't.
+pub fn has_periodic_inputs(sequence: &Sequence) -> bool {
+    let change_ticks: Vec<usize> = (1..sequence.tick_count)
+        .filter(|&i| {
+            sequence.move_dir[i] != sequence.move_dir[i - 1]
+                || sequence.jump[i] != sequence.jump[i - 1]
+                || sequence.fire[i] != sequence.fire[i - 1]
+                || sequence.hook[i] != sequence.hook[i - 1]
+        })
+        .collect();
+
+    if change_ticks.len() < MIN_PERIODIC_CHANGES {
+        return false;
+    }
+
+    let interval = change_ticks[1] - change_ticks[0];
+    change_ticks.windows(2).all(|w| w[1] - w[0] == interval)
+}
+
+/// Combines the name blocklist with input heuristics.
+/// Meant to be applied to cleaned sequences, as AFK stretches would trigger the aim heuristic.
+pub fn is_bot(sequence: &Sequence) -> bool {
+    is_bot_name(&sequence.player_name)
+        || longest_static_aim(sequence) >= STATIC_AIM_TICKS
+        || has_periodic_inputs(sequence)
+}
@@ -0,0 +1,57 @@
+//! Cooperative cancellation of long running extractions.
+
+use crate::parser::ParseError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// limits are only checked every this many chunks
+const CHECK_INTERVAL: u32 = 1024;
+
+/// Shared flag to stop parsing early, checked between files and chunks.
+/// Clones refer to the same flag, so it can be cancelled from another thread.
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// request all users of this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Conditions that stop parsing a single file early.
+/// Sequences completed until then are kept, see [`crate::extractor::ParsedFile::error`].
+#[derive(Clone, Default, Debug)]
+pub struct ParseLimits {
+    pub cancel: Option<CancellationToken>,
+
+    /// maximum time spent on a single file
+    pub timeout: Option<Duration>,
+}
+
+impl ParseLimits {
+    /// Error if parsing has to stop before the given chunk, started is when the file was opened.
+    pub(crate) fn check(&self, chunk_index: u32, started: Instant) -> Result<(), ParseError> {
+        if !chunk_index.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if self.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Err(ParseError::Cancelled);
+        }
+        match self.timeout {
+            Some(timeout) if started.elapsed() > timeout => Err(ParseError::Timeout(timeout)),
+            _ => Ok(()),
+        }
+    }
+}
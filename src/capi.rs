@@ -0,0 +1,169 @@
+//! C interface to stream the sequences of a teehistorian file, enabled by the `capi` feature.
+//! See include/teehistorian_extractor.h for the declarations.
+//!
+//! All data handed out stays owned by the iterator and is valid until the next call to
+//! [`teehist_sequence_iter_next`] or [`teehist_sequence_iter_free`].
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::path::Path;
+use std::ptr;
+
+use crate::error::Result;
+use crate::extractor::{Extractor, Sequence};
+use crate::parser::ParserConfig;
+
+/// parser settings, see [`ParserConfig`]
+#[repr(C)]
+pub struct TeehistParserConfig {
+    pub cut_kill: bool,
+    pub cut_rescue: bool,
+    pub max_speed: i32,
+}
+
+/// A sequence as seen from C. All tick arrays have tick_count entries.
+/// timeout_code is null if unknown, finish_time is only valid if has_finish_time is set.
+#[repr(C)]
+pub struct TeehistSequence {
+    pub start_tick: usize,
+    pub tick_count: usize,
+    pub player_name: *const c_char,
+    pub map_name: *const c_char,
+    pub teehist_name: *const c_char,
+    pub timeout_code: *const c_char,
+    pub has_finish_time: bool,
+    pub finish_time: i32,
+    pub pos_x: *const i32,
+    pub pos_y: *const i32,
+    pub move_dir: *const i32,
+    pub target_x: *const i32,
+    pub target_y: *const i32,
+    pub jump: *const bool,
+    pub fire: *const bool,
+    pub hook: *const bool,
+}
+
+/// opaque iterator handle
+pub struct TeehistSequenceIter {
+    sequences: Box<dyn Iterator<Item = Result<Sequence>>>,
+    current: Option<Sequence>,
+    /// player, map, teehistorian name and timeout code of current
+    strings: Vec<CString>,
+    error: Option<CString>,
+}
+
+/// C string without interior nul bytes, which can't be represented
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+/// Open a teehistorian file for streaming its sequences.
+/// config may be null to use the defaults. Returns null if path is not valid UTF-8.
+/// Errors while opening or parsing the file are reported by [`teehist_sequence_iter_next`].
+///
+/// # Safety
+/// path must be a valid nul-terminated string, config null or a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn teehist_sequence_iter_open(
+    path: *const c_char,
+    config: *const TeehistParserConfig,
+) -> *mut TeehistSequenceIter {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return ptr::null_mut();
+    };
+    let parser_config = match config.as_ref() {
+        Some(config) => ParserConfig {
+            cut_kill: config.cut_kill,
+            cut_rescue: config.cut_rescue,
+            max_speed: config.max_speed,
+            ..Default::default()
+        },
+        None => ParserConfig::default(),
+    };
+    let sequences = Extractor::sequence_iter(Path::new(path), &parser_config);
+    Box::into_raw(Box::new(TeehistSequenceIter {
+        sequences: Box::new(sequences),
+        current: None,
+        strings: Vec::new(),
+        error: None,
+    }))
+}
+
+/// Advance to the next sequence and describe it in out.
+/// Returns 1 if a sequence was written, 0 at the end of the file and -1 on error, see
+/// [`teehist_sequence_iter_error`]. Sequences completed before an error are returned first.
+///
+/// # Safety
+/// iter must come from [`teehist_sequence_iter_open`], out must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn teehist_sequence_iter_next(
+    iter: *mut TeehistSequenceIter,
+    out: *mut TeehistSequence,
+) -> c_int {
+    let (Some(iter), Some(out)) = (iter.as_mut(), out.as_mut()) else {
+        return -1;
+    };
+    let sequence = match iter.sequences.next() {
+        None => return 0,
+        Some(Err(err)) => {
+            iter.error = Some(c_string(&err.to_string()));
+            return -1;
+        }
+        Some(Ok(sequence)) => iter.current.insert(sequence),
+    };
+
+    iter.strings = vec![
+        c_string(&sequence.player_name),
+        c_string(&sequence.map_name),
+        c_string(&sequence.teehist_name),
+    ];
+    if let Some(timeout_code) = &sequence.timeout_code {
+        iter.strings.push(c_string(timeout_code));
+    }
+
+    *out = TeehistSequence {
+        start_tick: sequence.start_tick,
+        tick_count: sequence.tick_count,
+        player_name: iter.strings[0].as_ptr(),
+        map_name: iter.strings[1].as_ptr(),
+        teehist_name: iter.strings[2].as_ptr(),
+        timeout_code: iter.strings.get(3).map_or(ptr::null(), |s| s.as_ptr()),
+        has_finish_time: sequence.finish_time.is_some(),
+        finish_time: sequence.finish_time.unwrap_or_default(),
+        pos_x: sequence.pos_x.as_ptr(),
+        pos_y: sequence.pos_y.as_ptr(),
+        move_dir: sequence.move_dir.as_ptr(),
+        target_x: sequence.target_x.as_ptr(),
+        target_y: sequence.target_y.as_ptr(),
+        jump: sequence.jump.as_ptr(),
+        fire: sequence.fire.as_ptr(),
+        hook: sequence.hook.as_ptr(),
+    };
+    1
+}
+
+/// Message of the last error, null if none occurred.
+///
+/// # Safety
+/// iter must come from [`teehist_sequence_iter_open`].
+#[no_mangle]
+pub unsafe extern "C" fn teehist_sequence_iter_error(
+    iter: *const TeehistSequenceIter,
+) -> *const c_char {
+    iter.as_ref()
+        .and_then(|iter| iter.error.as_ref())
+        .map_or(ptr::null(), |error| error.as_ptr())
+}
+
+/// Close the file and release all data of the iterator.
+///
+/// # Safety
+/// iter must come from [`teehist_sequence_iter_open`] and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn teehist_sequence_iter_free(iter: *mut TeehistSequenceIter) {
+    if !iter.is_null() {
+        drop(Box::from_raw(iter));
+    }
+}
@@ -0,0 +1,131 @@
+//! SHA-256 checksums of the files of a dataset, so copies between machines can be checked for
+//! silent corruption.
+//!
+//! Checksums are stored as SHA256SUMS in the format of `sha256sum`, so `sha256sum -c SHA256SUMS`
+//! works as well. It also lists manifest.json and thereby signs off the counts and schema of
+//! the dataset.
+
+use log::info;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::error::{Error, Result};
+use crate::export::export_files;
+use crate::remote::file_sha256;
+
+/// file listing the sha256 of every other file of a dataset, in the format of `sha256sum`
+pub const CHECKSUM_FILE: &str = "SHA256SUMS";
+
+/// a line of SHA256SUMS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    /// lowercase hex sha256
+    pub sha256: String,
+    pub file_name: String,
+}
+
+impl Checksum {
+    /// format as SHA256SUMS line
+    pub fn to_line(&self) -> String {
+        format!("{}  {}", self.sha256, self.file_name)
+    }
+}
+
+/// Parse the lines of SHA256SUMS, both the text ("  ") and binary (" *") mode of `sha256sum`
+pub fn parse_checksums(content: &str) -> Result<Vec<Checksum>> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (sha256, file_name) = line
+                .split_once("  ")
+                .or_else(|| line.split_once(" *"))
+                .filter(|(sha256, file_name)| {
+                    sha256.len() == 64
+                        && sha256.bytes().all(|b| b.is_ascii_hexdigit())
+                        && !file_name.is_empty()
+                })
+                .ok_or_else(|| {
+                    Error::InvalidExport(format!("invalid {} line {:?}", CHECKSUM_FILE, line))
+                })?;
+            Ok(Checksum {
+                sha256: sha256.to_ascii_lowercase(),
+                file_name: file_name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Write SHA256SUMS of the files of the dataset in folder_path, see [`export_files`].
+/// Returns the written checksums.
+pub fn write_checksums(folder_path: &Path) -> Result<Vec<Checksum>> {
+    let checksums = export_files(folder_path)
+        .iter()
+        .map(|path| {
+            Ok(Checksum {
+                sha256: file_sha256(path)?,
+                file_name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into(),
+            })
+        })
+        .collect::<Result<Vec<Checksum>>>()?;
+    save_checksums(folder_path, &checksums)?;
+    Ok(checksums)
+}
+
+/// write checksums as SHA256SUMS into folder_path, replacing an outdated one once complete
+pub fn save_checksums(folder_path: &Path, checksums: &[Checksum]) -> Result<()> {
+    let tmp_path = folder_path.join(format!("{}.tmp", CHECKSUM_FILE));
+    let mut checksum_file = BufWriter::new(File::create(&tmp_path)?);
+    for checksum in checksums {
+        writeln!(checksum_file, "{}", checksum.to_line())?;
+    }
+    checksum_file.flush()?;
+    drop(checksum_file);
+    fs::rename(tmp_path, folder_path.join(CHECKSUM_FILE))?;
+    Ok(())
+}
+
+/// Compare the files of the dataset in folder_path against its SHA256SUMS and describe every
+/// missing, modified or unlisted file
+pub fn verify(folder_path: &Path) -> Result<Vec<String>> {
+    let checksum_path = folder_path.join(CHECKSUM_FILE);
+    if !checksum_path.is_file() {
+        return Err(Error::InvalidExport(format!(
+            "no {} in {:?}",
+            CHECKSUM_FILE, folder_path
+        )));
+    }
+    let checksums = parse_checksums(&fs::read_to_string(checksum_path)?)?;
+
+    let mut issues = Vec::new();
+    for checksum in &checksums {
+        let path = folder_path.join(&checksum.file_name);
+        if !path.is_file() {
+            issues.push(format!("{} is missing", checksum.file_name));
+            continue;
+        }
+        let sha256 = file_sha256(&path)?;
+        if sha256 != checksum.sha256 {
+            issues.push(format!(
+                "{} has sha256 {}, expected {}",
+                checksum.file_name, sha256, checksum.sha256
+            ));
+        } else {
+            info!("{}: OK", checksum.file_name);
+        }
+    }
+    for path in export_files(folder_path) {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !checksums.iter().any(|c| c.file_name == file_name) {
+            issues.push(format!("{} is not listed in {}", file_name, CHECKSUM_FILE));
+        }
+    }
+    Ok(issues)
+}
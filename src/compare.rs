@@ -0,0 +1,362 @@
+//! Drift between two exported datasets, to notice when a new scrape changes the data
+//! distribution a model was trained on.
+//!
+//! Feature distributions are estimated from sequences spread evenly over each dataset,
+//! padding after the recorded ticks of a sequence is skipped.
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, Write},
+};
+
+use crate::dataset::{Dataset, DatasetError};
+
+/// bins of the feature histograms
+pub const HISTOGRAM_BINS: usize = 20;
+
+/// characters of the text histograms, from empty to the fullest bin
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A value of the old and the new dataset
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Pair<T> {
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FeatureDrift {
+    pub name: String,
+    pub mean: Pair<f64>,
+    /// largest distance between the empirical distribution functions, in 0..=1
+    pub ks_statistic: f64,
+    /// HISTOGRAM_BINS equally wide bins from min to max of both samples
+    pub min: f32,
+    pub max: f32,
+    /// share of the values in each bin
+    pub histogram: Pair<Vec<f64>>,
+}
+
+impl FeatureDrift {
+    /// Compare the samples of a feature, non-finite values are ignored
+    pub fn new(name: &str, mut old: Vec<f32>, mut new: Vec<f32>) -> FeatureDrift {
+        old.retain(|value| value.is_finite());
+        new.retain(|value| value.is_finite());
+        old.sort_unstable_by(f32::total_cmp);
+        new.sort_unstable_by(f32::total_cmp);
+
+        let min = old
+            .first()
+            .into_iter()
+            .chain(new.first())
+            .copied()
+            .reduce(f32::min);
+        let max = old
+            .last()
+            .into_iter()
+            .chain(new.last())
+            .copied()
+            .reduce(f32::max);
+        let (min, max) = (min.unwrap_or(0.), max.unwrap_or(0.));
+        FeatureDrift {
+            name: name.to_string(),
+            mean: Pair {
+                old: mean(&old),
+                new: mean(&new),
+            },
+            ks_statistic: ks_statistic(&old, &new),
+            min,
+            max,
+            histogram: Pair {
+                old: histogram(&old, min, max),
+                new: histogram(&new, min, max),
+            },
+        }
+    }
+}
+
+fn mean(values: &[f32]) -> f64 {
+    values.iter().map(|&v| v as f64).sum::<f64>() / values.len().max(1) as f64
+}
+
+/// shares of the values in HISTOGRAM_BINS bins from min to max
+fn histogram(values: &[f32], min: f32, max: f32) -> Vec<f64> {
+    let mut bins = vec![0.; HISTOGRAM_BINS];
+    let width = (max - min) as f64;
+    for &value in values {
+        let bin = if width > 0. {
+            ((value - min) as f64 / width * HISTOGRAM_BINS as f64) as usize
+        } else {
+            0
+        };
+        bins[bin.min(HISTOGRAM_BINS - 1)] += 1. / values.len() as f64;
+    }
+    bins
+}
+
+/// Two-sample Kolmogorov-Smirnov statistic of sorted samples. 0 if both are empty, 1 if
+/// only one is.
+pub fn ks_statistic(old: &[f32], new: &[f32]) -> f64 {
+    if old.is_empty() || new.is_empty() {
+        return if old.len() == new.len() { 0. } else { 1. };
+    }
+    let (mut i, mut j, mut statistic) = (0, 0, 0f64);
+    while i < old.len() && j < new.len() {
+        // step over all values equal to the smallest remaining one, ties move both functions
+        let value = old[i].min(new[j]);
+        while i < old.len() && old[i] <= value {
+            i += 1;
+        }
+        while j < new.len() && new[j] <= value {
+            j += 1;
+        }
+        let distance = (i as f64 / old.len() as f64 - j as f64 / new.len() as f64).abs();
+        statistic = statistic.max(distance);
+    }
+    statistic
+}
+
+/// Share of the ticks of a map or player in the old and new dataset
+#[derive(Serialize, Debug, Clone)]
+pub struct ShareChange {
+    pub name: String,
+    pub share: Pair<f64>,
+}
+
+impl ShareChange {
+    pub fn change(&self) -> f64 {
+        self.share.new - self.share.old
+    }
+}
+
+/// Composition of the ticks of two datasets, as returned by [`Dataset::map_counts`] and
+/// [`Dataset::player_counts`]
+#[derive(Serialize, Debug, Clone)]
+pub struct CompositionDrift {
+    /// total variation distance of the shares, 0 for identical and 1 for disjoint compositions
+    pub distance: f64,
+    /// all names of both datasets, largest change first
+    pub changes: Vec<ShareChange>,
+}
+
+impl CompositionDrift {
+    pub fn new(
+        old: &HashMap<&str, (usize, usize)>,
+        new: &HashMap<&str, (usize, usize)>,
+    ) -> CompositionDrift {
+        let total = |counts: &HashMap<&str, (usize, usize)>| {
+            counts
+                .values()
+                .map(|&(_, ticks)| ticks)
+                .sum::<usize>()
+                .max(1) as f64
+        };
+        let (old_total, new_total) = (total(old), total(new));
+        let share = |counts: &HashMap<&str, (usize, usize)>, name: &str, total: f64| {
+            counts
+                .get(name)
+                .map_or(0., |&(_, ticks)| ticks as f64 / total)
+        };
+
+        let names: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+        let mut changes: Vec<ShareChange> = names
+            .into_iter()
+            .map(|name| ShareChange {
+                name: name.to_string(),
+                share: Pair {
+                    old: share(old, name, old_total),
+                    new: share(new, name, new_total),
+                },
+            })
+            .collect();
+        changes.sort_by(|a, b| b.change().abs().total_cmp(&a.change().abs()));
+        CompositionDrift {
+            distance: changes.iter().map(|c| c.change().abs()).sum::<f64>() / 2.,
+            changes,
+        }
+    }
+}
+
+/// Differences of the sequence layouts, empty if the datasets are compatible
+pub fn schema_differences(
+    (old_columns, old_seq_length): (&[String], usize),
+    (new_columns, new_seq_length): (&[String], usize),
+) -> Vec<String> {
+    let mut differences = Vec::new();
+    if old_seq_length != new_seq_length {
+        differences.push(format!(
+            "seq_length changed from {} to {}",
+            old_seq_length, new_seq_length
+        ));
+    }
+    let removed: Vec<&String> = old_columns
+        .iter()
+        .filter(|c| !new_columns.contains(c))
+        .collect();
+    let added: Vec<&String> = new_columns
+        .iter()
+        .filter(|c| !old_columns.contains(c))
+        .collect();
+    if !removed.is_empty() {
+        differences.push(format!("removed columns {:?}", removed));
+    }
+    if !added.is_empty() {
+        differences.push(format!("added columns {:?}", added));
+    }
+    let old_order: Vec<&String> = old_columns
+        .iter()
+        .filter(|c| new_columns.contains(c))
+        .collect();
+    let new_order: Vec<&String> = new_columns
+        .iter()
+        .filter(|c| old_columns.contains(c))
+        .collect();
+    if old_order != new_order {
+        differences.push(format!(
+            "column order changed from {:?} to {:?}",
+            old_order, new_order
+        ));
+    }
+    differences
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DriftReport {
+    pub sequences: Pair<usize>,
+    pub schema: Vec<String>,
+    /// features present in both datasets, in the column order of the old one
+    pub features: Vec<FeatureDrift>,
+    pub maps: CompositionDrift,
+    pub players: CompositionDrift,
+}
+
+impl DriftReport {
+    /// Compare two datasets, sampling at most max_sequences sequences of each for the
+    /// feature distributions
+    pub fn new(
+        old: &Dataset,
+        new: &Dataset,
+        max_sequences: usize,
+    ) -> Result<DriftReport, DatasetError> {
+        let mut old_samples = sample_features(old, max_sequences)?;
+        let mut new_samples = sample_features(new, max_sequences)?;
+        let features = old
+            .column_names
+            .iter()
+            .filter_map(|name| {
+                let old_values = old_samples.remove(name)?;
+                let new_values = new_samples.remove(name)?;
+                Some(FeatureDrift::new(name, old_values, new_values))
+            })
+            .collect();
+
+        Ok(DriftReport {
+            sequences: Pair {
+                old: old.meta.len(),
+                new: new.meta.len(),
+            },
+            schema: schema_differences(
+                (&old.column_names, old.seq_length()),
+                (&new.column_names, new.seq_length()),
+            ),
+            features,
+            maps: CompositionDrift::new(&old.map_counts(), &new.map_counts()),
+            players: CompositionDrift::new(&old.player_counts(), &new.player_counts()),
+        })
+    }
+
+    pub fn write_json(&self, writer: impl Write) -> crate::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Human-readable report listing the top_k largest map and player changes
+    pub fn write_text(&self, top_k: usize, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "sequences: {} -> {}",
+            self.sequences.old, self.sequences.new
+        )?;
+        if self.schema.is_empty() {
+            writeln!(writer, "schema: unchanged")?;
+        } else {
+            writeln!(writer, "schema:")?;
+            for difference in &self.schema {
+                writeln!(writer, "  {}", difference)?;
+            }
+        }
+
+        writeln!(writer, "features (histograms from min to max, old / new):")?;
+        for feature in &self.features {
+            writeln!(
+                writer,
+                "  {:<10} ks={:.3} mean {:.3} -> {:.3}  [{}, {}] {} / {}",
+                feature.name,
+                feature.ks_statistic,
+                feature.mean.old,
+                feature.mean.new,
+                feature.min,
+                feature.max,
+                bars(&feature.histogram.old),
+                bars(&feature.histogram.new)
+            )?;
+        }
+
+        for (title, composition) in [("maps", &self.maps), ("players", &self.players)] {
+            writeln!(
+                writer,
+                "{} (distance {:.3}), top {} changes of tick share:",
+                title, composition.distance, top_k
+            )?;
+            for change in composition.changes.iter().take(top_k) {
+                writeln!(
+                    writer,
+                    "  {:<20} {:>6.2}% -> {:>6.2}%",
+                    change.name,
+                    change.share.old * 100.,
+                    change.share.new * 100.
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// histogram as bar characters, scaled to its fullest bin
+fn bars(histogram: &[f64]) -> String {
+    let max = histogram.iter().copied().fold(0., f64::max);
+    histogram
+        .iter()
+        .map(|&share| {
+            if max > 0. {
+                BARS[((share / max) * (BARS.len() - 1) as f64).round() as usize]
+            } else {
+                BARS[0]
+            }
+        })
+        .collect()
+}
+
+/// column name -> values of the recorded ticks of up to max_sequences sequences
+fn sample_features(
+    dataset: &Dataset,
+    max_sequences: usize,
+) -> Result<HashMap<String, Vec<f32>>, DatasetError> {
+    let rows = dataset.meta.len().min(dataset.shape().0);
+    let count = max_sequences.min(rows);
+    let mut samples: HashMap<String, Vec<f32>> = HashMap::new();
+    for i in 0..count {
+        let row = i * rows / count;
+        let ticks = dataset.meta[row].ticks.min(dataset.seq_length());
+        let sequence = dataset.read_sequences(row, row + 1)?;
+        for (feature, name) in dataset.column_names.iter().enumerate() {
+            let values = sequence.slice(ndarray::s![0, ..ticks, feature]);
+            samples
+                .entry(name.clone())
+                .or_default()
+                .extend(values.iter());
+        }
+    }
+    Ok(samples)
+}
@@ -0,0 +1,143 @@
+use crate::export::ExportConfig;
+use crate::parser::ParserConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// file name of the effective config written into the output folder
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Schema version of [`RunConfig`], increased whenever keys are renamed or change meaning.
+/// Configs without a version predate versioning and are treated as version 0.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("could not read config {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("invalid toml config: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("invalid yaml config: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("could not serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+
+    #[error("unsupported config format {0:?}, expected .toml, .yaml or .yml")]
+    UnsupportedFormat(PathBuf),
+
+    #[error("invalid config: {0}")]
+    Invalid(String),
+
+    #[error("config version {0} is newer than the supported version {CONFIG_VERSION}")]
+    UnsupportedVersion(u32),
+}
+
+/// Full configuration of an extraction run, as read from `--config` and written to
+/// config.toml in the output folder. Missing keys fall back to their defaults.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunConfig {
+    /// schema version, see [`CONFIG_VERSION`]
+    #[serde(default)]
+    pub version: u32,
+    pub parser: ParserConfig,
+    pub export: ExportConfig,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig::new(ParserConfig::default(), ExportConfig::default())
+    }
+}
+
+impl RunConfig {
+    /// config of the current schema version
+    pub fn new(parser: ParserConfig, export: ExportConfig) -> RunConfig {
+        RunConfig {
+            version: CONFIG_VERSION,
+            parser,
+            export,
+        }
+    }
+
+    /// read a toml or yaml config, the format is chosen by file extension.
+    /// Configs of older versions are migrated to the current one.
+    pub fn load(path: &Path) -> Result<RunConfig, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        let config: RunConfig = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.into())),
+        };
+        let config = config.migrate()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// upgrade a deserialized config to CONFIG_VERSION
+    fn migrate(mut self) -> Result<RunConfig, ConfigError> {
+        if self.version > CONFIG_VERSION {
+            return Err(ConfigError::UnsupportedVersion(self.version));
+        }
+        // version 0 -> 1: only the version key was added
+        if self.version == 0 {
+            self.version = 1;
+        }
+        Ok(self)
+    }
+
+    /// check parser and export config, see their validate functions
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.parser.validate()?;
+        self.export.validate()
+    }
+
+    /// write as config.toml into folder_path
+    pub fn save(&self, folder_path: &Path) -> Result<(), ConfigError> {
+        self.save_as(&folder_path.join(CONFIG_FILE_NAME))
+    }
+
+    /// write as toml or yaml, the format is chosen by file extension like in [`RunConfig::load`]
+    pub fn save_as(&self, path: &Path) -> Result<(), ConfigError> {
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::to_string_pretty(self)?,
+            Some("yaml" | "yml") => serde_yaml::to_string(self)?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.into())),
+        };
+        fs::write(path, content).map_err(|e| ConfigError::Io(path.into(), e))
+    }
+}
+
+/// serde helper for optional lists of glob patterns, stored as their pattern strings
+pub(crate) mod patterns {
+    use glob::Pattern;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        patterns: &Option<Vec<Pattern>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        patterns
+            .as_ref()
+            .map(|p| p.iter().map(Pattern::as_str).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<Pattern>>, D::Error> {
+        Option::<Vec<String>>::deserialize(deserializer)?
+            .map(|p| {
+                p.iter()
+                    .map(|s| Pattern::new(s).map_err(D::Error::custom))
+                    .collect()
+            })
+            .transpose()
+    }
+}
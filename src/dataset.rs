@@ -0,0 +1,1121 @@
+use chrono::{DateTime, Utc};
+use glob::Pattern;
+use hdf5_metno::{
+    self as hdf5,
+    file::{CacheDecreaseMode, CacheIncreaseMode, FlashIncreaseMode, MetadataCacheConfig},
+    types::VarLenAscii,
+};
+use log::{info, warn};
+
+use crate::checksum;
+use crate::config::{RunConfig, CONFIG_FILE_NAME};
+use crate::embedding;
+use crate::error::Error;
+use crate::export::{feature_range, recorded_time};
+use crate::registry::{PlayerRegistry, REGISTRY_FILE};
+use ndarray::{s, Array2, Array3, ArrayView3, Axis};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::{self, create_dir_all, File},
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+
+/// header of meta.csv, one row per exported sequence
+pub const META_HEADER: &str =
+    "seq_id,player_id,player,start,ticks,map,teehist,timeout,finish_time,\
+anomaly_score,anomaly_flags,map_width,map_height,map_stars,map_spawns,map_category,map_points,\
+map_release,record_time,record_rank,record_finishers,recorded,weight,labels,auth_name,auth_level,session_id,session_seq,route_cells,route";
+
+/// Schema version of the dataset files, written into manifest.json as schema_version and
+/// increased whenever meta.csv or sequences.h5 columns are renamed or change meaning.
+/// Datasets without a version predate versioning and are treated as version 0.
+pub const DATASET_VERSION: u32 = 1;
+
+/// sequences are copied in chunks of this size to bound memory
+const COPY_CHUNK_SIZE: usize = 1000;
+
+/// approximate size of a chunk of the sequences dataset
+const CHUNK_BYTES: usize = 1 << 20;
+
+/// chunk cache of the sequences dataset, holds a few partially written chunks
+const CHUNK_CACHE_BYTES: usize = 16 * CHUNK_BYTES;
+
+/// hash table slots of the chunk cache, a prime about 100 times the amount of cached chunks
+const CHUNK_CACHE_SLOTS: usize = 1597;
+
+/// Amount of sequences per chunk of the sequences dataset.
+/// Writes of whole chunks avoid reading back partially written ones.
+pub fn chunk_sequences(seq_length: usize, num_features: usize) -> usize {
+    (CHUNK_BYTES / (seq_length * num_features * size_of::<f32>()).max(1)).max(1)
+}
+
+/// Metadata cache keeping changed metadata in memory until the file is flushed, so the
+/// metadata on disk describes the file as of the last flush and a crash between flushes
+/// leaves it readable. Without evictions HDF5 requires automatic resizing to be off.
+fn flush_only_metadata_cache() -> MetadataCacheConfig {
+    MetadataCacheConfig {
+        evictions_enabled: false,
+        incr_mode: CacheIncreaseMode::Off,
+        flash_incr_mode: FlashIncreaseMode::Off,
+        decr_mode: CacheDecreaseMode::Off,
+        ..MetadataCacheConfig::default()
+    }
+}
+
+/// Open the dataset of an existing sequences.h5 for writing
+pub fn open_sequences_file(folder_path: &Path) -> Result<hdf5::Dataset, DatasetError> {
+    let seq_file = hdf5::File::with_options()
+        .with_fapl(|fapl| {
+            fapl.chunk_cache(CHUNK_CACHE_SLOTS, CHUNK_CACHE_BYTES, 1.0)
+                .mdc_config(&flush_only_metadata_cache())
+        })
+        .open_rw(folder_path.join("sequences.h5"))?;
+    Ok(seq_file.dataset("sequences")?)
+}
+
+#[derive(Error, Debug)]
+pub enum DatasetError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("hdf5 error: {0}")]
+    Hdf5(#[from] hdf5::Error),
+
+    #[error("could not read meta.csv: {0}")]
+    Meta(#[from] csv::Error),
+
+    #[error("incompatible datasets: {0}")]
+    SchemaMismatch(String),
+
+    #[error("dataset failed validation with {0} issues")]
+    ValidationFailed(usize),
+
+    #[error("invalid manifest.json: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    #[error("dataset version {0} is newer than the supported version {DATASET_VERSION}")]
+    UnsupportedVersion(u32),
+
+    #[error("invalid reshape: {0}")]
+    InvalidReshape(String),
+
+    #[error("sequences.h5 has {sequences} rows and meta.csv {meta} rows, expected {expected}")]
+    CountMismatch {
+        sequences: usize,
+        meta: usize,
+        expected: usize,
+    },
+}
+
+/// a single row of meta.csv
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaRow {
+    pub seq_id: usize,
+    pub player_id: usize,
+    pub player: Arc<str>,
+    pub start: usize,
+    pub ticks: usize,
+    pub map: Arc<str>,
+    pub teehist: Arc<str>,
+    pub timeout: Option<String>,
+    pub finish_time: Option<i32>,
+    /// see [`crate::anomaly::AnomalyScore`], missing in exports of older versions
+    #[serde(default)]
+    pub anomaly_score: Option<f32>,
+    /// flagged heuristics joined by '|'
+    #[serde(default)]
+    pub anomaly_flags: Option<String>,
+    /// see [`crate::map_info::MapInfo`], empty for maps without map file
+    #[serde(default)]
+    pub map_width: Option<usize>,
+    #[serde(default)]
+    pub map_height: Option<usize>,
+    #[serde(default)]
+    pub map_stars: Option<u8>,
+    /// spawn positions as "x:y" joined by '|'
+    #[serde(default)]
+    pub map_spawns: Option<String>,
+    #[serde(default)]
+    pub map_category: Option<String>,
+    #[serde(default)]
+    pub map_points: Option<u32>,
+    /// release date as YYYY-MM-DD
+    #[serde(default)]
+    pub map_release: Option<String>,
+    /// see [`crate::records::PlayerRecord`], empty if the player has no finish on the map
+    #[serde(default)]
+    pub record_time: Option<f32>,
+    #[serde(default)]
+    pub record_rank: Option<usize>,
+    #[serde(default)]
+    pub record_finishers: Option<usize>,
+    /// start of the sequence as RFC 3339 UTC time, from the start_time of the teehistorian
+    /// header. Empty for files without it.
+    #[serde(default)]
+    pub recorded: Option<String>,
+    /// sample weight, see [`crate::weights`]. Empty unless weight rules were given.
+    #[serde(default)]
+    pub weight: Option<f32>,
+    /// labels of external annotations joined by '|', see [`crate::labels`]
+    #[serde(default)]
+    pub labels: Option<String>,
+    /// server account the player was logged into, see [`crate::parser::PlayerAuth`].
+    /// Identifies the player more reliably than the name.
+    #[serde(default)]
+    pub auth_name: Option<String>,
+    #[serde(default)]
+    pub auth_level: Option<i32>,
+    /// play session of the sequence, see [`crate::session`]
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// position of the sequence in its session, starting at 0. Sequences dropped after
+    /// cleaning, e.g. as duplicates or outliers, leave gaps.
+    #[serde(default)]
+    pub session_seq: Option<usize>,
+    /// coarse path of the sequence as grid cells "x:y" joined by '|', see [`crate::route`]
+    #[serde(default)]
+    pub route_cells: Option<String>,
+    /// route of the sequence among those of its map, 0 is the most common
+    #[serde(default)]
+    pub route: Option<usize>,
+}
+
+impl MetaRow {
+    /// format as meta.csv line, player names are always quoted and other string columns if
+    /// they contain a separator, quote or line break
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},\"{}\",{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.seq_id,
+            self.player_id,
+            self.player.replace('"', "\"\""),
+            self.start,
+            self.ticks,
+            escaped(&self.map),
+            escaped(&self.teehist),
+            optional_str(self.timeout.as_deref()),
+            self.finish_time.map(|t| t.to_string()).unwrap_or_default(),
+            self.anomaly_score
+                .map(|s| format!("{:.3}", s))
+                .unwrap_or_default(),
+            optional_str(self.anomaly_flags.as_deref()),
+            optional(self.map_width),
+            optional(self.map_height),
+            optional(self.map_stars),
+            optional_str(self.map_spawns.as_deref()),
+            optional_str(self.map_category.as_deref()),
+            optional(self.map_points),
+            optional_str(self.map_release.as_deref()),
+            optional(self.record_time),
+            optional(self.record_rank),
+            optional(self.record_finishers),
+            optional_str(self.recorded.as_deref()),
+            optional(self.weight),
+            optional_str(self.labels.as_deref()),
+            optional_str(self.auth_name.as_deref()),
+            optional(self.auth_level),
+            optional_str(self.session_id.as_deref()),
+            optional(self.session_seq),
+            optional_str(self.route_cells.as_deref()),
+            optional(self.route)
+        )
+    }
+}
+
+/// value of an optional meta.csv column, empty if None
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// value of an optional string column of meta.csv, empty if None, see [`escaped`]
+fn optional_str(value: Option<&str>) -> Cow<'_, str> {
+    value.map(escaped).unwrap_or_default()
+}
+
+/// Value of a string column of meta.csv. Values such as timeout codes and labels come from
+/// players or annotation files, so they are quoted like csv writers do if they contain a
+/// separator, quote or line break, with quotes doubled.
+fn escaped(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Create sequences.h5 with an empty, resizable (sequences, seq_length, features) dataset
+/// and the column names as attribute. Like [`open_sequences_file`], metadata only reaches the
+/// disk when the file is flushed.
+pub fn create_sequences_file(
+    folder_path: &Path,
+    seq_length: usize,
+    column_names: &[String],
+) -> Result<hdf5::Dataset, DatasetError> {
+    let seq_file = hdf5::File::with_options()
+        .with_fapl(|fapl| fapl.mdc_config(&flush_only_metadata_cache()))
+        .create(folder_path.join("sequences.h5"))?;
+    let seq_dataset = seq_file
+        .new_dataset::<f32>()
+        .shape((hdf5::Extent::resizable(0), seq_length, column_names.len()))
+        .chunk((
+            chunk_sequences(seq_length, column_names.len()),
+            seq_length,
+            column_names.len(),
+        ))
+        .chunk_cache(CHUNK_CACHE_SLOTS, CHUNK_CACHE_BYTES, 1.0)
+        .create("sequences")?;
+
+    // add column named header attribute
+    let column_names_vla: Vec<VarLenAscii> = column_names
+        .iter()
+        .map(|s| VarLenAscii::from_ascii(s.as_bytes()).unwrap())
+        .collect();
+    let attr = seq_dataset
+        .new_attr::<VarLenAscii>()
+        .shape(column_names_vla.len())
+        .create("column_names")?;
+    attr.write(&column_names_vla)?;
+
+    Ok(seq_dataset)
+}
+
+/// schema_version of a manifest.json, 0 for datasets without manifest or version
+pub fn schema_version(manifest: &serde_json::Value) -> Result<u32, DatasetError> {
+    let version = manifest["schema_version"].as_u64().unwrap_or(0);
+    match u32::try_from(version) {
+        Ok(version) if version <= DATASET_VERSION => Ok(version),
+        _ => Err(DatasetError::UnsupportedVersion(
+            version.try_into().unwrap_or(u32::MAX),
+        )),
+    }
+}
+
+/// Read access to an exported dataset folder (sequences.h5 + meta.csv).
+/// Datasets of older versions are migrated to DATASET_VERSION when opened.
+pub struct Dataset {
+    pub folder_path: PathBuf,
+    pub meta: Vec<MetaRow>,
+    pub column_names: Vec<String>,
+    /// schema version of the files on disk, before migration
+    pub version: u32,
+    seq_dataset: hdf5::Dataset,
+}
+
+impl Dataset {
+    pub fn open(folder_path: &Path) -> Result<Dataset, DatasetError> {
+        let manifest_path = folder_path.join("manifest.json");
+        let version = if manifest_path.is_file() {
+            schema_version(&serde_json::from_reader(File::open(manifest_path)?)?)?
+        } else {
+            0
+        };
+
+        let seq_file = hdf5::File::open(folder_path.join("sequences.h5"))?;
+        let seq_dataset = seq_file.dataset("sequences")?;
+        let column_names = seq_dataset
+            .attr("column_names")?
+            .read_1d::<VarLenAscii>()?
+            .iter()
+            .map(|s| s.as_str().to_string())
+            .collect();
+
+        let meta = read_meta(&folder_path.join("meta.csv"))?;
+
+        let mut dataset = Dataset {
+            folder_path: folder_path.to_path_buf(),
+            meta,
+            column_names,
+            version,
+            seq_dataset,
+        };
+        dataset.migrate();
+        Ok(dataset)
+    }
+
+    /// upgrade meta rows and column names read from files of an older version in place
+    fn migrate(&mut self) {
+        // version 0 -> 1: only schema_version was added. meta.csv of early exports lacks
+        // later columns, they are read as empty
+        if self.version < DATASET_VERSION {
+            info!(
+                "migrating {:?} from dataset version {} to {}",
+                self.folder_path, self.version, DATASET_VERSION
+            );
+        }
+    }
+
+    /// contents of manifest.json, null for exports of versions without it
+    pub fn manifest(&self) -> Result<serde_json::Value, Error> {
+        let manifest_path = self.folder_path.join("manifest.json");
+        if !manifest_path.is_file() {
+            return Ok(serde_json::Value::Null);
+        }
+        Ok(serde_json::from_reader(File::open(manifest_path)?)?)
+    }
+
+    /// (sequences, seq_length, features) of sequences.h5
+    pub fn shape(&self) -> (usize, usize, usize) {
+        let shape = self.seq_dataset.shape();
+        (shape[0], shape[1], shape[2])
+    }
+
+    pub fn seq_length(&self) -> usize {
+        self.shape().1
+    }
+
+    /// Behavioral embeddings of all sequences, None if the export didn't write them, see
+    /// [`crate::embedding`]
+    pub fn embeddings(&self) -> Result<Option<Array2<f32>>, DatasetError> {
+        embedding::read_embeddings(&self.seq_dataset.file()?)
+    }
+
+    /// read tick data of the sequences in [start, end)
+    pub fn read_sequences(&self, start: usize, end: usize) -> Result<Array3<f32>, DatasetError> {
+        Ok(self.seq_dataset.read_slice(s![start..end, .., ..])?)
+    }
+
+    /// Check the integrity of the dataset and describe every problem found.
+    /// Feature values are compared against the ranges the export restricts them to,
+    /// velocity ranges are only checked if max_speed is given.
+    pub fn validate(&self, max_speed: Option<i32>) -> Result<Vec<String>, DatasetError> {
+        let mut issues = Vec::new();
+        let (row_count, _, feature_count) = self.shape();
+
+        if row_count != self.meta.len() {
+            issues.push(format!(
+                "sequences.h5 has {} sequences but meta.csv has {} rows",
+                row_count,
+                self.meta.len()
+            ));
+        }
+        if feature_count != self.column_names.len() {
+            issues.push(format!(
+                "sequences.h5 has {} features but {} column names",
+                feature_count,
+                self.column_names.len()
+            ));
+        }
+
+        // seq_ids have to be unique and match the row in sequences.h5
+        let mut seq_ids = HashSet::new();
+        let mut duplicate_seq_ids = 0;
+        let mut misplaced_seq_ids = 0;
+        for (row_index, row) in self.meta.iter().enumerate() {
+            if !seq_ids.insert(row.seq_id) {
+                duplicate_seq_ids += 1;
+            } else if row.seq_id != row_index {
+                misplaced_seq_ids += 1;
+            }
+        }
+        if duplicate_seq_ids > 0 {
+            issues.push(format!("{} duplicate seq_ids", duplicate_seq_ids));
+        }
+        if misplaced_seq_ids > 0 {
+            issues.push(format!(
+                "{} seq_ids don't match their row index",
+                misplaced_seq_ids
+            ));
+        }
+
+        // every player has exactly one id. Ids of exports with a registry have to match it,
+        // otherwise they are unique per name and assigned without gaps
+        let mut id_names: HashMap<usize, HashSet<&str>> = HashMap::new();
+        let mut name_ids: HashMap<&str, HashSet<usize>> = HashMap::new();
+        for row in &self.meta {
+            id_names
+                .entry(row.player_id)
+                .or_default()
+                .insert(&*row.player);
+            name_ids
+                .entry(&*row.player)
+                .or_default()
+                .insert(row.player_id);
+        }
+        for (name, ids) in &name_ids {
+            if ids.len() > 1 {
+                issues.push(format!("player {:?} has multiple ids {:?}", name, ids));
+            }
+        }
+        let registry_path = self.folder_path.join(REGISTRY_FILE);
+        if registry_path.is_file() {
+            match PlayerRegistry::load(&registry_path) {
+                Ok(registry) => {
+                    for (name, ids) in &name_ids {
+                        let registered = registry
+                            .players
+                            .get(registry.canonical_name(name))
+                            .map(|identity| identity.player_id);
+                        if ids.iter().any(|&id| Some(id) != registered) {
+                            issues.push(format!(
+                                "player {:?} has ids {:?}, {} has {:?}",
+                                name, ids, REGISTRY_FILE, registered
+                            ));
+                        }
+                    }
+                }
+                Err(err) => issues.push(format!("invalid {}: {}", REGISTRY_FILE, err)),
+            }
+        } else {
+            for (player_id, names) in &id_names {
+                if names.len() > 1 {
+                    issues.push(format!("player_id {} is used by {:?}", player_id, names));
+                }
+            }
+            let max_player_id = id_names.keys().max().copied();
+            if let Some(max_player_id) = max_player_id {
+                let orphaned = (0..=max_player_id)
+                    .filter(|id| !id_names.contains_key(id))
+                    .count();
+                if orphaned > 0 {
+                    issues.push(format!("{} player_ids without any sequence", orphaned));
+                }
+            }
+        }
+
+        // scan tick data for non-finite and out-of-range values
+        let ranges: Vec<Option<(f32, f32)>> = self
+            .column_names
+            .iter()
+            .map(|name| feature_range(name, max_speed))
+            .collect();
+        let mut non_finite = vec![0usize; feature_count];
+        let mut out_of_range = vec![0usize; feature_count];
+        for start in (0..row_count).step_by(COPY_CHUNK_SIZE) {
+            let end = (start + COPY_CHUNK_SIZE).min(row_count);
+            let data = self.read_sequences(start, end)?;
+            for ((_, _, feature), value) in data.indexed_iter() {
+                if !value.is_finite() {
+                    non_finite[feature] += 1;
+                } else if let Some(Some((min, max))) = ranges.get(feature) {
+                    if value < min || value > max {
+                        out_of_range[feature] += 1;
+                    }
+                }
+            }
+        }
+        for (feature, name) in self.column_names.iter().enumerate().take(feature_count) {
+            if non_finite[feature] > 0 {
+                issues.push(format!(
+                    "{} has {} NaN/Inf values",
+                    name, non_finite[feature]
+                ));
+            }
+            if out_of_range[feature] > 0 {
+                let (min, max) = ranges[feature].unwrap();
+                issues.push(format!(
+                    "{} has {} values outside [{}, {}]",
+                    name, out_of_range[feature], min, max
+                ));
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// player name -> (sequence count, tick count)
+    pub fn player_counts(&self) -> HashMap<&str, (usize, usize)> {
+        counts_by(&self.meta, |row| &row.player)
+    }
+
+    /// map name -> (sequence count, tick count)
+    pub fn map_counts(&self) -> HashMap<&str, (usize, usize)> {
+        counts_by(&self.meta, |row| &row.map)
+    }
+}
+
+/// read all rows of a meta.csv
+pub fn read_meta(path: &Path) -> Result<Vec<MetaRow>, DatasetError> {
+    Ok(csv::Reader::from_path(path)?
+        .deserialize()
+        .collect::<Result<_, _>>()?)
+}
+
+/// key of the rows -> (sequence count, tick count)
+pub fn counts_by<'a>(
+    meta: &'a [MetaRow],
+    key: fn(&MetaRow) -> &str,
+) -> HashMap<&'a str, (usize, usize)> {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for row in meta {
+        let count = counts.entry(key(row)).or_insert((0, 0));
+        count.0 += 1;
+        count.1 += row.ticks;
+    }
+    counts
+}
+
+/// manifest fields that change the meaning of the feature columns
+const MANIFEST_SCHEMA_FIELDS: [&str; 2] = ["smoothing", "phases"];
+
+/// Combine datasets into a new dataset at output_path.
+/// seq_ids are renumbered. player_ids are assigned by the union of the players.json of the
+/// datasets, see [`PlayerRegistry::union`], so the same player shares one id across all
+/// merged datasets and ids of datasets exported one after another are kept.
+pub fn merge(input_paths: &[PathBuf], output_path: &Path) -> Result<(), Error> {
+    let datasets = input_paths
+        .iter()
+        .map(|path| Dataset::open(path))
+        .collect::<Result<Vec<Dataset>, DatasetError>>()?;
+
+    let Some(first) = datasets.first() else {
+        return Err(DatasetError::SchemaMismatch("no datasets to merge".to_string()).into());
+    };
+    let manifests = datasets
+        .iter()
+        .map(|dataset| dataset.manifest())
+        .collect::<Result<Vec<_>, Error>>()?;
+    for (dataset, manifest) in datasets.iter().zip(&manifests) {
+        if dataset.column_names != first.column_names || dataset.seq_length() != first.seq_length()
+        {
+            return Err(DatasetError::SchemaMismatch(format!(
+                "{:?} has columns={:?} seq_length={}, expected columns={:?} seq_length={}",
+                dataset.folder_path,
+                dataset.column_names,
+                dataset.seq_length(),
+                first.column_names,
+                first.seq_length()
+            ))
+            .into());
+        }
+        for field in MANIFEST_SCHEMA_FIELDS {
+            if manifest[field] != manifests[0][field] {
+                return Err(DatasetError::SchemaMismatch(format!(
+                    "{:?} has {}={}, expected {}",
+                    dataset.folder_path, field, manifest[field], manifests[0][field]
+                ))
+                .into());
+            }
+        }
+        let row_count = dataset.shape().0;
+        if row_count != dataset.meta.len() {
+            return Err(DatasetError::CountMismatch {
+                sequences: row_count,
+                meta: dataset.meta.len(),
+                expected: dataset.meta.len(),
+            }
+            .into());
+        }
+    }
+
+    let mut registry = PlayerRegistry::default();
+    for dataset in &datasets {
+        let registry_path = dataset.folder_path.join(REGISTRY_FILE);
+        if registry_path.is_file() {
+            registry.union(&PlayerRegistry::load(&registry_path)?)?;
+        }
+    }
+
+    create_dir_all(output_path)?;
+    let seq_dataset = create_sequences_file(output_path, first.seq_length(), &first.column_names)?;
+    let mut meta_file = File::create(output_path.join("meta.csv"))?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+
+    let mut player_ids = HashSet::new();
+    let mut sequence_count = 0;
+    for dataset in &datasets {
+        info!(
+            "merging {:?} with {} sequences",
+            dataset.folder_path,
+            dataset.meta.len()
+        );
+
+        for row in &dataset.meta {
+            let player_id = registry.player_id(&row.player);
+            player_ids.insert(player_id);
+            let merged_row = MetaRow {
+                seq_id: sequence_count,
+                player_id,
+                ..row.clone()
+            };
+            writeln!(meta_file, "{}", merged_row.to_csv())?;
+            sequence_count += 1;
+        }
+
+        let row_count = dataset.shape().0;
+        for start in (0..row_count).step_by(COPY_CHUNK_SIZE) {
+            let end = (start + COPY_CHUNK_SIZE).min(row_count);
+            let data = dataset.read_sequences(start, end)?;
+            let current_size = seq_dataset.shape()[0];
+            let new_size = current_size + data.shape()[0];
+            seq_dataset.resize((new_size, first.seq_length(), first.column_names.len()))?;
+            seq_dataset.write_slice(&data.view(), (current_size..new_size, .., ..))?;
+        }
+    }
+    registry.save(&output_path.join(REGISTRY_FILE))?;
+
+    let processed_files: u64 = manifests
+        .iter()
+        .filter_map(|manifest| manifest["processed_files"].as_u64())
+        .sum();
+    let mut manifest = manifests.into_iter().next().unwrap_or_default();
+    if manifest.is_object() {
+        manifest["processed_files"] = processed_files.into();
+    }
+    write_derived_manifest(
+        output_path,
+        manifest,
+        first,
+        first.seq_length(),
+        sequence_count,
+        player_ids.len(),
+        input_paths,
+    )?;
+    checksum::write_checksums(output_path)?;
+
+    info!(
+        "merged {} datasets into {} sequences of {} players",
+        datasets.len(),
+        sequence_count,
+        player_ids.len()
+    );
+    Ok(())
+}
+
+/// Write the manifest of a dataset derived from the datasets at input_paths: the manifest of
+/// the first of them with the counts of the derived one and the paths it was derived from
+fn write_derived_manifest(
+    output_path: &Path,
+    manifest: serde_json::Value,
+    first: &Dataset,
+    seq_length: usize,
+    sequence_count: usize,
+    player_count: usize,
+    input_paths: &[PathBuf],
+) -> Result<(), Error> {
+    let mut manifest = match manifest {
+        serde_json::Value::Object(manifest) => manifest,
+        _ => serde_json::Map::new(),
+    };
+    manifest.remove("stop_reason");
+    manifest.insert("schema_version".into(), DATASET_VERSION.into());
+    manifest.insert("sequence_count".into(), sequence_count.into());
+    manifest.insert("player_count".into(), player_count.into());
+    manifest.insert("seq_length".into(), seq_length.into());
+    manifest.insert("column_names".into(), first.column_names.clone().into());
+    let derived_from: Vec<String> = input_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    manifest.insert("derived_from".into(), derived_from.into());
+    serde_json::to_writer_pretty(File::create(output_path.join("manifest.json"))?, &manifest)?;
+    Ok(())
+}
+
+/// Sequences of a dataset to keep, by their meta.csv row. Unset criteria keep all sequences.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetFilter {
+    pub filter_players: Option<HashSet<String>>,
+    /// takes precedence over filter_players
+    pub exclude_players: Option<HashSet<String>>,
+    /// map name globs to keep
+    pub filter_maps: Option<Vec<Pattern>>,
+    /// map name globs to drop, takes precedence over filter_maps
+    pub exclude_maps: Option<Vec<Pattern>>,
+    /// Keep sequences recorded at or after this time. Sequences without recorded time, such as
+    /// of exports of older versions, are dropped by since and until.
+    pub since: Option<DateTime<Utc>>,
+    /// keep sequences recorded before this time
+    pub until: Option<DateTime<Utc>>,
+    /// keep sequences with at least this many ticks
+    pub min_ticks: Option<usize>,
+}
+
+impl DatasetFilter {
+    pub fn matches(&self, row: &MetaRow) -> bool {
+        let matches_map = |patterns: &Vec<Pattern>| patterns.iter().any(|p| p.matches(&row.map));
+        if self
+            .filter_players
+            .as_ref()
+            .is_some_and(|players| !players.contains(&*row.player))
+            || self
+                .exclude_players
+                .as_ref()
+                .is_some_and(|players| players.contains(&*row.player))
+            || self.filter_maps.as_ref().is_some_and(|p| !matches_map(p))
+            || self.exclude_maps.as_ref().is_some_and(matches_map)
+            || self
+                .min_ticks
+                .is_some_and(|min_ticks| row.ticks < min_ticks)
+        {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        match row
+            .recorded
+            .as_deref()
+            .and_then(|recorded| DateTime::parse_from_rfc3339(recorded).ok())
+        {
+            Some(recorded) => {
+                self.since.is_none_or(|since| recorded >= since)
+                    && self.until.is_none_or(|until| recorded < until)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Copy the sequences of a dataset matching the filter to output_path.
+/// seq_ids are renumbered, player_ids are kept, so players.json and config.toml are copied
+/// as they are. Returns the amount of kept sequences.
+pub fn filter(
+    input_path: &Path,
+    output_path: &Path,
+    filter: &DatasetFilter,
+) -> Result<usize, Error> {
+    let dataset = Dataset::open(input_path)?;
+    let (row_count, seq_length, feature_count) = dataset.shape();
+    if row_count != dataset.meta.len() {
+        return Err(DatasetError::CountMismatch {
+            sequences: row_count,
+            meta: dataset.meta.len(),
+            expected: dataset.meta.len(),
+        }
+        .into());
+    }
+    let kept: Vec<usize> = (0..row_count)
+        .filter(|&index| filter.matches(&dataset.meta[index]))
+        .collect();
+
+    create_dir_all(output_path)?;
+    let seq_dataset = create_sequences_file(output_path, seq_length, &dataset.column_names)?;
+    let mut meta_file = File::create(output_path.join("meta.csv"))?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    let mut player_ids = HashSet::new();
+    for (seq_id, &index) in kept.iter().enumerate() {
+        let row = &dataset.meta[index];
+        player_ids.insert(row.player_id);
+        let filtered_row = MetaRow {
+            seq_id,
+            ..row.clone()
+        };
+        writeln!(meta_file, "{}", filtered_row.to_csv())?;
+    }
+
+    for start in (0..row_count).step_by(COPY_CHUNK_SIZE) {
+        let end = (start + COPY_CHUNK_SIZE).min(row_count);
+        // kept rows of the chunk, relative to its start
+        let chunk_rows: Vec<usize> = kept
+            [kept.partition_point(|&i| i < start)..kept.partition_point(|&i| i < end)]
+            .iter()
+            .map(|&i| i - start)
+            .collect();
+        if chunk_rows.is_empty() {
+            continue;
+        }
+        let data = dataset
+            .read_sequences(start, end)?
+            .select(Axis(0), &chunk_rows);
+        let current_size = seq_dataset.shape()[0];
+        let new_size = current_size + chunk_rows.len();
+        seq_dataset.resize((new_size, seq_length, feature_count))?;
+        seq_dataset.write_slice(&data.view(), (current_size..new_size, .., ..))?;
+    }
+
+    for file_name in [REGISTRY_FILE, CONFIG_FILE_NAME] {
+        if input_path.join(file_name).is_file() {
+            fs::copy(input_path.join(file_name), output_path.join(file_name))?;
+        }
+    }
+    write_derived_manifest(
+        output_path,
+        dataset.manifest()?,
+        &dataset,
+        seq_length,
+        kept.len(),
+        player_ids.len(),
+        &[input_path.to_path_buf()],
+    )?;
+    checksum::write_checksums(output_path)?;
+
+    info!(
+        "kept {} of {} sequences, of {} players",
+        kept.len(),
+        row_count,
+        player_ids.len()
+    );
+    Ok(kept.len())
+}
+
+/// Ranges of consecutive meta.csv rows whose sequences continue each other: of the same file
+/// and player, each starting within or right after the ticks of the one before it. Sequences
+/// cut from one gameplay duration form a run unless some of them were dropped.
+pub fn continuous_runs(meta: &[MetaRow]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (index, row) in meta.iter().enumerate() {
+        let continues = index > 0 && {
+            let previous = &meta[index - 1];
+            previous.teehist == row.teehist
+                && previous.player == row.player
+                && row.start > previous.start
+                && row.start <= previous.start + previous.ticks
+        };
+        match runs.last_mut() {
+            Some(run) if continues => run.end = index + 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    runs
+}
+
+/// Join the tick data of the sequences of a run, ticks overlapping the sequence before,
+/// e.g. of kept tails, are taken from the earlier one. Returns the ticks and for each
+/// sequence the tick its data starts at.
+pub fn concat_run(meta: &[MetaRow], data: ArrayView3<f32>) -> (Array2<f32>, Vec<usize>) {
+    let first_start = meta.first().map_or(0, |row| row.start);
+    let mut ticks = Array2::zeros((0, data.shape()[2]));
+    let mut offsets = Vec::with_capacity(meta.len());
+    for (row, sequence) in meta.iter().zip(data.outer_iter()) {
+        let skipped = (first_start + ticks.nrows()).saturating_sub(row.start);
+        offsets.push(ticks.nrows());
+        let new_ticks = sequence.slice(s![skipped.min(sequence.nrows()).., ..]);
+        ticks
+            .append(Axis(0), new_ticks)
+            .expect("sequences of a dataset have the same features");
+    }
+    (ticks, offsets)
+}
+
+/// Cut the sequences of a dataset into sequences of seq_length ticks, the next starting stride
+/// ticks after the one before, and write them to output_path. Continuous runs of sequences are
+/// joined before cutting, see [`continuous_runs`], ticks at the end of a run not filling a
+/// sequence are dropped. New sequences take the meta.csv row of the sequence they start in.
+/// If there is an `active` column, sequences without active tick are dropped like in export.
+///
+/// Smoothing and phase labels aren't recomputed, they restart at the bounds of the original
+/// sequences. Returns the amount of written sequences.
+pub fn reshape(
+    input_path: &Path,
+    output_path: &Path,
+    seq_length: usize,
+    stride: usize,
+) -> Result<usize, Error> {
+    if seq_length == 0 || stride == 0 {
+        return Err(DatasetError::InvalidReshape(format!(
+            "seq_length={} and stride={} must be positive",
+            seq_length, stride
+        ))
+        .into());
+    }
+    let dataset = Dataset::open(input_path)?;
+    let (row_count, _, feature_count) = dataset.shape();
+    if row_count != dataset.meta.len() {
+        return Err(DatasetError::CountMismatch {
+            sequences: row_count,
+            meta: dataset.meta.len(),
+            expected: dataset.meta.len(),
+        }
+        .into());
+    }
+    let active_column = dataset.column_names.iter().position(|c| c == "active");
+
+    create_dir_all(output_path)?;
+    let seq_dataset = create_sequences_file(output_path, seq_length, &dataset.column_names)?;
+    let mut meta_file = File::create(output_path.join("meta.csv"))?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    let mut player_ids = HashSet::new();
+    let mut sequence_count = 0;
+    for run in continuous_runs(&dataset.meta) {
+        let meta = &dataset.meta[run.clone()];
+        let data = dataset.read_sequences(run.start, run.end)?;
+        let (ticks, offsets) = concat_run(meta, data.view());
+
+        let mut windows = Vec::new();
+        let mut window_start = 0;
+        while window_start + seq_length <= ticks.nrows() {
+            let window = ticks.slice(s![window_start..window_start + seq_length, ..]);
+            let active = active_column
+                .is_none_or(|column| window.column(column).iter().any(|&value| value > 0.0));
+            if active {
+                // the last sequence whose ticks start at or before the window
+                let source = offsets.partition_point(|&offset| offset <= window_start) - 1;
+                let row = &meta[source];
+                let start = meta[0].start + window_start;
+                player_ids.insert(row.player_id);
+                let window_row = MetaRow {
+                    seq_id: sequence_count,
+                    start,
+                    ticks: seq_length,
+                    recorded: row
+                        .recorded
+                        .as_deref()
+                        .and_then(|recorded| DateTime::parse_from_rfc3339(recorded).ok())
+                        .map(|recorded| {
+                            recorded_time(recorded.with_timezone(&Utc), start - row.start)
+                        }),
+                    // the path of the source doesn't match the window
+                    route_cells: None,
+                    route: None,
+                    ..row.clone()
+                };
+                writeln!(meta_file, "{}", window_row.to_csv())?;
+                windows.push(window);
+                sequence_count += 1;
+            }
+            window_start += stride;
+        }
+        if windows.is_empty() {
+            continue;
+        }
+        let windows = ndarray::stack(Axis(0), &windows)?;
+        let current_size = seq_dataset.shape()[0];
+        let new_size = current_size + windows.shape()[0];
+        seq_dataset.resize((new_size, seq_length, feature_count))?;
+        seq_dataset.write_slice(&windows.view(), (current_size..new_size, .., ..))?;
+    }
+
+    if input_path.join(REGISTRY_FILE).is_file() {
+        fs::copy(
+            input_path.join(REGISTRY_FILE),
+            output_path.join(REGISTRY_FILE),
+        )?;
+    }
+    // the config of the reshaped dataset, unless its padding doesn't fit the new seq_length
+    if input_path.join(CONFIG_FILE_NAME).is_file() {
+        let mut config = RunConfig::load(&input_path.join(CONFIG_FILE_NAME))?;
+        config.export.seq_length = seq_length;
+        match config.validate() {
+            Ok(()) => config.save(output_path)?,
+            Err(err) => warn!("not copying {}: {}", CONFIG_FILE_NAME, err),
+        }
+    }
+    let mut manifest = dataset.manifest()?;
+    if manifest.is_object() {
+        manifest["stride"] = stride.into();
+    }
+    write_derived_manifest(
+        output_path,
+        manifest,
+        &dataset,
+        seq_length,
+        sequence_count,
+        player_ids.len(),
+        &[input_path.to_path_buf()],
+    )?;
+    checksum::write_checksums(output_path)?;
+
+    info!(
+        "reshaped {} sequences of {} ticks into {} sequences of {} ticks",
+        row_count,
+        dataset.seq_length(),
+        sequence_count,
+        seq_length
+    );
+    Ok(sequence_count)
+}
+
+/// Rewrite meta.csv, manifest.json and SHA256SUMS of a dataset of an older version in place,
+/// in the format of DATASET_VERSION. sequences.h5 is left as it is. Returns the version the
+/// dataset had before.
+pub fn upgrade(folder_path: &Path) -> Result<u32, Error> {
+    let dataset = Dataset::open(folder_path)?;
+    if dataset.version == DATASET_VERSION {
+        info!(
+            "{:?} already has dataset version {}",
+            folder_path, DATASET_VERSION
+        );
+        return Ok(dataset.version);
+    }
+
+    // written next to the old files first, so an interrupted upgrade leaves them intact
+    let meta_path = folder_path.join("meta.csv");
+    let tmp_meta_path = folder_path.join("meta.csv.tmp");
+    let mut meta_file = File::create(&tmp_meta_path)?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    for row in &dataset.meta {
+        writeln!(meta_file, "{}", row.to_csv())?;
+    }
+    meta_file.flush()?;
+    fs::rename(tmp_meta_path, meta_path)?;
+
+    let mut manifest = match dataset.manifest()? {
+        serde_json::Value::Object(manifest) => manifest,
+        _ => serde_json::Map::new(),
+    };
+    manifest.insert("schema_version".into(), DATASET_VERSION.into());
+    manifest
+        .entry("sequence_count")
+        .or_insert(dataset.meta.len().into());
+    manifest
+        .entry("seq_length")
+        .or_insert(dataset.seq_length().into());
+    manifest
+        .entry("column_names")
+        .or_insert(dataset.column_names.clone().into());
+    serde_json::to_writer_pretty(File::create(folder_path.join("manifest.json"))?, &manifest)?;
+    checksum::write_checksums(folder_path)?;
+
+    info!(
+        "upgraded {:?} from dataset version {} to {}",
+        folder_path, dataset.version, DATASET_VERSION
+    );
+    Ok(dataset.version)
+}
+
+/// Pseudonym of a player name, the first 16 hex digits of sha256(salt + name)
+pub fn anonymize_name(name: &str, salt: &str) -> String {
+    let digest = Sha256::digest(format!("{}{}", salt, name).as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("anon_{}", &hex[..16])
+}
+
+/// Copy a dataset to output_path with the player and auth_name columns replaced by salted
+/// hashes. Timeout codes are dropped if drop_timeout_codes is set. Files that may contain
+/// player names, such as config.toml, are not copied.
+pub fn anonymize(
+    input_path: &Path,
+    output_path: &Path,
+    salt: &str,
+    drop_timeout_codes: bool,
+) -> Result<(), Error> {
+    let dataset = Dataset::open(input_path)?;
+    create_dir_all(output_path)?;
+    fs::copy(
+        input_path.join("sequences.h5"),
+        output_path.join("sequences.h5"),
+    )?;
+    if input_path.join("manifest.json").is_file() {
+        let mut manifest: serde_json::Value =
+            serde_json::from_reader(File::open(input_path.join("manifest.json"))?)?;
+        // meta.csv is rewritten in the current format
+        if manifest.is_object() {
+            manifest["schema_version"] = DATASET_VERSION.into();
+        }
+        serde_json::to_writer_pretty(File::create(output_path.join("manifest.json"))?, &manifest)?;
+    }
+
+    let mut meta_file = File::create(output_path.join("meta.csv"))?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    for row in &dataset.meta {
+        let anonymized_row = MetaRow {
+            player: anonymize_name(&row.player, salt).into(),
+            auth_name: row
+                .auth_name
+                .as_ref()
+                .map(|auth_name| anonymize_name(auth_name, salt)),
+            timeout: if drop_timeout_codes {
+                None
+            } else {
+                row.timeout.clone()
+            },
+            ..row.clone()
+        };
+        writeln!(meta_file, "{}", anonymized_row.to_csv())?;
+    }
+    checksum::write_checksums(output_path)?;
+
+    info!(
+        "anonymized {} sequences of {} players",
+        dataset.meta.len(),
+        dataset.player_counts().len()
+    );
+    Ok(())
+}
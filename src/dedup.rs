@@ -0,0 +1,71 @@
+//! Fingerprints of cleaned sequences to drop duplicates, e.g. of files present twice in the
+//! input or of dummies copying the inputs of their owner.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, hash::Hasher, str::FromStr};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::export::ExportConfig;
+use crate::extractor::Sequence;
+
+/// Which sequences count as duplicates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    /// same inputs, aim targets and movement relative to the first tick
+    Exact,
+    /// same move direction, jump, fire and hook inputs, regardless of aim and movement
+    Near,
+}
+
+impl DedupMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            DedupMode::Exact => "exact",
+            DedupMode::Near => "near",
+        }
+    }
+}
+
+impl fmt::Display for DedupMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(DedupMode::Exact),
+            "near" => Ok(DedupMode::Near),
+            _ => Err(format!(
+                "unknown dedup mode '{}', expected exact or near",
+                s
+            )),
+        }
+    }
+}
+
+/// Hash of the exported ticks of a cleaned sequence, equal for duplicates
+pub fn fingerprint(sequence: &Sequence, config: &ExportConfig, mode: DedupMode) -> u64 {
+    let ticks = config.source_ticks().min(sequence.tick_count);
+    let mut hasher = Xxh3::new();
+    hasher.write_usize(ticks);
+    for tick in 0..ticks {
+        hasher.write_i32(sequence.move_dir[tick]);
+        hasher.write_u8(
+            sequence.jump[tick] as u8
+                | (sequence.fire[tick] as u8) << 1
+                | (sequence.hook[tick] as u8) << 2,
+        );
+        if mode == DedupMode::Exact {
+            hasher.write_i32(sequence.target_x[tick]);
+            hasher.write_i32(sequence.target_y[tick]);
+            hasher.write_i32(sequence.pos_x[tick] - sequence.pos_x[0]);
+            hasher.write_i32(sequence.pos_y[tick] - sequence.pos_y[0]);
+        }
+    }
+    hasher.finish()
+}
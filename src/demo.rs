@@ -0,0 +1,258 @@
+//! Re-encode extracted sequences as DDNet demo, to watch the exact gameplay of a sequence in
+//! the client.
+//!
+//! Sequences only hold positions and inputs, so the demo shows tees moving, looking and
+//! walking into their direction. Hooks, weapons and other players' interactions aren't
+//! recorded and don't show up.
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Seek, Write},
+    path::Path,
+    thread,
+};
+use twsnap::{
+    compat::ddnet::{DemoKind, DemoMapHash, DemoWriter},
+    enums::Direction,
+    items::{GameInfo as SnapGameInfo, Player, Tee},
+    time::{Duration, Instant},
+    uid::{LegacyId, PlayerUid, UidGenerator},
+    AnglePrecision, Position, PositionPrecision, Snap, Velocity, VelocityPrecision,
+};
+
+use crate::error::{Error, Result};
+use crate::extractor::{Extractor, Sequence};
+use crate::heatmap::find_map_file;
+use crate::parser::{GameInfo, ParserConfig};
+
+/// net version of DDNet 0.6 servers
+pub const NET_VERSION: &str = "0.6 626fce9a778df4d4";
+
+/// player slots of a DDNet server
+const MAX_CLIENTS: LegacyId = 64;
+
+/// server ticks per second
+const TICK_RATE: usize = 50;
+
+/// stack size of the thread writing a demo, snapshots are built on the stack and overflow
+/// the 2MB of spawned threads in debug builds
+const WRITER_STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Sequences of a teehistorian file to convert, matching the player and start columns of
+/// meta.csv. Sequences are only cut the same way as in an export with equal parser config.
+#[derive(Debug, Clone, Default)]
+pub struct DemoFilter {
+    pub player: Option<String>,
+    pub start_tick: Option<usize>,
+}
+
+impl DemoFilter {
+    pub fn matches(&self, sequence: &Sequence) -> bool {
+        self.player
+            .as_deref()
+            .is_none_or(|player| *sequence.player_name == *player)
+            && self
+                .start_tick
+                .is_none_or(|start_tick| sequence.start_tick == start_tick)
+    }
+}
+
+/// Convert the sequences of a teehistorian file matching the filter into a demo at output.
+/// The map is embedded if maps_folder contains it. Returns the amount of converted sequences.
+pub fn convert_file(
+    path: &Path,
+    filter: &DemoFilter,
+    parser_config: &ParserConfig,
+    maps_folder: Option<&Path>,
+    output: &Path,
+) -> Result<usize> {
+    let game_info = Extractor::get_game_info(path)
+        .ok_or_else(|| Error::Demo(format!("{:?}: couldn't read header", path)))?;
+    let sequences: Vec<Sequence> = Extractor::get_ddnet_sequences(path, parser_config)?
+        .iter()
+        .filter_map(
+            |ddnet_sequence| match Sequence::from_ddnet_sequence(ddnet_sequence) {
+                Ok(sequence) => Some(sequence),
+                Err(err) => {
+                    debug!("skipping sequence: {}", err);
+                    None
+                }
+            },
+        )
+        .filter(|sequence| filter.matches(sequence))
+        .collect();
+    if sequences.is_empty() {
+        return Err(Error::Demo(format!(
+            "{:?}: no sequence matches {:?}",
+            path, filter
+        )));
+    }
+
+    let map_data = maps_folder
+        .and_then(|folder| find_map_file(folder, &game_info.map_name))
+        .map(fs::read)
+        .transpose()?;
+    let timestamp = game_info
+        .start_time()
+        .map(|time| time.format("%Y-%m-%d_%H-%M-%S").to_string())
+        .unwrap_or_default();
+    let writer = BufWriter::new(File::create(output)?);
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(WRITER_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                write_demo(
+                    writer,
+                    &sequences,
+                    &game_info.map_name,
+                    map_data.as_deref(),
+                    map_hash(&game_info, map_data.as_deref()),
+                    &timestamp,
+                )
+            })?
+            .join()
+            .map_err(|_| Error::Demo("demo writer panicked".to_string()))?
+    })?;
+    Ok(sequences.len())
+}
+
+/// Hash of the map the demo is played on, the client only loads a map with this hash.
+/// Map data is hashed if available, otherwise the hash of the teehistorian header is used.
+pub fn map_hash(game_info: &GameInfo, map_data: Option<&[u8]>) -> DemoMapHash {
+    if let Some(map_data) = map_data {
+        return DemoMapHash::Sha256(Sha256::digest(map_data).into());
+    }
+    if let Some(sha256) = game_info.map_sha256.as_deref().and_then(parse_sha256) {
+        return DemoMapHash::Sha256(sha256);
+    }
+    let crc = game_info
+        .map_crc
+        .as_deref()
+        .and_then(|crc| u32::from_str_radix(crc, 16).ok());
+    DemoMapHash::Crc(crc.unwrap_or_default())
+}
+
+fn parse_sha256(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut sha256 = [0; 32];
+    for (i, byte) in sha256.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(sha256)
+}
+
+/// Write a server demo with a snapshot for every tick from the first start to the last end
+/// of the sequences. Sequences played at the same time share the demo, each gets a free
+/// player slot for as long as it lasts.
+pub fn write_demo<W: Write + Seek + 'static>(
+    writer: W,
+    sequences: &[Sequence],
+    map_name: &str,
+    map_data: Option<&[u8]>,
+    hash: DemoMapHash,
+    timestamp: &str,
+) -> Result<()> {
+    let mut sequences: Vec<&Sequence> = sequences.iter().collect();
+    sequences.sort_by_key(|sequence| sequence.start_tick);
+    let first_tick = sequences.first().map_or(0, |s| s.start_tick);
+    let end_tick = sequences
+        .iter()
+        .map(|s| s.start_tick + s.tick_count)
+        .max()
+        .unwrap_or(first_tick);
+
+    let demo_error = |err: twsnap::compat::ddnet::WriteError| Error::Demo(err.to_string());
+    let mut demo = DemoWriter::new(
+        writer,
+        DemoKind::Server,
+        timestamp,
+        NET_VERSION,
+        map_name,
+        map_data,
+        hash,
+        ((end_tick - first_tick) / TICK_RATE) as i32,
+    )
+    .map_err(demo_error)?;
+
+    let uids = UidGenerator::new();
+    let game_info_uid = uids.next_snap();
+    let mut snap = Snap::default();
+    // (slot, uid, sequence) of the sequences playing at the current tick
+    let mut active: Vec<(LegacyId, PlayerUid, &Sequence)> = Vec::new();
+    let mut next = 0;
+    for tick in first_tick..end_tick {
+        active.retain(|(_, _, sequence)| tick < sequence.start_tick + sequence.tick_count);
+        while next < sequences.len() && sequences[next].start_tick <= tick {
+            let sequence = sequences[next];
+            next += 1;
+            let free_slot = (0..MAX_CLIENTS).find(|slot| active.iter().all(|(s, _, _)| s != slot));
+            match free_slot {
+                Some(slot) => active.push((slot, uids.next_player(slot), sequence)),
+                None => warn!(
+                    "no free player slot for {} at tick {}, skipping sequence",
+                    sequence.player_name, sequence.start_tick
+                ),
+            }
+        }
+
+        snap.clear();
+        snap.game_infos
+            .insert(game_info_uid, SnapGameInfo::default());
+        for &(_, uid, sequence) in &active {
+            snap.players.insert(uid, player(sequence, tick, uid));
+        }
+        demo.write_snapshot(tick as i32, &snap)
+            .map_err(demo_error)?;
+    }
+    Ok(())
+}
+
+/// player of a sequence at a tick it is playing
+fn player(sequence: &Sequence, tick: usize, uid: PlayerUid) -> Player {
+    let i = tick - sequence.start_tick;
+    let mut player = Player {
+        uid,
+        ..Default::default()
+    };
+    // names are cut to the 15 bytes a snapshot holds
+    for c in sequence.player_name.chars() {
+        if player.name.try_push(c).is_err() {
+            break;
+        }
+    }
+
+    let (x, y) = (sequence.pos_x[i], sequence.pos_y[i]);
+    let (dx, dy) = match i {
+        0 => (0, 0),
+        _ => (x - sequence.pos_x[i - 1], y - sequence.pos_y[i - 1]),
+    };
+    let (target_x, target_y) = (sequence.target_x[i], sequence.target_y[i]);
+    player.tee = Some(Tee {
+        tick: Instant::zero() + Duration::from_ticks(tick as i32),
+        // positions are in world units, 32 per tile like the fixed point bits
+        pos: Position::new(
+            PositionPrecision::from_bits(x),
+            PositionPrecision::from_bits(y),
+        ),
+        vel: Velocity::new(
+            VelocityPrecision::from_num(dx),
+            VelocityPrecision::from_num(dy),
+        ),
+        angle: AnglePrecision::from_num((target_y as f32).atan2(target_x as f32)),
+        direction: match sequence.move_dir[i].signum() {
+            -1 => Direction::Left,
+            1 => Direction::Right,
+            _ => Direction::None,
+        },
+        target: Position::new(
+            PositionPrecision::from_bits(target_x),
+            PositionPrecision::from_bits(target_y),
+        ),
+        ..Default::default()
+    });
+    player
+}
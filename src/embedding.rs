@@ -0,0 +1,185 @@
+//! Fixed-size behavioral embeddings of sequences, e.g. to study how re-identifiable players
+//! are from their inputs alone.
+//!
+//! An embedding consists of normalized histograms of inputs, aim dynamics and movement
+//! rhythm, see [`embedding_names`]. Exports with [`ExportConfig::embeddings`] write them as
+//! the embeddings dataset of sequences.h5, row i belongs to seq_id i. Datasets written by
+//! filter, merge and reshape don't carry them over.
+//!
+//! [`ExportConfig::embeddings`]: crate::export::ExportConfig::embeddings
+
+use hdf5_metno::{self as hdf5, types::VarLenAscii};
+use ndarray::Array2;
+
+use crate::alias::{bin, AIM_SPEED_BOUNDS};
+use crate::dataset::DatasetError;
+use crate::extractor::Sequence;
+
+/// name of the embeddings dataset in sequences.h5
+pub const EMBEDDINGS_DATASET: &str = "embeddings";
+
+/// server ticks per second
+const TICK_RATE: f32 = 50.;
+
+/// embeddings per chunk of the embeddings dataset
+const EMBEDDING_CHUNK: usize = 4096;
+
+/// upper bounds of the aim distance bins
+const AIM_DISTANCE_BOUNDS: [f32; 6] = [100., 200., 300., 400., 600., 800.];
+
+/// upper bounds of the bins of ticks between two input changes, the last bin is open
+const INPUT_INTERVAL_BOUNDS: [usize; 8] = [1, 2, 3, 5, 8, 13, 21, 34];
+
+/// upper bounds of the horizontal speed bins in units per tick
+const SPEED_BOUNDS: [i32; 5] = [0, 2, 5, 10, 15];
+
+/// aim directions are binned into this many equal sectors, starting at the right
+const AIM_SECTORS: usize = 8;
+
+/// groups of the embedding with their amount of values, in order
+const GROUPS: [(&str, usize); 9] = [
+    // fraction of ticks moving left, standing, moving right
+    ("move_dir", 3),
+    // fraction of ticks jump, fire and hook are held
+    ("held", 3),
+    // jump, fire and hook presses per second
+    ("presses", 3),
+    // move direction changes per second
+    ("turns", 1),
+    ("aim_speed", AIM_SPEED_BOUNDS.len() + 1),
+    ("aim_distance", AIM_DISTANCE_BOUNDS.len() + 1),
+    ("aim_sector", AIM_SECTORS),
+    ("input_interval", INPUT_INTERVAL_BOUNDS.len() + 1),
+    ("speed", SPEED_BOUNDS.len() + 1),
+];
+
+/// amount of values of an embedding
+pub const EMBEDDING_SIZE: usize = {
+    let mut size = 0;
+    let mut i = 0;
+    while i < GROUPS.len() {
+        size += GROUPS[i].1;
+        i += 1;
+    }
+    size
+};
+
+/// names of the values of an embedding, e.g. aim_speed_3 for the fourth aim speed bin
+pub fn embedding_names() -> Vec<String> {
+    GROUPS
+        .iter()
+        .flat_map(|&(group, len)| (0..len).map(move |i| format!("{}_{}", group, i)))
+        .collect()
+}
+
+/// histogram normalized to fractions, all 0 if empty
+fn normalized(counts: &[u32]) -> impl Iterator<Item = f32> + '_ {
+    let total = counts.iter().sum::<u32>().max(1) as f32;
+    counts.iter().map(move |&count| count as f32 / total)
+}
+
+/// Embedding of the first ticks of a sequence, histograms are normalized so sequences of
+/// different lengths are comparable
+pub fn embed(sequence: &Sequence, ticks: usize) -> [f32; EMBEDDING_SIZE] {
+    let ticks = ticks.min(sequence.tick_count);
+    let seconds = (ticks as f32 / TICK_RATE).max(1. / TICK_RATE);
+    let buttons = [&sequence.jump, &sequence.fire, &sequence.hook];
+
+    let mut move_dir = [0; 3];
+    for &dir in &sequence.move_dir[..ticks] {
+        move_dir[(dir.signum() + 1) as usize] += 1;
+    }
+    let held = buttons.map(|button| {
+        button[..ticks].iter().filter(|&&held| held).count() as f32 / ticks.max(1) as f32
+    });
+    let presses = buttons
+        .map(|button| (1..ticks).filter(|&t| button[t] && !button[t - 1]).count() as f32 / seconds);
+    let turns = (1..ticks)
+        .filter(|&t| sequence.move_dir[t] != sequence.move_dir[t - 1])
+        .count() as f32
+        / seconds;
+
+    let mut aim_speed = [0; AIM_SPEED_BOUNDS.len() + 1];
+    let mut aim_distance = [0; AIM_DISTANCE_BOUNDS.len() + 1];
+    let mut aim_sector = [0; AIM_SECTORS];
+    let angle = |t: usize| {
+        (sequence.target_y[t] as f64)
+            .atan2(sequence.target_x[t] as f64)
+            .to_degrees()
+    };
+    for t in 0..ticks {
+        if t > 0 {
+            let delta = (angle(t) - angle(t - 1)).abs();
+            let delta = delta.min(360. - delta);
+            if delta > 0. {
+                aim_speed[bin(&AIM_SPEED_BOUNDS, delta)] += 1;
+            }
+        }
+        let (x, y) = (sequence.target_x[t] as f32, sequence.target_y[t] as f32);
+        aim_distance[bin(&AIM_DISTANCE_BOUNDS, x.hypot(y))] += 1;
+        let sector = (angle(t).rem_euclid(360.) / (360. / AIM_SECTORS as f64)) as usize;
+        aim_sector[sector.min(AIM_SECTORS - 1)] += 1;
+    }
+
+    let mut input_interval = [0; INPUT_INTERVAL_BOUNDS.len() + 1];
+    let changed = |t: usize| {
+        sequence.move_dir[t] != sequence.move_dir[t - 1]
+            || buttons.iter().any(|button| button[t] != button[t - 1])
+    };
+    let mut last_change = None;
+    for t in (1..ticks).filter(|&t| changed(t)) {
+        if let Some(last_change) = last_change {
+            input_interval[bin(&INPUT_INTERVAL_BOUNDS, t - last_change)] += 1;
+        }
+        last_change = Some(t);
+    }
+
+    let mut speed = [0; SPEED_BOUNDS.len() + 1];
+    for t in 1..ticks {
+        let speed_x = (sequence.pos_x[t] - sequence.pos_x[t - 1]).abs();
+        speed[bin(&SPEED_BOUNDS, speed_x)] += 1;
+    }
+
+    let mut embedding = [0.; EMBEDDING_SIZE];
+    let values = normalized(&move_dir)
+        .chain(held)
+        .chain(presses)
+        .chain([turns])
+        .chain(normalized(&aim_speed))
+        .chain(normalized(&aim_distance))
+        .chain(normalized(&aim_sector))
+        .chain(normalized(&input_interval))
+        .chain(normalized(&speed));
+    for (value, embedded) in values.zip(embedding.iter_mut()) {
+        *embedded = value;
+    }
+    embedding
+}
+
+/// Create the empty, resizable (sequences, EMBEDDING_SIZE) embeddings dataset with the value
+/// names as attribute
+pub fn create_embeddings_dataset(file: &hdf5::File) -> Result<hdf5::Dataset, DatasetError> {
+    let dataset = file
+        .new_dataset::<f32>()
+        .shape((hdf5::Extent::resizable(0), EMBEDDING_SIZE))
+        .chunk((EMBEDDING_CHUNK, EMBEDDING_SIZE))
+        .create(EMBEDDINGS_DATASET)?;
+    let names: Vec<VarLenAscii> = embedding_names()
+        .iter()
+        .map(|name| VarLenAscii::from_ascii(name.as_bytes()).unwrap())
+        .collect();
+    dataset
+        .new_attr::<VarLenAscii>()
+        .shape(names.len())
+        .create("embedding_names")?
+        .write(&names)?;
+    Ok(dataset)
+}
+
+/// embeddings of all sequences of sequences.h5, None if it has none
+pub fn read_embeddings(file: &hdf5::File) -> Result<Option<Array2<f32>>, DatasetError> {
+    if !file.link_exists(EMBEDDINGS_DATASET) {
+        return Ok(None);
+    }
+    Ok(Some(file.dataset(EMBEDDINGS_DATASET)?.read_2d()?))
+}
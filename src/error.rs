@@ -0,0 +1,88 @@
+use crate::config::ConfigError;
+use crate::dataset::DatasetError;
+use crate::parser::ParseError;
+use hdf5_metno as hdf5;
+use thiserror::Error;
+
+/// Errors of the library API. Module specific errors are wrapped, so callers only have to
+/// handle a single type.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a readable teehistorian file: {0}")]
+    Teehistorian(#[from] teehistorian::Error),
+
+    #[error("parse error: {0}")]
+    Parse(#[from] ParseError),
+
+    #[error(transparent)]
+    Dataset(#[from] DatasetError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("hdf5 error: {0}")]
+    Hdf5(#[from] hdf5::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("shape error: {0}")]
+    Shape(#[from] ndarray::ShapeError),
+
+    #[error("arrow error: {0}")]
+    Arrow(#[from] arrow::error::ArrowError),
+
+    #[error("parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error("invalid sequence: {0}")]
+    InvalidSequence(String),
+
+    #[error("invalid export: {0}")]
+    InvalidExport(String),
+
+    #[error("invalid map file: {0}")]
+    InvalidMap(String),
+
+    #[error("invalid ranks dump: {0}")]
+    InvalidRecords(String),
+
+    #[error("invalid annotations: {0}")]
+    InvalidLabels(String),
+
+    #[error("demo error: {0}")]
+    Demo(String),
+
+    #[error("broker error: {0}")]
+    Broker(String),
+}
+
+impl Error {
+    /// short identifier of the error variant, see [`ParseError::kind`]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Teehistorian(_) => "teehistorian",
+            Error::Parse(err) => err.kind(),
+            Error::Dataset(_) => "dataset",
+            Error::Config(_) => "config",
+            Error::Hdf5(_) => "hdf5",
+            Error::Json(_) => "json",
+            Error::Shape(_) => "shape",
+            Error::Arrow(_) => "arrow",
+            Error::Parquet(_) => "parquet",
+            Error::InvalidSequence(_) => "invalid_sequence",
+            Error::InvalidExport(_) => "invalid_export",
+            Error::InvalidMap(_) => "invalid_map",
+            Error::InvalidRecords(_) => "invalid_records",
+            Error::InvalidLabels(_) => "invalid_labels",
+            Error::Demo(_) => "demo",
+            Error::Broker(_) => "broker",
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
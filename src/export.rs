@@ -1,144 +1,278 @@
-use hdf5_metno::{self as hdf5, types::VarLenAscii};
-use log::info;
-use ndarray::{Array2, Array3};
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use log::{info, warn};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs::{create_dir_all, File, OpenOptions},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, create_dir_all, File, OpenOptions},
     io::Write,
-    path::PathBuf,
+    mem,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, mpsc, Arc},
+    thread,
+    time::Instant,
 };
 
-use crate::extractor::{Extractor, Sequence};
-use crate::parser::ParserConfig;
-use crate::preprocess::Duration;
+use crate::anomaly::{self, AnomalyScore};
+use crate::bot_filter;
+use crate::cancel::{CancellationToken, ParseLimits};
+use crate::checksum::{self, CHECKSUM_FILE};
+use crate::config::{ConfigError, CONFIG_FILE_NAME, CONFIG_VERSION};
+use crate::dataset::{MetaRow, DATASET_VERSION};
+use crate::dedup::{self, DedupMode};
+use crate::embedding;
+use crate::error::{Error, Result};
+use crate::extractor::{teehist_name, Extractor, FileError, ParsedFile, Sequence};
+use crate::labels::LabelIndex;
+use crate::map_info::{GameLayers, MapCatalog};
+use crate::metrics::Metrics;
+use crate::outliers::OutlierBounds;
+use crate::parser::{
+    is_valid_player_name, sanitize_player_name, ClientSession, DDNetSequence, ParserConfig,
+};
+use crate::phase::Phase;
+use crate::preprocess::{activity_ratio, frozen_ticks, ActivityInput, Duration, Durations};
+use crate::privacy::PrivacyNoise;
+use crate::processed::{file_hash, ProcessedEntry, PROCESSED_FILE};
+use crate::progress::ExportProgress;
+use crate::records::RecordIndex;
+use crate::registry::{PlayerRegistry, REGISTRY_FILE};
+use crate::route::{self, RouteConfig};
+use crate::session;
+use crate::sink::{ExportSink, Hdf5Sink};
+use crate::smoothing::{Smoothing, SMOOTHABLE_COLUMNS};
+use crate::weights::{self, WeightRule};
 
-const MAX_AIM_DISTANCE: f32 = 1000.0;
+pub const MAX_AIM_DISTANCE: f32 = 1000.0;
 
-fn bool_to_unit_f32(b: bool) -> f32 {
-    if b {
-        1.0
-    } else {
-        0.0
+/// server ticks per second
+const TICK_RATE: usize = 50;
+
+/// value of the recorded column of meta.csv for the tick the given amount of ticks after start
+pub(crate) fn recorded_time(start: DateTime<Utc>, ticks: usize) -> String {
+    format_recorded(tick_time(start, ticks))
+}
+
+/// time of the tick the given amount of ticks after start
+fn tick_time(start: DateTime<Utc>, ticks: usize) -> DateTime<Utc> {
+    start + TimeDelta::milliseconds((ticks * 1000 / TICK_RATE) as i64)
+}
+
+fn format_recorded(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// file name of the export state persisted after each batch
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// small summary of the last checkpoint, e.g. to monitor long runs, see [`Progress`]
+pub const PROGRESS_FILE: &str = "progress.json";
+
+/// files written into the output folder by an export, other files are left alone
+const EXPORT_FILES: [&str; 17] = [
+    "sequences.h5",
+    "meta.csv",
+    "meta.csv.tmp",
+    CHECKPOINT_FILE,
+    "checkpoint.json.tmp",
+    PROGRESS_FILE,
+    "progress.json.tmp",
+    PROCESSED_FILE,
+    "parse_errors.csv",
+    "manifest.json",
+    "summary.json",
+    "ledger.csv",
+    CONFIG_FILE_NAME,
+    REGISTRY_FILE,
+    "players.json.tmp",
+    CHECKSUM_FILE,
+    "SHA256SUMS.tmp",
+];
+
+/// Whether folder_path holds the dataset or checkpoint of an earlier export
+pub fn contains_export(folder_path: &Path) -> bool {
+    ["sequences.h5", "meta.csv", CHECKPOINT_FILE]
+        .iter()
+        .any(|file| folder_path.join(file).exists())
+}
+
+/// Files of the export in folder_path without temporary files and SHA256SUMS, e.g. to
+/// upload them, see [`crate::upload`]
+pub fn export_files(folder_path: &Path) -> Vec<PathBuf> {
+    EXPORT_FILES
+        .iter()
+        .filter(|file| !file.ends_with(".tmp") && **file != CHECKSUM_FILE)
+        .map(|file| folder_path.join(file))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Remove the files of an earlier export from folder_path, returns how many were removed.
+/// Other files in the folder are kept.
+pub fn remove_export_files(folder_path: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for file in EXPORT_FILES {
+        let path = folder_path.join(file);
+        if path.is_file() {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
     }
+    Ok(removed)
 }
 
-fn log_sequence_info(sequences: &[Sequence]) {
-    let total_ticks = sequences.iter().map(|s| s.tick_count).sum::<usize>();
+/// Value range a feature column is restricted to by the export, None if it is unbounded.
+/// Velocities are bounded by the max_speed of the parser, if known.
+pub fn feature_range(column_name: &str, max_speed: Option<i32>) -> Option<(f32, f32)> {
+    match column_name {
+        "move_dir" => Some((-1.0, 1.0)),
+        "jump" | "fire" | "hook" | "frozen" | "active" => Some((0.0, 1.0)),
+        "vel_x" | "vel_y" => max_speed.map(|s| (-s as f32, s as f32)),
+        "aim_angle" => Some((-180.0, 180.0)),
+        "aim_distance" => Some((0.0, MAX_AIM_DISTANCE)),
+        "phase" => Some((0.0, (Phase::ALL.len() - 1) as f32)),
+        _ => None,
+    }
+}
+
+fn log_sequence_info(sequence_count: usize, total_ticks: usize) {
     info!(
         "sequences={}, ticks={} => {:.1} hours of gameplay",
-        sequences.len(),
+        sequence_count,
         total_ticks,
         (total_ticks as f32 / (50. * 60. * 60.))
     );
 }
 
-#[derive(Clone)]
+/// which sequences to keep based on whether the player finished the map
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FinishFilter {
+    Finished,
+    Unfinished,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ExportConfig {
     pub seq_length: usize,
     pub afk_ticks: usize,
+    /// inputs whose changes count as activity, see [`Duration::get_non_afk_durations`]
+    pub afk_inputs: Vec<ActivityInput>,
     pub afk_padding: usize,
+    /// join gameplay durations separated by fewer ticks before padding and cutting them,
+    /// 0 keeps them apart
+    pub merge_gap: usize,
+    /// Cut out stretches of more than this many frozen ticks, e.g. freeze jail waits.
+    /// Needs the map files, see [`crate::preprocess::frozen_ticks`].
+    pub max_freeze_ticks: Option<usize>,
+    /// add a `frozen` column, 1 in ticks the player was frozen. Always 0 without map file.
+    pub freeze_mask: bool,
+    /// Keep afk and cut freeze stretches, cutting whole sequences into windows, and add an
+    /// `active` column that is 0 in them. Windows without any active tick are dropped.
+    pub mask_inactive: bool,
+    /// add a categorical `phase` column with the [`Phase`] of each tick
+    pub phase_labels: bool,
+    /// export the last seq_length ticks of each gameplay duration that doesn't divide evenly,
+    /// overlapping the sequence before it, instead of dropping the remaining ticks
+    pub keep_tails: bool,
     pub use_vel: bool,
     pub use_rel_target: bool,
     pub use_aim_angle: bool,
     pub use_aim_distance: bool,
+    /// column name -> filter applied to it, see [`crate::smoothing`]
+    pub smoothing: BTreeMap<String, Smoothing>,
     pub dry_run: bool,
+    /// stop exporting once the output would exceed this many bytes
+    pub max_dataset_bytes: Option<u64>,
+    /// keep only finished or unfinished sequences
+    pub finish_filter: Option<FinishFilter>,
+    /// drop sequences of likely bots, see [`bot_filter::is_bot`]
+    pub drop_bots: bool,
+    /// drop cleaned sequences with a lower fraction of ticks with any input
+    pub min_activity_ratio: Option<f32>,
+    /// drop sequences with the same fingerprint as an earlier one, see [`dedup::fingerprint`]
+    pub dedup: Option<DedupMode>,
+    /// Drop sequences whose velocities or aim targets lie beyond this quantile of a sample,
+    /// see [`crate::outliers`]
+    pub outlier_quantile: Option<f64>,
+    /// clip the values of outliers to the quantiles instead of dropping them
+    pub clip_outliers: bool,
+    /// the first sequences of a run, up to this many, are the sample of the quantiles
+    pub outlier_sample: usize,
+    /// fraction of cleaned sequences to keep, randomly sampled
+    pub sample_fraction: Option<f64>,
+    /// seed for sampling, random if not set
+    pub seed: Option<u64>,
+    /// export sequences of invalid player names under a sanitized name instead of dropping
+    /// them, see [`is_valid_player_name`]
+    pub keep_invalid_names: bool,
+    /// fill the weight column of meta.csv by these rules once the export is finalized, see
+    /// [`crate::weights`]
+    pub sample_weights: Vec<WeightRule>,
+    /// a play session ends once its player is absent for more ticks, see [`crate::session`]
+    pub session_gap: usize,
+    /// write a behavioral embedding of each sequence into sequences.h5, see
+    /// [`crate::embedding`]
+    pub embeddings: bool,
+    /// add noise to positions and cursor targets and coarsen recording times, see
+    /// [`crate::privacy`]
+    pub privacy: Option<PrivacyNoise>,
+    /// write the coarse path of each sequence into meta.csv and cluster them into routes per
+    /// map once the export is finalized, see [`crate::route`]
+    pub routes: Option<RouteConfig>,
 }
 
-/// keeps track of relevant meta-data to remain consistent even among batched export
-pub struct Exporter {
-    /// player_name -> (player_id, sequence_count)
-    pub players: HashMap<String, (usize, usize)>,
-
-    /// amount of registered players
-    pub player_count: usize,
-
-    /// total amount of sequences
-    pub sequence_count: usize,
-
-    num_features: usize,
-
-    seq_dataset: Option<hdf5::Dataset>,
-    meta_file: Option<File>,
-
-    config: ExportConfig,
-}
-
-impl Exporter {
-    /// Initialze empty dataset, use add function to add (batches) of data to it
-    pub fn new(folder_path: &PathBuf, config: ExportConfig) -> Exporter {
-        let column_names = Exporter::get_column_names(
-            config.use_vel,
-            config.use_rel_target,
-            config.use_aim_angle,
-            config.use_aim_distance,
-        );
-        let num_features = column_names.len();
-
-        // if we use velocity, we need to cut off the last tick as velocity cant be calculated
-        // TODO: this approach is kind of stupid
-        let mut config = config;
-        if config.use_vel {
-            config.seq_length -= 1;
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            seq_length: 1000,
+            afk_ticks: 500,
+            afk_inputs: vec![ActivityInput::Move],
+            afk_padding: 15,
+            merge_gap: 0,
+            max_freeze_ticks: None,
+            freeze_mask: false,
+            mask_inactive: false,
+            phase_labels: false,
+            keep_tails: false,
+            use_vel: true,
+            use_rel_target: false,
+            use_aim_angle: true,
+            use_aim_distance: true,
+            smoothing: BTreeMap::new(),
+            dry_run: false,
+            max_dataset_bytes: None,
+            finish_filter: None,
+            drop_bots: false,
+            min_activity_ratio: None,
+            dedup: None,
+            outlier_quantile: None,
+            clip_outliers: false,
+            outlier_sample: 10_000,
+            sample_fraction: None,
+            seed: None,
+            keep_invalid_names: false,
+            sample_weights: Vec::new(),
+            session_gap: 5 * 60 * TICK_RATE,
+            embeddings: false,
+            privacy: None,
+            routes: None,
         }
+    }
+}
 
-        let (seq_dataset, meta_file) = if !config.dry_run {
-            assert!(folder_path.is_dir(), "Output path is not a directory");
-            create_dir_all(folder_path).expect("Failed to create dataset directory");
-
-            // initialize sequences hdf5 file
-            let seq_file = hdf5::File::create(folder_path.join("sequences.h5"))
-                .expect("Failed to create sequences.h5");
-            let seq_dataset = seq_file
-                .new_dataset::<f32>()
-                .shape((hdf5::Extent::resizable(0), config.seq_length, num_features))
-                .create("sequences")
-                .expect("failed to create sequences.h5");
-
-            // add column named header attribute
-            let column_names_vla: Vec<VarLenAscii> = column_names
-                .iter()
-                .map(|s| VarLenAscii::from_ascii(s.as_bytes()).unwrap())
-                .collect();
-            let attr = seq_dataset
-                .new_attr::<VarLenAscii>()
-                .shape(column_names_vla.len())
-                .create("column_names")
-                .expect("Failed to create column_names attribute");
-            attr.write(&column_names_vla)
-                .expect("Failed to write column_names attribute");
-
-            // initialize meta
-            let mut meta_file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(folder_path.join("meta.csv"))
-                .unwrap();
-            writeln!(meta_file, "seq_id,player_id,player,start,ticks,map,teehist")
-                .expect("Failed to write header to meta.csv");
-
-            (Some(seq_dataset), Some(meta_file))
-        } else {
-            (None, None)
-        };
-
-        Exporter {
-            players: HashMap::new(),
-            player_count: 0,
-            sequence_count: 0,
-            seq_dataset,
-            meta_file,
-            num_features,
-            config,
+impl ExportConfig {
+    /// builder starting from the default config
+    pub fn builder() -> ExportConfigBuilder {
+        ExportConfigBuilder {
+            config: ExportConfig::default(),
         }
     }
 
-    fn get_column_names(
-        use_vel: bool,
-        use_rel_target: bool,
-        use_aim_angle: bool,
-        use_aim_distance: bool,
-    ) -> Vec<String> {
+    /// names of the exported feature columns, in order
+    pub fn column_names(&self) -> Vec<String> {
         let mut column_names = vec![
             "move_dir".to_string(),
             "jump".to_string(),
@@ -146,220 +280,1711 @@ impl Exporter {
             "hook".to_string(),
         ];
 
-        if use_vel {
+        if self.use_vel {
             column_names.push("vel_x".to_string());
             column_names.push("vel_y".to_string());
         }
 
-        if use_rel_target {
+        if self.use_rel_target {
             column_names.push("target_rel_x".to_string());
             column_names.push("target_rel_y".to_string());
         }
 
-        if use_aim_angle {
+        if self.use_aim_angle {
             column_names.push("aim_angle".to_string());
         }
 
-        if use_aim_distance {
+        if self.use_aim_distance {
             column_names.push("aim_distance".to_string());
         }
 
+        if self.freeze_mask {
+            column_names.push("frozen".to_string());
+        }
+
+        if self.mask_inactive {
+            column_names.push("active".to_string());
+        }
+
+        if self.phase_labels {
+            column_names.push("phase".to_string());
+        }
+
         column_names
     }
 
-    fn sequence_to_tick_array(&self, seq: &Sequence) -> Array2<f32> {
-        let mut data = Vec::new();
-        data.extend(
-            seq.move_dir
-                .iter()
-                .take(self.config.seq_length)
-                .map(|&i| i as f32),
-        );
-        data.extend(
-            seq.jump
-                .iter()
-                .take(self.config.seq_length)
-                .map(|&b| bool_to_unit_f32(b)),
-        );
-        data.extend(
-            seq.fire
-                .iter()
-                .take(self.config.seq_length)
-                .map(|&b| bool_to_unit_f32(b)),
-        );
-        data.extend(
-            seq.hook
-                .iter()
-                .take(self.config.seq_length)
-                .map(|&b| bool_to_unit_f32(b)),
-        );
+    /// whether cleaning needs to know in which ticks players were frozen
+    pub fn detects_freeze(&self) -> bool {
+        self.max_freeze_ticks.is_some() || self.freeze_mask || self.phase_labels
+    }
 
-        if self.config.use_vel {
-            let vel_x: Vec<f32> = seq.pos_x.windows(2).map(|w| (w[1] - w[0]) as f32).collect();
-            let vel_y: Vec<f32> = seq.pos_y.windows(2).map(|w| (w[1] - w[0]) as f32).collect();
-            data.extend(&vel_x[..self.config.seq_length]);
-            data.extend(&vel_y[..self.config.seq_length]);
+    /// Ticks of a cleaned sequence needed to export seq_length ticks.
+    /// The velocity of the last exported tick needs the position of the following tick.
+    pub fn source_ticks(&self) -> usize {
+        if self.use_vel {
+            self.seq_length + 1
+        } else {
+            self.seq_length
         }
+    }
 
-        if self.config.use_rel_target {
-            data.extend(
-                seq.target_x
-                    .iter()
-                    .take(self.config.seq_length)
-                    .map(|&i| i as f32),
+    /// check for values and combinations the export can't handle
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+        if self.seq_length == 0 {
+            return invalid("seq_length must be positive".to_string());
+        }
+        if 2 * self.afk_padding >= self.seq_length {
+            return invalid(format!(
+                "afk_padding={} on both sides leaves no gameplay in seq_length={}",
+                self.afk_padding, self.seq_length
+            ));
+        }
+        if self.afk_ticks == 0 {
+            return invalid("afk_ticks must be positive".to_string());
+        }
+        if self.afk_inputs.is_empty() {
+            return invalid("afk_inputs must not be empty".to_string());
+        }
+        if self.max_freeze_ticks == Some(0) {
+            return invalid("max_freeze_ticks must be positive".to_string());
+        }
+        let column_names = self.column_names();
+        for (column, smoothing) in &self.smoothing {
+            if !SMOOTHABLE_COLUMNS.contains(&column.as_str()) {
+                return invalid(format!(
+                    "column {} can't be smoothed, expected one of {}",
+                    column,
+                    SMOOTHABLE_COLUMNS.join(", ")
+                ));
+            }
+            if !column_names.contains(column) {
+                return invalid(format!("smoothed column {} is not exported", column));
+            }
+            if let Err(message) = smoothing.validate() {
+                return invalid(format!("smoothing of {}: {}", column, message));
+            }
+        }
+        if self.max_dataset_bytes == Some(0) {
+            return invalid("max_dataset_bytes must be positive".to_string());
+        }
+        if let Some(ratio) = self.min_activity_ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return invalid(format!("min_activity_ratio={} not in [0, 1]", ratio));
+            }
+        }
+        if let Some(quantile) = self.outlier_quantile {
+            if !(quantile > 0.0 && quantile < 0.5) {
+                return invalid(format!("outlier_quantile={} not in (0, 0.5)", quantile));
+            }
+        }
+        if self.outlier_sample == 0 {
+            return invalid("outlier_sample must be positive".to_string());
+        }
+        if let Some(fraction) = self.sample_fraction {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return invalid(format!("sample_fraction={} not in (0, 1]", fraction));
+            }
+        }
+        for rule in &self.sample_weights {
+            if let Err(message) = rule.validate() {
+                return invalid(format!("sample weight rule {:?}: {}", rule, message));
+            }
+        }
+        if let Some(privacy) = &self.privacy {
+            if let Err(message) = privacy.validate() {
+                return invalid(format!("privacy noise: {}", message));
+            }
+        }
+        if let Some(routes) = &self.routes {
+            if let Err(message) = routes.validate() {
+                return invalid(format!("routes: {}", message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builder for [`ExportConfig`], unset values keep their defaults.
+/// Optional settings accept both plain values and Options.
+pub struct ExportConfigBuilder {
+    config: ExportConfig,
+}
+
+impl ExportConfigBuilder {
+    pub fn seq_length(mut self, seq_length: usize) -> Self {
+        self.config.seq_length = seq_length;
+        self
+    }
+
+    pub fn afk_ticks(mut self, afk_ticks: usize) -> Self {
+        self.config.afk_ticks = afk_ticks;
+        self
+    }
+
+    pub fn afk_inputs(mut self, afk_inputs: Vec<ActivityInput>) -> Self {
+        self.config.afk_inputs = afk_inputs;
+        self
+    }
+
+    pub fn afk_padding(mut self, afk_padding: usize) -> Self {
+        self.config.afk_padding = afk_padding;
+        self
+    }
+
+    pub fn merge_gap(mut self, merge_gap: usize) -> Self {
+        self.config.merge_gap = merge_gap;
+        self
+    }
+
+    pub fn max_freeze_ticks(mut self, max_freeze_ticks: impl Into<Option<usize>>) -> Self {
+        self.config.max_freeze_ticks = max_freeze_ticks.into();
+        self
+    }
+
+    pub fn freeze_mask(mut self, freeze_mask: bool) -> Self {
+        self.config.freeze_mask = freeze_mask;
+        self
+    }
+
+    pub fn mask_inactive(mut self, mask_inactive: bool) -> Self {
+        self.config.mask_inactive = mask_inactive;
+        self
+    }
+
+    pub fn phase_labels(mut self, phase_labels: bool) -> Self {
+        self.config.phase_labels = phase_labels;
+        self
+    }
+
+    pub fn keep_tails(mut self, keep_tails: bool) -> Self {
+        self.config.keep_tails = keep_tails;
+        self
+    }
+
+    pub fn use_vel(mut self, use_vel: bool) -> Self {
+        self.config.use_vel = use_vel;
+        self
+    }
+
+    pub fn use_rel_target(mut self, use_rel_target: bool) -> Self {
+        self.config.use_rel_target = use_rel_target;
+        self
+    }
+
+    pub fn use_aim_angle(mut self, use_aim_angle: bool) -> Self {
+        self.config.use_aim_angle = use_aim_angle;
+        self
+    }
+
+    pub fn use_aim_distance(mut self, use_aim_distance: bool) -> Self {
+        self.config.use_aim_distance = use_aim_distance;
+        self
+    }
+
+    pub fn smoothing(mut self, smoothing: BTreeMap<String, Smoothing>) -> Self {
+        self.config.smoothing = smoothing;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    pub fn max_dataset_bytes(mut self, max_dataset_bytes: impl Into<Option<u64>>) -> Self {
+        self.config.max_dataset_bytes = max_dataset_bytes.into();
+        self
+    }
+
+    pub fn finish_filter(mut self, finish_filter: impl Into<Option<FinishFilter>>) -> Self {
+        self.config.finish_filter = finish_filter.into();
+        self
+    }
+
+    pub fn drop_bots(mut self, drop_bots: bool) -> Self {
+        self.config.drop_bots = drop_bots;
+        self
+    }
+
+    pub fn min_activity_ratio(mut self, min_activity_ratio: impl Into<Option<f32>>) -> Self {
+        self.config.min_activity_ratio = min_activity_ratio.into();
+        self
+    }
+
+    pub fn dedup(mut self, dedup: impl Into<Option<DedupMode>>) -> Self {
+        self.config.dedup = dedup.into();
+        self
+    }
+
+    pub fn outlier_quantile(mut self, outlier_quantile: impl Into<Option<f64>>) -> Self {
+        self.config.outlier_quantile = outlier_quantile.into();
+        self
+    }
+
+    pub fn clip_outliers(mut self, clip_outliers: bool) -> Self {
+        self.config.clip_outliers = clip_outliers;
+        self
+    }
+
+    pub fn outlier_sample(mut self, outlier_sample: usize) -> Self {
+        self.config.outlier_sample = outlier_sample;
+        self
+    }
+
+    pub fn sample_fraction(mut self, sample_fraction: impl Into<Option<f64>>) -> Self {
+        self.config.sample_fraction = sample_fraction.into();
+        self
+    }
+
+    pub fn seed(mut self, seed: impl Into<Option<u64>>) -> Self {
+        self.config.seed = seed.into();
+        self
+    }
+
+    pub fn keep_invalid_names(mut self, keep_invalid_names: bool) -> Self {
+        self.config.keep_invalid_names = keep_invalid_names;
+        self
+    }
+
+    pub fn sample_weights(mut self, sample_weights: Vec<WeightRule>) -> Self {
+        self.config.sample_weights = sample_weights;
+        self
+    }
+
+    pub fn session_gap(mut self, session_gap: usize) -> Self {
+        self.config.session_gap = session_gap;
+        self
+    }
+
+    pub fn embeddings(mut self, embeddings: bool) -> Self {
+        self.config.embeddings = embeddings;
+        self
+    }
+
+    pub fn privacy(mut self, privacy: impl Into<Option<PrivacyNoise>>) -> Self {
+        self.config.privacy = privacy.into();
+        self
+    }
+
+    pub fn routes(mut self, routes: impl Into<Option<RouteConfig>>) -> Self {
+        self.config.routes = routes.into();
+        self
+    }
+
+    /// validated config, see [`ExportConfig::validate`]
+    pub fn build(self) -> Result<ExportConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// summary of a finished export, written as manifest.json next to the dataset
+#[derive(Serialize)]
+struct Manifest<'a> {
+    /// see [`DATASET_VERSION`]
+    schema_version: u32,
+    /// schema version of the config.toml next to the dataset
+    config_version: u32,
+    sequence_count: usize,
+    player_count: usize,
+    seq_length: usize,
+    column_names: &'a [String],
+    processed_files: usize,
+    stop_reason: &'a str,
+    /// column name -> filter applied to it
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    smoothing: &'a BTreeMap<String, Smoothing>,
+    /// names of the values of the phase column
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phases: Option<Vec<&'static str>>,
+    /// rules of the weight column of meta.csv
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    sample_weights: &'a [WeightRule],
+    /// names of the values of the embeddings dataset in sequences.h5
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding_names: Option<Vec<String>>,
+    /// noise added for a public release, the data is perturbed if set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    privacy: Option<&'a PrivacyNoise>,
+    /// name of a sequence value -> scale of the Laplace noise added to it
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    noise_scales: BTreeMap<String, f64>,
+    /// clustering of the route column of meta.csv
+    #[serde(skip_serializing_if = "Option::is_none")]
+    routes: Option<&'a RouteConfig>,
+}
+
+/// Machine-readable outcome of a run, written as summary.json next to the dataset.
+/// Counts cover the current run only, also when it was resumed.
+#[derive(Serialize, Default, Debug)]
+pub struct RunSummary {
+    pub files_processed: usize,
+    /// error kind -> amount of files whose parsing stopped with it
+    pub parse_errors: BTreeMap<String, usize>,
+    /// sequences after conversion, before cleaning
+    pub sequences_converted: usize,
+    pub sequences_kept: usize,
+    /// reason -> amount of dropped sequences
+    pub sequences_dropped: BTreeMap<String, usize>,
+    /// exported sequences with an anomaly score of at least [`anomaly::FLAG_THRESHOLD`]
+    pub sequences_flagged: usize,
+    /// dropped duplicates of a sequence of the same player, e.g. of files present twice
+    pub duplicates_same_player: usize,
+    /// dropped duplicates of a sequence of another player, e.g. of dummies copying inputs
+    pub duplicates_other_player: usize,
+    /// outliers whose values were clipped instead of dropping them
+    pub sequences_clipped: usize,
+    /// bounds outliers were dropped or clipped at, see [`OutlierBounds`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outlier_bounds: Option<OutlierBounds>,
+    pub players: usize,
+    pub total_ticks: usize,
+}
+
+impl RunSummary {
+    fn count_dropped(&mut self, reason: &str, count: usize) {
+        if count > 0 {
+            *self
+                .sequences_dropped
+                .entry(reason.to_string())
+                .or_insert(0) += count;
+        }
+    }
+
+    /// add the conversion and cleaning counts of a single file
+    fn add_file_counts(&mut self, counts: RunSummary) {
+        self.sequences_converted += counts.sequences_converted;
+        for (reason, count) in counts.sequences_dropped {
+            self.count_dropped(&reason, count);
+        }
+    }
+}
+
+/// A file after parsing, conversion and cleaning, passed from the parsing threads to the
+/// exporting thread in [`Exporter::handle_batch`].
+struct CleanedFile {
+    /// the parsed sequences are moved to cleaned
+    parsed_file: Result<ParsedFile>,
+    hash: Option<String>,
+    /// approximate memory of the parsed ddnet sequences
+    ddnet_bytes: usize,
+    ddnet_count: usize,
+    cleaned: Result<Vec<Sequence>>,
+    /// converted and dropped sequences of this file
+    counts: RunSummary,
+    /// cleaned sequences and counts for each of [`Exporter::resolutions`]
+    resolutions: Vec<(Result<Vec<Sequence>>, RunSummary)>,
+}
+
+/// Convert the ddnet sequences of a file and cut them into gameplay sequences without afk
+/// parts. Bots and inactive sequences are dropped as configured, counted in summary.
+/// Sequences are processed in parallel, also within a single file.
+/// The result is ordered by cid and start tick, so the export is reproducible, and assigned
+/// to the play sessions of the client sessions of the file.
+fn clean_sequences(
+    ddnet_sequences: &mut [DDNetSequence],
+    client_sessions: &[ClientSession],
+    export_config: &ExportConfig,
+    game_layers: &GameLayers,
+    summary: &mut RunSummary,
+) -> Result<Vec<Sequence>> {
+    // the parser completes the sequences still active at the end of a file in hash order
+    ddnet_sequences.sort_by_key(|ddnet_seq| (ddnet_seq.cid, ddnet_seq.start_tick));
+
+    // Convert DDNetSequence -> Sequence, Err holds the reason for dropping it
+    let converted: Vec<Result<Sequence, &str>> = ddnet_sequences
+        .par_iter()
+        .map(|ddnet_seq| {
+            let mut sequence = Sequence::from_ddnet_sequence(ddnet_seq).map_err(|err| {
+                warn!("{}", err);
+                "invalid"
+            })?;
+
+            let finish_included = match export_config.finish_filter {
+                Some(FinishFilter::Finished) => sequence.finish_time.is_some(),
+                Some(FinishFilter::Unfinished) => sequence.finish_time.is_none(),
+                None => true,
+            };
+            if sequence.tick_count < export_config.source_ticks() {
+                Err("too_short")
+            } else if !finish_included {
+                Err("finish_filter")
+            } else if !is_valid_player_name(&sequence.player_name) {
+                if !export_config.keep_invalid_names {
+                    return Err("invalid_name");
+                }
+                sequence.player_name = sanitize_player_name(&sequence.player_name).into();
+                Ok(sequence)
+            } else {
+                Ok(sequence)
+            }
+        })
+        .collect();
+    let mut sequences: Vec<Sequence> = Vec::with_capacity(converted.len());
+    for sequence in converted {
+        match sequence {
+            Ok(sequence) => sequences.push(sequence),
+            Err(reason) => summary.count_dropped(reason, 1),
+        }
+    }
+    summary.sequences_converted += sequences.len();
+
+    if export_config.detects_freeze() {
+        sequences.par_iter_mut().for_each(|sequence| {
+            if let Some(game_layer) = game_layers.get(&sequence.map_name) {
+                sequence.frozen = frozen_ticks(sequence, &game_layer);
+            }
+        });
+    }
+
+    // Clean sequences
+    let cleaned_sequences: Vec<Sequence> = sequences
+        .into_par_iter()
+        .map(|mut sequence| {
+            let durations = Duration::get_non_afk_durations(
+                &sequence,
+                export_config.afk_ticks,
+                &export_config.afk_inputs,
             );
-            data.extend(
-                seq.target_y
-                    .iter()
-                    .take(self.config.seq_length)
-                    .map(|&i| i as f32),
+            let durations = durations.merge_gaps(export_config.merge_gap);
+            let durations = match export_config.max_freeze_ticks {
+                Some(max_ticks) => {
+                    durations.remove(&Duration::get_freeze_durations(&sequence.frozen, max_ticks))
+                }
+                None => durations,
+            };
+            let durations = durations.pad(sequence.tick_count - 1, export_config.afk_padding);
+            // the gameplay durations become the mask, the windows cover the whole sequence
+            let durations = if export_config.mask_inactive {
+                sequence.active = durations.mask(sequence.tick_count);
+                Durations::new(vec![Duration::new(0, sequence.tick_count - 1)])
+            } else {
+                durations
+            };
+            let extra_ticks = export_config.source_ticks() - export_config.seq_length;
+            let windows = durations.cut(
+                export_config.seq_length,
+                extra_ticks,
+                export_config.keep_tails,
             );
+            Duration::extract_sub_sequences(&sequence, &windows)
+        })
+        .collect::<Result<Vec<Vec<Sequence>>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // drop windows without gameplay, only cut out when masking
+    let cleaned_sequences = if export_config.mask_inactive {
+        let window_count = cleaned_sequences.len();
+        let active_windows: Vec<Sequence> = cleaned_sequences
+            .into_par_iter()
+            .filter(|sequence| sequence.active.contains(&true))
+            .collect();
+        summary.count_dropped("inactive", window_count - active_windows.len());
+        active_windows
+    } else {
+        cleaned_sequences
+    };
+
+    // drop sequences of known bot names or with bot-like inputs
+    let cleaned_sequences = if export_config.drop_bots {
+        let cleaned_count = cleaned_sequences.len();
+        let human_sequences: Vec<Sequence> = cleaned_sequences
+            .into_par_iter()
+            .filter(|sequence| !bot_filter::is_bot(sequence))
+            .collect();
+        summary.count_dropped("bot", cleaned_count - human_sequences.len());
+        human_sequences
+    } else {
+        cleaned_sequences
+    };
+
+    // drop sequences without enough actual input
+    let mut cleaned_sequences = match export_config.min_activity_ratio {
+        Some(min_ratio) => {
+            let cleaned_count = cleaned_sequences.len();
+            let active_sequences: Vec<Sequence> = cleaned_sequences
+                .into_par_iter()
+                .filter(|sequence| activity_ratio(sequence) >= min_ratio)
+                .collect();
+            summary.count_dropped("low_activity", cleaned_count - active_sequences.len());
+            active_sequences
         }
+        None => cleaned_sequences,
+    };
 
-        if self.config.use_aim_angle {
-            data.extend(
-                seq.target_x
-                    .iter()
-                    .zip(seq.target_y.iter())
-                    .take(self.config.seq_length)
-                    .map(|(&x, &y)| (y as f32).atan2(x as f32).to_degrees()),
-            );
+    session::assign_sessions(
+        &mut cleaned_sequences,
+        client_sessions,
+        export_config.session_gap,
+    );
+    Ok(cleaned_sequences)
+}
+
+/// Filter or transform applied to every cleaned sequence before export, see
+/// [`Exporter::add_hook`]. Returning None drops the sequence.
+pub type SequenceHook = Box<dyn Fn(&Sequence) -> Option<Sequence> + Send + Sync>;
+
+/// exporter state after the last completed batch, used to resume interrupted runs
+#[derive(Serialize, Deserialize, Default)]
+struct Checkpoint {
+    processed_files: Vec<PathBuf>,
+    players: HashMap<Arc<str>, (usize, usize)>,
+    player_count: usize,
+    sequence_count: usize,
+    file_ticks: HashMap<Arc<str>, usize>,
+    /// fingerprint -> player of the exported sequences, if deduplicated
+    #[serde(default)]
+    fingerprints: HashMap<u64, Arc<str>>,
+    /// resumed runs keep the outlier bounds of the first run
+    #[serde(default)]
+    outlier_bounds: Option<OutlierBounds>,
+    /// completed batches, of resumed runs as well
+    #[serde(default)]
+    batches: usize,
+}
+
+/// Contents of progress.json, written together with each checkpoint. sequences.h5 and
+/// meta.csv hold exactly sequence_count sequences as of this point, later rows belong to an
+/// unfinished batch and are discarded when the export is resumed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub batches: usize,
+    pub processed_files: usize,
+    pub sequence_count: usize,
+    /// RFC 3339 UTC time the checkpoint was written at
+    pub updated: String,
+}
+
+/// Write a small file at once: into a temporary file synced to disk, which then replaces path
+fn write_synced(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    let mut tmp_file = File::create(&tmp_path)?;
+    tmp_file.write_all(contents)?;
+    tmp_file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    // the rename itself is only durable once the folder is synced, folders can't be opened
+    // as files on windows
+    #[cfg(unix)]
+    if let Some(folder_path) = path.parent() {
+        File::open(folder_path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Keeps track of relevant meta-data to remain consistent even among batched export.
+/// Sequences are stored by the sink, sequences.h5 and meta.csv by default.
+pub struct Exporter<S: ExportSink = Hdf5Sink> {
+    /// player_name -> (player_id, sequence_count), aliases share the id of their canonical name
+    pub players: HashMap<Arc<str>, (usize, usize)>,
+
+    /// amount of players in this export, aliases are counted once
+    pub player_count: usize,
+
+    /// ids in players
+    player_ids: HashSet<usize>,
+
+    /// total amount of sequences
+    pub sequence_count: usize,
+
+    /// completed batches, each ends with a checkpoint
+    pub batches: usize,
+
+    num_features: usize,
+
+    /// input files that have been fully parsed and exported
+    pub processed_files: Vec<PathBuf>,
+
+    /// teehistorian file name -> amount of exported ticks
+    pub file_ticks: HashMap<Arc<str>, usize>,
+
+    /// teehistorian file name -> start of its recording, for the files of the current batch
+    recording_starts: HashMap<Arc<str>, DateTime<Utc>>,
+
+    /// if set, no new files are parsed after this point in time
+    pub deadline: Option<Instant>,
+
+    /// Stops the run between files and chunks. Files interrupted mid-parse are discarded and
+    /// not marked as processed, so a resumed run parses them again.
+    pub cancel: Option<CancellationToken>,
+
+    /// Parsing of a single file stops after this duration, its completed sequences are kept.
+    pub file_timeout: Option<std::time::Duration>,
+
+    /// whether max_dataset_bytes was reached, no further sequences are written
+    pub quota_reached: bool,
+
+    /// progress bars, advanced per parsed file and updated after each batch
+    pub progress: Option<ExportProgress>,
+
+    /// counters updated per exported file, e.g. served by [`crate::metrics::serve`]
+    pub metrics: Option<Arc<Metrics>>,
+
+    /// approximate memory of the ddnet sequences parsed in the last batch, files are
+    /// exported as they complete so this is not held in memory at once
+    pub last_batch_bytes: usize,
+
+    /// counts of this run, see [`RunSummary`]
+    pub summary: RunSummary,
+
+    /// map name -> amount of exported sequences
+    pub map_sequences: HashMap<Arc<str>, usize>,
+
+    /// Source of player_ids, saved as players.json with each checkpoint. Set it to the
+    /// registry of an earlier export before the first batch to keep its player_ids.
+    pub registry: PlayerRegistry,
+
+    /// Source of the map columns of meta.csv. Empty by default, so they stay empty.
+    pub maps: MapCatalog,
+
+    /// source of the record columns of meta.csv, empty by default
+    pub records: RecordIndex,
+
+    /// source of the labels column of meta.csv, empty by default
+    pub labels: LabelIndex,
+
+    /// fingerprint -> player of the exported sequences, see [`ExportConfig::dedup`]
+    fingerprints: HashMap<u64, Arc<str>>,
+
+    /// bounds of outlier removal, computed once outlier_sample sequences are collected
+    pub outlier_bounds: Option<OutlierBounds>,
+
+    /// sequences held back until the outlier bounds are known
+    outlier_sample: Vec<Sequence>,
+
+    /// size of all meta.csv rows, also tracked in dry runs
+    meta_bytes: u64,
+
+    column_names: Vec<String>,
+    folder_path: PathBuf,
+    rng: StdRng,
+    sink: S,
+    processed_log: Option<File>,
+    error_log: Option<File>,
+    hooks: Vec<SequenceHook>,
+
+    /// Exporters of the same files at other seq_lengths, each into its own folder. Files are
+    /// parsed once and their sequences cleaned and cut for each of them.
+    pub resolutions: Vec<Exporter<S>>,
+
+    config: ExportConfig,
+}
+
+impl Exporter<Hdf5Sink> {
+    /// Initialze empty dataset, use add function to add (batches) of data to it.
+    /// folder_path is created if missing, it must not contain an earlier export, see
+    /// [`remove_export_files`].
+    pub fn new(folder_path: &PathBuf, config: ExportConfig) -> Result<Exporter> {
+        Exporter::with_sink(folder_path, config, Hdf5Sink::new(folder_path))
+    }
+
+    /// Continue an interrupted export in folder_path from its checkpoint.json.
+    /// Rows written after the last checkpoint are discarded, so no sequence is exported twice.
+    pub fn resume(folder_path: &PathBuf, config: ExportConfig) -> Result<Exporter> {
+        Exporter::resume_with_sink(folder_path, config, Hdf5Sink::new(folder_path))
+    }
+}
+
+impl<S: ExportSink> Exporter<S> {
+    /// Like [`Exporter::new`], but sequences are stored by the given sink.
+    /// Checkpoints and logs are still written to folder_path.
+    pub fn with_sink(folder_path: &PathBuf, config: ExportConfig, sink: S) -> Result<Exporter<S>> {
+        Exporter::create(folder_path, config, None, sink)
+    }
+
+    /// Like [`Exporter::resume`], the sink is opened with the sequence count of the checkpoint
+    pub fn resume_with_sink(
+        folder_path: &PathBuf,
+        config: ExportConfig,
+        sink: S,
+    ) -> Result<Exporter<S>> {
+        let checkpoint_path = folder_path.join(CHECKPOINT_FILE);
+        if !checkpoint_path.is_file() {
+            return Err(Error::InvalidExport(format!(
+                "{:?} has no {}, there is no export to resume",
+                folder_path, CHECKPOINT_FILE
+            )));
+        }
+        let checkpoint_file = File::open(checkpoint_path)?;
+        let checkpoint: Checkpoint = serde_json::from_reader(checkpoint_file)?;
+        info!(
+            "resuming after {} files with {} sequences",
+            checkpoint.processed_files.len(),
+            checkpoint.sequence_count
+        );
+        Exporter::create(folder_path, config, Some(checkpoint), sink)
+    }
+
+    fn create(
+        folder_path: &PathBuf,
+        config: ExportConfig,
+        checkpoint: Option<Checkpoint>,
+        mut sink: S,
+    ) -> Result<Exporter<S>> {
+        config.validate()?;
+        let column_names = config.column_names();
+        let num_features = column_names.len();
+
+        if !config.dry_run {
+            if folder_path.exists() && !folder_path.is_dir() {
+                return Err(Error::InvalidExport(format!(
+                    "output path {:?} is not a directory",
+                    folder_path
+                )));
+            }
+            if checkpoint.is_none() && contains_export(folder_path) {
+                return Err(Error::InvalidExport(format!(
+                    "output folder {:?} already contains an export, overwrite or resume it",
+                    folder_path
+                )));
+            }
+            create_dir_all(folder_path)?;
+            sink.open(&config, checkpoint.as_ref().map(|c| c.sequence_count))?;
+        } else if checkpoint.is_some() {
+            return Err(Error::InvalidExport("can't resume a dry run".to_string()));
         }
 
-        if self.config.use_aim_distance {
-            data.extend(
-                seq.target_x
-                    .iter()
-                    .zip(seq.target_y.iter())
-                    .take(self.config.seq_length)
-                    .map(|(&x, &y)| ((x.pow(2) + y.pow(2)) as f32).sqrt().min(MAX_AIM_DISTANCE)),
-            );
+        // log of ingested files, continued by resumed runs
+        let processed_path = folder_path.join(PROCESSED_FILE);
+        let processed_log = if config.dry_run {
+            None
+        } else if checkpoint.is_some() {
+            let processed_log = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(processed_path)?;
+            Some(processed_log)
+        } else {
+            Some(File::create(processed_path)?)
+        };
+
+        // errors of all files, continued by resumed runs
+        let error_path = folder_path.join("parse_errors.csv");
+        let error_log = if config.dry_run {
+            None
+        } else if checkpoint.is_some() && error_path.is_file() {
+            let error_log = OpenOptions::new().append(true).open(error_path)?;
+            Some(error_log)
+        } else {
+            let mut error_log = File::create(error_path)?;
+            writeln!(
+                error_log,
+                "file,server,chunk,tick,error,recovered_sequences,message"
+            )?;
+            Some(error_log)
+        };
+
+        // players of checkpoints written without a registry keep their ids
+        let registry_path = folder_path.join(REGISTRY_FILE);
+        let mut registry = if checkpoint.is_some() && registry_path.is_file() {
+            PlayerRegistry::load(&registry_path)?
+        } else {
+            PlayerRegistry::default()
+        };
+        let checkpoint = checkpoint.unwrap_or_default();
+        for (name, &(player_id, _)) in &checkpoint.players {
+            registry.register(name, player_id);
         }
 
-        assert!((data.len() % self.config.seq_length) == 0);
-        let n_features = data.len() / self.config.seq_length;
+        Ok(Exporter {
+            player_ids: checkpoint.players.values().map(|&(id, _)| id).collect(),
+            players: checkpoint.players,
+            player_count: checkpoint.player_count,
+            sequence_count: checkpoint.sequence_count,
+            batches: checkpoint.batches,
+            processed_files: checkpoint.processed_files,
+            file_ticks: checkpoint.file_ticks,
+            recording_starts: HashMap::new(),
+            deadline: None,
+            cancel: None,
+            file_timeout: None,
+            quota_reached: false,
+            progress: None,
+            metrics: None,
+            last_batch_bytes: 0,
+            summary: RunSummary::default(),
+            map_sequences: HashMap::new(),
+            registry,
+            maps: MapCatalog::default(),
+            records: RecordIndex::default(),
+            labels: LabelIndex::default(),
+            fingerprints: checkpoint.fingerprints,
+            outlier_bounds: checkpoint.outlier_bounds,
+            outlier_sample: Vec::new(),
+            meta_bytes: 0,
+            column_names,
+            folder_path: folder_path.clone(),
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            sink,
+            processed_log,
+            error_log,
+            hooks: Vec::new(),
+            resolutions: Vec::new(),
+            num_features,
+            config,
+        })
+    }
 
-        let data_array = Array2::from_shape_vec((n_features, self.config.seq_length), data)
-            .expect("shape mismatch while converting sequence to ndarray")
-            .reversed_axes(); // transpose to (seq_length, n_features)
+    /// bytes of a single exported sequence in sequences.h5
+    fn sequence_bytes(&self) -> u64 {
+        (self.config.seq_length * self.num_features * std::mem::size_of::<f32>()) as u64
+    }
 
-        data_array
+    /// Current size of the exported data in bytes, estimated for dry runs.
+    /// Sinks writing in the background lag behind, so the estimate of the sequences handed
+    /// to the sink is used if it is larger.
+    fn dataset_bytes(&self) -> u64 {
+        let estimate = self.sequence_count as u64 * self.sequence_bytes() + self.meta_bytes;
+        if self.config.dry_run {
+            estimate
+        } else {
+            self.sink.bytes_written().max(estimate)
+        }
     }
 
-    pub fn add_to_dataset(&mut self, sequences: &[Sequence]) {
-        let mut tick_data =
-            Array3::<f32>::zeros((sequences.len(), self.config.seq_length, self.num_features));
-        for (seq_index, seq) in sequences.iter().enumerate() {
+    /// Whether count more sequences fit into max_dataset_bytes next to the exported ones and
+    /// those held back in the outlier sample, which are exported regardless once the sample
+    /// is complete. Files are checked before dedup records their fingerprints, so a file cut
+    /// by the quota can be exported completely by a resumed run with a larger quota. Sets
+    /// quota_reached and counts the sequences as dropped if they don't fit.
+    fn fits_size_quota(&mut self, count: usize) -> bool {
+        let max_bytes = match self.config.max_dataset_bytes {
+            Some(max_bytes) if !self.config.dry_run => max_bytes,
+            _ => return true,
+        };
+
+        let held_bytes = (self.outlier_sample.len() + count) as u64 * self.sequence_bytes();
+        if self.dataset_bytes() + held_bytes <= max_bytes {
+            return true;
+        }
+        warn!(
+            "dataset size quota of {} bytes reached, {} sequences don't fit",
+            max_bytes, count
+        );
+        self.summary.count_dropped("size_quota", count);
+        self.quota_reached = true;
+        false
+    }
+
+    pub fn add_to_dataset(&mut self, sequences: &[Sequence]) -> Result<()> {
+        // everything exported of a sequence is computed from a single noisy copy of it
+        let noisy: Vec<Sequence>;
+        let sequences = match &self.config.privacy {
+            Some(privacy) => {
+                noisy = sequences
+                    .par_iter()
+                    .map_init(rand::thread_rng, |rng, seq| {
+                        privacy.perturb_sequence(seq, rng)
+                    })
+                    .collect();
+                &noisy[..]
+            }
+            None => sequences,
+        };
+        self.summary.sequences_kept += sequences.len();
+        let anomaly_scores: Vec<AnomalyScore> =
+            sequences.par_iter().map(AnomalyScore::new).collect();
+        let mut meta_rows = Vec::with_capacity(sequences.len());
+        for (seq, anomaly_score) in sequences.iter().zip(anomaly_scores) {
             // add new entry if player name is seen for first time
-            if !self.players.contains_key(&seq.player_name) {
-                // use current player count as id for player
-                self.players
-                    .insert(seq.player_name.clone(), (self.player_count, 0));
-                self.player_count += 1;
+            let player = self
+                .players
+                .entry(seq.player_name.clone())
+                .or_insert_with(|| {
+                    let player_id = self.registry.player_id(&seq.player_name);
+                    if self.player_ids.insert(player_id) {
+                        self.player_count += 1;
+                    }
+                    (player_id, 0)
+                });
+            if let Some(timeout_code) = &seq.timeout_code {
+                self.registry
+                    .add_timeout_code(&seq.player_name, timeout_code);
+            }
+            if let Some(auth) = &seq.auth {
+                self.registry.add_auth_name(&seq.player_name, &auth.name);
             }
-            let player = self.players.get_mut(&seq.player_name).unwrap();
 
             // increment player seq counts
             player.1 += 1;
 
-            let meta_csv = format!(
-                "{},{},\"{}\",{},{},{},{}",
-                self.sequence_count,
-                player.0, // player_id
-                seq.player_name,
-                seq.start_tick,
-                seq.tick_count,
-                seq.map_name,
-                seq.teehist_name
-            );
-
-            self.sequence_count += 1;
+            // cleaned sequences may hold an extra tick for the velocity, see source_ticks
+            let ticks = seq.tick_count.min(self.config.seq_length);
+            *self.file_ticks.entry(seq.teehist_name.clone()).or_insert(0) += ticks;
 
-            // we want to count the players, but dont actually save anything, so we skip here
-            if self.config.dry_run {
-                continue;
+            let map_info = self.maps.get(&seq.map_name);
+            let record = self.records.get(&seq.map_name, &seq.player_name);
+            let meta_row = MetaRow {
+                seq_id: self.sequence_count,
+                player_id: player.0,
+                player: seq.player_name.clone(),
+                start: seq.start_tick,
+                ticks,
+                map: seq.map_name.clone(),
+                teehist: seq.teehist_name.clone(),
+                timeout: seq.timeout_code.clone(),
+                finish_time: seq.finish_time,
+                anomaly_score: Some(anomaly_score.score()),
+                anomaly_flags: Some(anomaly_score.flags()),
+                map_width: map_info.size.map(|(width, _)| width),
+                map_height: map_info.size.map(|(_, height)| height),
+                map_stars: map_info.stars,
+                map_spawns: map_info.size.map(|_| map_info.spawn_points_csv()),
+                map_category: map_info.category.clone(),
+                map_points: map_info.points,
+                map_release: map_info
+                    .release
+                    .map(|release| release.format("%Y-%m-%d").to_string()),
+                record_time: record.map(|record| record.time),
+                record_rank: record.map(|record| record.rank),
+                record_finishers: record.map(|record| record.finishers),
+                recorded: self.recording_starts.get(&seq.teehist_name).map(|&start| {
+                    let recorded = tick_time(start, seq.start_tick);
+                    match &self.config.privacy {
+                        Some(privacy) => format_recorded(privacy.coarsen(recorded)),
+                        None => format_recorded(recorded),
+                    }
+                }),
+                weight: None,
+                labels: self.labels.get(seq, ticks),
+                auth_name: seq.auth.as_ref().map(|auth| auth.name.clone()),
+                auth_level: seq.auth.as_ref().map(|auth| auth.level),
+                session_id: seq.session_id.clone(),
+                session_seq: seq.session_id.as_ref().map(|_| seq.session_seq),
+                route_cells: self.config.routes.as_ref().map(|routes| {
+                    route::format_route_cells(&route::route_cells(seq, ticks, routes.cell_size))
+                }),
+                route: None,
+            };
+            if anomaly_score.score() >= anomaly::FLAG_THRESHOLD {
+                self.summary.sequences_flagged += 1;
             }
-            writeln!(self.meta_file.as_ref().unwrap(), "{}", meta_csv)
-                .expect("Failed to write to sequences.csv");
 
-            // add array2 representation of sequence
-            let sequence_ticks = self.sequence_to_tick_array(seq);
-            tick_data
-                .index_axis_mut(ndarray::Axis(0), seq_index)
-                .assign(&sequence_ticks);
+            self.sequence_count += 1;
+            *self.map_sequences.entry(seq.map_name.clone()).or_insert(0) += 1;
+            self.meta_bytes += meta_row.to_csv().len() as u64 + 1;
+            meta_rows.push(meta_row);
         }
 
+        // we want to count the players, but dont actually save anything in dry runs
         if self.config.dry_run {
-            return;
+            return Ok(());
         }
-
-        // Append ALL sequence ticks to seq_dataset
-        let seq_dataset = self.seq_dataset.as_ref().unwrap();
-        let current_size = seq_dataset.shape()[0];
-        let new_size = current_size + tick_data.shape()[0];
-        seq_dataset
-            .resize((new_size, self.config.seq_length, self.num_features))
-            .expect("Failed to resize dataset");
-        seq_dataset
-            .write_slice(&tick_data.view(), (current_size..new_size, .., ..))
-            .expect("Failed to write data");
+        self.sink.write_batch(sequences, &meta_rows)
     }
 
-    /// parse and export a batch of paths
+    /// Parse and export a batch of paths.
+    /// Files are parsed, converted and cleaned in parallel and exported one by one in the order
+    /// of batch_paths, so only the sequences of files in flight are held in memory.
+    /// Sequences of a file are exported ordered by cid and start tick.
     pub fn handle_batch(
         &mut self,
         batch_paths: &[PathBuf],
         parser_config: &ParserConfig,
         export_config: &ExportConfig,
-    ) {
-        // parse batch -> DDNetSequences
-        let mut sequence_batch = Vec::new();
-        for path in batch_paths {
-            let x = Extractor::get_ddnet_sequences(&path, &parser_config);
-            sequence_batch.extend(x);
-        }
-        info!("extracted {} ddnet sequences", sequence_batch.len());
-
-        // Convert DDNetSequence -> Sequence
-        let mut sequences: Vec<Sequence> = Vec::new();
-        while let Some(ddnet_seq) = sequence_batch.pop() {
-            let sequence = Sequence::from_ddnet_sequence(&ddnet_seq);
-
-            if sequence.tick_count > export_config.seq_length {
-                sequences.push(sequence);
+    ) -> Result<()> {
+        if self.quota_reached {
+            info!("dataset size quota reached, skipping remaining files");
+            return Ok(());
+        }
+
+        let deadline = self.deadline;
+        let limits = ParseLimits {
+            cancel: self.cancel.clone(),
+            timeout: self.file_timeout,
+        };
+        let files_progress = self
+            .progress
+            .as_ref()
+            .map(|progress| progress.files.clone());
+        let hash_files = self.processed_log.is_some();
+        let game_layers = self.maps.game_layers();
+        let resolution_configs: Vec<ExportConfig> = self
+            .resolutions
+            .iter()
+            .map(|resolution| resolution.config.clone())
+            .collect();
+        let clean_file = |path: &PathBuf| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+            let mut parsed_file = Extractor::parse_file_with(path, parser_config, &limits, &mut []);
+            // partially parsed files are re-parsed when resuming
+            if matches!(&parsed_file, Ok(ParsedFile { error: Some(error), .. }) if error.kind == "cancelled")
+            {
+                return None;
+            }
+            let hash = if hash_files {
+                file_hash(path).ok()
+            } else {
+                None
+            };
+            let mut ddnet_sequences = parsed_file
+                .as_mut()
+                .map(|parsed_file| mem::take(&mut parsed_file.sequences))
+                .unwrap_or_default();
+            let client_sessions = parsed_file
+                .as_ref()
+                .map(|parsed_file| parsed_file.sessions.as_slice())
+                .unwrap_or_default();
+            let ddnet_bytes = ddnet_sequences.iter().map(|s| s.memory_bytes()).sum();
+            let ddnet_count = ddnet_sequences.len();
+            let mut counts = RunSummary::default();
+            let cleaned = clean_sequences(
+                &mut ddnet_sequences,
+                client_sessions,
+                export_config,
+                &game_layers,
+                &mut counts,
+            );
+            let resolutions = resolution_configs
+                .iter()
+                .map(|config| {
+                    let mut counts = RunSummary::default();
+                    let cleaned = clean_sequences(
+                        &mut ddnet_sequences,
+                        client_sessions,
+                        config,
+                        &game_layers,
+                        &mut counts,
+                    );
+                    (cleaned, counts)
+                })
+                .collect();
+            if let Some(files_progress) = &files_progress {
+                files_progress.inc(1);
+            }
+            Some(CleanedFile {
+                parsed_file,
+                hash,
+                ddnet_bytes,
+                ddnet_count,
+                cleaned,
+                counts,
+                resolutions,
+            })
+        };
+
+        // files a resolution already exported before its run was interrupted
+        let resolutions_processed: Vec<HashSet<&PathBuf>> = self
+            .resolutions
+            .iter()
+            .map(|resolution| {
+                let processed: HashSet<&PathBuf> = resolution.processed_files.iter().collect();
+                batch_paths
+                    .iter()
+                    .filter(|path| processed.contains(path))
+                    .collect()
+            })
+            .collect();
+        let mut batch_processed_files = Vec::new();
+        let mut resolutions_files = vec![Vec::new(); self.resolutions.len()];
+        let mut batch_hashes = Vec::new();
+        let mut ddnet_count = 0;
+        let mut exported_count = 0;
+        let mut exported_ticks = 0;
+        self.last_batch_bytes = 0;
+        thread::scope(|scope| -> Result<()> {
+            // bounded, so parsing pauses while exporting lags behind
+            let (sender, receiver) = mpsc::sync_channel(rayon::current_num_threads() * 2);
+            scope.spawn(move || {
+                // stops early once the receiver is dropped
+                let _ = batch_paths
+                    .par_iter()
+                    .enumerate()
+                    .try_for_each_with(sender, |sender, (index, path)| {
+                        sender.send((index, clean_file(path))).map_err(|_| ())
+                    });
+            });
+
+            // files complete out of order, they are exported in the order of batch_paths
+            let mut pending = BTreeMap::new();
+            let mut next_index = 0;
+            for (index, cleaned_file) in receiver {
+                pending.insert(index, cleaned_file);
+                while let Some(cleaned_file) = pending.remove(&next_index) {
+                    let path = &batch_paths[next_index];
+                    next_index += 1;
+                    let Some(cleaned_file) = cleaned_file else {
+                        if self.cancelled() {
+                            info!("run cancelled, skipping remaining files");
+                        } else {
+                            info!("time budget expired, skipping remaining files");
+                        }
+                        return Ok(());
+                    };
+
+                    if let Some(hash) = cleaned_file.hash {
+                        batch_hashes.push((path.clone(), hash));
+                    }
+                    let parsed_file = cleaned_file.parsed_file.unwrap_or_else(|err| {
+                        warn!("path={:?} couldn't be parsed: {}", path, err);
+                        ParsedFile {
+                            error: Some(FileError::from(&err)),
+                            ..Default::default()
+                        }
+                    });
+                    self.last_batch_bytes += cleaned_file.ddnet_bytes;
+                    ddnet_count += cleaned_file.ddnet_count;
+
+                    let sequence_count = self.sequence_count;
+                    let (count, ticks) = self.export_file(
+                        path,
+                        &parsed_file,
+                        cleaned_file.cleaned,
+                        cleaned_file.counts,
+                        export_config,
+                    )?;
+                    exported_count += count;
+                    exported_ticks += ticks;
+                    self.record_metrics(
+                        &parsed_file,
+                        self.sequence_count - sequence_count,
+                        pending.len(),
+                    );
+                    // files cut by a quota stay pending, as do all files after them
+                    for (((resolution, (cleaned, counts)), processed), files) in self
+                        .resolutions
+                        .iter_mut()
+                        .zip(cleaned_file.resolutions)
+                        .zip(&resolutions_processed)
+                        .zip(&mut resolutions_files)
+                    {
+                        if !processed.contains(path) && !resolution.quota_reached {
+                            let config = resolution.config.clone();
+                            resolution.export_file(path, &parsed_file, cleaned, counts, &config)?;
+                            if !resolution.quota_reached {
+                                files.push(path.clone());
+                            }
+                        }
+                    }
+                    if self.quota_reached {
+                        info!("dataset size quota reached, leaving remaining files pending");
+                        return Ok(());
+                    }
+                    batch_processed_files.push(path.clone());
+                }
             }
+            Ok(())
+        })?;
+
+        // resolutions are checkpointed first, so they are never behind the files of the
+        // checkpoint of this exporter that resumed runs continue from
+        for (resolution, files) in self.resolutions.iter_mut().zip(resolutions_files) {
+            let config = resolution.config.clone();
+            let hashes: Vec<_> = batch_hashes
+                .iter()
+                .filter(|(path, _)| files.contains(path))
+                .cloned()
+                .collect();
+            resolution.finish_batch(files, &hashes, &config)?;
         }
-        info!("converted to {} sequences", sequences.len());
-        log_sequence_info(&sequences);
+        batch_hashes.retain(|(path, _)| batch_processed_files.contains(path));
+        let processed_count = batch_processed_files.len();
+        let (count, ticks) =
+            self.finish_batch(batch_processed_files, &batch_hashes, export_config)?;
+        info!(
+            "extracted {} ddnet sequences ({:.1} MB) from {} files",
+            ddnet_count,
+            self.last_batch_bytes as f64 / 1e6,
+            processed_count
+        );
+        log_sequence_info(exported_count + count, exported_ticks + ticks);
+        Ok(())
+    }
 
-        // Clean sequences
-        let cleaned_sequences: Vec<Sequence> = sequences
+    /// Export the cleaned sequences of a parsed file, see [`Exporter::handle_batch`].
+    /// Returns the amount of exported sequences and their ticks.
+    fn export_file(
+        &mut self,
+        path: &Path,
+        parsed_file: &ParsedFile,
+        cleaned: Result<Vec<Sequence>>,
+        counts: RunSummary,
+        export_config: &ExportConfig,
+    ) -> Result<(usize, usize)> {
+        if let Some(start_time) = parsed_file.start_time {
+            self.recording_starts
+                .insert(teehist_name(path).into(), start_time);
+        }
+        if let Some(error) = &parsed_file.error {
+            *self
+                .summary
+                .parse_errors
+                .entry(error.kind.to_string())
+                .or_insert(0) += 1;
+            self.log_parse_error(path, parsed_file, error)?;
+        }
+        self.summary.add_file_counts(counts);
+
+        let sequences = self.sample_and_hook(cleaned?, export_config);
+        // counted before dedup and outlier removal, which may only drop sequences
+        if !self.fits_size_quota(sequences.len()) {
+            return Ok((0, 0));
+        }
+        let sequences = self.remove_duplicates(sequences, export_config);
+        let sequences = self.remove_outliers(sequences, export_config);
+        let ticks = sequences
             .iter()
-            .flat_map(|sequence| {
-                let durations = Duration::get_non_afk_durations(sequence, export_config.afk_ticks);
-                let durations = Duration::pad_durations(
-                    durations,
-                    sequence.tick_count - 1,
-                    export_config.afk_padding,
-                );
-                let durations: Vec<Duration> = durations
+            .map(|s| s.tick_count.min(export_config.seq_length))
+            .sum::<usize>();
+        self.add_to_dataset(&sequences)?;
+        Ok((sequences.len(), ticks))
+    }
+
+    /// Export the outlier sample if complete and checkpoint the processed files of a batch.
+    /// Returns the amount of sequences and ticks of the sample.
+    fn finish_batch(
+        &mut self,
+        processed_files: Vec<PathBuf>,
+        hashes: &[(PathBuf, String)],
+        export_config: &ExportConfig,
+    ) -> Result<(usize, usize)> {
+        let mut exported = (0, 0);
+        // the sample is exported with its batch, so checkpoints never miss sequences
+        if !self.outlier_sample.is_empty() {
+            let sequences = self.finish_outlier_sample(export_config);
+            exported = (
+                sequences.len(),
+                sequences
                     .iter()
-                    .flat_map(|duration| duration.cut_duration(export_config.seq_length))
+                    .map(|s| s.tick_count.min(export_config.seq_length))
+                    .sum::<usize>(),
+            );
+            let sequence_count = self.sequence_count;
+            self.add_to_dataset(&sequences)?;
+            if let Some(metrics) = &self.metrics {
+                metrics.sequences_exported.fetch_add(
+                    (self.sequence_count - sequence_count) as u64,
+                    Ordering::Relaxed,
+                );
+            }
+        }
+
+        self.recording_starts.clear();
+        self.summary.files_processed += processed_files.len();
+        self.processed_files.extend(processed_files);
+        self.write_checkpoint()?;
+        self.log_processed(hashes)?;
+        if let Some(progress) = &self.progress {
+            progress.set_exported(self.sequence_count, self.dataset_bytes());
+        }
+        Ok(exported)
+    }
+
+    /// Drop sequences whose fingerprint was exported before, in this or the resumed run
+    fn remove_duplicates(
+        &mut self,
+        sequences: Vec<Sequence>,
+        export_config: &ExportConfig,
+    ) -> Vec<Sequence> {
+        let Some(mode) = export_config.dedup else {
+            return sequences;
+        };
+        let fingerprints: Vec<u64> = sequences
+            .par_iter()
+            .map(|sequence| dedup::fingerprint(sequence, export_config, mode))
+            .collect();
+        let sequence_count = sequences.len();
+        let mut unique = Vec::with_capacity(sequence_count);
+        for (sequence, fingerprint) in sequences.into_iter().zip(fingerprints) {
+            match self.fingerprints.get(&fingerprint) {
+                Some(player) if *player == sequence.player_name => {
+                    self.summary.duplicates_same_player += 1
+                }
+                Some(_) => self.summary.duplicates_other_player += 1,
+                None => {
+                    self.fingerprints
+                        .insert(fingerprint, sequence.player_name.clone());
+                    unique.push(sequence);
+                }
+            }
+        }
+        self.summary
+            .count_dropped("duplicate", sequence_count - unique.len());
+        unique
+    }
+
+    /// Drop or clip outliers. Until the bounds are known sequences are collected as sample
+    /// and nothing is returned.
+    fn remove_outliers(
+        &mut self,
+        sequences: Vec<Sequence>,
+        export_config: &ExportConfig,
+    ) -> Vec<Sequence> {
+        if export_config.outlier_quantile.is_none() {
+            return sequences;
+        }
+        let Some(bounds) = &self.outlier_bounds else {
+            self.outlier_sample.extend(sequences);
+            if self.outlier_sample.len() < export_config.outlier_sample {
+                return Vec::new();
+            }
+            return self.finish_outlier_sample(export_config);
+        };
+
+        if export_config.clip_outliers {
+            let mut sequences = sequences;
+            let clipped = sequences
+                .par_iter_mut()
+                .map(|sequence| bounds.clip(sequence, export_config))
+                .filter(|&clipped| clipped)
+                .count();
+            self.summary.sequences_clipped += clipped;
+            sequences
+        } else {
+            let sequence_count = sequences.len();
+            let kept: Vec<Sequence> = sequences
+                .into_par_iter()
+                .filter(|sequence| !bounds.is_outlier(sequence, export_config))
+                .collect();
+            self.summary
+                .count_dropped("outlier", sequence_count - kept.len());
+            kept
+        }
+    }
+
+    /// Compute the outlier bounds from the sample collected so far, returns the sample
+    /// without its outliers
+    fn finish_outlier_sample(&mut self, export_config: &ExportConfig) -> Vec<Sequence> {
+        let (Some(quantile), None) = (export_config.outlier_quantile, &self.outlier_bounds) else {
+            return Vec::new();
+        };
+        let sample = mem::take(&mut self.outlier_sample);
+        if sample.is_empty() {
+            return sample;
+        }
+        let bounds = OutlierBounds::from_sample(&sample, export_config, quantile);
+        info!(
+            "outlier bounds of {} sequences: {:?}",
+            bounds.sample_size, bounds.bounds
+        );
+        self.outlier_bounds = Some(bounds);
+        self.remove_outliers(sample, export_config)
+    }
+
+    /// count an exported file and its sequences in metrics, if set
+    fn record_metrics(&self, parsed_file: &ParsedFile, exported: usize, export_queue: usize) {
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+        metrics.files_processed.fetch_add(1, Ordering::Relaxed);
+        let _ =
+            metrics
+                .files_pending
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pending| {
+                    pending.checked_sub(1)
+                });
+        metrics
+            .ticks_parsed
+            .fetch_add(parsed_file.ticks.max(0) as u64, Ordering::Relaxed);
+        metrics
+            .sequences_exported
+            .fetch_add(exported as u64, Ordering::Relaxed);
+        if let Some(error) = &parsed_file.error {
+            metrics.add_parse_error(error.kind);
+        }
+        metrics
+            .bytes_written
+            .store(self.dataset_bytes(), Ordering::Relaxed);
+        metrics
+            .export_queue
+            .store(export_queue as u64, Ordering::Relaxed);
+        metrics
+            .sink_queue
+            .store(self.sink.queued_batches(), Ordering::Relaxed);
+    }
+
+    /// Randomly subsample the cleaned sequences of a file and apply the hooks
+    fn sample_and_hook(
+        &mut self,
+        cleaned_sequences: Vec<Sequence>,
+        export_config: &ExportConfig,
+    ) -> Vec<Sequence> {
+        let cleaned_sequences = match export_config.sample_fraction {
+            Some(fraction) => {
+                let cleaned_count = cleaned_sequences.len();
+                let sampled_sequences: Vec<Sequence> = cleaned_sequences
+                    .into_iter()
+                    .filter(|_| self.rng.gen_bool(fraction))
                     .collect();
-                Duration::extract_sub_sequences(sequence, durations)
+                self.summary
+                    .count_dropped("sampling", cleaned_count - sampled_sequences.len());
+                sampled_sequences
+            }
+            None => cleaned_sequences,
+        };
+
+        // user hooks, each may drop or replace a sequence
+        if self.hooks.is_empty() {
+            return cleaned_sequences;
+        }
+        let cleaned_count = cleaned_sequences.len();
+        let hooked_sequences: Vec<Sequence> = cleaned_sequences
+            .into_iter()
+            .filter_map(|sequence| {
+                self.hooks
+                    .iter()
+                    .try_fold(sequence, |sequence, hook| hook(&sequence))
             })
             .collect();
-        info!("cleaned gameplay sequences:");
-        log_sequence_info(&cleaned_sequences);
+        self.summary
+            .count_dropped("hook", cleaned_count - hooked_sequences.len());
+        hooked_sequences
+    }
+
+    /// Register a hook that runs after cleaning, bot filtering and sampling.
+    /// Hooks run in order of registration, a sequence dropped by one hook skips the rest.
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&Sequence) -> Option<Sequence> + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// whether the deadline of a time-budgeted run has passed
+    pub fn budget_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// whether the run was cancelled through [`Exporter::cancel`]
+    pub fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    /// Flush outputs and persist the current state to checkpoint.json and progress.json.
+    /// Both are written to a temporary file first, so a kill mid-write keeps the previous
+    /// checkpoint, and only after the flushed outputs are synced to disk.
+    fn write_checkpoint(&mut self) -> Result<()> {
+        if self.config.dry_run {
+            return Ok(());
+        }
+        self.sink.flush()?;
+        self.sink.check_count(self.sequence_count)?;
+        self.batches += 1;
+
+        let checkpoint = Checkpoint {
+            processed_files: self.processed_files.clone(),
+            players: self.players.clone(),
+            player_count: self.player_count,
+            sequence_count: self.sequence_count,
+            file_ticks: self.file_ticks.clone(),
+            fingerprints: self.fingerprints.clone(),
+            outlier_bounds: self.outlier_bounds.clone(),
+            batches: self.batches,
+        };
+        // the registry holds at least the players of the checkpoint
+        self.registry.save(&self.folder_path.join(REGISTRY_FILE))?;
+        write_synced(
+            &self.folder_path.join(CHECKPOINT_FILE),
+            &serde_json::to_vec(&checkpoint)?,
+        )?;
+
+        let progress = Progress {
+            batches: self.batches,
+            processed_files: self.processed_files.len(),
+            sequence_count: self.sequence_count,
+            updated: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        write_synced(
+            &self.folder_path.join(PROGRESS_FILE),
+            &serde_json::to_vec_pretty(&progress)?,
+        )?;
+        Ok(())
+    }
+
+    /// append a row to parse_errors.csv
+    fn log_parse_error(
+        &mut self,
+        path: &Path,
+        parsed_file: &ParsedFile,
+        error: &FileError,
+    ) -> Result<()> {
+        let Some(error_log) = self.error_log.as_mut() else {
+            return Ok(());
+        };
+        writeln!(
+            error_log,
+            "\"{}\",\"{}\",{},{},{},{},\"{}\"",
+            path.to_string_lossy(),
+            parsed_file
+                .server_name
+                .as_deref()
+                .unwrap_or_default()
+                .replace('"', "\"\""),
+            error.chunk_index,
+            error.tick,
+            error.kind,
+            parsed_file.sequences.len(),
+            error.message.replace('"', "\"\"")
+        )?;
+        error_log.flush()?;
+        Ok(())
+    }
+
+    /// Append ingested files to processed.jsonl.
+    /// Called after the checkpoint, so a logged file is always part of the resumable dataset.
+    fn log_processed(&mut self, hashes: &[(PathBuf, String)]) -> Result<()> {
+        let Some(processed_log) = self.processed_log.as_mut() else {
+            return Ok(());
+        };
+        for (path, hash) in hashes {
+            let entry = ProcessedEntry {
+                hash: hash.clone(),
+                path: path.clone(),
+                ticks: *self
+                    .file_ticks
+                    .get(teehist_name(path).as_str())
+                    .unwrap_or(&0),
+            };
+            let line = serde_json::to_string(&entry)?;
+            writeln!(processed_log, "{}", line)?;
+        }
+        processed_log.flush()?;
+        Ok(())
+    }
 
-        self.add_to_dataset(&cleaned_sequences);
+    /// Flush all outputs and write ledger.csv, manifest.json and SHA256SUMS.
+    /// Leaves a valid dataset behind, also when the run was stopped early.
+    pub fn finalize(&mut self, all_paths: &[PathBuf]) -> Result<()> {
+        if self.config.dry_run {
+            return Ok(());
+        }
+
+        for resolution in &mut self.resolutions {
+            resolution.finalize(all_paths)?;
+        }
+        self.sink.finalize()?;
+        self.sink.check_count(self.sequence_count)?;
+
+        self.write_ledger(all_paths)?;
+
+        let stop_reason = if self.quota_reached {
+            "size_quota"
+        } else if self.budget_expired() {
+            "time_budget"
+        } else if self.cancelled() {
+            "cancelled"
+        } else {
+            "completed"
+        };
+        let manifest = Manifest {
+            schema_version: DATASET_VERSION,
+            config_version: CONFIG_VERSION,
+            sequence_count: self.sequence_count,
+            player_count: self.player_count,
+            seq_length: self.config.seq_length,
+            column_names: &self.column_names,
+            processed_files: self.processed_files.len(),
+            stop_reason,
+            smoothing: &self.config.smoothing,
+            phases: self.config.phase_labels.then(Phase::names),
+            sample_weights: &self.config.sample_weights,
+            embedding_names: self.config.embeddings.then(embedding::embedding_names),
+            privacy: self.config.privacy.as_ref(),
+            noise_scales: self
+                .config
+                .privacy
+                .as_ref()
+                .map(PrivacyNoise::noise_scales)
+                .unwrap_or_default(),
+            routes: self.config.routes.as_ref(),
+        };
+        let manifest_file = File::create(self.folder_path.join("manifest.json"))?;
+        serde_json::to_writer_pretty(manifest_file, &manifest)?;
+
+        self.summary.players = self.player_count;
+        self.summary.outlier_bounds = self.outlier_bounds.clone();
+        self.summary.total_ticks = self.file_ticks.values().sum();
+        let summary_file = File::create(self.folder_path.join("summary.json"))?;
+        serde_json::to_writer_pretty(summary_file, &self.summary)?;
+        self.registry.save(&self.folder_path.join(REGISTRY_FILE))?;
+        if !self.config.sample_weights.is_empty() && self.folder_path.join("meta.csv").is_file() {
+            weights::write_sample_weights(&self.folder_path, &self.config.sample_weights)?;
+        }
+        if let Some(routes) = &self.config.routes {
+            if self.folder_path.join("meta.csv").is_file() {
+                route::write_routes(&self.folder_path, routes.max_distance)?;
+            }
+        }
+        checksum::write_checksums(&self.folder_path)?;
+        Ok(())
+    }
+
+    /// Write ledger.csv listing every input path as processed or pending, so an
+    /// interrupted or time-budgeted run can be continued with the pending files.
+    /// Processed files also record their exported ticks, see [`crate::index::load_ledger_yields`].
+    fn write_ledger(&self, all_paths: &[PathBuf]) -> Result<()> {
+        let mut ledger_file = File::create(self.folder_path.join("ledger.csv"))?;
+        writeln!(ledger_file, "path,status,ticks")?;
+        let processed: HashSet<&PathBuf> = self.processed_files.iter().collect();
+        for path in all_paths {
+            let (status, ticks) = if processed.contains(path) {
+                let ticks = self
+                    .file_ticks
+                    .get(teehist_name(path).as_str())
+                    .unwrap_or(&0);
+                ("processed", ticks.to_string())
+            } else {
+                ("pending", String::new())
+            };
+            writeln!(
+                ledger_file,
+                "\"{}\",{},{}",
+                path.to_string_lossy(),
+                status,
+                ticks
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Report the expected output of a dry run, extrapolated by scale if only a sample of
+    /// the input files was processed.
+    pub fn print_dry_run_estimate(&self, scale: f64, elapsed: std::time::Duration, k: usize) {
+        let sequences = self.sequence_count as f64 * scale;
+        let sequence_bytes = sequences * self.sequence_bytes() as f64;
+        let meta_bytes = self.meta_bytes as f64 * scale;
+        info!(
+            "estimated output: {:.0} sequences x {} ticks x {} features x {} bytes",
+            sequences,
+            self.config.seq_length,
+            self.num_features,
+            std::mem::size_of::<f32>()
+        );
+        info!(
+            "estimated size: sequences.h5 {:.2} GB, meta.csv {:.2} MB",
+            sequence_bytes / 1e9,
+            meta_bytes / 1e6
+        );
+        info!(
+            "estimated duration: {}",
+            humantime::format_duration(std::time::Duration::from_secs_f64(
+                elapsed.as_secs_f64() * scale
+            ))
+        );
+
+        let mut maps: Vec<_> = self.map_sequences.iter().collect();
+        maps.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        info!("Top {} of {} Maps:", k, maps.len());
+        for (map_name, count) in maps.iter().take(k) {
+            info!("Map: {}, Count: {:.0}", map_name, **count as f64 * scale);
+        }
     }
 
     pub fn print_summary(&self, k: usize) {
         info!("unique players: {}", self.players.len());
 
         let mut players: Vec<_> = self.players.iter().collect();
-        players.sort_by(|a, b| b.1 .1.cmp(&a.1 .1));
+        players.sort_by_key(|(_, (_, count))| std::cmp::Reverse(*count));
 
         info!("Top {} Players:", k);
         for (name, (id, count)) in players.iter().take(k) {
@@ -374,5 +1999,13 @@ impl Exporter {
             .join(",");
 
         info!("top-k names: '{}'", top_names);
+
+        if self.outlier_bounds.is_some() {
+            info!(
+                "outliers: {} dropped, {} clipped",
+                self.summary.sequences_dropped.get("outlier").unwrap_or(&0),
+                self.summary.sequences_clipped
+            );
+        }
     }
 }
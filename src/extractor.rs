@@ -1,21 +1,45 @@
-use crate::parser::{DDNetSequence, Parser, ParserConfig};
+use crate::cancel::ParseLimits;
+use crate::error::{Error, Result};
+use crate::parser::{
+    start_info_name, Anomaly, ClientSession, DDNetSequence, GameInfo, ParseError, Parser,
+    ParserConfig, ParserEvents, PlayerAuth,
+};
+use crate::remote;
+use crate::tail::{GrowingFile, TailConfig};
+use chrono::{DateTime, Utc};
 use log::{debug, error, warn};
+use memmap2::Mmap;
 use serde::Serialize;
 use std::{
+    collections::HashSet,
     fs::{self, File},
-    path::PathBuf,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
-use teehistorian::{Th, ThBufReader};
+use teehistorian::{Chunk, Th, ThBufRead, ThBufReader};
 
 /// Simplified and more human-readible representation of DDNetSequences.
-#[derive(Serialize, Debug)]
+/// Names are shared with the other sequences of the same file.
+#[derive(Serialize, Debug, Clone)]
 pub struct Sequence {
     // sequence data
     pub start_tick: usize,
     pub tick_count: usize,
-    pub player_name: String,
-    pub map_name: String,
-    pub teehist_name: String,
+    /// client id of the player in the teehistorian file
+    pub cid: i32,
+    pub player_name: Arc<str>,
+    pub timeout_code: Option<String>,
+    /// account the player was logged into, a more reliable identity than the player name
+    pub auth: Option<PlayerAuth>,
+    pub finish_time: Option<i32>,
+    pub map_name: Arc<str>,
+    pub teehist_name: Arc<str>,
+    /// play session of the sequence, see [`crate::session`]. None until the export assigns it.
+    pub session_id: Option<Arc<str>>,
+    /// position of the sequence in its session, starting at 0
+    pub session_seq: usize,
 
     // tick data
     pub pos_x: Vec<i32>,
@@ -26,21 +50,48 @@ pub struct Sequence {
     pub jump: Vec<bool>,
     pub fire: Vec<bool>,
     pub hook: Vec<bool>,
+    /// whether the player was frozen, see [`crate::preprocess::frozen_ticks`].
+    /// Empty unless the export detects freeze.
+    pub frozen: Vec<bool>,
+    /// whether the tick is gameplay, false in afk and cut freeze stretches.
+    /// Empty unless the export masks them instead of cutting them out.
+    pub active: Vec<bool>,
 }
 
 impl Sequence {
-    pub fn from_ddnet_sequence(ddnet_sequence: &DDNetSequence) -> Sequence {
+    pub fn from_ddnet_sequence(ddnet_sequence: &DDNetSequence) -> Result<Sequence> {
+        let invalid = |reason: &str| {
+            Error::InvalidSequence(format!(
+                "cid={} start_tick={}: {}",
+                ddnet_sequence.cid, ddnet_sequence.start_tick, reason
+            ))
+        };
         let start_tick = ddnet_sequence.start_tick as usize;
         let end_tick = ddnet_sequence
             .end_tick
-            .expect("ddnet sequence has no end tick") as usize;
-        let tick_count = end_tick - start_tick;
+            .ok_or_else(|| invalid("no end tick"))? as usize;
+        let tick_count = end_tick
+            .checked_sub(start_tick)
+            .ok_or_else(|| invalid("end tick before start tick"))?;
 
         // Sanity checks
-        assert!(tick_count == ddnet_sequence.input_vectors.len());
-        assert!(tick_count == ddnet_sequence.player_positions.len());
-        assert!(ddnet_sequence.player_name.is_some());
-        assert!(ddnet_sequence.teehist_path.is_some());
+        if tick_count != ddnet_sequence.input_vectors.len()
+            || tick_count != ddnet_sequence.player_positions.len()
+        {
+            return Err(invalid("tick count doesn't match tick data"));
+        }
+        let player_name = ddnet_sequence
+            .player_name
+            .clone()
+            .ok_or_else(|| invalid("no player name"))?;
+        let map_name = ddnet_sequence
+            .map_name
+            .clone()
+            .ok_or_else(|| invalid("no map name"))?;
+        let teehist_name = ddnet_sequence
+            .teehist_path
+            .clone()
+            .ok_or_else(|| invalid("no teehistorian path"))?;
 
         // prepare vecs for all tick data
         let mut pos_x = Vec::with_capacity(tick_count);
@@ -67,9 +118,10 @@ impl Sequence {
             hook.push(input_vector[5] == 1);
         }
 
-        Sequence {
+        Ok(Sequence {
             start_tick,
             tick_count,
+            cid: ddnet_sequence.cid,
             pos_x,
             pos_y,
             move_dir,
@@ -78,10 +130,17 @@ impl Sequence {
             jump,
             fire,
             hook,
-            player_name: ddnet_sequence.player_name.clone().unwrap(),
-            map_name: ddnet_sequence.map_name.clone().unwrap(),
-            teehist_name: ddnet_sequence.teehist_path.clone().unwrap(),
-        }
+            frozen: Vec::new(),
+            active: Vec::new(),
+            player_name,
+            timeout_code: ddnet_sequence.timeout_code.clone(),
+            auth: ddnet_sequence.auth.clone(),
+            finish_time: ddnet_sequence.finish_time,
+            map_name,
+            teehist_name,
+            session_id: None,
+            session_seq: 0,
+        })
     }
 
     // pub fn meta_to_csv(&self) -> String {
@@ -92,48 +151,499 @@ impl Sequence {
     // }
 }
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Open a teehistorian file for reading.
+/// gzip and zstd compressed files are detected by their magic bytes and decompressed on the fly.
+/// Remote urls are streamed, see [`crate::remote`].
+pub fn open_teehistorian(path: &Path) -> io::Result<Box<dyn Read>> {
+    let file: Box<dyn Read> = match remote::is_remote(path) {
+        true => remote::open(path)?,
+        false => Box::new(File::open(path)?),
+    };
+    let mut reader = BufReader::new(file);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+    } else if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// File name without compression suffix and extension, e.g. "abc" for "abc.teehistorian.zst".
+/// Used to refer to the same recording regardless of whether it is stored compressed.
+pub fn teehist_name(path: &Path) -> String {
+    let path = match path.extension().and_then(|e| e.to_str()) {
+        Some("zst" | "gz") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether the file name ends with one of the extensions, "*" accepts any file.
+/// Extensions may contain dots, e.g. "teehistorian.zst".
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    extensions
+        .iter()
+        .any(|ext| ext == "*" || file_name.ends_with(&format!(".{}", ext)))
+}
+
+/// recursively collect all files below dir with a matching extension
+fn collect_dir(dir: &Path, extensions: &[String], paths: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("couldn't read directory {:?}: {}", dir, err);
+            return;
+        }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_dir(&path, extensions, paths);
+        } else if has_extension(&path, extensions) {
+            paths.push(path);
+        }
+    }
+}
+
+/// error that stopped parsing a file early
+#[derive(Debug, Clone)]
+pub struct FileError {
+    /// short identifier, see [`Error::kind`]
+    pub kind: &'static str,
+    pub message: String,
+    pub chunk_index: u32,
+    pub tick: i32,
+}
+
+impl From<&Error> for FileError {
+    /// error of a file that couldn't be parsed at all
+    fn from(err: &Error) -> Self {
+        FileError {
+            kind: err.kind(),
+            message: err.to_string(),
+            chunk_index: 0,
+            tick: 0,
+        }
+    }
+}
+
+/// sequences of a single teehistorian file and the error that stopped parsing early
+#[derive(Default)]
+pub struct ParsedFile {
+    pub sequences: Vec<DDNetSequence>,
+    pub error: Option<FileError>,
+    pub server_name: Option<String>,
+    /// start of the recording from the header
+    pub start_time: Option<DateTime<Utc>>,
+    /// amount of parsed ticks
+    pub ticks: i32,
+    pub sessions: Vec<ClientSession>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// Open a teehistorian file and parse its header.
+/// None if the map of the file is filtered out by the config.
+fn open_parser<'a>(path: &Path, config: &ParserConfig) -> Result<Option<(ThReader, Parser<'a>)>> {
+    open_parser_with(path, ThSource::open(path, config.mmap)?, config)
+}
+
+/// Like [`open_parser`], reading from the given source
+fn open_parser_with<'a>(
+    path: &Path,
+    source: ThSource,
+    config: &ParserConfig,
+) -> Result<Option<(ThReader, Parser<'a>)>> {
+    let mut th = Th::parse(source)?;
+
+    let header_bytes = th
+        .header()
+        .map_err(|err| ParseError::Header(err.to_string()))?;
+
+    let mut parser = Parser::new(config.clone());
+    parser.parse_header(header_bytes)?;
+
+    // skip files of unwanted maps before parsing any chunks
+    let map_name = parser.map_name().unwrap_or_default();
+    if !config.is_map_included(map_name) {
+        debug!("skipping path={:?}, map={} is filtered out", path, map_name);
+        return Ok(None);
+    }
+    Ok(Some((th, parser)))
+}
+
+/// Input of the parser, a possibly decompressing stream, a memory mapped file or a file that
+/// is still being written
+enum ThSource {
+    Buffered(ThBufReader<Box<dyn Read>>),
+    Mapped { mmap: Mmap, pos: usize },
+    Growing(GrowingFile),
+}
+
+impl ThSource {
+    /// Open a teehistorian file, uncompressed local files are memory mapped if mmap is set
+    fn open(path: &Path, mmap: bool) -> io::Result<ThSource> {
+        if mmap && !remote::is_remote(path) {
+            let file = File::open(path)?;
+            // SAFETY: see ParserConfig::mmap, input files must not change while being parsed
+            let mmap = unsafe { Mmap::map(&file)? };
+            if !mmap.starts_with(&ZSTD_MAGIC) && !mmap.starts_with(&GZIP_MAGIC) {
+                return Ok(ThSource::Mapped { mmap, pos: 0 });
+            }
+        }
+        Ok(ThSource::Buffered(ThBufReader::new(open_teehistorian(
+            path,
+        )?)))
+    }
+}
+
+impl ThBufRead for ThSource {
+    fn get_buf(&self) -> &[u8] {
+        match self {
+            ThSource::Buffered(reader) => reader.get_buf(),
+            ThSource::Mapped { mmap, pos } => &mmap[*pos..],
+            ThSource::Growing(file) => file.get_buf(),
+        }
+    }
+
+    fn fill_buf(&mut self) -> io::Result<usize> {
+        match self {
+            ThSource::Buffered(reader) => reader.fill_buf(),
+            // the whole file is mapped already
+            ThSource::Mapped { .. } => Ok(0),
+            ThSource::Growing(file) => file.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self {
+            ThSource::Buffered(reader) => reader.consume(amount),
+            ThSource::Mapped { pos, .. } => *pos += amount,
+            ThSource::Growing(file) => file.consume(amount),
+        }
+    }
+}
+
+type ThReader = Th<ThSource>;
+
+/// Sequences of a single teehistorian file, yielded as soon as they are completed.
+/// Created by [`Extractor::sequence_iter`] and [`Extractor::tail_sequences`].
+///
+/// Sequences completed before an error are yielded first, the error is the last item.
+/// Only completed sequences are released early, the parser still keeps the tick history of
+/// the file until it is dropped.
+pub struct SequenceIter {
+    /// None once the file is exhausted or parsing stopped
+    th: Option<ThReader>,
+    parser: Option<Parser<'static>>,
+    teehist_name: Arc<str>,
+    error: Option<Error>,
+    /// tailed files end once the server stops writing, without eos chunk
+    tailing: bool,
+}
+
+impl SequenceIter {
+    fn open(path: &Path, config: &ParserConfig) -> SequenceIter {
+        SequenceIter::new(path, open_parser(path, config), false)
+    }
+
+    fn tail(path: &Path, config: &ParserConfig, tail_config: TailConfig) -> SequenceIter {
+        let opened = GrowingFile::open(path, tail_config)
+            .map_err(Error::from)
+            .and_then(|file| open_parser_with(path, ThSource::Growing(file), config));
+        SequenceIter::new(path, opened, true)
+    }
+
+    fn new(
+        path: &Path,
+        opened: Result<Option<(ThReader, Parser<'static>)>>,
+        tailing: bool,
+    ) -> SequenceIter {
+        let (th, parser, error) = match opened {
+            Ok(Some((th, parser))) => (Some(th), Some(parser), None),
+            Ok(None) => (None, None, None),
+            Err(err) => (None, None, Some(err)),
+        };
+        SequenceIter {
+            th,
+            parser,
+            teehist_name: teehist_name(path).into(),
+            error,
+            tailing,
+        }
+    }
+
+    /// Register event callbacks, e.g. to compute features of every tick as it is parsed
+    /// instead of waiting for completed sequences. Nothing is called for filtered out maps.
+    pub fn add_events(&mut self, events: impl ParserEvents + 'static) {
+        if let Some(parser) = self.parser.as_mut() {
+            parser.add_events(events);
+        }
+    }
+
+    /// index of the last parsed tick
+    pub fn tick(&self) -> i32 {
+        self.parser.as_ref().map_or(0, |parser| parser.tick_index)
+    }
+}
+
+impl Iterator for SequenceIter {
+    type Item = Result<Sequence>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let parser = self.parser.as_mut();
+            if let Some(parser) = parser.filter(|p| !p.completed_sequences.is_empty()) {
+                let mut ddnet_seq = parser.completed_sequences.remove(0);
+                ddnet_seq.teehist_path = Some(self.teehist_name.clone());
+                return Some(Sequence::from_ddnet_sequence(&ddnet_seq));
+            }
+            if let Some(err) = self.error.take() {
+                return Some(Err(err));
+            }
+
+            let (th, parser) = (self.th.as_mut()?, self.parser.as_mut()?);
+            // like parse_file, truncated or corrupt files end with an error
+            let status = match th.next_chunk() {
+                Ok(chunk) => {
+                    let eos = matches!(chunk, Chunk::Eos);
+                    parser.parse_chunk(chunk).map(|_| eos).map_err(Error::from)
+                }
+                Err(err) if err.is_eof() && self.tailing => Ok(true),
+                Err(err) => Err(Error::from(err)),
+            };
+            match status {
+                Ok(false) => {}
+                Ok(true) => self.th = None,
+                Err(err) => {
+                    self.th = None;
+                    self.error = Some(err);
+                }
+            }
+        }
+    }
+}
+
 pub struct Extractor;
 impl Extractor {
+    /// Resolve input arguments to a sorted list of teehistorian files.
+    /// Each input can be a file, a directory that is traversed recursively, a glob pattern,
+    /// an http(s) url, an `s3://bucket/prefix` url listing all objects below the prefix, or
+    /// `@file` with one input per line.
+    /// Files found in directories, by globs or below s3 prefixes are filtered by extension,
+    /// explicitly listed files and urls are always included.
+    pub fn collect_input_paths(inputs: &[PathBuf], extensions: &[String]) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for input in inputs {
+            let input_str = input.to_string_lossy();
+            if let Some(list_path) = input_str.strip_prefix('@') {
+                match fs::read_to_string(list_path) {
+                    Ok(list) => {
+                        let listed: Vec<PathBuf> = list
+                            .lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(PathBuf::from)
+                            .collect();
+                        paths.extend(Extractor::collect_input_paths(&listed, extensions));
+                    }
+                    Err(err) => error!("couldn't read input list {:?}: {}", list_path, err),
+                }
+            } else if remote::parse_s3_url(&input_str).is_some() {
+                // a prefix naming a single object lists just that object
+                match remote::list_s3(&input_str) {
+                    Ok(objects) => paths.extend(
+                        objects
+                            .into_iter()
+                            .filter(|object| object == input || has_extension(object, extensions)),
+                    ),
+                    Err(err) => error!("couldn't list {:?}: {}", input_str, err),
+                }
+            } else if remote::is_remote(input) || input.is_file() {
+                paths.push(input.clone());
+            } else if input.is_dir() {
+                collect_dir(input, extensions, &mut paths);
+            } else if input_str.contains(['*', '?', '[']) {
+                match glob::glob(&input_str) {
+                    Ok(matches) => {
+                        for path in matches.filter_map(|m| m.ok()) {
+                            if path.is_dir() {
+                                collect_dir(&path, extensions, &mut paths);
+                            } else if has_extension(&path, extensions) {
+                                paths.push(path);
+                            }
+                        }
+                    }
+                    Err(err) => error!("invalid input pattern {:?}: {}", input_str, err),
+                }
+            } else {
+                warn!("input {:?} does not exist", input);
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
     /// Extract all sequences of all teehistorian files in the provided path.
     /// Can either be a folder or an individual teehistorian file.
-    pub fn get_all_ddnet_sequences(path: PathBuf, config: &ParserConfig) -> Vec<DDNetSequence> {
+    pub fn get_all_ddnet_sequences(
+        path: PathBuf,
+        config: &ParserConfig,
+    ) -> Result<Vec<DDNetSequence>> {
         let mut sequences: Vec<DDNetSequence> = Vec::new();
 
         if path.is_dir() {
-            for (file_index, entry) in fs::read_dir(path).unwrap().enumerate() {
-                let path = entry.unwrap().path();
+            for (file_index, entry) in fs::read_dir(path)?.enumerate() {
+                let path = entry?.path();
                 debug!(
                     "Parsing index={} name={}",
                     file_index,
                     path.to_string_lossy()
                 );
-                sequences.extend(Extractor::get_ddnet_sequences(&path, config));
+                sequences.extend(Extractor::get_ddnet_sequences(&path, config)?);
             }
         } else if path.is_file() {
             debug!("Parsing name={}", path.to_string_lossy());
-            sequences.extend(Extractor::get_ddnet_sequences(&path, config));
+            sequences.extend(Extractor::get_ddnet_sequences(&path, config)?);
         }
 
-        sequences
+        Ok(sequences)
+    }
+
+    /// Parse only the header of a teehistorian file, None if it can't be read
+    pub fn get_game_info(path: &Path) -> Option<GameInfo> {
+        let f = open_teehistorian(path).ok()?;
+        let mut th = Th::parse(ThBufReader::new(f)).ok()?;
+        let header_bytes = th.header().ok()?;
+        GameInfo::from_header_bytes(header_bytes).ok()
+    }
+
+    /// Names of all players that sent start info in a teehistorian file.
+    /// Only net messages are inspected, no game state is tracked.
+    pub fn get_player_names(path: &Path) -> HashSet<String> {
+        let mut player_names = HashSet::new();
+        let Ok(f) = open_teehistorian(path) else {
+            return player_names;
+        };
+        let Ok(mut th) = Th::parse(ThBufReader::new(f)) else {
+            return player_names;
+        };
+        if th.header().is_err() {
+            return player_names;
+        }
+        while let Ok(chunk) = th.next_chunk() {
+            match chunk {
+                Chunk::NetMessage(net_msg) => player_names.extend(start_info_name(&net_msg)),
+                Chunk::Eos => break,
+                _ => {}
+            }
+        }
+        player_names
+    }
+
+    /// Start time of a teehistorian file based on its header.
+    /// Falls back to the file modification time if the header has no valid start_time.
+    pub fn get_start_time(path: &PathBuf) -> Option<DateTime<Utc>> {
+        let header_time = Extractor::get_game_info(path).and_then(|g| g.start_time());
+
+        header_time.or_else(|| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from)
+        })
+    }
+
+    /// Stream the sequences of a single teehistorian file as they complete during parsing,
+    /// see [`SequenceIter`]. Files of maps filtered out by the config yield nothing.
+    pub fn sequence_iter(
+        path: &Path,
+        config: &ParserConfig,
+    ) -> impl Iterator<Item = Result<Sequence>> {
+        SequenceIter::open(path, config)
+    }
+
+    /// Like [`Extractor::sequence_iter`], but for a file that a running server is still
+    /// writing. Waits for new data at the end of the file until the end of stream chunk, the
+    /// idle timeout or cancellation. Sequences still active by then are dropped, like in
+    /// truncated files. Compressed files can't be tailed.
+    pub fn tail_sequences(
+        path: &Path,
+        config: &ParserConfig,
+        tail_config: TailConfig,
+    ) -> SequenceIter {
+        SequenceIter::tail(path, config, tail_config)
     }
 
     /// Extract ddnet sequences for a single teehistorian file
-    pub fn get_ddnet_sequences(path: &PathBuf, config: &ParserConfig) -> Vec<DDNetSequence> {
-        let f = File::open(&path).unwrap();
-        let mut th = Th::parse(ThBufReader::new(f)).unwrap();
+    pub fn get_ddnet_sequences(path: &Path, config: &ParserConfig) -> Result<Vec<DDNetSequence>> {
+        Ok(Extractor::parse_file(path, config)?.sequences)
+    }
+
+    /// Extract ddnet sequences for a single teehistorian file.
+    /// Files that can't be opened or have an invalid header are an error. Sequences completed
+    /// before a later parse error are kept, the error is reported in [`ParsedFile::error`].
+    pub fn parse_file(path: &Path, config: &ParserConfig) -> Result<ParsedFile> {
+        Extractor::parse_file_with(path, config, &ParseLimits::default(), &mut [])
+    }
 
-        let header_bytes = th.header();
-        if header_bytes.is_err() {
-            error!("coulnt parse header of file {:?}", path);
-            return Vec::new();
+    /// Like [`Extractor::parse_file`], but also passes every parser event to the callbacks.
+    /// Nothing is reported for maps that are filtered out by the config.
+    pub fn parse_file_with_events(
+        path: &Path,
+        config: &ParserConfig,
+        events: &mut [&mut dyn ParserEvents],
+    ) -> Result<ParsedFile> {
+        Extractor::parse_file_with(path, config, &ParseLimits::default(), events)
+    }
+
+    /// Like [`Extractor::parse_file_with_events`], but parsing stops early once a limit is hit.
+    /// The sequences completed until then are returned, the error kind is "cancelled" or
+    /// "timeout".
+    pub fn parse_file_with(
+        path: &Path,
+        config: &ParserConfig,
+        limits: &ParseLimits,
+        events: &mut [&mut dyn ParserEvents],
+    ) -> Result<ParsedFile> {
+        let started = Instant::now();
+        let Some((mut th, mut parser)) = open_parser(path, config)? else {
+            return Ok(ParsedFile::default());
+        };
+        for events in events.iter_mut() {
+            parser.add_events(events);
         }
 
-        let mut parser = Parser::new(config.clone());
-        parser.parse_header(header_bytes.unwrap());
-        while let Ok(chunk) = th.next_chunk() {
-            let parse_status = parser.parse_chunk(chunk);
+        let mut error = None;
+        loop {
+            let parse_status = match th.next_chunk() {
+                Ok(chunk) => limits
+                    .check(parser.chunk_index, started)
+                    .and_then(|_| parser.parse_chunk(chunk))
+                    .map_err(Error::from),
+                // complete files end after the eos chunk
+                Err(err) if err.is_eof() && parser.finished => break,
+                // truncated or corrupt files
+                Err(err) => Err(Error::from(err)),
+            };
 
             if let Err(err) = parse_status {
+                error = Some(FileError {
+                    kind: err.kind(),
+                    message: err.to_string(),
+                    chunk_index: parser.chunk_index,
+                    tick: parser.tick_index,
+                });
                 warn!(
                     "path={:?}\nerror={:}\nrecovering {:} completed sequences.",
                     path,
@@ -145,12 +655,21 @@ impl Extractor {
         }
 
         // add teehistorian file name to all extracted sequences
+        let teehist_name: Arc<str> = teehist_name(path).into();
         for ddnet_seq in parser.completed_sequences.iter_mut() {
-            ddnet_seq.teehist_path = path
-                .file_stem()
-                .and_then(|s| s.to_str().map(|str_val| str_val.to_string()));
+            ddnet_seq.teehist_path = Some(teehist_name.clone());
         }
 
-        parser.completed_sequences
+        let server_name = parser.server_name().map(str::to_string);
+        let start_time = parser.start_time();
+        Ok(ParsedFile {
+            sequences: parser.completed_sequences,
+            error,
+            server_name,
+            start_time,
+            ticks: parser.tick_index,
+            sessions: parser.sessions,
+            anomalies: parser.anomalies,
+        })
     }
 }
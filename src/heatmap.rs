@@ -0,0 +1,171 @@
+//! 2D histograms of player positions, to sanity-check extracted positions per map.
+//! Teleporter artifacts show up as isolated hot spots, misaligned positions as trails
+//! through walls.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::error::Result;
+use crate::extractor::Extractor;
+use crate::map_file::{GameLayer, TILE_DEATH, TILE_FREEZE, TILE_NOHOOK, TILE_SIZE, TILE_SOLID};
+use crate::parser::{ParserConfig, ParserEvents};
+use crate::png::RgbImage;
+use crate::tick::Tick;
+
+/// heatmaps without a map file are limited to this many pixels per side
+const MAX_SIDE: i64 = 4096;
+
+/// Ticks spent by players in each cell of cell_size x cell_size world units
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    pub cell_size: i32,
+    pub cells: HashMap<(i32, i32), u64>,
+}
+
+impl Heatmap {
+    pub fn new(cell_size: i32) -> Heatmap {
+        assert!(cell_size > 0, "cell_size must be positive");
+        Heatmap {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, x: i32, y: i32, ticks: u64) {
+        let cell = (x.div_euclid(self.cell_size), y.div_euclid(self.cell_size));
+        *self.cells.entry(cell).or_insert(0) += ticks;
+    }
+
+    pub fn merge(&mut self, other: Heatmap) {
+        for (cell, ticks) in other.cells {
+            *self.cells.entry(cell).or_insert(0) += ticks;
+        }
+    }
+
+    pub fn total_ticks(&self) -> u64 {
+        self.cells.values().sum()
+    }
+
+    /// Render with one pixel per cell and log-scaled colors.
+    /// With a game layer, the image covers the map and the collision tiles are drawn below
+    /// the heat. Otherwise it covers all visited cells. None if there is nothing to draw.
+    pub fn render(&self, game_layer: Option<&GameLayer>) -> Option<RgbImage> {
+        let (min, max) = match game_layer {
+            Some(layer) => {
+                let size = |tiles: usize| (tiles as i64 * TILE_SIZE as i64 - 1) as i32;
+                (
+                    (0, 0),
+                    (
+                        size(layer.width).div_euclid(self.cell_size),
+                        size(layer.height).div_euclid(self.cell_size),
+                    ),
+                )
+            }
+            None => {
+                let min_x = self.cells.keys().map(|c| c.0).min()?;
+                let min_y = self.cells.keys().map(|c| c.1).min()?;
+                let max_x = self.cells.keys().map(|c| c.0).max()?;
+                let max_y = self.cells.keys().map(|c| c.1).max()?;
+                (
+                    (min_x, min_y),
+                    (
+                        max_x.min((min_x as i64 + MAX_SIDE - 1) as i32),
+                        max_y.min((min_y as i64 + MAX_SIDE - 1) as i32),
+                    ),
+                )
+            }
+        };
+        let width = (max.0 as i64 - min.0 as i64 + 1) as usize;
+        let height = (max.1 as i64 - min.1 as i64 + 1) as usize;
+        let mut image = RgbImage::new(width, height, [0, 0, 0]);
+
+        if let Some(layer) = game_layer {
+            for y in 0..height {
+                for x in 0..width {
+                    // tile at the center of the cell
+                    let world = |cell: usize| {
+                        (cell as i64 * self.cell_size as i64 + self.cell_size as i64 / 2) as i32
+                    };
+                    let color = match layer.tile_at(world(x), world(y)) {
+                        Some(TILE_SOLID) => [90, 90, 90],
+                        Some(TILE_NOHOOK) => [55, 55, 70],
+                        Some(TILE_DEATH) => [90, 25, 25],
+                        Some(TILE_FREEZE) => [25, 35, 90],
+                        _ => continue,
+                    };
+                    image.pixels[y * width + x] = color;
+                }
+            }
+        }
+
+        let max_ticks = self.cells.values().copied().max()?;
+        let scale = ((max_ticks + 1) as f64).ln();
+        for (&(x, y), &ticks) in self.cells.iter() {
+            let (Ok(px), Ok(py)) = (
+                usize::try_from(x as i64 - min.0 as i64),
+                usize::try_from(y as i64 - min.1 as i64),
+            ) else {
+                continue;
+            };
+            if let Some(pixel) = image.get_mut(px, py) {
+                *pixel = heat_color(((ticks + 1) as f64).ln() / scale);
+            }
+        }
+        Some(image)
+    }
+}
+
+/// black-red-yellow-white color scale for values in 0..=1
+fn heat_color(value: f64) -> [u8; 3] {
+    // keep rarely visited cells visible on black
+    let value = value.max(0.15);
+    let channel = |offset: f64| ((value * 3. - offset).clamp(0., 1.) * 255.) as u8;
+    [channel(0.), channel(1.), channel(2.)]
+}
+
+/// [`ParserEvents`] that adds the position of every player in every tick to a heatmap
+pub struct HeatmapEvents {
+    pub heatmap: Heatmap,
+}
+
+impl ParserEvents for HeatmapEvents {
+    fn on_tick(&mut self, _tick: i32, state: &Tick) {
+        for &(x, y) in state.player_positions.values() {
+            self.heatmap.add(x, y, 1);
+        }
+    }
+}
+
+/// Heatmap of a single file with its map name, None for files without a readable header
+pub fn file_heatmap(path: &Path, cell_size: i32) -> Result<Option<(String, Heatmap)>> {
+    let Some(game_info) = Extractor::get_game_info(path) else {
+        return Ok(None);
+    };
+    let mut events = HeatmapEvents {
+        heatmap: Heatmap::new(cell_size),
+    };
+    Extractor::parse_file_with_events(path, &ParserConfig::default(), &mut [&mut events])?;
+    Ok(Some((game_info.map_name.to_string(), events.heatmap)))
+}
+
+/// map file of map_name in maps_folder, if it exists
+pub fn find_map_file(maps_folder: &Path, map_name: &str) -> Option<PathBuf> {
+    let path = maps_folder.join(format!("{}.map", map_name));
+    path.is_file().then_some(path)
+}
+
+/// map name usable as file name
+pub fn file_stem(map_name: &str) -> String {
+    map_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,482 @@
+//! Write a dataset in the layout of the Hugging Face Hub, so `datasets.load_dataset` can read
+//! it directly: parquet shards below data/ and a README.md dataset card describing their
+//! features. The folder can be pushed to a dataset repository on the Hub with
+//! [`push_to_hub`], which needs the `remote` feature.
+//!
+//! Each row of the shards is one sequence: its meta.csv columns followed by one fixed size
+//! list of seq_length values per feature column.
+
+use arrow::array::{
+    ArrayRef, FixedSizeListArray, Float32Array, Int32Array, StringArray, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, FieldRef, Schema};
+use arrow::record_batch::RecordBatch;
+use log::info;
+use ndarray::{s, ArrayView3};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::{
+    fs::{self, create_dir_all, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::dataset::{Dataset, DatasetError, MetaRow};
+use crate::error::Result;
+
+/// folder of the parquet shards, relative to the dataset card
+pub const DATA_DIR: &str = "data";
+
+/// file name of the dataset card
+pub const CARD_FILE: &str = "README.md";
+
+/// approximate uncompressed size of the tick data of a shard
+const SHARD_BYTES: usize = 256 << 20;
+
+/// meta.csv columns written to the shards with their Hugging Face dtype, in the order of
+/// [`meta_arrays`]. Timeout codes and account names are left out, they link the names of a
+/// player.
+const META_FEATURES: [(&str, &str); 16] = [
+    ("seq_id", "uint64"),
+    ("player_id", "uint64"),
+    ("player", "string"),
+    ("start", "uint64"),
+    ("ticks", "uint64"),
+    ("map", "string"),
+    ("teehist", "string"),
+    ("finish_time", "int32"),
+    ("anomaly_score", "float32"),
+    ("anomaly_flags", "string"),
+    ("recorded", "string"),
+    ("weight", "float32"),
+    ("labels", "string"),
+    ("session_id", "string"),
+    ("session_seq", "uint64"),
+    ("route", "uint64"),
+];
+
+/// Amount of sequences per shard, so the tick data of a shard has about SHARD_BYTES
+pub fn shard_sequences(seq_length: usize, num_features: usize) -> usize {
+    (SHARD_BYTES / (seq_length * num_features * size_of::<f32>()).max(1)).max(1)
+}
+
+/// path of a shard relative to the dataset card, e.g. data/train-00000-of-00004.parquet
+pub fn shard_path(index: usize, count: usize) -> String {
+    format!("{}/train-{:05}-of-{:05}.parquet", DATA_DIR, index, count)
+}
+
+fn arrow_type(dtype: &str) -> DataType {
+    match dtype {
+        "uint64" => DataType::UInt64,
+        "int32" => DataType::Int32,
+        "float32" => DataType::Float32,
+        _ => DataType::Utf8,
+    }
+}
+
+/// element of the fixed size lists of the feature columns
+fn tick_field() -> FieldRef {
+    Arc::new(Field::new("item", DataType::Float32, false))
+}
+
+/// arrow schema of the shards of a dataset with the given feature columns
+pub fn shard_schema(column_names: &[String], seq_length: usize) -> Schema {
+    let meta_fields = META_FEATURES
+        .iter()
+        .map(|(name, dtype)| Field::new(*name, arrow_type(dtype), true));
+    let feature_fields = column_names.iter().map(|name| {
+        Field::new(
+            name,
+            DataType::FixedSizeList(tick_field(), seq_length as i32),
+            false,
+        )
+    });
+    Schema::new(meta_fields.chain(feature_fields).collect::<Vec<_>>())
+}
+
+/// meta columns of the shard rows, see [`META_FEATURES`]
+fn meta_arrays(meta: &[MetaRow]) -> Vec<ArrayRef> {
+    let numbers = |value: fn(&MetaRow) -> usize| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(
+            meta.iter().map(|row| value(row) as u64),
+        ))
+    };
+    let strings = |value: fn(&MetaRow) -> Option<&str>| -> ArrayRef {
+        Arc::new(StringArray::from_iter(meta.iter().map(value)))
+    };
+    vec![
+        numbers(|row| row.seq_id),
+        numbers(|row| row.player_id),
+        strings(|row| Some(&*row.player)),
+        numbers(|row| row.start),
+        numbers(|row| row.ticks),
+        strings(|row| Some(&*row.map)),
+        strings(|row| Some(&*row.teehist)),
+        Arc::new(Int32Array::from_iter(
+            meta.iter().map(|row| row.finish_time),
+        )),
+        Arc::new(Float32Array::from_iter(
+            meta.iter().map(|row| row.anomaly_score),
+        )),
+        strings(|row| row.anomaly_flags.as_deref()),
+        strings(|row| row.recorded.as_deref()),
+        Arc::new(Float32Array::from_iter(meta.iter().map(|row| row.weight))),
+        strings(|row| row.labels.as_deref()),
+        strings(|row| row.session_id.as_deref()),
+        Arc::new(UInt64Array::from_iter(
+            meta.iter().map(|row| row.session_seq.map(|seq| seq as u64)),
+        )),
+        Arc::new(UInt64Array::from_iter(
+            meta.iter().map(|row| row.route.map(|route| route as u64)),
+        )),
+    ]
+}
+
+/// Write the sequences with their meta rows as zstd compressed parquet file
+pub fn write_shard(
+    path: &Path,
+    column_names: &[String],
+    meta: &[MetaRow],
+    data: ArrayView3<f32>,
+) -> Result<()> {
+    let seq_length = data.shape()[1];
+    let schema = Arc::new(shard_schema(column_names, seq_length));
+    let mut columns = meta_arrays(meta);
+    for feature in 0..column_names.len() {
+        let values =
+            Float32Array::from_iter_values(data.slice(s![.., .., feature]).iter().copied());
+        columns.push(Arc::new(FixedSizeListArray::try_new(
+            tick_field(),
+            seq_length as i32,
+            Arc::new(values),
+            None,
+        )?));
+    }
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Dataset card stub with the feature schema and splits in its yaml header, as the Hub reads
+/// them. Description and license are left for the publisher to fill in.
+pub fn dataset_card(
+    name: &str,
+    column_names: &[String],
+    seq_length: usize,
+    sequence_count: usize,
+) -> String {
+    let mut card = String::from("---\nconfigs:\n- config_name: default\n  data_files:\n");
+    card += &format!("  - split: train\n    path: {}/train-*\n", DATA_DIR);
+    card += "dataset_info:\n  features:\n";
+    for (feature, dtype) in META_FEATURES {
+        card += &format!("  - name: {}\n    dtype: {}\n", feature, dtype);
+    }
+    for column in column_names {
+        card += &format!(
+            "  - name: {}\n    sequence: float32\n    length: {}\n",
+            column, seq_length
+        );
+    }
+    card += &format!(
+        "  splits:\n  - name: train\n    num_examples: {}\n",
+        sequence_count
+    );
+    card += "tags:\n- ddnet\n- teeworlds\n- behavior\n---\n\n";
+
+    card += &format!("# {}\n\n", name);
+    card += "Player inputs and movement of DDNet gameplay, extracted from teehistorian server \
+recordings with [teehistorian_extractor](https://github.com/iMilchshake/teehistorian_extractor).\n\n";
+    card += &format!(
+        "Each of the {} rows is a sequence of {} ticks (50 per second) of a single player. \
+Feature columns hold one value per tick:\n\n",
+        sequence_count, seq_length
+    );
+    for column in column_names {
+        card += &format!("- `{}`\n", column);
+    }
+    card += "\nThe remaining columns describe where the sequence comes from: `teehist` is the \
+recording, `start` the tick the sequence starts at and `recorded` its wall clock time.\n\n";
+    card += "## Before publishing\n\n\
+- Player names are as recorded, run `teehistorian_extractor anonymize` on the dataset first \
+to replace them with pseudonyms.\n\
+- Add a `license` to the yaml header and describe the servers and time span of the \
+recordings.\n";
+    card
+}
+
+/// Write the dataset at input_path into output_path in the layout of the Hub, see the module
+/// docs. shard_size is the amount of sequences per shard, by default about 256 MB of tick data
+/// each. Returns the written files.
+pub fn export_huggingface(
+    input_path: &Path,
+    output_path: &Path,
+    shard_size: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    let dataset = Dataset::open(input_path)?;
+    let (row_count, seq_length, feature_count) = dataset.shape();
+    if row_count != dataset.meta.len() {
+        return Err(DatasetError::CountMismatch {
+            sequences: row_count,
+            meta: dataset.meta.len(),
+            expected: dataset.meta.len(),
+        }
+        .into());
+    }
+    let shard_size = shard_size
+        .unwrap_or_else(|| shard_sequences(seq_length, feature_count))
+        .max(1);
+    let shard_count = row_count.div_ceil(shard_size).max(1);
+
+    create_dir_all(output_path.join(DATA_DIR))?;
+    let mut written = Vec::new();
+    for shard in 0..shard_count {
+        let start = shard * shard_size;
+        let end = (start + shard_size).min(row_count);
+        let path = output_path.join(shard_path(shard, shard_count));
+        let data = dataset.read_sequences(start, end)?;
+        write_shard(
+            &path,
+            &dataset.column_names,
+            &dataset.meta[start..end],
+            data.view(),
+        )?;
+        info!(
+            "wrote sequences {}..{} to {:?}",
+            start,
+            end,
+            path.file_name().unwrap_or_default()
+        );
+        written.push(path);
+    }
+
+    let name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "DDNet gameplay".to_string());
+    let card_path = output_path.join(CARD_FILE);
+    fs::write(
+        &card_path,
+        dataset_card(&name, &dataset.column_names, seq_length, row_count),
+    )?;
+    written.push(card_path);
+    Ok(written)
+}
+
+/// Hub url, `HF_ENDPOINT` or https://huggingface.co
+fn hub_endpoint() -> String {
+    std::env::var("HF_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .unwrap_or_else(|| "https://huggingface.co".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// standard base64 with padding, as the commit api expects for file contents
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | ((byte as u32) << (16 - 8 * i))
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((group >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// a file of the folder pushed to the Hub, path relative to the repository root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HubFile {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Body of a commit to the Hub, as newline delimited json. Shards are stored with git lfs
+/// and referenced by their sha256, the card is sent inline. data/ is cleared first, so
+/// shards of an earlier push with more shards don't remain.
+pub fn commit_payload(summary: &str, card: &[u8], shards: &[HubFile]) -> String {
+    let mut lines = vec![
+        serde_json::json!({"key": "header", "value": {"summary": summary, "description": ""}}),
+        serde_json::json!({"key": "deletedFolder", "value": {"path": DATA_DIR}}),
+        serde_json::json!({"key": "file", "value": {
+            "path": CARD_FILE,
+            "content": base64(card),
+            "encoding": "base64",
+        }}),
+    ];
+    lines.extend(shards.iter().map(|shard| {
+        serde_json::json!({"key": "lfsFile", "value": {
+            "path": shard.path,
+            "algo": "sha256",
+            "oid": shard.sha256,
+            "size": shard.size,
+        }})
+    }));
+    lines.iter().map(|line| line.to_string() + "\n").collect()
+}
+
+/// Upload a folder written by [`export_huggingface`] to the dataset repository repo_id, e.g.
+/// `user/ddnet-behavior`, as a single commit to its main branch. The repository is created
+/// if it doesn't exist, private unless public is set. token is a Hub access token with write
+/// access. Returns the uploaded files.
+#[cfg(feature = "remote")]
+pub fn push_to_hub(folder_path: &Path, repo_id: &str, token: &str, public: bool) -> Result<usize> {
+    use crate::remote::file_sha256;
+    use serde_json::json;
+    use std::io;
+
+    let endpoint = hub_endpoint();
+    let authorization = format!("Bearer {}", token);
+    let request_error = |url: &str, err: ureq::Error| io::Error::other(format!("{}: {}", url, err));
+    let post_json = |url: &str, content_type: &str, body: &serde_json::Value| {
+        ureq::post(url)
+            .header("Authorization", &authorization)
+            .header("Content-Type", content_type)
+            .header("Accept", content_type)
+            .send(body.to_string())
+            .map_err(|err| request_error(url, err))?
+            .into_body()
+            .read_to_string()
+            .map_err(|err| request_error(url, err))
+    };
+
+    let (organization, name) = repo_id.split_once('/').unwrap_or(("", repo_id));
+    let create_url = format!("{}/api/repos/create", endpoint);
+    let create = json!({
+        "type": "dataset",
+        "name": name,
+        "organization": (!organization.is_empty()).then_some(organization),
+        "private": !public,
+    });
+    match post_json(&create_url, "application/json", &create) {
+        Ok(_) => info!("created dataset repository {}", repo_id),
+        // 409 conflict: the repository exists already
+        Err(err) if err.to_string().contains("409") => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut shard_paths: Vec<PathBuf> = fs::read_dir(folder_path.join(DATA_DIR))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    shard_paths.retain(|path| path.extension().is_some_and(|e| e == "parquet"));
+    shard_paths.sort();
+    let shards = shard_paths
+        .iter()
+        .map(|path| {
+            Ok(HubFile {
+                path: format!(
+                    "{}/{}",
+                    DATA_DIR,
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ),
+                sha256: file_sha256(path)?,
+                size: fs::metadata(path)?.len(),
+            })
+        })
+        .collect::<io::Result<Vec<HubFile>>>()?;
+
+    // git lfs batch api: objects already stored on the Hub come back without upload action
+    let batch_url = format!(
+        "{}/datasets/{}.git/info/lfs/objects/batch",
+        endpoint, repo_id
+    );
+    let objects: Vec<_> = shards
+        .iter()
+        .map(|shard| json!({"oid": shard.sha256, "size": shard.size}))
+        .collect();
+    let batch = json!({
+        "operation": "upload",
+        "transfers": ["basic"],
+        "objects": objects,
+        "hash_algo": "sha256",
+    });
+    let response: serde_json::Value = serde_json::from_str(&post_json(
+        &batch_url,
+        "application/vnd.git-lfs+json",
+        &batch,
+    )?)?;
+    for (shard, path) in shards.iter().zip(&shard_paths) {
+        let object = response["objects"]
+            .as_array()
+            .and_then(|objects| objects.iter().find(|o| o["oid"] == shard.sha256.as_str()))
+            .ok_or_else(|| io::Error::other(format!("{}: no lfs object", shard.path)))?;
+        if let Some(error) = object.get("error") {
+            return Err(io::Error::other(format!("{}: {}", shard.path, error)).into());
+        }
+        let actions = &object["actions"];
+        let Some(upload_url) = actions["upload"]["href"].as_str() else {
+            info!("{} is already on the hub", shard.path);
+            continue;
+        };
+        let mut request = ureq::put(upload_url);
+        if let Some(headers) = actions["upload"]["header"].as_object() {
+            for (name, value) in headers {
+                request = request.header(name, value.as_str().unwrap_or_default());
+            }
+        }
+        request
+            .send(File::open(path)?)
+            .map_err(|err| request_error(&shard.path, err))?;
+        if let Some(verify_url) = actions["verify"]["href"].as_str() {
+            let verify = json!({"oid": shard.sha256, "size": shard.size});
+            post_json(verify_url, "application/vnd.git-lfs+json", &verify)?;
+        }
+        info!("uploaded {} ({} bytes)", shard.path, shard.size);
+    }
+
+    let card = fs::read(folder_path.join(CARD_FILE))?;
+    let commit_url = format!("{}/api/datasets/{}/commit/main", endpoint, repo_id);
+    ureq::post(&commit_url)
+        .header("Authorization", &authorization)
+        .header("Content-Type", "application/x-ndjson")
+        .send(commit_payload(
+            "Upload dataset with teehistorian_extractor",
+            &card,
+            &shards,
+        ))
+        .map_err(|err| request_error(&commit_url, err))?;
+    info!(
+        "committed {} shards to {}/datasets/{}",
+        shards.len(),
+        endpoint,
+        repo_id
+    );
+    Ok(shards.len() + 1)
+}
+
+/// Upload a folder written by [`export_huggingface`] to the dataset repository repo_id, e.g.
+/// `user/ddnet-behavior`, as a single commit to its main branch. The repository is created
+/// if it doesn't exist, private unless public is set. token is a Hub access token with write
+/// access. Returns the uploaded files.
+#[cfg(not(feature = "remote"))]
+pub fn push_to_hub(
+    _folder_path: &Path,
+    repo_id: &str,
+    _token: &str,
+    _public: bool,
+) -> Result<usize> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "{}/datasets/{}: built without the remote feature",
+            hub_endpoint(),
+            repo_id
+        ),
+    )
+    .into())
+}
@@ -0,0 +1,140 @@
+use log::{info, warn};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::extractor::{teehist_name, Extractor};
+
+/// header information of a single teehistorian file, used to plan processing order
+#[derive(Debug)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    pub map_name: Option<String>,
+    pub file_size: u64,
+}
+
+impl IndexEntry {
+    /// file name without extension, matches `Sequence::teehist_name`
+    pub fn teehist_name(&self) -> String {
+        teehist_name(&self.path)
+    }
+}
+
+/// Index over teehistorian headers. Only the header of each file is read.
+pub struct HeaderIndex {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl HeaderIndex {
+    pub fn build(paths: &[PathBuf]) -> HeaderIndex {
+        let entries = paths
+            .iter()
+            .map(|path| IndexEntry {
+                path: path.clone(),
+                map_name: Extractor::get_game_info(path).map(|g| g.map_name.to_string()),
+                file_size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+            })
+            .collect();
+        HeaderIndex { entries }
+    }
+
+    /// amount of indexed files per map
+    pub fn map_counts(&self) -> HashMap<&str, usize> {
+        let mut map_counts = HashMap::new();
+        for entry in &self.entries {
+            if let Some(map_name) = &entry.map_name {
+                *map_counts.entry(map_name.as_str()).or_insert(0) += 1;
+            }
+        }
+        map_counts
+    }
+
+    /// Estimate exported ticks for each file.
+    /// Files with a known prior yield use it directly. All others are estimated from their
+    /// file size and the ticks per byte observed for their map (or globally) in prior yields.
+    /// Estimates are weighted by map popularity, as more recordings of a map make its
+    /// sequences more useful for training.
+    pub fn expected_yields(&self, prior_yields: &HashMap<String, usize>) -> Vec<f64> {
+        // ticks per byte, per map and globally
+        let mut map_rates: HashMap<&str, (f64, f64)> = HashMap::new();
+        let mut global_rate = (0.0, 0.0);
+        for entry in &self.entries {
+            if let Some(&ticks) = prior_yields.get(&entry.teehist_name()) {
+                global_rate.0 += ticks as f64;
+                global_rate.1 += entry.file_size as f64;
+                if let Some(map_name) = &entry.map_name {
+                    let rate = map_rates.entry(map_name.as_str()).or_insert((0.0, 0.0));
+                    rate.0 += ticks as f64;
+                    rate.1 += entry.file_size as f64;
+                }
+            }
+        }
+        let global_rate = if global_rate.1 > 0.0 {
+            global_rate.0 / global_rate.1
+        } else {
+            1.0
+        };
+
+        let map_counts = self.map_counts();
+        self.entries
+            .iter()
+            .map(|entry| {
+                let map_name = entry.map_name.as_deref().unwrap_or_default();
+                let popularity = (1.0 + *map_counts.get(map_name).unwrap_or(&0) as f64).ln();
+                let ticks = match prior_yields.get(&entry.teehist_name()) {
+                    Some(&ticks) => ticks as f64,
+                    None => {
+                        let rate = map_rates
+                            .get(map_name)
+                            .filter(|(_, bytes)| *bytes > 0.0)
+                            .map(|(ticks, bytes)| ticks / bytes)
+                            .unwrap_or(global_rate);
+                        entry.file_size as f64 * rate
+                    }
+                };
+                ticks * popularity.max(1.0)
+            })
+            .collect()
+    }
+
+    /// indexed paths sorted by expected yield, most valuable first
+    pub fn order_by_expected_yield(&self, prior_yields: &HashMap<String, usize>) -> Vec<PathBuf> {
+        let yields = self.expected_yields(prior_yields);
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by(|&a, &b| yields[b].total_cmp(&yields[a]));
+        order
+            .into_iter()
+            .map(|i| self.entries[i].path.clone())
+            .collect()
+    }
+}
+
+/// Load per-file exported ticks from a ledger.csv of a previous run.
+/// Returns teehistorian file name -> ticks for all processed files.
+pub fn load_ledger_yields(ledger_path: &PathBuf) -> HashMap<String, usize> {
+    let mut yields = HashMap::new();
+    let content = match fs::read_to_string(ledger_path) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("couldn't read ledger {:?}: {}", ledger_path, err);
+            return yields;
+        }
+    };
+
+    for line in content.lines().skip(1) {
+        // path is quoted and may contain commas, so split from the right
+        let mut fields = line.rsplitn(3, ',');
+        let (Some(ticks), Some(status), Some(path)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if status != "processed" {
+            continue;
+        }
+        if let Ok(ticks) = ticks.parse::<usize>() {
+            let path = PathBuf::from(path.trim_matches('"'));
+            yields.insert(teehist_name(&path), ticks);
+        }
+    }
+
+    info!("loaded prior yields for {} files", yields.len());
+    yields
+}
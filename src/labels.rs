@@ -0,0 +1,220 @@
+//! Annotations of external sources, e.g. manual cheat labels or ban lists, joined onto the
+//! exported sequences as the labels column of meta.csv.
+//!
+//! Annotation files are csv or parquet with a `label` column and optional `teehist`, `cid`,
+//! `player`, `start_tick` and `end_tick` columns. Missing or empty key columns match any
+//! sequence, so a ban list only needs `player` and `label`. Ticks are server ticks like the
+//! start column of meta.csv, both ends are inclusive.
+
+use arrow::{
+    array::{ArrayRef, AsArray},
+    compute::cast,
+    datatypes::{DataType, Int64Type},
+    record_batch::RecordBatch,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, path::Path};
+
+use crate::error::{Error, Result};
+use crate::extractor::{teehist_name, Sequence};
+
+/// separator of the labels of a sequence in meta.csv
+pub const LABEL_SEPARATOR: char = '|';
+
+/// row of an annotation file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Annotation {
+    /// name of the teehistorian file, with or without extension and folders
+    #[serde(default)]
+    pub teehist: Option<String>,
+    #[serde(default)]
+    pub cid: Option<i32>,
+    #[serde(default)]
+    pub player: Option<String>,
+    /// first annotated server tick
+    #[serde(default)]
+    pub start_tick: Option<usize>,
+    /// last annotated server tick
+    #[serde(default)]
+    pub end_tick: Option<usize>,
+    pub label: String,
+}
+
+impl Annotation {
+    /// whether the annotation covers any of the given ticks of the sequence
+    fn matches(&self, sequence: &Sequence, ticks: usize) -> bool {
+        let last_tick = sequence.start_tick + ticks.max(1) - 1;
+        self.cid.is_none_or(|cid| cid == sequence.cid)
+            && self
+                .player
+                .as_deref()
+                .is_none_or(|player| player == &*sequence.player_name)
+            && self.start_tick.is_none_or(|start| start <= last_tick)
+            && self.end_tick.is_none_or(|end| end >= sequence.start_tick)
+    }
+}
+
+/// teehistorian file name -> annotations of the file
+#[derive(Debug, Default)]
+pub struct LabelIndex {
+    files: HashMap<String, Vec<Annotation>>,
+    /// annotations without teehist, matching sequences of every file
+    any_file: Vec<Annotation>,
+}
+
+impl LabelIndex {
+    /// Load a csv or parquet annotation file, chosen by file extension
+    pub fn load(path: &Path) -> Result<LabelIndex> {
+        let invalid = |message: String| Error::InvalidLabels(format!("{:?}: {}", path, message));
+        let annotations = match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => csv::Reader::from_path(path)
+                .and_then(|mut reader| reader.deserialize().collect::<Result<Vec<_>, _>>())
+                .map_err(|err| invalid(err.to_string()))?,
+            Some("parquet") => {
+                let reader =
+                    ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+                let mut annotations = Vec::new();
+                for batch in reader {
+                    annotations.extend(parquet_annotations(&batch?).map_err(invalid)?);
+                }
+                annotations
+            }
+            _ => {
+                return Err(invalid(
+                    "unsupported annotation file, expected .csv or .parquet".to_string(),
+                ))
+            }
+        };
+        LabelIndex::from_annotations(annotations).map_err(invalid)
+    }
+
+    /// Index of annotations, teehist is reduced to the file name without extension.
+    /// Labels must not be empty or contain [`LABEL_SEPARATOR`].
+    pub fn from_annotations(
+        annotations: impl IntoIterator<Item = Annotation>,
+    ) -> Result<LabelIndex, String> {
+        let mut index = LabelIndex::default();
+        for mut annotation in annotations {
+            if annotation.label.is_empty() || annotation.label.contains(LABEL_SEPARATOR) {
+                return Err(format!(
+                    "label {:?} is empty or contains '{}'",
+                    annotation.label, LABEL_SEPARATOR
+                ));
+            }
+            if let (Some(start), Some(end)) = (annotation.start_tick, annotation.end_tick) {
+                if start > end {
+                    return Err(format!(
+                        "label {} ends at tick {} before its start tick {}",
+                        annotation.label, end, start
+                    ));
+                }
+            }
+            match annotation
+                .teehist
+                .take()
+                .filter(|teehist| !teehist.is_empty())
+            {
+                Some(teehist) => {
+                    let name = teehist_name(Path::new(&teehist));
+                    annotation.teehist = Some(name.clone());
+                    index.files.entry(name).or_default().push(annotation);
+                }
+                None => index.any_file.push(annotation),
+            }
+        }
+        Ok(index)
+    }
+
+    /// Sorted labels of the annotations covering any of the given ticks of the sequence,
+    /// joined by [`LABEL_SEPARATOR`]. None if no annotation matches.
+    pub fn get(&self, sequence: &Sequence, ticks: usize) -> Option<String> {
+        let file_annotations = self
+            .files
+            .get(&*sequence.teehist_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let mut labels: Vec<&str> = file_annotations
+            .iter()
+            .chain(&self.any_file)
+            .filter(|annotation| annotation.matches(sequence, ticks))
+            .map(|annotation| annotation.label.as_str())
+            .collect();
+        if labels.is_empty() {
+            return None;
+        }
+        labels.sort_unstable();
+        labels.dedup();
+        Some(labels.join(&LABEL_SEPARATOR.to_string()))
+    }
+
+    /// amount of annotations
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum::<usize>() + self.any_file.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// annotations of a record batch of a parquet file, columns are cast to strings and integers
+fn parquet_annotations(batch: &RecordBatch) -> Result<Vec<Annotation>, String> {
+    let column = |name: &str, data_type: &DataType| -> Result<Option<ArrayRef>, String> {
+        batch
+            .column_by_name(name)
+            .map(|column| cast(column, data_type).map_err(|err| format!("{}: {}", name, err)))
+            .transpose()
+    };
+    let strings = |name: &str| -> Result<Vec<Option<String>>, String> {
+        Ok(match column(name, &DataType::Utf8)? {
+            Some(column) => column
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.map(str::to_string))
+                .collect(),
+            None => vec![None; batch.num_rows()],
+        })
+    };
+    let numbers = |name: &str| -> Result<Vec<Option<i64>>, String> {
+        Ok(match column(name, &DataType::Int64)? {
+            Some(column) => column.as_primitive::<Int64Type>().iter().collect(),
+            None => vec![None; batch.num_rows()],
+        })
+    };
+    let ticks = |name: &str| -> Result<Vec<Option<usize>>, String> {
+        numbers(name)?
+            .into_iter()
+            .map(|tick| {
+                tick.map(|tick| usize::try_from(tick).map_err(|_| format!("{}: {}", name, tick)))
+                    .transpose()
+            })
+            .collect()
+    };
+
+    if batch.column_by_name("label").is_none() {
+        return Err("no label column".to_string());
+    }
+    let labels = strings("label")?;
+    let teehists = strings("teehist")?;
+    let cids = numbers("cid")?;
+    let players = strings("player")?;
+    let start_ticks = ticks("start_tick")?;
+    let end_ticks = ticks("end_tick")?;
+    (0..batch.num_rows())
+        .map(|row| {
+            Ok(Annotation {
+                teehist: teehists[row].clone(),
+                cid: cids[row]
+                    .map(|cid| i32::try_from(cid).map_err(|_| format!("cid: {}", cid)))
+                    .transpose()?,
+                player: players[row].clone(),
+                start_tick: start_ticks[row],
+                end_tick: end_ticks[row],
+                label: labels[row]
+                    .clone()
+                    .ok_or_else(|| format!("row {} has no label", row))?,
+            })
+        })
+        .collect()
+}
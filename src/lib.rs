@@ -1,5 +1,50 @@
+pub mod alias;
+pub mod anomaly;
+pub mod audit;
+pub mod bot_filter;
+pub mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checksum;
+pub mod compare;
+pub mod config;
+pub mod dataset;
+pub mod dedup;
+pub mod demo;
+pub mod embedding;
+pub mod error;
 pub mod export;
 pub mod extractor;
+pub mod heatmap;
+pub mod huggingface;
+pub mod index;
+pub mod labels;
+pub mod map_file;
+pub mod map_info;
+pub mod metrics;
+pub mod nats;
+pub mod outliers;
 pub mod parser;
-pub mod tick;
+pub mod phase;
+pub mod player_stats;
+pub mod plot;
+pub mod png;
 pub mod preprocess;
+pub mod privacy;
+pub mod processed;
+pub mod progress;
+pub mod reaction;
+pub mod records;
+pub mod registry;
+pub mod remote;
+pub mod report;
+pub mod route;
+pub mod session;
+pub mod sink;
+pub mod smoothing;
+pub mod tail;
+pub mod tick;
+pub mod upload;
+pub mod weights;
+
+pub use error::{Error, Result};
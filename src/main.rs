@@ -1,37 +1,667 @@
-use clap::Parser;
-use log::info;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use glob::Pattern;
+use indicatif::MultiProgress;
+use indicatif_log_bridge::LogWrapper;
 use log::LevelFilter;
+use log::{debug, error, info, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use teehistorian_extractor::alias::{self, AliasWeights};
+use teehistorian_extractor::audit::{self, AuditConfig, AuditReport};
+use teehistorian_extractor::checksum;
+use teehistorian_extractor::compare::DriftReport;
+use teehistorian_extractor::config::{ConfigError, RunConfig, CONFIG_FILE_NAME};
+use teehistorian_extractor::dataset::{self, Dataset, DatasetFilter};
+use teehistorian_extractor::dedup::DedupMode;
+use teehistorian_extractor::demo::{self, DemoFilter};
+use teehistorian_extractor::export::remove_export_files;
 use teehistorian_extractor::export::ExportConfig;
 use teehistorian_extractor::export::Exporter;
-use teehistorian_extractor::parser::ParserConfig;
+use teehistorian_extractor::export::FinishFilter;
+use teehistorian_extractor::extractor::{teehist_name, Extractor};
+use teehistorian_extractor::heatmap::{self, Heatmap};
+use teehistorian_extractor::huggingface;
+use teehistorian_extractor::index::{load_ledger_yields, HeaderIndex};
+use teehistorian_extractor::labels::LabelIndex;
+use teehistorian_extractor::map_file::GameLayer;
+use teehistorian_extractor::map_info::MapCatalog;
+use teehistorian_extractor::metrics::{self, Metrics};
+use teehistorian_extractor::nats::{self, NatsPayload, NatsSink};
+use teehistorian_extractor::parser::{NameNormalization, ParserConfig};
+use teehistorian_extractor::player_stats;
+use teehistorian_extractor::plot::Trajectory;
+use teehistorian_extractor::preprocess::ActivityInput;
+use teehistorian_extractor::privacy::PrivacyNoise;
+use teehistorian_extractor::processed::{file_hash, load_processed_hashes};
+use teehistorian_extractor::progress::ExportProgress;
+use teehistorian_extractor::reaction::{self, ReactionConfig};
+use teehistorian_extractor::records::RecordIndex;
+use teehistorian_extractor::registry::{self, PlayerRegistry};
+use teehistorian_extractor::report;
+use teehistorian_extractor::route::{self, RouteConfig};
+use teehistorian_extractor::sink::{BackgroundSink, ExportSink, Hdf5Sink};
+use teehistorian_extractor::smoothing::{parse_column_smoothing, Smoothing};
+use teehistorian_extractor::tail::TailConfig;
+use teehistorian_extractor::weights::{self, parse_weight_rule, WeightRule};
+
+/// amount of sequences plotted in --html-report
+const REPORT_SAMPLE_PLOTS: usize = 6;
+
+/// order in which input files are processed
+#[derive(ValueEnum, Clone, Debug)]
+enum FileOrder {
+    /// sorted by path
+    Default,
+    /// largest files first, as they usually contain the most gameplay
+    Largest,
+    /// shuffled, see --seed
+    Random,
+    /// highest expected yield first, based on map popularity, file size and --prior-ledger
+    Yield,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum PlotFormat {
+    Svg,
+    Png,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum LogFormat {
+    Text,
+    Json,
+}
 
 #[derive(Parser, Debug)]
 struct Cli {
-    /// Input data directory
+    /// Logging level (error, warn, info, debug, trace)
+    #[clap(short, long, default_value = "info", global = true)]
+    log_level: LevelFilter,
+
+    /// format of log lines, json writes one object per line
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Extract a dataset from a directory of teehistorian files
+    Extract(Box<ExtractArgs>),
+    /// Parse a single teehistorian file and print what it contains
+    Inspect(InspectArgs),
+    /// Follow a teehistorian file that a running server is still writing and print its
+    /// sequences as json lines once they are completed
+    Tail(TailArgs),
+    /// Check the implicit tick alignment of teehistorian files against the continuity of
+    /// player movement
+    Audit(AuditArgs),
+    /// Summarize an exported dataset
+    Stats(StatsArgs),
+    /// Report differences of feature distributions, map and player composition and schema
+    /// between two exported datasets
+    Compare(CompareArgs),
+    /// Report playtime, maps, activity and sessions of each player in teehistorian files
+    PlayerStats(PlayerStatsArgs),
+    /// Report the distributions of input latencies of each player after wall hits and aim
+    /// snaps in teehistorian files, without any tick data
+    ReactionTimes(ReactionArgs),
+    /// Rank pairs of player names that likely belong to the same person, based on shared
+    /// timeout codes and similar input behavior
+    Aliases(AliasArgs),
+    /// Render a heatmap of player positions for each map as PNG
+    Heatmap(HeatmapArgs),
+    /// Plot the path and inputs of an exported sequence
+    Plot(PlotArgs),
+    /// Convert the sequences of a teehistorian file into a DDNet demo to replay them in the
+    /// client
+    Demo(DemoArgs),
+    /// Check an exported dataset for inconsistencies and invalid values
+    Validate(ValidateArgs),
+    /// Check the files of a dataset against their checksums in SHA256SUMS, e.g. after copying
+    /// it to another machine
+    Verify(VerifyArgs),
+    /// Combine multiple exported datasets into one
+    Merge(MergeArgs),
+    /// Copy the sequences of a dataset matching filters into a new dataset, without parsing
+    /// the teehistorian files again
+    Filter(FilterArgs),
+    /// Cut the sequences of a dataset into a different seq_length, without parsing the
+    /// teehistorian files again
+    Reshape(ReshapeArgs),
+    /// Write a copy of a dataset with player names replaced by hashed ids
+    Anonymize(AnonymizeArgs),
+    /// Write a dataset as parquet shards with a dataset card for the Hugging Face Hub and
+    /// optionally push it there
+    Huggingface(HuggingfaceArgs),
+    /// Rewrite a dataset of an older version in place in the current format
+    Upgrade(UpgradeArgs),
+    /// Fill the weight column of meta.csv of a dataset in place, e.g. after filter or merge
+    Weights(WeightsArgs),
+    /// Cluster the route column of meta.csv of a dataset exported with --route-cell-size
+    /// again in place, e.g. after filter or merge
+    Routes(RoutesArgs),
+    /// List player names found in teehistorian files, with the amount of files they appear in
+    LsPlayers(ListArgs),
+    /// List maps of teehistorian files based on their headers, with the amount of files
+    LsMaps(ListArgs),
+}
+
+/// teehistorian files read by a command
+#[derive(Args, Debug)]
+struct InputArgs {
+    /// Input files, directories (searched recursively), glob patterns, http(s) urls,
+    /// s3://bucket/prefix urls or @file with one input per line, can be repeated
     #[clap(short, long, default_value = "./data/teehistorian/")]
-    input: PathBuf,
+    input: Vec<PathBuf>,
 
-    /// Filepath for output dataset folder
-    #[clap(short, long, default_value = "./data/out/dataset/")]
+    /// csv list of accepted file extensions for files found in directories or by globs,
+    /// "*" accepts any file
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "teehistorian,teehistorian.zst,teehistorian.gz"
+    )]
+    extensions: Vec<String>,
+}
+
+impl InputArgs {
+    /// accepted files of all inputs, see [`Extractor::collect_input_paths`]
+    fn paths(&self) -> Vec<PathBuf> {
+        Extractor::collect_input_paths(&self.input, &self.extensions)
+    }
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// teehistorian file
+    file: PathBuf,
+
+    /// Cut sequence on player kill
+    #[clap(short = 'k', long)]
+    cut_kill: bool,
+
+    /// Cut sequence on player rescue (/r)
+    #[clap(short = 'r', long)]
+    cut_rescue: bool,
+
+    /// maximum amount of anomalies to list
+    #[clap(long, default_value = "50")]
+    max_anomalies: usize,
+}
+
+#[derive(Args, Debug)]
+struct TailArgs {
+    /// uncompressed teehistorian file
+    file: PathBuf,
+
+    /// Cut sequence on player kill
+    #[clap(short = 'k', long)]
+    cut_kill: bool,
+
+    /// Cut sequence on player rescue (/r)
+    #[clap(short = 'r', long)]
+    cut_rescue: bool,
+
+    /// time between checks for new data at the end of the file (e.g. "100ms")
+    #[clap(long, default_value = "100ms")]
+    poll_interval: humantime::Duration,
+
+    /// stop once the file hasn't grown for this long (e.g. "5min"), by default only the end
+    /// of the recording stops
+    #[clap(long)]
+    idle_timeout: Option<humantime::Duration>,
+
+    /// file to append the json lines to, defaults to stdout
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    /// serve Prometheus metrics at http://<addr>/metrics while tailing (e.g. "0.0.0.0:9184")
+    #[clap(long)]
+    metrics_addr: Option<SocketAddr>,
+}
+
+#[derive(Args, Debug)]
+struct AuditArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// minimum change of movement between two ticks (units per tick) to count as a spike
+    #[clap(long, default_value = "6")]
+    spike_threshold: i32,
+
+    /// maximum deviation of the combined movement of two spikes to count as cancelling out
+    #[clap(long, default_value = "3")]
+    tolerance: i32,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// amount of top players and maps to list
+    #[clap(short = 'p', long, default_value = "10")]
+    print_top_k: usize,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// exported dataset folder used as reference, e.g. the training data of a model
+    old: PathBuf,
+
+    /// exported dataset folder compared against the reference
+    new: PathBuf,
+
+    /// maximum amount of sequences per dataset sampled for the feature distributions
+    #[clap(long, default_value = "5000")]
+    max_sequences: usize,
+
+    /// amount of largest map and player changes to list
+    #[clap(short = 'p', long, default_value = "10")]
+    print_top_k: usize,
+
+    /// print the full report as json
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct PlayerStatsArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Ticks of no movement that counts as player being AFK
+    #[clap(short, long, default_value = "500")]
+    afk_ticks: usize,
+
+    /// csv list of inputs whose changes count as movement for --afk-ticks, out of move, jump,
+    /// fire, hook and target (aim)
+    #[clap(long, value_delimiter = ',', default_value = "move")]
+    afk_inputs: Vec<ActivityInput>,
+
+    #[clap(short, long, value_enum, default_value = "csv")]
+    format: ReportFormat,
+
+    /// report file, printed to stdout if not set
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ReactionArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// ticks after an event within which an input counts as reaction to it
+    #[clap(long, default_value = "25")]
+    max_latency: usize,
+
+    /// horizontal speed in units per tick before a wall hit
+    #[clap(long, default_value = "5")]
+    min_speed: i32,
+
+    /// aim angle change in degrees within one tick that counts as aim snap
+    #[clap(long, default_value = "45")]
+    snap_angle: f64,
+
+    /// leave out players with fewer reactions to an event
+    #[clap(long, default_value = "10")]
+    min_events: u64,
+
+    /// json reports include the full latency histograms
+    #[clap(short, long, value_enum, default_value = "csv")]
+    format: ReportFormat,
+
+    /// report file, printed to stdout if not set
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AliasArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// players with fewer ticks (5 minutes by default) aren't compared, their behavior is too
+    /// noisy
+    #[clap(long, default_value = "15000")]
+    min_ticks: u64,
+
+    /// minimum alias score (0 to 1) of listed pairs
+    #[clap(long, default_value = "0.8")]
+    min_score: f64,
+
+    /// only list the k highest scoring pairs
+    #[clap(short = 'p', long, default_value = "50")]
+    print_top_k: usize,
+}
+
+#[derive(Args, Debug)]
+struct HeatmapArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output folder, one <map>.png is written per map
+    #[clap(short, long, default_value = "./data/out/heatmaps/")]
+    output_folder: PathBuf,
+
+    /// size of a heatmap cell (pixel) in world units, a tile is 32 units
+    #[clap(short, long, default_value = "32", value_parser = clap::value_parser!(i32).range(1..))]
+    cell_size: i32,
+
+    /// folder with <map>.map files, their game layer is drawn below the heatmap
+    #[clap(short, long)]
+    maps: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AnonymizeArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// folder for the anonymized copy
+    #[clap(short, long)]
+    output_folder: PathBuf,
+
+    /// secret mixed into the name hashes. Reuse it to get matching ids across datasets,
+    /// random if not set
+    #[clap(long)]
+    salt: Option<String>,
+
+    /// also remove timeout codes, as they identify clients
+    #[clap(long)]
+    drop_timeout_codes: bool,
+}
+
+#[derive(Args, Debug)]
+struct HuggingfaceArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// folder for the parquet shards and README.md dataset card
+    #[clap(short, long)]
     output_folder: PathBuf,
 
-    /// ticks per sequence
-    #[clap(short, long, default_value = "1000")]
+    /// sequences per parquet shard, by default about 256 MB of uncompressed tick data
+    #[clap(long)]
+    shard_size: Option<usize>,
+
+    /// push the folder to this dataset repository on the Hub, e.g. user/ddnet-behavior,
+    /// with the access token in HF_TOKEN. Needs the remote feature.
+    #[clap(long)]
+    push_to_hub: Option<String>,
+
+    /// create the repository as public instead of private if it doesn't exist yet
+    #[clap(long, requires = "push_to_hub")]
+    public: bool,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// only list the k most frequent entries
+    #[clap(short = 'p', long)]
+    print_top_k: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct PlotArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// seq_id of the sequence in meta.csv
+    seq_id: usize,
+
+    #[clap(short, long, value_enum, default_value = "svg")]
+    format: PlotFormat,
+
+    /// output file, defaults to seq_<seq_id>.<format> in the current directory
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DemoArgs {
+    /// teehistorian file
+    file: PathBuf,
+
+    /// only convert sequences of this player, as in the player column of meta.csv
+    #[clap(short, long)]
+    player: Option<String>,
+
+    /// only convert the sequence starting at this tick, as in the start column of meta.csv
+    #[clap(short, long)]
+    start: Option<usize>,
+
+    /// Cut sequence on player kill, must match the export to find its sequences
+    #[clap(short = 'k', long)]
+    cut_kill: bool,
+
+    /// Cut sequence on player rescue (/r), must match the export to find its sequences
+    #[clap(short = 'r', long)]
+    cut_rescue: bool,
+
+    /// folder with <map>.map files, the map is embedded into the demo if found
+    #[clap(short, long)]
+    maps: Option<PathBuf>,
+
+    /// output file, defaults to <teehistorian name>.demo in the current directory
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ValidateArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct UpgradeArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct WeightsArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// csv list of weight rules, see --sample-weights of extract
+    #[clap(long, required = true, value_delimiter = ',', value_parser = parse_weight_rule)]
+    rules: Vec<WeightRule>,
+}
+
+#[derive(Args, Debug)]
+struct RoutesArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// highest jaccard distance (0-1) of the visited cells of a sequence to the first
+    /// sequence of its route, see --route-max-distance of extract
+    #[clap(long, default_value = "0.5")]
+    max_distance: f64,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// exported dataset folders to combine, they need the same columns, seq_length, smoothing
+    /// and phase labels
+    #[clap(required = true, num_args = 2..)]
+    datasets: Vec<PathBuf>,
+
+    /// folder for the merged dataset
+    #[clap(short, long)]
+    output_folder: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// folder for the filtered dataset
+    #[clap(short, long)]
+    output_folder: PathBuf,
+
+    /// csv list of player names to keep
+    #[clap(short = 'f', long, value_delimiter = ',')]
+    filter_players: Option<Vec<String>>,
+
+    /// csv list of player names to drop
+    #[clap(long, value_delimiter = ',')]
+    exclude_players: Option<Vec<String>>,
+
+    /// csv list of map name globs to keep (e.g. "Kobra*")
+    #[clap(long, value_delimiter = ',')]
+    filter_maps: Option<Vec<Pattern>>,
+
+    /// csv list of map name globs to drop. Takes precedence over --filter-maps.
+    #[clap(long, value_delimiter = ',')]
+    exclude_maps: Option<Vec<Pattern>>,
+
+    /// only keep sequences recorded at or after this date (YYYY-MM-DD or RFC 3339). Drops
+    /// sequences of exports without recorded column.
+    #[clap(long, value_parser = parse_date)]
+    since: Option<DateTime<Utc>>,
+
+    /// only keep sequences recorded before this date (YYYY-MM-DD or RFC 3339)
+    #[clap(long, value_parser = parse_date)]
+    until: Option<DateTime<Utc>>,
+
+    /// only keep sequences with at least this many ticks
+    #[clap(long)]
+    min_ticks: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct ReshapeArgs {
+    /// exported dataset folder
+    dataset: PathBuf,
+
+    /// folder for the reshaped dataset
+    #[clap(short, long)]
+    output_folder: PathBuf,
+
+    /// length of the new sequences in ticks. Consecutive sequences of a gameplay duration are
+    /// joined first, so they can also get longer.
+    #[clap(long)]
     seq_length: usize,
 
+    /// ticks between the starts of consecutive sequences, seq_length by default. Smaller values
+    /// give overlapping sequences.
+    #[clap(long)]
+    stride: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct ExtractArgs {
+    /// toml or yaml file with [parser] and [export] sections, explicitly passed flags override it
+    #[clap(short, long)]
+    config: Option<PathBuf>,
+
+    /// continue an interrupted run in --output-folder from its checkpoint.json, or append
+    /// new files to a finished one. Files listed in its processed.jsonl are skipped.
+    /// Uses the config.toml of that run unless --config is given.
+    #[clap(long, visible_alias = "append")]
+    resume: bool,
+
+    /// replace an earlier export in --output-folder, by default the run fails if the folder
+    /// already contains one. Other files in the folder are kept.
+    #[clap(long, conflicts_with = "resume")]
+    overwrite: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Filepath for output dataset folder, created if missing
+    #[clap(short, long, default_value = "./data/out/dataset/")]
+    output_folder: PathBuf,
+
+    /// Ticks per sequence. A csv list, e.g. 250,500,1000, parses each file once and exports
+    /// a dataset per seq_length into <output-folder>/seq_length_<n>
+    #[clap(short, long, value_delimiter = ',', default_value = "1000")]
+    seq_length: Vec<usize>,
+
     /// Ticks of no movement that counts as player being AFK
     #[clap(short, long, default_value = "500")]
     afk_ticks: usize,
 
+    /// csv list of inputs whose changes count as movement for --afk-ticks, out of move, jump,
+    /// fire, hook and target (aim)
+    #[clap(long, value_delimiter = ',', default_value = "move")]
+    afk_inputs: Vec<ActivityInput>,
+
     /// Ticks of padding around durations, after afk removal
     #[clap(long = "ap", default_value = "15")]
     afk_padding: usize,
 
-    /// Logging level (error, warn, info, debug, trace)
-    #[clap(short, long, default_value = "info")]
-    log_level: LevelFilter,
+    /// Join durations separated by fewer ticks, e.g. by a pause just above --afk-ticks, before
+    /// padding and cutting them into sequences. 0 keeps them apart
+    #[clap(long, default_value = "0")]
+    merge_gap: usize,
+
+    /// A play session of a player ends after an absence of more ticks, e.g. spectating.
+    /// Sessions also end when the player leaves the server
+    #[clap(long, default_value = "15000")]
+    session_gap: usize,
+
+    /// Keep afk and cut freeze stretches instead of cutting them out, adding an active column
+    /// that is 0 in them. Sequences are cut from whole player lifetimes, sequences without
+    /// any active tick are dropped
+    #[clap(long)]
+    mask_inactive: bool,
+
+    /// Cut out stretches of more than this many ticks in freeze, e.g. waiting in a freeze jail.
+    /// Freeze is detected from the game layer of the map files in --maps
+    #[clap(long)]
+    max_freeze_ticks: Option<usize>,
+
+    /// Add a frozen column, 1 in ticks the player was in freeze. Needs --maps, the column is
+    /// 0 for maps without map file
+    #[clap(long)]
+    freeze_mask: bool,
+
+    /// Add a phase column with the heuristic gameplay phase of each tick: 0 moving, 1 hooking,
+    /// 2 waiting, 3 frozen and 4 falling. Frozen needs --maps
+    #[clap(long)]
+    phase_labels: bool,
+
+    /// Also export the last seq_length ticks of durations that don't divide into sequences
+    /// evenly, overlapping the previous sequence. By default the remaining ticks are dropped
+    #[clap(long)]
+    keep_tails: bool,
 
     /// Cut sequence on player kill
     #[clap(short = 'k', long)]
@@ -45,79 +675,1807 @@ struct Cli {
     #[clap(short = 'b', long, default_value = "1000")]
     file_chunk_size: usize,
 
-    /// number of teehistorian files to process before saving to file
+    /// number of threads used to parse files, defaults to the number of cores
+    #[clap(long)]
+    threads: Option<usize>,
+
+    /// approximate memory limit of a parsed batch in gigabytes. If a batch exceeds it,
+    /// the file chunk size of the following batches is reduced accordingly.
+    #[clap(long = "max-batch-memory")]
+    max_batch_memory_gb: Option<f64>,
+
+    /// maximum number of teehistorian files to process, files processed by the resumed run
+    /// don't count
     #[clap(long, default_value = "2000")]
     max_files: usize,
 
     #[clap(long, default_value = "100")]
     max_speed: i32,
 
+    /// fill gaps of up to this many ticks without inputs, e.g. of a client timing out and
+    /// reconnecting, with the last input before them. Longer gaps split the sequence
+    #[clap(long, default_value = "0")]
+    max_input_gap: usize,
+
     #[clap(long, default_value = "1000")]
     max_aim_distance: i32,
 
     #[clap(short = 'd', long)]
     dry_run: bool,
 
+    /// memory map uncompressed input files instead of reading them through a buffer
+    #[clap(long)]
+    mmap: bool,
+
+    /// with --dry-run, only process this many randomly chosen files and extrapolate
+    /// size and duration estimates to all input files
+    #[clap(long)]
+    dry_run_sample: Option<usize>,
+
     /// after export, give summary of players with top k amount of sequences
     #[clap(short = 'p', long)]
     print_top_k: Option<usize>,
 
+    /// after export, write an html report with summary, players, maps, drop reasons, alias
+    /// candidates and a few sample plots to this file
+    #[clap(long)]
+    html_report: Option<PathBuf>,
+
     /// csv list of player names to include. All others will be filtered out.
     #[clap(short = 'f', long, value_delimiter = ',')]
     filter_players: Option<Vec<String>>,
+
+    /// file with newline-separated player names to include, combined with --filter-players
+    #[clap(long)]
+    players_file: Option<PathBuf>,
+
+    /// file with newline-separated player names to exclude
+    #[clap(long)]
+    exclude_players_file: Option<PathBuf>,
+
+    /// csv list of timeout code globs to include (exact or prefix, e.g. "abc*")
+    #[clap(long, value_delimiter = ',')]
+    filter_timeout_codes: Option<Vec<Pattern>>,
+
+    /// csv list of timeout code globs to exclude, e.g. known bot clients
+    #[clap(long, value_delimiter = ',')]
+    exclude_timeout_codes: Option<Vec<Pattern>>,
+
+    /// strip control characters from player names and apply unicode NFKC normalization
+    #[clap(long)]
+    normalize_names: bool,
+
+    /// skip sequences of clients without timeout code, by default they are exported with an
+    /// empty timeout
+    #[clap(long)]
+    require_timeout_code: bool,
+
+    /// csv list of map name globs to include (e.g. "Kobra*"). Other maps are skipped.
+    #[clap(long, value_delimiter = ',')]
+    filter_maps: Option<Vec<Pattern>>,
+
+    /// csv list of map name globs to exclude. Takes precedence over --filter-maps.
+    #[clap(long, value_delimiter = ',')]
+    exclude_maps: Option<Vec<Pattern>>,
+
+    /// stop parsing new files after this duration (e.g. "2h", "90min") and finalize the dataset
+    #[clap(long)]
+    time_budget: Option<humantime::Duration>,
+
+    /// stop parsing a single file after this duration (e.g. "30s"), keeping its sequences so far
+    #[clap(long)]
+    file_timeout: Option<humantime::Duration>,
+
+    /// order in which input files are processed
+    #[clap(long, value_enum, default_value = "default")]
+    file_order: FileOrder,
+
+    /// seed for random file order and --sample-fraction, random if not set
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// only export sequences in which the player finished the map
+    #[clap(long, conflicts_with = "only_unfinished")]
+    only_finished: bool,
+
+    /// only export sequences in which the player did not finish the map
+    #[clap(long)]
+    only_unfinished: bool,
+
+    /// drop sequences of known bot names or with bot-like inputs (periodic inputs, static aim)
+    #[clap(long)]
+    drop_bots: bool,
+
+    /// export sequences of empty names or names with control characters under a sanitized name,
+    /// by default they are dropped
+    #[clap(long)]
+    keep_invalid_names: bool,
+
+    /// drop cleaned sequences whose fraction of ticks with any input (move, jump, fire, hook)
+    /// is below this threshold
+    #[clap(long)]
+    min_activity_ratio: Option<f32>,
+
+    /// Drop duplicates of earlier sequences: exact ones with the same inputs, aim and movement,
+    /// or near ones with the same move, jump, fire and hook inputs
+    #[clap(long)]
+    dedup: Option<DedupMode>,
+
+    /// Drop sequences whose velocities, relative aim targets or aim distances lie beyond this
+    /// quantile (0-0.5) of the extremes of --outlier-sample sequences, e.g. 0.001
+    #[clap(long)]
+    outlier_quantile: Option<f64>,
+
+    /// clip the values of outliers to the quantiles instead of dropping them
+    #[clap(long, requires = "outlier_quantile")]
+    clip_outliers: bool,
+
+    /// amount of sequences at the start of the run the outlier quantiles are computed from
+    #[clap(long, default_value = "10000")]
+    outlier_sample: usize,
+
+    /// csv list of causal filters of velocity and aim columns, <column>=ma:<window> for a
+    /// moving average or <column>=ema:<alpha> for exponential smoothing, e.g. vel_x=ma:5
+    #[clap(long, value_delimiter = ',', value_parser = parse_column_smoothing)]
+    smooth: Vec<(String, Smoothing)>,
+
+    /// export only a random fraction (0-1] of cleaned sequences, see --seed
+    #[clap(long, value_parser = parse_fraction)]
+    sample_fraction: Option<f64>,
+
+    /// Csv list of rules for a weight column in meta.csv, multiplied and normalized to mean 1:
+    /// player (inverse sequences of the player), map (inverse sequences on the map) or
+    /// recency:<half-life days> (halved per half-life before the newest sequence),
+    /// e.g. player,recency:90
+    #[clap(long, value_delimiter = ',', value_parser = parse_weight_rule)]
+    sample_weights: Vec<WeightRule>,
+
+    /// Write a behavioral embedding of each sequence (input, aim and movement rhythm
+    /// histograms) as embeddings dataset into sequences.h5
+    #[clap(long)]
+    embeddings: bool,
+
+    /// Add Laplace noise with this privacy budget per value to positions and cursor targets
+    /// and coarsen recording times, for public releases. Lower values mean more noise.
+    #[clap(long)]
+    privacy_epsilon: Option<f64>,
+
+    /// with --privacy-epsilon, round recording times in meta.csv down to this resolution
+    #[clap(long, default_value = "1day")]
+    privacy_time_resolution: humantime::Duration,
+
+    /// Write the path of each sequence through a grid of cells of this many world units (32
+    /// per tile) into meta.csv and cluster the sequences of each map into routes by the cells
+    /// they visit
+    #[clap(long)]
+    route_cell_size: Option<i32>,
+
+    /// with --route-cell-size, highest jaccard distance (0-1) of the visited cells of a
+    /// sequence to the first sequence of its route
+    #[clap(long, default_value = "0.5")]
+    route_max_distance: f64,
+
+    /// stop exporting once the dataset reaches this size in gigabytes
+    #[clap(long)]
+    max_dataset_gb: Option<f64>,
+
+    /// ledger.csv of a previous run, its per-file yields are used for --file-order yield
+    #[clap(long)]
+    prior_ledger: Option<PathBuf>,
+
+    /// players.json of a previous export, its players keep their player_ids and aliases.
+    /// Resumed runs continue with the registry of their checkpoint instead
+    #[clap(long)]
+    player_registry: Option<PathBuf>,
+
+    /// toml or yaml file mapping canonical player names to lists of aliases, which share the
+    /// player_id of the canonical name. Recorded in players.json, ignored by resumed runs
+    #[clap(long)]
+    aliases: Option<PathBuf>,
+
+    /// folder of <map_name>.map files, their size and spawn points are added to meta.csv and
+    /// freeze is detected from their game layer
+    #[clap(long)]
+    maps: Option<PathBuf>,
+
+    /// csv file with map and stars columns, the difficulty stars are added to meta.csv
+    #[clap(long)]
+    map_info: Option<PathBuf>,
+
+    /// json dump of the ddnet.org map info, category, points, stars and release date of the
+    /// listed maps are added to meta.csv
+    #[clap(long)]
+    ddnet_map_info: Option<PathBuf>,
+
+    /// query the ddnet.org map info of maps missing in --ddnet-map-info and cache the
+    /// responses in this folder
+    #[cfg(feature = "ddnet-api")]
+    #[clap(long)]
+    ddnet_api_cache: Option<PathBuf>,
+
+    /// ddnet ranks dump (csv, or sqlite with the sqlite feature), the best finish time of
+    /// each player on the map and its rank are added to meta.csv
+    #[clap(long)]
+    records: Option<PathBuf>,
+
+    /// Annotations (csv or parquet) with a label column and optional teehist, cid, player,
+    /// start_tick and end_tick columns, e.g. cheat labels or ban lists. Labels of the
+    /// annotations overlapping a sequence are added to meta.csv.
+    #[clap(long)]
+    labels: Option<PathBuf>,
+
+    /// only include files recorded at or after this date (YYYY-MM-DD or RFC 3339)
+    #[clap(long, value_parser = parse_date)]
+    since: Option<DateTime<Utc>>,
+
+    /// only include files recorded before this date (YYYY-MM-DD or RFC 3339)
+    #[clap(long, value_parser = parse_date)]
+    until: Option<DateTime<Utc>>,
+
+    /// upload the export with a SHA256SUMS file to s3://bucket/prefix once the run ended,
+    /// also when it stopped early. Other S3 compatible storage is used with AWS_ENDPOINT_URL
+    #[cfg(feature = "remote")]
+    #[clap(long, value_parser = parse_s3_prefix)]
+    upload: Option<String>,
+
+    /// serve Prometheus metrics at http://<addr>/metrics during the run (e.g. "0.0.0.0:9184")
+    #[clap(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// publish sequences as json messages to a NATS server (nats://[user:password@]host[:port])
+    /// instead of writing sequences.h5 and meta.csv
+    #[clap(long, value_parser = parse_nats_url)]
+    nats_url: Option<String>,
+
+    /// subject the sequences are published to, see --nats-url
+    #[clap(long, default_value = "teehistorian.sequences")]
+    nats_subject: String,
+
+    /// publish a message per tick with the features keyed by column name, instead of one per
+    /// sequence
+    #[clap(long, requires = "nats_url")]
+    nats_frames: bool,
+}
+
+/// read newline-separated player names, ignoring empty lines
+fn read_player_names(path: &PathBuf) -> HashSet<String> {
+    fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("failed to read player file {:?}: {}", path, err))
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// union of --filter-players and --players-file, None if neither is given
+fn get_filter_players(args: &ExtractArgs) -> Option<HashSet<String>> {
+    if args.filter_players.is_none() && args.players_file.is_none() {
+        return None;
+    }
+
+    let mut filter_players: HashSet<String> =
+        args.filter_players.iter().flatten().cloned().collect();
+    if let Some(players_file) = &args.players_file {
+        filter_players.extend(read_player_names(players_file));
+    }
+    info!("including {} player names", filter_players.len());
+    Some(filter_players)
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s
+        .parse()
+        .map_err(|e| format!("invalid fraction '{}': {}", s, e))?;
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("fraction must be in (0, 1], got {}", fraction))
+    }
 }
 
-fn batched_export(args: &Cli) {
-    let parser_config = ParserConfig::new(
-        args.cut_kill,
-        args.cut_rescue,
-        args.max_speed,
-        args.filter_players.clone(),
+#[cfg(feature = "remote")]
+fn parse_s3_prefix(s: &str) -> Result<String, String> {
+    match teehistorian_extractor::remote::parse_s3_url(s) {
+        Some(_) => Ok(s.to_string()),
+        None => Err(format!("expected s3://bucket/prefix, got '{}'", s)),
+    }
+}
+
+fn parse_nats_url(s: &str) -> Result<String, String> {
+    match nats::parse_nats_url(s) {
+        Some(_) => Ok(s.to_string()),
+        None => Err(format!(
+            "expected nats://[user:password@]host[:port], got '{}'",
+            s
+        )),
+    }
+}
+
+fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("invalid date '{}': {}", s, e))
+}
+
+/// keep paths whose recording start lies within [since, until)
+fn filter_paths_by_date(
+    paths: &mut Vec<PathBuf>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) {
+    if since.is_none() && until.is_none() {
+        return;
+    }
+
+    let file_count = paths.len();
+    paths.retain(|path| match Extractor::get_start_time(path) {
+        Some(start_time) => {
+            since.is_none_or(|since| start_time >= since)
+                && until.is_none_or(|until| start_time < until)
+        }
+        None => false,
+    });
+    info!("date filter kept {} of {} files", paths.len(), file_count);
+}
+
+fn order_paths(
+    paths: &mut [PathBuf],
+    file_order: &FileOrder,
+    seed: Option<u64>,
+    prior_ledger: &Option<PathBuf>,
+) {
+    match file_order {
+        FileOrder::Default => {}
+        FileOrder::Largest => {
+            paths.sort_by_cached_key(|path| {
+                std::cmp::Reverse(fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+            });
+        }
+        FileOrder::Random => {
+            let mut rng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            paths.shuffle(&mut rng);
+        }
+        FileOrder::Yield => {
+            let prior_yields = prior_ledger
+                .as_ref()
+                .map(load_ledger_yields)
+                .unwrap_or_default();
+            let index = HeaderIndex::build(paths);
+            let ordered = index.order_by_expected_yield(&prior_yields);
+            paths.clone_from_slice(&ordered);
+        }
+    }
+}
+
+/// overwrite target with value if any of the given args was passed on the command line
+fn override_if_passed<T>(matches: &ArgMatches, ids: &[&str], target: &mut T, value: T) {
+    if ids
+        .iter()
+        .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+    {
+        *target = value;
+    }
+}
+
+/// Parser and export config of the extract command.
+/// Values of --config are used unless the corresponding flag is passed explicitly.
+fn get_run_config(args: &ExtractArgs, matches: &ArgMatches) -> Result<RunConfig, ConfigError> {
+    let parser_config = ParserConfig::builder()
+        .cut_kill(args.cut_kill)
+        .cut_rescue(args.cut_rescue)
+        .max_speed(args.max_speed)
+        .max_input_gap(args.max_input_gap)
+        .filter_players(get_filter_players(args))
+        .exclude_players(args.exclude_players_file.as_ref().map(read_player_names))
+        .filter_timeout_codes(args.filter_timeout_codes.clone())
+        .exclude_timeout_codes(args.exclude_timeout_codes.clone())
+        .require_timeout_code(args.require_timeout_code)
+        .name_normalization(if args.normalize_names {
+            NameNormalization::all()
+        } else {
+            NameNormalization::default()
+        })
+        .filter_maps(args.filter_maps.clone())
+        .exclude_maps(args.exclude_maps.clone())
+        .mmap(args.mmap)
+        .build()?;
+    let export_config = ExportConfig::builder()
+        .seq_length(extract_seq_lengths(args)[0])
+        .afk_ticks(args.afk_ticks)
+        .afk_inputs(args.afk_inputs.clone())
+        .afk_padding(args.afk_padding)
+        .merge_gap(args.merge_gap)
+        .session_gap(args.session_gap)
+        .max_freeze_ticks(args.max_freeze_ticks)
+        .freeze_mask(args.freeze_mask)
+        .mask_inactive(args.mask_inactive)
+        .phase_labels(args.phase_labels)
+        .keep_tails(args.keep_tails)
+        .dry_run(args.dry_run)
+        .max_dataset_bytes(args.max_dataset_gb.map(|gb| (gb * 1e9) as u64))
+        .finish_filter(if args.only_finished {
+            Some(FinishFilter::Finished)
+        } else if args.only_unfinished {
+            Some(FinishFilter::Unfinished)
+        } else {
+            None
+        })
+        .drop_bots(args.drop_bots)
+        .keep_invalid_names(args.keep_invalid_names)
+        .min_activity_ratio(args.min_activity_ratio)
+        .dedup(args.dedup)
+        .outlier_quantile(args.outlier_quantile)
+        .clip_outliers(args.clip_outliers)
+        .outlier_sample(args.outlier_sample)
+        .smoothing(args.smooth.iter().cloned().collect())
+        .sample_fraction(args.sample_fraction)
+        .seed(args.seed)
+        .sample_weights(args.sample_weights.clone())
+        .embeddings(args.embeddings)
+        .privacy(privacy_noise(args))
+        .routes(args.route_cell_size.map(|cell_size| RouteConfig {
+            cell_size,
+            max_distance: args.route_max_distance,
+        }))
+        .build()?;
+
+    let resume_config = args
+        .resume
+        .then(|| extract_output_folders(args)[0].join(CONFIG_FILE_NAME))
+        .filter(|path| path.is_file());
+    let Some(config_path) = args.config.as_ref().or(resume_config.as_ref()) else {
+        return Ok(RunConfig::new(parser_config, export_config));
+    };
+    info!("loading config {:?}", config_path);
+    let mut config = RunConfig::load(config_path)?;
+
+    let parser = &mut config.parser;
+    override_if_passed(
+        matches,
+        &["cut_kill"],
+        &mut parser.cut_kill,
+        parser_config.cut_kill,
+    );
+    override_if_passed(
+        matches,
+        &["cut_rescue"],
+        &mut parser.cut_rescue,
+        parser_config.cut_rescue,
+    );
+    override_if_passed(
+        matches,
+        &["max_speed"],
+        &mut parser.max_speed,
+        parser_config.max_speed,
+    );
+    override_if_passed(
+        matches,
+        &["max_input_gap"],
+        &mut parser.max_input_gap,
+        parser_config.max_input_gap,
+    );
+    override_if_passed(
+        matches,
+        &["filter_players", "players_file"],
+        &mut parser.filter_players,
+        parser_config.filter_players,
+    );
+    override_if_passed(
+        matches,
+        &["exclude_players_file"],
+        &mut parser.exclude_players,
+        parser_config.exclude_players,
+    );
+    override_if_passed(
+        matches,
+        &["filter_timeout_codes"],
+        &mut parser.filter_timeout_codes,
+        parser_config.filter_timeout_codes,
+    );
+    override_if_passed(
+        matches,
+        &["exclude_timeout_codes"],
+        &mut parser.exclude_timeout_codes,
+        parser_config.exclude_timeout_codes,
+    );
+    override_if_passed(
+        matches,
+        &["require_timeout_code"],
+        &mut parser.require_timeout_code,
+        parser_config.require_timeout_code,
+    );
+    override_if_passed(
+        matches,
+        &["normalize_names"],
+        &mut parser.name_normalization,
+        parser_config.name_normalization,
+    );
+    override_if_passed(
+        matches,
+        &["filter_maps"],
+        &mut parser.filter_maps,
+        parser_config.filter_maps,
+    );
+    override_if_passed(
+        matches,
+        &["exclude_maps"],
+        &mut parser.exclude_maps,
+        parser_config.exclude_maps,
+    );
+    override_if_passed(matches, &["mmap"], &mut parser.mmap, parser_config.mmap);
+
+    let export = &mut config.export;
+    override_if_passed(
+        matches,
+        &["seq_length"],
+        &mut export.seq_length,
+        export_config.seq_length,
+    );
+    override_if_passed(
+        matches,
+        &["afk_ticks"],
+        &mut export.afk_ticks,
+        export_config.afk_ticks,
+    );
+    override_if_passed(
+        matches,
+        &["afk_inputs"],
+        &mut export.afk_inputs,
+        export_config.afk_inputs.clone(),
+    );
+    override_if_passed(
+        matches,
+        &["afk_padding"],
+        &mut export.afk_padding,
+        export_config.afk_padding,
+    );
+    override_if_passed(
+        matches,
+        &["merge_gap"],
+        &mut export.merge_gap,
+        export_config.merge_gap,
+    );
+    override_if_passed(
+        matches,
+        &["session_gap"],
+        &mut export.session_gap,
+        export_config.session_gap,
+    );
+    override_if_passed(
+        matches,
+        &["max_freeze_ticks"],
+        &mut export.max_freeze_ticks,
+        export_config.max_freeze_ticks,
     );
-    let export_config = ExportConfig {
-        seq_length: args.seq_length,
-        afk_ticks: args.afk_ticks,
-        afk_padding: args.afk_padding,
-        dry_run: args.dry_run,
-        use_vel: true,
-        use_rel_target: false,
-        use_aim_angle: true,
-        use_aim_distance: true,
+    override_if_passed(
+        matches,
+        &["freeze_mask"],
+        &mut export.freeze_mask,
+        export_config.freeze_mask,
+    );
+    override_if_passed(
+        matches,
+        &["mask_inactive"],
+        &mut export.mask_inactive,
+        export_config.mask_inactive,
+    );
+    override_if_passed(
+        matches,
+        &["phase_labels"],
+        &mut export.phase_labels,
+        export_config.phase_labels,
+    );
+    override_if_passed(
+        matches,
+        &["keep_tails"],
+        &mut export.keep_tails,
+        export_config.keep_tails,
+    );
+    override_if_passed(
+        matches,
+        &["dry_run"],
+        &mut export.dry_run,
+        export_config.dry_run,
+    );
+    override_if_passed(
+        matches,
+        &["max_dataset_gb"],
+        &mut export.max_dataset_bytes,
+        export_config.max_dataset_bytes,
+    );
+    override_if_passed(
+        matches,
+        &["only_finished", "only_unfinished"],
+        &mut export.finish_filter,
+        export_config.finish_filter,
+    );
+    override_if_passed(
+        matches,
+        &["drop_bots"],
+        &mut export.drop_bots,
+        export_config.drop_bots,
+    );
+    override_if_passed(
+        matches,
+        &["keep_invalid_names"],
+        &mut export.keep_invalid_names,
+        export_config.keep_invalid_names,
+    );
+    override_if_passed(
+        matches,
+        &["min_activity_ratio"],
+        &mut export.min_activity_ratio,
+        export_config.min_activity_ratio,
+    );
+    override_if_passed(matches, &["dedup"], &mut export.dedup, export_config.dedup);
+    override_if_passed(
+        matches,
+        &["outlier_quantile"],
+        &mut export.outlier_quantile,
+        export_config.outlier_quantile,
+    );
+    override_if_passed(
+        matches,
+        &["clip_outliers"],
+        &mut export.clip_outliers,
+        export_config.clip_outliers,
+    );
+    override_if_passed(
+        matches,
+        &["outlier_sample"],
+        &mut export.outlier_sample,
+        export_config.outlier_sample,
+    );
+    override_if_passed(
+        matches,
+        &["smooth"],
+        &mut export.smoothing,
+        export_config.smoothing.clone(),
+    );
+    override_if_passed(
+        matches,
+        &["sample_fraction"],
+        &mut export.sample_fraction,
+        export_config.sample_fraction,
+    );
+    override_if_passed(matches, &["seed"], &mut export.seed, export_config.seed);
+    override_if_passed(
+        matches,
+        &["sample_weights"],
+        &mut export.sample_weights,
+        export_config.sample_weights.clone(),
+    );
+    override_if_passed(
+        matches,
+        &["embeddings"],
+        &mut export.embeddings,
+        export_config.embeddings,
+    );
+    override_if_passed(
+        matches,
+        &["privacy_epsilon", "privacy_time_resolution"],
+        &mut export.privacy,
+        export_config.privacy.clone(),
+    );
+    override_if_passed(
+        matches,
+        &["route_cell_size", "route_max_distance"],
+        &mut export.routes,
+        export_config.routes.clone(),
+    );
+
+    config.validate()?;
+    Ok(config)
+}
+
+/// privacy noise of the extract args, None unless --privacy-epsilon is passed
+fn privacy_noise(args: &ExtractArgs) -> Option<PrivacyNoise> {
+    args.privacy_epsilon.map(|epsilon| PrivacyNoise {
+        epsilon,
+        time_resolution: args.privacy_time_resolution.as_secs(),
+    })
+}
+
+/// seq_lengths of the extract args, sorted and without duplicates
+fn extract_seq_lengths(args: &ExtractArgs) -> Vec<usize> {
+    let mut seq_lengths = args.seq_length.clone();
+    seq_lengths.sort_unstable();
+    seq_lengths.dedup();
+    seq_lengths
+}
+
+/// Dataset folder of each seq_length of the extract args, seq_length_<n> subfolders of the
+/// output folder if there are several
+fn extract_output_folders(args: &ExtractArgs) -> Vec<PathBuf> {
+    match extract_seq_lengths(args) {
+        seq_lengths if seq_lengths.len() > 1 => seq_lengths
+            .iter()
+            .map(|seq_length| {
+                args.output_folder
+                    .join(format!("seq_length_{}", seq_length))
+            })
+            .collect(),
+        _ => vec![args.output_folder.clone()],
+    }
+}
+
+/// Apply the player registry, aliases, map info, records and labels of the extract args to an
+/// exporter
+fn configure_exporter<S: ExportSink>(
+    exporter: &mut Exporter<S>,
+    args: &ExtractArgs,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(registry_path) = &args.player_registry {
+        if args.resume {
+            warn!("ignoring --player-registry, the resumed run keeps its own registry");
+        } else {
+            exporter.registry = PlayerRegistry::load(registry_path)?;
+            info!(
+                "loaded {} players and {} aliases from {:?}",
+                exporter.registry.players.len(),
+                exporter.registry.aliases.len(),
+                registry_path
+            );
+        }
+    }
+    if let Some(aliases_path) = &args.aliases {
+        if args.resume {
+            warn!("ignoring --aliases, sequences of the resumed run already have player_ids");
+        } else {
+            let aliases = registry::load_alias_map(aliases_path)?;
+            exporter.registry.apply_aliases(&aliases)?;
+            info!(
+                "applied aliases of {} players from {:?}",
+                aliases.len(),
+                aliases_path
+            );
+        }
+    }
+
+    exporter.maps = MapCatalog::new(args.maps.as_deref());
+    if let Some(map_info_path) = &args.map_info {
+        exporter.maps.load_stars(map_info_path)?;
+    }
+    if let Some(ddnet_map_info_path) = &args.ddnet_map_info {
+        exporter.maps.load_ddnet_info(ddnet_map_info_path)?;
+    }
+    #[cfg(feature = "ddnet-api")]
+    if let Some(cache_folder) = &args.ddnet_api_cache {
+        exporter.maps.use_ddnet_api(cache_folder)?;
+    }
+    if let Some(records_path) = &args.records {
+        exporter.records = RecordIndex::load(records_path)?;
+        info!(
+            "loaded {} records from {:?}",
+            exporter.records.len(),
+            records_path
+        );
+    }
+    if let Some(labels_path) = &args.labels {
+        exporter.labels = LabelIndex::load(labels_path)?;
+        info!(
+            "loaded {} annotations from {:?}",
+            exporter.labels.len(),
+            labels_path
+        );
+    }
+    Ok(())
+}
+
+fn batched_export(
+    args: &ExtractArgs,
+    matches: &ArgMatches,
+    multi_progress: &MultiProgress,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    let RunConfig {
+        parser: parser_config,
+        export: export_config,
+        ..
+    } = get_run_config(args, matches)?;
+    // further seq_lengths are cut from the same parsed files, each into a folder of its own
+    let output_folders = extract_output_folders(args);
+    let seq_lengths = match extract_seq_lengths(args) {
+        seq_lengths if seq_lengths.len() > 1 => seq_lengths,
+        _ => vec![export_config.seq_length],
     };
-    let mut exporter = Exporter::new(&args.output_folder, export_config.clone());
+    if seq_lengths.len() > 1 && args.nats_url.is_some() {
+        return Err("--nats-url can't export multiple seq_lengths".into());
+    }
+    let export_configs = seq_lengths
+        .iter()
+        .map(|&seq_length| {
+            let config = ExportConfig {
+                seq_length,
+                ..export_config.clone()
+            };
+            config.validate()?;
+            Ok(config)
+        })
+        .collect::<Result<Vec<ExportConfig>, ConfigError>>()?;
+    let export_config = export_configs[0].clone();
+
+    if args.overwrite && !export_config.dry_run {
+        for output_folder in &output_folders {
+            let removed = remove_export_files(output_folder)?;
+            info!(
+                "removed {} files of an earlier export in {:?}",
+                removed, output_folder
+            );
+        }
+    }
+    let deadline = args
+        .time_budget
+        .map(|budget| Instant::now() + budget.into());
+    let mut exporters = Vec::with_capacity(output_folders.len());
+    for (output_folder, config) in output_folders.iter().zip(&export_configs) {
+        // sequences are written on a separate thread while the next files are parsed
+        let sink: Box<dyn ExportSink + Send> = match &args.nats_url {
+            Some(url) => {
+                let payload = match args.nats_frames {
+                    true => NatsPayload::Frame,
+                    false => NatsPayload::Sequence,
+                };
+                Box::new(NatsSink::new(url, &args.nats_subject, payload))
+            }
+            None => Box::new(Hdf5Sink::new(output_folder)),
+        };
+        let sink = BackgroundSink::new(sink);
+        let mut exporter = if args.resume {
+            Exporter::resume_with_sink(output_folder, config.clone(), sink)?
+        } else {
+            Exporter::with_sink(output_folder, config.clone(), sink)?
+        };
+        if !config.dry_run && !args.resume {
+            RunConfig::new(parser_config.clone(), config.clone()).save(output_folder)?;
+        }
+        exporter.deadline = deadline;
+        configure_exporter(&mut exporter, args)?;
+        exporters.push(exporter);
+    }
+    let mut exporter = exporters.remove(0);
+    exporter.resolutions = exporters;
+    exporter.file_timeout = args.file_timeout.map(Into::into);
+    if let Some(addr) = args.metrics_addr {
+        exporter.metrics = Some(start_metrics(addr)?);
+    }
 
     // get all files
-    let mut paths: Vec<_> = fs::read_dir(&args.input)
-        .unwrap()
-        .filter_map(|entry| entry.ok().map(|e| e.path()))
+    let mut paths = args.input.paths();
+    filter_paths_by_date(&mut paths, args.since, args.until);
+    order_paths(
+        &mut paths,
+        &args.file_order,
+        export_config.seed,
+        &args.prior_ledger,
+    );
+
+    // files of the interrupted run are already part of the dataset
+    let processed: HashSet<PathBuf> = exporter.processed_files.iter().cloned().collect();
+    let mut pending_paths: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| !processed.contains(*path))
+        .cloned()
         .collect();
-    paths.truncate(args.max_files);
-    let file_count = paths.len();
-    let batch_count = (file_count + args.file_chunk_size - 1) / args.file_chunk_size;
+
+    // files ingested by earlier runs, also if they were moved or renamed since
+    if args.resume {
+        let processed_hashes = load_processed_hashes(&output_folders[0]);
+        if !processed_hashes.is_empty() {
+            pending_paths = pending_paths
+                .into_par_iter()
+                .filter(|path| file_hash(path).is_ok_and(|hash| !processed_hashes.contains(&hash)))
+                .collect();
+            info!(
+                "skipping files of {} already processed hashes",
+                processed_hashes.len()
+            );
+        }
+    }
+
+    // the limit applies to new files, so resumed runs don't spend it on processed ones
+    pending_paths.truncate(args.max_files);
+
+    // dry runs may only look at a sample, estimates are scaled up accordingly
+    let total_file_count = pending_paths.len();
+    if let Some(sample_size) = args.dry_run_sample.filter(|_| export_config.dry_run) {
+        let mut rng = match export_config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        pending_paths.shuffle(&mut rng);
+        pending_paths.truncate(sample_size.max(1));
+        info!(
+            "dry run on a sample of {} of {} files",
+            pending_paths.len(),
+            total_file_count
+        );
+    }
+    let started = Instant::now();
+
+    let file_count = pending_paths.len();
+    if let Some(metrics) = &exporter.metrics {
+        metrics
+            .files_pending
+            .store(file_count as u64, Ordering::Relaxed);
+    }
+    let mut file_chunk_size = args.file_chunk_size;
+    let mut batch_count = file_count.div_ceil(file_chunk_size);
     info!("found {} files to parse", file_count);
+    exporter.progress = Some(ExportProgress::new(multi_progress, batch_count, file_count));
+    let max_batch_bytes = args.max_batch_memory_gb.map(|gb| (gb * 1e9) as usize);
 
     // process all files in batches
-    for (batch_index, batch_paths) in paths.chunks(args.file_chunk_size).enumerate() {
+    let mut batch_start = 0;
+    let mut batch_index = 0;
+    while batch_start < file_count {
+        let batch_paths =
+            &pending_paths[batch_start..(batch_start + file_chunk_size).min(file_count)];
+        if exporter.budget_expired() {
+            info!(
+                "time budget expired after {} files",
+                exporter.processed_files.len()
+            );
+            break;
+        }
+        if exporter.quota_reached {
+            info!(
+                "dataset size quota reached after {} files",
+                exporter.processed_files.len()
+            );
+            break;
+        }
         info!(
             "[{}/{}] parsing {} files",
             batch_index + 1,
             batch_count,
             batch_paths.len()
         );
-        exporter.handle_batch(batch_paths, &parser_config, &export_config);
+        exporter.handle_batch(batch_paths, &parser_config, &export_config)?;
+        batch_start += batch_paths.len();
+        batch_index += 1;
+
+        // shrink following batches if this one used too much memory
+        if let Some(max_batch_bytes) = max_batch_bytes {
+            if exporter.last_batch_bytes > max_batch_bytes {
+                let bytes_per_file = (exporter.last_batch_bytes / batch_paths.len()).max(1);
+                file_chunk_size = (max_batch_bytes / bytes_per_file).clamp(1, file_chunk_size);
+                batch_count = batch_index + (file_count - batch_start).div_ceil(file_chunk_size);
+                warn!(
+                    "batch used {:.1} GB, reducing file chunk size to {}",
+                    exporter.last_batch_bytes as f64 / 1e9,
+                    file_chunk_size
+                );
+            }
+        }
+        if let Some(progress) = &exporter.progress {
+            progress.batches.set_length(batch_count as u64);
+            progress.batches.inc(1);
+        }
+    }
+    if let Some(progress) = exporter.progress.take() {
+        progress.finish();
     }
+    exporter.finalize(&paths)?;
 
     exporter.print_summary(args.print_top_k.unwrap_or(10));
+    for (resolution, output_folder) in exporter.resolutions.iter().zip(&output_folders[1..]) {
+        info!(
+            "{:?}: {} sequences of {} players",
+            output_folder, resolution.sequence_count, resolution.player_count
+        );
+    }
+    if let Some(report_path) = &args.html_report {
+        // dry runs have no sequences to plot
+        let sample_plots = if export_config.dry_run {
+            Vec::new()
+        } else {
+            report::sample_plots(&output_folders[0], REPORT_SAMPLE_PLOTS).unwrap_or_else(|err| {
+                warn!("no sample plots in report: {}", err);
+                Vec::new()
+            })
+        };
+        let html = report::render_html(
+            &exporter,
+            started.elapsed(),
+            args.print_top_k.unwrap_or(10),
+            &sample_plots,
+        );
+        fs::write(report_path, html)?;
+        info!("wrote report to {:?}", report_path);
+    }
+    #[cfg(feature = "remote")]
+    if let Some(url) = args.upload.as_ref().filter(|_| !export_config.dry_run) {
+        for output_folder in &output_folders {
+            // datasets of multiple seq_lengths keep their subfolder below the url
+            let url = match output_folder.strip_prefix(&args.output_folder) {
+                Ok(subfolder) if output_folders.len() > 1 => {
+                    format!("{}/{}", url.trim_end_matches('/'), subfolder.display())
+                }
+                _ => url.clone(),
+            };
+            let uploaded = teehistorian_extractor::upload::upload_export(output_folder, &url)?;
+            info!("uploaded {} files to {}", uploaded.len(), url);
+        }
+    }
+    if export_config.dry_run {
+        let processed_count = exporter.summary.files_processed.max(1);
+        exporter.print_dry_run_estimate(
+            total_file_count as f64 / processed_count as f64,
+            started.elapsed(),
+            args.print_top_k.unwrap_or(10),
+        );
+    }
+    Ok(())
+}
+
+fn inspect(args: &InspectArgs) -> Result<(), Box<dyn Error>> {
+    let Some(game_info) = Extractor::get_game_info(&args.file) else {
+        return Err(format!("couldn't read header of {:?}", args.file).into());
+    };
+    println!("file:        {}", args.file.to_string_lossy());
+    println!("server:      {}", game_info.server_name);
+    println!("map:         {}", game_info.map_name);
+    println!(
+        "start time:  {}",
+        game_info.start_time.as_deref().unwrap_or("unknown")
+    );
+
+    let parser_config = ParserConfig {
+        cut_kill: args.cut_kill,
+        cut_rescue: args.cut_rescue,
+        ..Default::default()
+    };
+    let parsed_file = Extractor::parse_file(&args.file, &parser_config)?;
+    let sequences = &parsed_file.sequences;
+    println!(
+        "duration:    {} ticks => {:.1} minutes",
+        parsed_file.ticks,
+        parsed_file.ticks as f32 / (50. * 60.)
+    );
+
+    println!("sessions:    {}", parsed_file.sessions.len());
+    for session in &parsed_file.sessions {
+        let leave_tick = session
+            .leave_tick
+            .map(|tick| tick.to_string())
+            .unwrap_or("end".to_string());
+        println!(
+            "  cid={:<3} {:<20} join={:<8} leave={}",
+            session.cid,
+            session.player_name.as_deref().unwrap_or("?"),
+            session.join_tick,
+            leave_tick
+        );
+    }
+
+    // player -> (sequences, ticks)
+    let mut players: HashMap<&str, (usize, usize)> = HashMap::new();
+    for sequence in sequences {
+        let player_name = sequence.player_name.as_deref().unwrap_or_default();
+        let entry = players.entry(player_name).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += sequence.input_vectors.len();
+    }
+    let mut players: Vec<_> = players.into_iter().collect();
+    players.sort_by_key(|(_, (_, ticks))| std::cmp::Reverse(*ticks));
+
+    println!("sequences:   {}", sequences.len());
+    println!("players:     {}", players.len());
+    for (player_name, (sequence_count, ticks)) in players {
+        println!(
+            "  {:<20} sequences={:<5} ticks={}",
+            player_name, sequence_count, ticks
+        );
+    }
+
+    println!("anomalies:   {}", parsed_file.anomalies.len());
+    for anomaly in parsed_file.anomalies.iter().take(args.max_anomalies) {
+        let cid = anomaly
+            .cid
+            .map(|cid| format!("cid={}", cid))
+            .unwrap_or_default();
+        println!(
+            "  tick={:<8} {:<7} {}",
+            anomaly.tick, cid, anomaly.description
+        );
+    }
+    if parsed_file.anomalies.len() > args.max_anomalies {
+        println!(
+            "  ... and {} more",
+            parsed_file.anomalies.len() - args.max_anomalies
+        );
+    }
+
+    if let Some(error) = &parsed_file.error {
+        println!(
+            "parse error: chunk={} tick={} {}",
+            error.chunk_index, error.tick, error.message
+        );
+    }
+    Ok(())
+}
+
+fn tail(args: &TailArgs) -> Result<(), Box<dyn Error>> {
+    let parser_config = ParserConfig {
+        cut_kill: args.cut_kill,
+        cut_rescue: args.cut_rescue,
+        ..Default::default()
+    };
+    let tail_config = TailConfig {
+        poll_interval: args.poll_interval.into(),
+        idle_timeout: args.idle_timeout.map(Into::into),
+        ..Default::default()
+    };
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let metrics = args.metrics_addr.map(start_metrics).transpose()?;
+
+    let mut count = 0;
+    let mut sequences = Extractor::tail_sequences(&args.file, &parser_config, tail_config);
+    while let Some(sequence) = sequences.next() {
+        match sequence {
+            Ok(sequence) => {
+                let line = serde_json::to_string(&sequence)?;
+                writeln!(writer, "{}", line)?;
+                // consumers read the lines as they arrive
+                writer.flush()?;
+                count += 1;
+                if let Some(metrics) = &metrics {
+                    metrics.sequences_exported.fetch_add(1, Ordering::Relaxed);
+                    metrics
+                        .bytes_written
+                        .fetch_add(line.len() as u64 + 1, Ordering::Relaxed);
+                }
+            }
+            Err(err) => {
+                warn!("{:?}: {}", args.file, err);
+                if let Some(metrics) = &metrics {
+                    metrics.add_parse_error(err.kind());
+                }
+            }
+        }
+        if let Some(metrics) = &metrics {
+            metrics
+                .ticks_parsed
+                .store(sequences.tick().max(0) as u64, Ordering::Relaxed);
+        }
+    }
+    info!("{} sequences", count);
+    Ok(())
+}
+
+/// serve the metrics of a run at addr, see [`metrics::serve`]
+fn start_metrics(addr: SocketAddr) -> Result<Arc<Metrics>, Box<dyn Error>> {
+    let metrics = Arc::new(Metrics::default());
+    let addr = metrics::serve(addr, metrics.clone())?;
+    info!("serving metrics at http://{}/metrics", addr);
+    Ok(metrics)
+}
+
+/// print sorted (name, (sequences, ticks)) entries, largest tick count first
+fn print_top_counts(title: &str, counts: HashMap<&str, (usize, usize)>, k: usize) {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, (_, ticks))| std::cmp::Reverse(*ticks));
+    println!("top {} {}:", k, title);
+    for (name, (sequence_count, ticks)) in counts.iter().take(k) {
+        println!(
+            "  {:<20} sequences={:<7} ticks={}",
+            name, sequence_count, ticks
+        );
+    }
+}
+
+fn stats(args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    let dataset = Dataset::open(&args.dataset)?;
+    let (sequence_count, seq_length, feature_count) = dataset.shape();
+    let total_ticks: usize = dataset.meta.iter().map(|row| row.ticks).sum();
+
+    println!("dataset:     {}", args.dataset.to_string_lossy());
+    println!("version:     {}", dataset.version);
+    println!(
+        "sequences:   {} ({} meta rows)",
+        sequence_count,
+        dataset.meta.len()
+    );
+    println!("seq_length:  {}", seq_length);
+    println!("features:    {} {:?}", feature_count, dataset.column_names);
+    println!(
+        "ticks:       {} => {:.1} hours of gameplay",
+        total_ticks,
+        total_ticks as f32 / (50. * 60. * 60.)
+    );
+
+    let player_counts = dataset.player_counts();
+    println!("players:     {}", player_counts.len());
+    print_top_counts("players", player_counts, args.print_top_k);
+    print_top_counts("maps", dataset.map_counts(), args.print_top_k);
+    Ok(())
+}
+
+fn compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    let old = Dataset::open(&args.old)?;
+    let new = Dataset::open(&args.new)?;
+    let report = DriftReport::new(&old, &new, args.max_sequences)?;
+    if args.json {
+        report.write_json(std::io::stdout().lock())?;
+    } else {
+        report.write_text(args.print_top_k, std::io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+fn player_stats(args: &PlayerStatsArgs) -> Result<(), Box<dyn Error>> {
+    let paths = args.input.paths();
+    info!("collecting player statistics of {} files", paths.len());
+    let stats = paths
+        .par_iter()
+        .filter_map(|path| {
+            match player_stats::file_player_stats(path, args.afk_ticks, &args.afk_inputs) {
+                Ok(stats) => Some(stats),
+                Err(err) => {
+                    warn!("skipping {:?}: {}", path, err);
+                    None
+                }
+            }
+        })
+        .reduce(HashMap::new, |mut stats, other| {
+            for (player, player_stats) in other {
+                stats.entry(player).or_default().merge(player_stats);
+            }
+            stats
+        });
+    let reports = player_stats::player_reports(stats);
+    info!("{} players", reports.len());
+
+    let writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match args.format {
+        ReportFormat::Csv => player_stats::write_csv(&reports, writer)?,
+        ReportFormat::Json => player_stats::write_json(&reports, writer)?,
+    }
+    Ok(())
+}
+
+fn reaction_times(args: &ReactionArgs) -> Result<(), Box<dyn Error>> {
+    let paths = args.input.paths();
+    info!("measuring reaction times in {} files", paths.len());
+    let config = ReactionConfig {
+        max_latency: args.max_latency,
+        min_speed: args.min_speed,
+        snap_angle: args.snap_angle,
+    };
+    let reactions = paths
+        .par_iter()
+        .filter_map(|path| match reaction::file_reactions(path, &config) {
+            Ok(reactions) => Some(reactions),
+            Err(err) => {
+                warn!("skipping {:?}: {}", path, err);
+                None
+            }
+        })
+        .reduce(HashMap::new, |mut reactions, other| {
+            reaction::merge_players(&mut reactions, other);
+            reactions
+        });
+    let reports = reaction::reaction_reports(reactions, args.min_events);
+    info!("{} player reaction distributions", reports.len());
+
+    let writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match args.format {
+        ReportFormat::Csv => reaction::write_csv(&reports, writer)?,
+        ReportFormat::Json => reaction::write_json(&reports, writer)?,
+    }
+    Ok(())
+}
+
+fn aliases(args: &AliasArgs) {
+    let paths = args.input.paths();
+    info!("collecting player fingerprints of {} files", paths.len());
+    let fingerprints = paths
+        .par_iter()
+        .filter_map(|path| match alias::file_fingerprints(path) {
+            Ok(fingerprints) => Some(fingerprints),
+            Err(err) => {
+                warn!("skipping {:?}: {}", path, err);
+                None
+            }
+        })
+        .reduce(HashMap::new, |mut fingerprints, other| {
+            for (player, fingerprint) in other {
+                fingerprints.entry(player).or_default().merge(fingerprint);
+            }
+            fingerprints
+        });
+    let candidates = alias::rank_aliases(
+        &fingerprints,
+        &AliasWeights::default(),
+        args.min_ticks,
+        args.min_score,
+    );
+    info!(
+        "{} of {} players compared, {} pairs above {}",
+        fingerprints
+            .values()
+            .filter(|f| f.ticks >= args.min_ticks)
+            .count(),
+        fingerprints.len(),
+        candidates.len(),
+        args.min_score
+    );
+
+    println!(
+        "{:>6} {:>8} {:>6} {:>6} {:>6}  players",
+        "score", "timeout", "input", "aim", "maps"
+    );
+    for candidate in candidates.iter().take(args.print_top_k) {
+        println!(
+            "{:>6.3} {:>8} {:>6.3} {:>6.3} {:>6.3}  {} | {}",
+            candidate.score,
+            candidate
+                .timeout_codes
+                .map(|jaccard| format!("{:.3}", jaccard))
+                .unwrap_or_else(|| "-".to_string()),
+            candidate.input_rate,
+            candidate.aim_speed,
+            candidate.maps,
+            candidate.player,
+            candidate.alias
+        );
+    }
+}
+
+fn heatmap(args: &HeatmapArgs) -> Result<(), Box<dyn Error>> {
+    let paths = args.input.paths();
+    info!("collecting player positions of {} files", paths.len());
+    let heatmaps = paths
+        .par_iter()
+        .filter_map(|path| match heatmap::file_heatmap(path, args.cell_size) {
+            Ok(heatmap) => heatmap,
+            Err(err) => {
+                warn!("skipping {:?}: {}", path, err);
+                None
+            }
+        })
+        .fold(
+            HashMap::new,
+            |mut heatmaps: HashMap<String, Heatmap>, (map, heatmap)| {
+                heatmaps
+                    .entry(map)
+                    .or_insert_with(|| Heatmap::new(args.cell_size))
+                    .merge(heatmap);
+                heatmaps
+            },
+        )
+        .reduce(HashMap::new, |mut heatmaps, other| {
+            for (map, heatmap) in other {
+                heatmaps
+                    .entry(map)
+                    .or_insert_with(|| Heatmap::new(args.cell_size))
+                    .merge(heatmap);
+            }
+            heatmaps
+        });
+
+    fs::create_dir_all(&args.output_folder)?;
+    let mut maps: Vec<_> = heatmaps.into_iter().collect();
+    maps.sort_by(|a, b| a.0.cmp(&b.0));
+    for (map_name, heatmap) in maps {
+        let game_layer = args
+            .maps
+            .as_deref()
+            .and_then(|maps| heatmap::find_map_file(maps, &map_name))
+            .and_then(|path| match GameLayer::load(&path) {
+                Ok(layer) => Some(layer),
+                Err(err) => {
+                    warn!("not drawing map {}: {}", map_name, err);
+                    None
+                }
+            });
+        let Some(image) = heatmap.render(game_layer.as_ref()) else {
+            debug!("no positions on map {}", map_name);
+            continue;
+        };
+        let path = args
+            .output_folder
+            .join(format!("{}.png", heatmap::file_stem(&map_name)));
+        image.write_png(&path)?;
+        info!(
+            "{}: {} ticks, {}x{} px -> {:?}",
+            map_name,
+            heatmap.total_ticks(),
+            image.width,
+            image.height,
+            path
+        );
+    }
+    Ok(())
+}
+
+/// print name and file count, most frequent first
+fn print_file_counts(counts: HashMap<String, usize>, k: Option<usize>) {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    println!("{} unique", counts.len());
+    for (name, count) in counts.iter().take(k.unwrap_or(usize::MAX)) {
+        println!("{:>7}  {}", count, name);
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
-    dbg!(&args);
-    colog::default_builder()
+fn audit(args: &AuditArgs) {
+    let paths = args.input.paths();
+    info!("auditing tick alignment of {} files", paths.len());
+    let config = AuditConfig {
+        spike_threshold: args.spike_threshold,
+        tolerance: args.tolerance,
+    };
+    let reports: Vec<_> = paths
+        .par_iter()
+        .map(|path| (path, audit::audit_file(path, &config)))
+        .collect();
+
+    println!(
+        "{:>10} {:>10} {:>8} {:>8} {:>8} {:>6}  file",
+        "spike_rate", "checked", "spikes", "stalls", "doubles", "max"
+    );
+    let mut total = AuditReport::default();
+    let mut failed = 0;
+    for (path, report) in reports {
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => {
+                warn!("couldn't audit {:?}: {}", path, err);
+                failed += 1;
+                continue;
+            }
+        };
+        println!(
+            "{:>10.6} {:>10} {:>8} {:>8} {:>8} {:>6}  {}",
+            report.spike_rate(),
+            report.checked,
+            report.spikes,
+            report.stalls,
+            report.doubles,
+            report.max_deviation,
+            path.to_string_lossy()
+        );
+        total.merge(&report);
+    }
+    println!(
+        "{:>10.6} {:>10} {:>8} {:>8} {:>8} {:>6}  total ({} ticks, {} files failed)",
+        total.spike_rate(),
+        total.checked,
+        total.spikes,
+        total.stalls,
+        total.doubles,
+        total.max_deviation,
+        total.ticks,
+        failed
+    );
+}
+
+fn ls_players(args: &ListArgs) {
+    let paths = args.input.paths();
+    info!("scanning {} files for player names", paths.len());
+    let player_counts = paths
+        .par_iter()
+        .map(|path| Extractor::get_player_names(path))
+        .fold(HashMap::new, |mut counts, names| {
+            for name in names {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+            counts
+        })
+        .reduce(HashMap::new, |mut counts, other| {
+            for (name, count) in other {
+                *counts.entry(name).or_insert(0) += count;
+            }
+            counts
+        });
+    print_file_counts(player_counts, args.print_top_k);
+}
+
+fn ls_maps(args: &ListArgs) {
+    let paths = args.input.paths();
+    info!("reading headers of {} files", paths.len());
+    let index = HeaderIndex::build(&paths);
+    let map_counts = index
+        .map_counts()
+        .into_iter()
+        .map(|(map_name, count)| (map_name.to_string(), count))
+        .collect();
+    print_file_counts(map_counts, args.print_top_k);
+}
+
+fn filter(args: &FilterArgs) -> Result<(), Box<dyn Error>> {
+    let filter = DatasetFilter {
+        filter_players: args
+            .filter_players
+            .as_ref()
+            .map(|players| players.iter().cloned().collect()),
+        exclude_players: args
+            .exclude_players
+            .as_ref()
+            .map(|players| players.iter().cloned().collect()),
+        filter_maps: args.filter_maps.clone(),
+        exclude_maps: args.exclude_maps.clone(),
+        since: args.since,
+        until: args.until,
+        min_ticks: args.min_ticks,
+    };
+    let kept = dataset::filter(&args.dataset, &args.output_folder, &filter)?;
+    if kept == 0 {
+        warn!("no sequences matched the filters");
+    }
+    Ok(())
+}
+
+fn anonymize(args: &AnonymizeArgs) -> Result<(), Box<dyn Error>> {
+    let salt = args.salt.clone().unwrap_or_else(|| {
+        warn!("no --salt given, ids won't match other anonymized datasets");
+        let mut rng = StdRng::from_entropy();
+        (0..32)
+            .map(|_| format!("{:x}", rng.gen_range(0..16)))
+            .collect()
+    });
+    dataset::anonymize(
+        &args.dataset,
+        &args.output_folder,
+        &salt,
+        args.drop_timeout_codes,
+    )?;
+    Ok(())
+}
+
+fn huggingface(args: &HuggingfaceArgs) -> Result<(), Box<dyn Error>> {
+    // check the token first instead of failing after writing the shards
+    let token = match &args.push_to_hub {
+        Some(_) => match std::env::var("HF_TOKEN") {
+            Ok(token) if !token.is_empty() => Some(token),
+            _ => return Err("--push-to-hub needs an access token in HF_TOKEN".into()),
+        },
+        None => None,
+    };
+    let written =
+        huggingface::export_huggingface(&args.dataset, &args.output_folder, args.shard_size)?;
+    info!("wrote {} files to {:?}", written.len(), args.output_folder);
+    if let Some((repo_id, token)) = args.push_to_hub.as_ref().zip(token) {
+        huggingface::push_to_hub(&args.output_folder, repo_id, &token, args.public)?;
+    }
+    Ok(())
+}
+
+fn plot(args: &PlotArgs) -> Result<(), Box<dyn Error>> {
+    let dataset = Dataset::open(&args.dataset)?;
+    let Some(row) = dataset
+        .meta
+        .iter()
+        .position(|row| row.seq_id == args.seq_id)
+    else {
+        return Err(format!("no sequence with seq_id {} in dataset", args.seq_id).into());
+    };
+    let meta = &dataset.meta[row];
+    let sequences = dataset.read_sequences(row, row + 1)?;
+    let features = sequences.index_axis(ndarray::Axis(0), 0);
+    let trajectory = Trajectory::from_features(&dataset.column_names, features)?;
+
+    let extension = match args.format {
+        PlotFormat::Svg => "svg",
+        PlotFormat::Png => "png",
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("seq_{}.{}", args.seq_id, extension)));
+    match args.format {
+        PlotFormat::Svg => {
+            let title = format!(
+                "seq {}: {} on {}, {} ticks from tick {} of {}",
+                meta.seq_id, meta.player, meta.map, meta.ticks, meta.start, meta.teehist
+            );
+            fs::write(&output, trajectory.render_svg(&title))?;
+        }
+        PlotFormat::Png => trajectory.render_png().write_png(&output)?,
+    }
+    info!(
+        "plotted {} ticks of {} on {} with {} input events to {:?}",
+        meta.ticks,
+        meta.player,
+        meta.map,
+        trajectory.events.len(),
+        output
+    );
+    Ok(())
+}
+
+fn demo(args: &DemoArgs) -> Result<(), Box<dyn Error>> {
+    let filter = DemoFilter {
+        player: args.player.clone(),
+        start_tick: args.start,
+    };
+    let parser_config = ParserConfig {
+        cut_kill: args.cut_kill,
+        cut_rescue: args.cut_rescue,
+        ..Default::default()
+    };
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.demo", teehist_name(&args.file))));
+    let count = demo::convert_file(
+        &args.file,
+        &filter,
+        &parser_config,
+        args.maps.as_deref(),
+        &output,
+    )?;
+    info!("wrote {} sequences to {:?}", count, output);
+    Ok(())
+}
+
+fn validate(args: &ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let dataset = Dataset::open(&args.dataset)?;
+
+    // velocities are clipped by the max_speed of the run, if its config is known
+    let max_speed = RunConfig::load(&args.dataset.join(CONFIG_FILE_NAME))
+        .map(|config| config.parser.max_speed)
+        .ok();
+    if max_speed.is_none() {
+        warn!(
+            "no {} in dataset, skipping velocity range check",
+            CONFIG_FILE_NAME
+        );
+    }
+
+    let issues = dataset.validate(max_speed)?;
+    for issue in &issues {
+        error!("{}", issue);
+    }
+    if !issues.is_empty() {
+        return Err(dataset::DatasetError::ValidationFailed(issues.len()).into());
+    }
+    info!(
+        "dataset is valid: {} sequences, {} features",
+        dataset.meta.len(),
+        dataset.column_names.len()
+    );
+    Ok(())
+}
+
+fn verify(args: &VerifyArgs) -> Result<(), Box<dyn Error>> {
+    let issues = checksum::verify(&args.dataset)?;
+    for issue in &issues {
+        error!("{}", issue);
+    }
+    if !issues.is_empty() {
+        return Err(dataset::DatasetError::ValidationFailed(issues.len()).into());
+    }
+    info!("all files match {}", checksum::CHECKSUM_FILE);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let args = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let mut logger_builder = match args.log_format {
+        LogFormat::Text => colog::default_builder(),
+        LogFormat::Json => {
+            let mut builder = env_logger::Builder::new();
+            builder.format(|buf, record| {
+                let line = serde_json::json!({
+                    "timestamp": Utc::now().to_rfc3339(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+            builder
+        }
+    };
+    let logger = logger_builder
         .filter_level(args.log_level)
         .target(env_logger::Target::Stdout)
-        .init();
-    batched_export(&args);
-    info!("done");
-    Ok(())
+        .build();
+    let multi_progress = MultiProgress::new();
+    LogWrapper::new(multi_progress.clone(), logger)
+        .try_init()
+        .expect("failed to initialize logger");
+    log::set_max_level(args.log_level);
+
+    let result = match &args.command {
+        Command::Extract(extract_args) => {
+            debug!("{:?}", extract_args);
+            let extract_matches = matches.subcommand_matches("extract").unwrap();
+            batched_export(extract_args, extract_matches, &multi_progress)
+        }
+        Command::Inspect(inspect_args) => inspect(inspect_args),
+        Command::Tail(tail_args) => tail(tail_args),
+        Command::Audit(audit_args) => {
+            audit(audit_args);
+            Ok(())
+        }
+        Command::Stats(stats_args) => stats(stats_args),
+        Command::Compare(compare_args) => compare(compare_args),
+        Command::PlayerStats(player_stats_args) => player_stats(player_stats_args),
+        Command::ReactionTimes(reaction_args) => reaction_times(reaction_args),
+        Command::Aliases(alias_args) => {
+            aliases(alias_args);
+            Ok(())
+        }
+        Command::Heatmap(heatmap_args) => heatmap(heatmap_args),
+        Command::Plot(plot_args) => plot(plot_args),
+        Command::Demo(demo_args) => demo(demo_args),
+        Command::Validate(validate_args) => validate(validate_args),
+        Command::Verify(verify_args) => verify(verify_args),
+        Command::Anonymize(anonymize_args) => anonymize(anonymize_args),
+        Command::Huggingface(huggingface_args) => huggingface(huggingface_args),
+        Command::Upgrade(upgrade_args) => dataset::upgrade(&upgrade_args.dataset)
+            .map(|_| ())
+            .map_err(Into::into),
+        Command::Weights(weights_args) => {
+            weights::weight_dataset(&weights_args.dataset, &weights_args.rules)
+                .map(|_| ())
+                .map_err(Into::into)
+        }
+        Command::Routes(routes_args) => {
+            route::route_dataset(&routes_args.dataset, routes_args.max_distance)
+                .map(|_| ())
+                .map_err(Into::into)
+        }
+        Command::LsPlayers(list_args) => {
+            ls_players(list_args);
+            Ok(())
+        }
+        Command::LsMaps(list_args) => {
+            ls_maps(list_args);
+            Ok(())
+        }
+        Command::Merge(merge_args) => {
+            dataset::merge(&merge_args.datasets, &merge_args.output_folder).map_err(Into::into)
+        }
+        Command::Filter(filter_args) => filter(filter_args),
+        Command::Reshape(reshape_args) => dataset::reshape(
+            &reshape_args.dataset,
+            &reshape_args.output_folder,
+            reshape_args.seq_length,
+            reshape_args.stride.unwrap_or(reshape_args.seq_length),
+        )
+        .map(|_| ())
+        .map_err(Into::into),
+    };
+
+    match result {
+        Ok(()) => {
+            info!("done");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            error!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
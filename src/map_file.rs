@@ -0,0 +1,240 @@
+//! Reader for the game layer of DDNet map files (datafile version 4).
+//! Only the game layer is parsed, it holds the collision tiles and entities like spawns.
+
+use flate2::read::ZlibDecoder;
+use std::{fs, io::Read, path::Path};
+
+use crate::error::{Error, Result};
+
+/// world units per tile
+pub const TILE_SIZE: i32 = 32;
+
+const MAPITEMTYPE_LAYER: u32 = 5;
+const LAYERTYPE_TILES: i32 = 2;
+const TILESLAYERFLAG_GAME: i32 = 1;
+/// tilemap versions from this one on compress runs of equal tiles
+const TILEMAP_VERSION_SKIP: i32 = 4;
+
+/// collision tile indices of the game layer
+pub const TILE_SOLID: u8 = 1;
+pub const TILE_DEATH: u8 = 2;
+pub const TILE_NOHOOK: u8 = 3;
+pub const TILE_FREEZE: u8 = 9;
+pub const TILE_UNFREEZE: u8 = 11;
+pub const TILE_DEEP_FREEZE: u8 = 12;
+pub const TILE_DEEP_UNFREEZE: u8 = 13;
+/// spawn entities of the game layer, any team
+pub const TILE_SPAWN: u8 = 192;
+pub const TILE_SPAWN_RED: u8 = 193;
+pub const TILE_SPAWN_BLUE: u8 = 194;
+
+/// tile indices of the game layer of a map, row by row
+#[derive(Debug, Clone)]
+pub struct GameLayer {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<u8>,
+}
+
+impl GameLayer {
+    pub fn load(path: &Path) -> Result<GameLayer> {
+        let bytes = fs::read(path)?;
+        GameLayer::from_bytes(&bytes)
+            .map_err(|reason| Error::InvalidMap(format!("{:?}: {}", path, reason)))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<GameLayer, String> {
+        let datafile = Datafile::parse(bytes)?;
+        for item in datafile.items_of_type(MAPITEMTYPE_LAYER) {
+            // CMapItemLayer (version, type, flags) followed by CMapItemLayerTilemap
+            let is_game_layer = item.get(1) == Some(&LAYERTYPE_TILES)
+                && item
+                    .get(6)
+                    .is_some_and(|flags| flags & TILESLAYERFLAG_GAME != 0);
+            if !is_game_layer {
+                continue;
+            }
+            let (Some(&version), Some(&width), Some(&height), Some(&data_index)) =
+                (item.get(3), item.get(4), item.get(5), item.get(14))
+            else {
+                return Err("truncated game layer".to_string());
+            };
+            let (Ok(width), Ok(height)) = (usize::try_from(width), usize::try_from(height)) else {
+                return Err(format!("invalid game layer size {}x{}", width, height));
+            };
+            let data = datafile.data(data_index)?;
+            let tiles = decode_tiles(&data, width * height, version >= TILEMAP_VERSION_SKIP)?;
+            return Ok(GameLayer {
+                width,
+                height,
+                tiles,
+            });
+        }
+        Err("no game layer".to_string())
+    }
+
+    /// tile at the world position, None outside of the map
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<u8> {
+        let tile_x = usize::try_from(x.div_euclid(TILE_SIZE)).ok()?;
+        let tile_y = usize::try_from(y.div_euclid(TILE_SIZE)).ok()?;
+        if tile_x >= self.width || tile_y >= self.height {
+            return None;
+        }
+        Some(self.tiles[tile_y * self.width + tile_x])
+    }
+
+    /// world positions of the centers of all spawn tiles, row by row
+    pub fn spawn_points(&self) -> Vec<(i32, i32)> {
+        let center = |tile: usize| tile as i32 * TILE_SIZE + TILE_SIZE / 2;
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, &tile)| matches!(tile, TILE_SPAWN | TILE_SPAWN_RED | TILE_SPAWN_BLUE))
+            .map(|(i, _)| (center(i % self.width), center(i / self.width)))
+            .collect()
+    }
+}
+
+/// CTile is (index, flags, skip, reserved), only the index is kept
+fn decode_tiles(data: &[u8], tile_count: usize, skip_compressed: bool) -> Result<Vec<u8>, String> {
+    let mut tiles = Vec::with_capacity(tile_count);
+    for tile in data.chunks_exact(4) {
+        let repeat = if skip_compressed {
+            tile[2] as usize + 1
+        } else {
+            1
+        };
+        tiles.extend(std::iter::repeat_n(tile[0], repeat));
+    }
+    if tiles.len() < tile_count {
+        return Err(format!(
+            "game layer has {} tiles, expected {}",
+            tiles.len(),
+            tile_count
+        ));
+    }
+    tiles.truncate(tile_count);
+    Ok(tiles)
+}
+
+/// item and data sections of a datafile, see teeworlds' datafile.cpp
+struct Datafile<'a> {
+    /// (type, id, payload) of each item
+    items: Vec<(u32, Vec<i32>)>,
+    /// compressed data, each with its uncompressed size
+    data: Vec<(&'a [u8], usize)>,
+}
+
+impl<'a> Datafile<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Datafile<'a>, String> {
+        let mut reader = IntReader { bytes, pos: 0 };
+        match bytes.get(0..4) {
+            Some(b"DATA") | Some(b"ATAD") => reader.pos = 4,
+            _ => return Err("not a datafile".to_string()),
+        }
+        let version = reader.next()?;
+        if version != 4 {
+            return Err(format!("unsupported datafile version {}", version));
+        }
+        let _size = reader.next()?;
+        let _swaplen = reader.next()?;
+        let num_item_types = reader.next_len()?;
+        let num_items = reader.next_len()?;
+        let num_data = reader.next_len()?;
+        let item_size = reader.next_len()?;
+        let data_size = reader.next_len()?;
+
+        reader.skip(num_item_types * 3)?;
+        let item_offsets = reader.next_lens(num_items)?;
+        let data_offsets = reader.next_lens(num_data)?;
+        let data_sizes = reader.next_lens(num_data)?;
+
+        let items_start = reader.pos;
+        let data_start = items_start + item_size;
+        let section = |start: usize, len: usize| {
+            bytes
+                .get(start..start + len)
+                .ok_or_else(|| "datafile is truncated".to_string())
+        };
+        let item_section = section(items_start, item_size)?;
+        let data_section = section(data_start, data_size)?;
+
+        let mut items = Vec::with_capacity(num_items);
+        for offset in item_offsets {
+            let mut item_reader = IntReader {
+                bytes: item_section,
+                pos: offset,
+            };
+            let type_and_id = item_reader.next()? as u32;
+            let payload_len = item_reader.next_len()? / 4;
+            items.push((type_and_id >> 16, item_reader.next_ints(payload_len)?));
+        }
+
+        let mut data = Vec::with_capacity(num_data);
+        for (i, (&start, &size)) in data_offsets.iter().zip(&data_sizes).enumerate() {
+            let end = data_offsets.get(i + 1).copied().unwrap_or(data_size);
+            let compressed = data_section
+                .get(start..end)
+                .ok_or_else(|| format!("data {} is out of bounds", i))?;
+            data.push((compressed, size));
+        }
+        Ok(Datafile { items, data })
+    }
+
+    fn items_of_type(&self, item_type: u32) -> impl Iterator<Item = &Vec<i32>> {
+        self.items
+            .iter()
+            .filter(move |(t, _)| *t == item_type)
+            .map(|(_, payload)| payload)
+    }
+
+    fn data(&self, index: i32) -> Result<Vec<u8>, String> {
+        let &(compressed, size) = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.data.get(index))
+            .ok_or_else(|| format!("no data with index {}", index))?;
+        let mut data = Vec::with_capacity(size);
+        ZlibDecoder::new(compressed)
+            .read_to_end(&mut data)
+            .map_err(|err| format!("couldn't decompress data {}: {}", index, err))?;
+        Ok(data)
+    }
+}
+
+/// little endian i32 reader with bounds checks
+struct IntReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl IntReader<'_> {
+    fn next(&mut self) -> Result<i32, String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| "datafile is truncated".to_string())?;
+        self.pos += 4;
+        Ok(i32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn next_len(&mut self) -> Result<usize, String> {
+        let value = self.next()?;
+        usize::try_from(value).map_err(|_| format!("invalid length {}", value))
+    }
+
+    fn next_ints(&mut self, count: usize) -> Result<Vec<i32>, String> {
+        (0..count).map(|_| self.next()).collect()
+    }
+
+    fn next_lens(&mut self, count: usize) -> Result<Vec<usize>, String> {
+        (0..count).map(|_| self.next_len()).collect()
+    }
+
+    fn skip(&mut self, ints: usize) -> Result<(), String> {
+        self.pos += ints * 4;
+        if self.pos > self.bytes.len() {
+            return Err("datafile is truncated".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+//! Map metadata for exported sequences: size and spawn points from DDNet map files,
+//! difficulty stars from an optional info file and category, points and release date from
+//! the ddnet.org map info. Map sizes allow normalizing positions and checking them for
+//! plausibility.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::error::{Error, Result};
+use crate::heatmap::find_map_file;
+use crate::map_file::GameLayer;
+
+/// map info of a single map as json, the map name is passed as `json` query parameter
+#[cfg(feature = "ddnet-api")]
+pub const DDNET_MAP_API: &str = "https://ddnet.org/maps/";
+
+/// what is known about a map, all parts are optional
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapInfo {
+    /// width and height in tiles, None without a readable map file
+    pub size: Option<(usize, usize)>,
+    /// world positions of the spawn tiles
+    pub spawn_points: Vec<(i32, i32)>,
+    /// difficulty from 0 to 5 stars
+    pub stars: Option<u8>,
+    /// server category on ddnet.org, e.g. Novice or Brutal
+    pub category: Option<String>,
+    pub points: Option<u32>,
+    pub release: Option<DateTime<Utc>>,
+}
+
+impl MapInfo {
+    /// spawn points as "x:y" joined by '|', the format of meta.csv
+    pub fn spawn_points_csv(&self) -> String {
+        self.spawn_points
+            .iter()
+            .map(|(x, y)| format!("{}:{}", x, y))
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+}
+
+/// row of a map info file, further columns are ignored
+#[derive(Deserialize)]
+struct MapInfoRow {
+    map: String,
+    stars: Option<u8>,
+}
+
+/// Map of the ddnet.org map info, as returned by its api or listed in dumps of all maps.
+/// Unknown fields are ignored.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DdnetMapInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub category: Option<String>,
+    pub points: Option<u32>,
+    /// stars
+    pub difficulty: Option<u8>,
+    /// unix timestamp or date string
+    #[serde(default, deserialize_with = "deserialize_release")]
+    pub release: Option<DateTime<Utc>>,
+}
+
+/// Parse a json dump holding a list of maps or a single map
+pub fn parse_ddnet_map_info(json: &str) -> Result<Vec<DdnetMapInfo>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Dump {
+        List(Vec<DdnetMapInfo>),
+        Single(DdnetMapInfo),
+    }
+    Ok(match serde_json::from_str(json)? {
+        Dump::List(maps) => maps,
+        Dump::Single(map) => vec![map],
+    })
+}
+
+fn deserialize_release<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<DateTime<Utc>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Release {
+        Timestamp(f64),
+        Text(String),
+    }
+    let release = match Option::<Release>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Release::Timestamp(seconds)) => DateTime::from_timestamp(seconds as i64, 0),
+        Some(Release::Text(text)) => parse_release(&text),
+    };
+    release
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom("invalid release date"))
+}
+
+/// RFC 3339, "YYYY-MM-DD HH:MM[:SS]" or "YYYY-MM-DD", in UTC
+fn parse_release(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(date.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// map name -> game layer, None for missing or unreadable map files
+type LoadedLayers = HashMap<Arc<str>, Option<Arc<GameLayer>>>;
+
+/// Game layers of the maps in a folder of `<map_name>.map` files, each map is read once on
+/// first use. Clones share the loaded maps, so parsing threads can use it.
+#[derive(Debug, Default, Clone)]
+pub struct GameLayers {
+    maps_folder: Option<PathBuf>,
+    layers: Arc<Mutex<LoadedLayers>>,
+}
+
+impl GameLayers {
+    pub fn new(maps_folder: Option<&Path>) -> GameLayers {
+        GameLayers {
+            maps_folder: maps_folder.map(Path::to_path_buf),
+            ..Default::default()
+        }
+    }
+
+    /// Game layer of the map, unreadable map files are skipped with a warning
+    pub fn get(&self, map_name: &Arc<str>) -> Option<Arc<GameLayer>> {
+        let maps_folder = self.maps_folder.as_deref()?;
+        if let Some(layer) = self.layers.lock().unwrap().get(map_name) {
+            return layer.clone();
+        }
+        // loaded without holding the lock, threads racing for the same map read it twice
+        let layer =
+            find_map_file(maps_folder, map_name).and_then(|map_path| {
+                match GameLayer::load(&map_path) {
+                    Ok(game_layer) => Some(Arc::new(game_layer)),
+                    Err(err) => {
+                        warn!("couldn't read map {}: {}", map_name, err);
+                        None
+                    }
+                }
+            });
+        self.layers
+            .lock()
+            .unwrap()
+            .insert(map_name.clone(), layer.clone());
+        layer
+    }
+}
+
+/// Info of the maps in a folder of `<map_name>.map` files, each map is read once on first use
+#[derive(Debug, Default)]
+pub struct MapCatalog {
+    game_layers: GameLayers,
+    /// map name -> stars of the info file
+    stars: HashMap<String, u8>,
+    /// map name -> ddnet.org map info
+    ddnet: HashMap<String, DdnetMapInfo>,
+    /// folder of cached ddnet.org api responses, maps missing in it are queried
+    #[cfg(feature = "ddnet-api")]
+    api_cache: Option<PathBuf>,
+    maps: HashMap<Arc<str>, Arc<MapInfo>>,
+}
+
+impl MapCatalog {
+    /// catalog of the map files in maps_folder, without one only stars are known
+    pub fn new(maps_folder: Option<&Path>) -> MapCatalog {
+        MapCatalog {
+            game_layers: GameLayers::new(maps_folder),
+            ..Default::default()
+        }
+    }
+
+    /// game layers of the map files, shared with the catalog
+    pub fn game_layers(&self) -> GameLayers {
+        self.game_layers.clone()
+    }
+
+    /// Read difficulty stars from a csv file with `map` and `stars` columns, like the map
+    /// list of ddnet.org. Stars of maps read before aren't updated.
+    pub fn load_stars(&mut self, path: &Path) -> Result<()> {
+        let invalid = |err: csv::Error| Error::InvalidMap(format!("{:?}: {}", path, err));
+        let mut reader = csv::Reader::from_path(path).map_err(invalid)?;
+        for row in reader.deserialize() {
+            let row: MapInfoRow = row.map_err(invalid)?;
+            if let Some(stars) = row.stars {
+                if stars > 5 {
+                    return Err(Error::InvalidMap(format!(
+                        "{:?}: {} has {} stars, expected 0 to 5",
+                        path, row.map, stars
+                    )));
+                }
+                self.stars.insert(row.map, stars);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a json dump of the ddnet.org map info, see [`parse_ddnet_map_info`]
+    pub fn load_ddnet_info(&mut self, path: &Path) -> Result<()> {
+        let json = fs::read_to_string(path)?;
+        for map in parse_ddnet_map_info(&json)? {
+            self.ddnet.insert(map.name.clone(), map);
+        }
+        Ok(())
+    }
+
+    /// Query [`DDNET_MAP_API`] for maps missing in the loaded dumps. Responses are cached
+    /// as `<map_name>.json` in cache_folder, so every map is queried once.
+    #[cfg(feature = "ddnet-api")]
+    pub fn use_ddnet_api(&mut self, cache_folder: &Path) -> Result<()> {
+        fs::create_dir_all(cache_folder)?;
+        self.api_cache = Some(cache_folder.to_path_buf());
+        Ok(())
+    }
+
+    /// ddnet.org info of a map, queried from the api if enabled
+    fn ddnet_info(&mut self, map_name: &str) -> Option<&DdnetMapInfo> {
+        #[cfg(feature = "ddnet-api")]
+        if !self.ddnet.contains_key(map_name) {
+            if let Some(cache_folder) = &self.api_cache {
+                match query_ddnet_info(cache_folder, map_name) {
+                    Ok(Some(map)) => {
+                        self.ddnet.insert(map_name.to_string(), map);
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!("couldn't get ddnet.org info of map {}: {}", map_name, err),
+                }
+            }
+        }
+        self.ddnet.get(map_name)
+    }
+
+    /// Info of the map, unreadable map files are skipped with a warning
+    pub fn get(&mut self, map_name: &Arc<str>) -> Arc<MapInfo> {
+        if let Some(info) = self.maps.get(map_name) {
+            return info.clone();
+        }
+
+        let stars = self.stars.get(map_name.as_ref()).copied();
+        let mut info = match self.ddnet_info(map_name) {
+            Some(ddnet) => MapInfo {
+                stars: stars.or(ddnet.difficulty),
+                category: ddnet.category.clone(),
+                points: ddnet.points,
+                release: ddnet.release,
+                ..Default::default()
+            },
+            None => MapInfo {
+                stars,
+                ..Default::default()
+            },
+        };
+        if let Some(game_layer) = self.game_layers.get(map_name) {
+            info.size = Some((game_layer.width, game_layer.height));
+            info.spawn_points = game_layer.spawn_points();
+        }
+        let info = Arc::new(info);
+        self.maps.insert(map_name.clone(), info.clone());
+        info
+    }
+}
+
+/// Cached api response of the map, queried if there is none. None for maps unknown to
+/// ddnet.org, which are queried again in the next run.
+#[cfg(feature = "ddnet-api")]
+fn query_ddnet_info(cache_folder: &Path, map_name: &str) -> Result<Option<DdnetMapInfo>> {
+    let cache_path = cache_folder.join(format!("{}.json", crate::heatmap::file_stem(map_name)));
+    if cache_path.is_file() {
+        let json = fs::read_to_string(&cache_path)?;
+        return Ok(parse_ddnet_map_info(&json)?.into_iter().next());
+    }
+
+    let json = ureq::get(DDNET_MAP_API)
+        .query("json", map_name)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| Error::InvalidMap(format!("{}: {}", DDNET_MAP_API, err)))?;
+    // responses without map info, e.g. of unknown maps, aren't cached
+    let Some(map) = parse_ddnet_map_info(&json)
+        .ok()
+        .and_then(|maps| maps.into_iter().find(|map| map.name == map_name))
+    else {
+        return Ok(None);
+    };
+    fs::write(&cache_path, &json)?;
+    Ok(Some(map))
+}
@@ -0,0 +1,186 @@
+//! Counters of long-running exports and tails, served in the Prometheus text format.
+//!
+//! Rates like sequences per second are left to Prometheus, e.g.
+//! `rate(teehistorian_sequences_exported_total[5m])`.
+
+use log::{debug, warn};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Counters and gauges of a run, updated by the exporter and read by the metrics endpoint
+#[derive(Debug)]
+pub struct Metrics {
+    pub files_processed: AtomicU64,
+    /// input files not parsed yet
+    pub files_pending: AtomicU64,
+    pub ticks_parsed: AtomicU64,
+    pub sequences_exported: AtomicU64,
+    /// size of the exported data, see [`crate::sink::ExportSink::bytes_written`]
+    pub bytes_written: AtomicU64,
+    /// parsed files waiting for earlier files of their batch before they are exported
+    pub export_queue: AtomicU64,
+    /// batches handed to the sink that aren't written yet
+    pub sink_queue: AtomicU64,
+    /// error kind -> amount of files whose parsing stopped with it
+    parse_errors: Mutex<BTreeMap<String, u64>>,
+    start_time: f64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            files_processed: AtomicU64::new(0),
+            files_pending: AtomicU64::new(0),
+            ticks_parsed: AtomicU64::new(0),
+            sequences_exported: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            export_queue: AtomicU64::new(0),
+            sink_queue: AtomicU64::new(0),
+            parse_errors: Mutex::new(BTreeMap::new()),
+            start_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |time| time.as_secs_f64()),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn add_parse_error(&self, kind: &str) {
+        let mut parse_errors = self.parse_errors.lock().unwrap();
+        *parse_errors.entry(kind.to_string()).or_insert(0) += 1;
+    }
+
+    /// all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: &AtomicU64| {
+            let _ = writeln!(text, "# HELP teehistorian_{} {}", name, help);
+            let _ = writeln!(text, "# TYPE teehistorian_{} {}", name, kind);
+            let _ = writeln!(
+                text,
+                "teehistorian_{} {}",
+                name,
+                value.load(Ordering::Relaxed)
+            );
+        };
+        metric(
+            "files_processed_total",
+            "counter",
+            "Input files parsed and exported.",
+            &self.files_processed,
+        );
+        metric(
+            "files_pending",
+            "gauge",
+            "Input files not parsed yet.",
+            &self.files_pending,
+        );
+        metric(
+            "ticks_parsed_total",
+            "counter",
+            "Ticks of all parsed input files.",
+            &self.ticks_parsed,
+        );
+        metric(
+            "sequences_exported_total",
+            "counter",
+            "Sequences written to the output.",
+            &self.sequences_exported,
+        );
+        metric(
+            "bytes_written",
+            "gauge",
+            "Size of the exported data in bytes.",
+            &self.bytes_written,
+        );
+        metric(
+            "export_queue_files",
+            "gauge",
+            "Parsed files waiting for earlier files of their batch.",
+            &self.export_queue,
+        );
+        metric(
+            "sink_queue_batches",
+            "gauge",
+            "Batches of sequences waiting to be written.",
+            &self.sink_queue,
+        );
+
+        let _ = writeln!(
+            text,
+            "# HELP teehistorian_parse_errors_total Input files whose parsing stopped with an error."
+        );
+        let _ = writeln!(text, "# TYPE teehistorian_parse_errors_total counter");
+        for (kind, count) in self.parse_errors.lock().unwrap().iter() {
+            let _ = writeln!(
+                text,
+                "teehistorian_parse_errors_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+        let _ = writeln!(
+            text,
+            "# HELP teehistorian_start_time_seconds Start of the run in seconds since the unix epoch."
+        );
+        let _ = writeln!(text, "# TYPE teehistorian_start_time_seconds gauge");
+        let _ = writeln!(text, "teehistorian_start_time_seconds {}", self.start_time);
+        text
+    }
+}
+
+/// Serve the metrics at `GET /metrics` of addr on a background thread.
+/// Returns the bound address, e.g. the chosen port of `127.0.0.1:0`.
+pub fn serve(addr: impl ToSocketAddrs, metrics: Arc<Metrics>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| respond(stream, &metrics));
+            if let Err(err) = result {
+                debug!("metrics request failed: {}", err);
+            }
+        }
+        warn!("metrics endpoint stopped");
+    });
+    Ok(local_addr)
+}
+
+/// answer a single request, scrapers open a new connection for each one
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, requests have no body
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => (
+            "404 Not Found",
+            "not found, metrics are at /metrics\n".to_string(),
+        ),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,259 @@
+//! Export sink publishing sequences to a NATS subject instead of writing files, to feed online
+//! training or anomaly detection.
+//!
+//! Speaks the NATS client protocol directly: a `CONNECT`, one `PUB` per message and a
+//! `PING` on each flush, whose `PONG` confirms that the server received everything before.
+//! Messages are json, either a whole sequence or one frame per tick.
+
+use log::info;
+use ndarray::Array2;
+use serde::Serialize;
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::TcpStream,
+};
+
+use crate::dataset::MetaRow;
+use crate::error::{Error, Result};
+use crate::export::ExportConfig;
+use crate::extractor::Sequence;
+use crate::sink::{write_features, ExportSink};
+
+/// default port of NATS servers
+const DEFAULT_PORT: u16 = 4222;
+
+/// What a single message holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NatsPayload {
+    /// meta row, column names and the features of all ticks of a sequence
+    #[default]
+    Sequence,
+    /// features of a single tick, keyed by column name
+    Frame,
+}
+
+#[derive(Serialize)]
+struct SequenceMessage<'a> {
+    meta: &'a MetaRow,
+    columns: &'a [String],
+    /// (ticks, features)
+    features: Vec<&'a [f32]>,
+}
+
+#[derive(Serialize)]
+struct FrameMessage<'a> {
+    seq_id: usize,
+    player_id: usize,
+    player: &'a str,
+    map: &'a str,
+    /// server tick of the frame
+    tick: usize,
+    features: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Connection to a NATS server, see [`NatsSink::new`]
+struct Connection {
+    writer: BufWriter<TcpStream>,
+    reader: BufReader<TcpStream>,
+    max_payload: usize,
+}
+
+/// Sink publishing each sequence, or each tick of it, as json message to a NATS subject.
+/// Resumed exports publish the sequences after the checkpoint, sequences published after the
+/// last checkpoint of the interrupted run are published again.
+pub struct NatsSink {
+    url: String,
+    subject: String,
+    payload: NatsPayload,
+    config: ExportConfig,
+    column_names: Vec<String>,
+    connection: Option<Connection>,
+    bytes_published: u64,
+}
+
+impl NatsSink {
+    /// Sink for a server at `nats://[user:password@]host[:port]`, connected on open
+    pub fn new(url: &str, subject: &str, payload: NatsPayload) -> NatsSink {
+        NatsSink {
+            url: url.to_string(),
+            subject: subject.to_string(),
+            payload,
+            config: ExportConfig::default(),
+            column_names: Vec::new(),
+            connection: None,
+            bytes_published: 0,
+        }
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let (user, address) = parse_nats_url(&self.url)
+            .ok_or_else(|| Error::Broker(format!("invalid nats url {:?}", self.url)))?;
+        let stream = TcpStream::connect(&address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let writer = BufWriter::new(stream);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let info: serde_json::Value = match line.strip_prefix("INFO ") {
+            Some(info) => serde_json::from_str(info)?,
+            None => return Err(Error::Broker(format!("unexpected greeting {:?}", line))),
+        };
+        let max_payload = info["max_payload"].as_u64().unwrap_or(1 << 20) as usize;
+
+        let mut options = serde_json::json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "teehistorian_extractor",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some((user, password)) = user {
+            options["user"] = user.into();
+            options["pass"] = password.into();
+        }
+        let mut connection = Connection {
+            writer,
+            reader,
+            max_payload,
+        };
+        write!(connection.writer, "CONNECT {}\r\n", options)?;
+        connection.ping()?;
+        info!("connected to nats server {}", address);
+        Ok(connection)
+    }
+
+    fn publish(&mut self, payload: &[u8]) -> Result<()> {
+        let Some(connection) = &mut self.connection else {
+            return Err(Error::InvalidExport("sink was not opened".to_string()));
+        };
+        if payload.len() > connection.max_payload {
+            return Err(Error::Broker(format!(
+                "message of {} bytes exceeds the max payload of {} bytes",
+                payload.len(),
+                connection.max_payload
+            )));
+        }
+        write!(
+            connection.writer,
+            "PUB {} {}\r\n",
+            self.subject,
+            payload.len()
+        )?;
+        connection.writer.write_all(payload)?;
+        connection.writer.write_all(b"\r\n")?;
+        self.bytes_published += payload.len() as u64;
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Send a PING and wait for its PONG. Errors the server reported for earlier messages
+    /// arrive before it.
+    fn ping(&mut self) -> Result<()> {
+        self.writer.write_all(b"PING\r\n")?;
+        self.writer.flush()?;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(Error::Broker("connection closed by server".to_string()));
+            }
+            match line.trim_end() {
+                "PONG" => return Ok(()),
+                "PING" => {
+                    self.writer.write_all(b"PONG\r\n")?;
+                    self.writer.flush()?;
+                }
+                "+OK" => {}
+                line if line.starts_with("INFO ") => {}
+                line => return Err(Error::Broker(line.to_string())),
+            }
+        }
+    }
+}
+
+/// (user, password) and host:port of a `nats://[user:password@]host[:port]` url
+pub fn parse_nats_url(url: &str) -> Option<(Option<(String, String)>, String)> {
+    let rest = url.strip_prefix("nats://")?.trim_end_matches('/');
+    let (user, host) = match rest.rsplit_once('@') {
+        Some((user, host)) => {
+            let (user, password) = user.split_once(':').unwrap_or((user, ""));
+            (Some((user.to_string(), password.to_string())), host)
+        }
+        None => (None, rest),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let address = match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+        _ => format!("{}:{}", host, DEFAULT_PORT),
+    };
+    Some((user, address))
+}
+
+impl ExportSink for NatsSink {
+    fn open(&mut self, config: &ExportConfig, _resume_count: Option<usize>) -> Result<()> {
+        self.config = config.clone();
+        self.column_names = config.column_names();
+        self.connection = Some(self.connect()?);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()> {
+        let shape = (self.config.seq_length, self.column_names.len());
+        let column_names = self.column_names.clone();
+        for (sequence, meta_row) in sequences.iter().zip(meta) {
+            let mut features = Array2::zeros(shape);
+            write_features(sequence, &self.config, features.view_mut())?;
+            // zeros are in standard layout, one row per tick
+            let rows = features
+                .as_slice()
+                .unwrap_or_default()
+                .chunks(shape.1.max(1));
+            match self.payload {
+                NatsPayload::Sequence => {
+                    let message = SequenceMessage {
+                        meta: meta_row,
+                        columns: &column_names,
+                        features: rows.collect(),
+                    };
+                    self.publish(&serde_json::to_vec(&message)?)?;
+                }
+                NatsPayload::Frame => {
+                    for (i, row) in rows.enumerate() {
+                        let message = FrameMessage {
+                            seq_id: meta_row.seq_id,
+                            player_id: meta_row.player_id,
+                            player: &meta_row.player,
+                            map: &meta_row.map,
+                            tick: meta_row.start + i,
+                            features: column_names
+                                .iter()
+                                .zip(row)
+                                .map(|(name, &value)| (name.clone(), value.into()))
+                                .collect(),
+                        };
+                        self.publish(&serde_json::to_vec(&message)?)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// waits until the server received everything published so far
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.connection {
+            Some(connection) => connection.ping(),
+            None => Ok(()),
+        }
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_published
+    }
+}
@@ -0,0 +1,193 @@
+//! Outlier removal based on quantiles of a sample of the exported sequences, instead of fixed
+//! limits like the max speed of the parser that fit some maps better than others.
+//!
+//! Only the unbounded features are checked: velocities, relative aim targets and the aim
+//! distance. Per sequence the extreme values of each of them are taken, a sequence is an
+//! outlier if one of them lies beyond the quantiles of the extremes of the sample.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::export::ExportConfig;
+use crate::extractor::Sequence;
+
+/// (min, max) of a feature in a single sequence
+type Extremes = (f32, f32);
+
+/// Value range of the unbounded features, computed from a sample of sequences
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutlierBounds {
+    /// column name -> (lower, upper) bound
+    pub bounds: BTreeMap<String, (f32, f32)>,
+    /// sequences the bounds are computed from
+    pub sample_size: usize,
+}
+
+impl OutlierBounds {
+    /// Bounds of the exported features of the sample, quantile of the sequence minima to
+    /// 1 - quantile of the sequence maxima
+    pub fn from_sample(sample: &[Sequence], config: &ExportConfig, quantile: f64) -> OutlierBounds {
+        let mut extremes: BTreeMap<&str, Vec<Extremes>> = BTreeMap::new();
+        for sequence in sample {
+            for (name, values) in unbounded_features(sequence, config) {
+                if let Some(range) = value_range(&values) {
+                    extremes.entry(name).or_default().push(range);
+                }
+            }
+        }
+
+        let bounds = extremes
+            .into_iter()
+            .map(|(name, extremes)| {
+                let mut minima: Vec<f32> = extremes.iter().map(|&(min, _)| min).collect();
+                let mut maxima: Vec<f32> = extremes.iter().map(|&(_, max)| max).collect();
+                let bounds = (
+                    nearest_rank(&mut minima, quantile),
+                    nearest_rank(&mut maxima, 1.0 - quantile),
+                );
+                (name.to_string(), bounds)
+            })
+            .collect();
+        OutlierBounds {
+            bounds,
+            sample_size: sample.len(),
+        }
+    }
+
+    /// whether any unbounded feature of the sequence lies beyond the bounds
+    pub fn is_outlier(&self, sequence: &Sequence, config: &ExportConfig) -> bool {
+        unbounded_features(sequence, config)
+            .into_iter()
+            .any(|(name, values)| {
+                let Some(&(lower, upper)) = self.bounds.get(name) else {
+                    return false;
+                };
+                values.iter().any(|&value| value < lower || value > upper)
+            })
+    }
+
+    /// Restrict the features of an outlier to the bounds. Velocities are clipped by moving
+    /// the following positions along, aim targets are clipped per axis and then scaled to the
+    /// aim distance bounds. Returns whether anything changed.
+    pub fn clip(&self, sequence: &mut Sequence, config: &ExportConfig) -> bool {
+        if !self.is_outlier(sequence, config) {
+            return false;
+        }
+        let ticks = exported_ticks(sequence, config);
+        let bound = |name: &str| self.bounds.get(name).copied();
+
+        if config.use_vel {
+            for (positions, name) in [
+                (&mut sequence.pos_x, "vel_x"),
+                (&mut sequence.pos_y, "vel_y"),
+            ] {
+                if let Some((lower, upper)) = bound(name) {
+                    clip_velocities(positions, lower, upper);
+                }
+            }
+        }
+
+        let targets = sequence.target_x[..ticks]
+            .iter_mut()
+            .zip(&mut sequence.target_y[..ticks]);
+        for (x, y) in targets {
+            if config.use_rel_target {
+                if let Some((lower, upper)) = bound("target_rel_x") {
+                    *x = clamp_i32(*x, lower, upper);
+                }
+                if let Some((lower, upper)) = bound("target_rel_y") {
+                    *y = clamp_i32(*y, lower, upper);
+                }
+            }
+            if config.use_aim_distance {
+                if let Some((lower, upper)) = bound("aim_distance") {
+                    let distance = aim_distance(*x, *y);
+                    if distance > 0.0 && (distance < lower || distance > upper) {
+                        let scale = distance.clamp(lower, upper) / distance;
+                        *x = (*x as f32 * scale).round() as i32;
+                        *y = (*y as f32 * scale).round() as i32;
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// ticks of the sequence that are exported, the velocity of the last one needs another tick
+fn exported_ticks(sequence: &Sequence, config: &ExportConfig) -> usize {
+    let extra_ticks = config.source_ticks() - config.seq_length;
+    config
+        .seq_length
+        .min(sequence.tick_count.saturating_sub(extra_ticks))
+}
+
+/// values of the enabled unbounded feature columns, as written by
+/// [`crate::sink::write_features`]
+fn unbounded_features(sequence: &Sequence, config: &ExportConfig) -> Vec<(&'static str, Vec<f32>)> {
+    let ticks = exported_ticks(sequence, config);
+    let velocities = |positions: &[i32]| {
+        positions[..=ticks]
+            .windows(2)
+            .map(|w| (w[1] - w[0]) as f32)
+            .collect()
+    };
+    let targets = || {
+        sequence.target_x[..ticks]
+            .iter()
+            .zip(&sequence.target_y[..ticks])
+    };
+
+    let mut features = Vec::new();
+    if config.use_vel && ticks > 0 {
+        features.push(("vel_x", velocities(&sequence.pos_x)));
+        features.push(("vel_y", velocities(&sequence.pos_y)));
+    }
+    if config.use_rel_target {
+        features.push(("target_rel_x", targets().map(|(&x, _)| x as f32).collect()));
+        features.push(("target_rel_y", targets().map(|(_, &y)| y as f32).collect()));
+    }
+    if config.use_aim_distance {
+        features.push((
+            "aim_distance",
+            targets().map(|(&x, &y)| aim_distance(x, y)).collect(),
+        ));
+    }
+    features
+}
+
+/// unlike the exported column, not capped at the max aim distance
+fn aim_distance(x: i32, y: i32) -> f32 {
+    ((x as f32).powi(2) + (y as f32).powi(2)).sqrt()
+}
+
+fn value_range(values: &[f32]) -> Option<Extremes> {
+    values.iter().fold(None, |range, &value| match range {
+        None => Some((value, value)),
+        Some((min, max)) => Some((value.min(min), value.max(max))),
+    })
+}
+
+/// value at the quantile (0 to 1) of values, which must not be empty
+fn nearest_rank(values: &mut [f32], quantile: f64) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let rank = ((values.len() - 1) as f64 * quantile).round() as usize;
+    values[rank]
+}
+
+fn clamp_i32(value: i32, lower: f32, upper: f32) -> i32 {
+    (value as f32).clamp(lower, upper).round() as i32
+}
+
+/// clip the differences of consecutive positions, later positions move along
+fn clip_velocities(positions: &mut [i32], lower: f32, upper: f32) {
+    let Some(&first) = positions.first() else {
+        return;
+    };
+    let mut previous_original = first;
+    for i in 1..positions.len() {
+        let original = positions[i];
+        positions[i] = positions[i - 1] + clamp_i32(original - previous_original, lower, upper);
+        previous_original = original;
+    }
+}
@@ -1,16 +1,22 @@
+use chrono::{DateTime, Utc};
 use core::str;
 use derivative::Derivative;
-use log::{debug, error, info, trace, warn};
+use glob::Pattern;
+use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::from_str;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use teehistorian::chunks::{
-    ConsoleCommand, Drop, InputDiff, InputNew, NetMessage, PlayerDiff, PlayerNew, PlayerOld,
+    Auth, ConsoleCommand, Drop, InputDiff, InputNew, NetMessage, PlayerDiff, PlayerFinish,
+    PlayerNew, PlayerOld,
 };
 use teehistorian::Chunk;
 use twgame_core::net_msg::{self, Team};
+use unicode_normalization::UnicodeNormalization;
 
-use crate::tick::Tick;
+use crate::config::ConfigError;
+use crate::tick::{Tick, TickHistory};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -23,20 +29,63 @@ pub enum ParseError {
 
     #[error("Parser expected some different state: {0}")]
     UnexpectedParserState(String),
+
+    #[error("invalid header: {0}")]
+    Header(String),
+
+    #[error("value out of range: {0}")]
+    OutOfRange(String),
+
+    #[error("parsing was cancelled")]
+    Cancelled,
+
+    #[error("parsing took longer than {0:?}")]
+    Timeout(std::time::Duration),
+}
+
+impl ParseError {
+    /// short identifier of the error variant, used to aggregate errors in summaries
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::NetMsgParseError() => "net_msg",
+            ParseError::UnhandledChunkError(_) => "unhandled_chunk",
+            ParseError::UnexpectedParserState(_) => "unexpected_parser_state",
+            ParseError::Header(_) => "header",
+            ParseError::OutOfRange(_) => "out_of_range",
+            ParseError::Cancelled => "cancelled",
+            ParseError::Timeout(_) => "timeout",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GameInfo {
     pub server_name: String,
-    pub map_name: String,
+    pub map_name: Arc<str>,
+    /// e.g. "2024-10-01 18:23:05 +0200"
+    #[serde(default)]
+    pub start_time: Option<String>,
+    /// hex encoded sha256 of the map file, missing in old recordings
+    #[serde(default)]
+    pub map_sha256: Option<String>,
+    /// hex encoded crc32 of the map file
+    #[serde(default)]
+    pub map_crc: Option<String>,
 }
 
 impl GameInfo {
-    pub fn from_header_bytes(header_bytes: &[u8]) -> Self {
+    pub fn from_header_bytes(header_bytes: &[u8]) -> Result<Self, ParseError> {
         let header_str =
-            str::from_utf8(header_bytes).expect("failed to convert header_bytes to utf-8");
-        let game_info = from_str(header_str).expect("failed to extract GameInfo from header_str");
-        game_info
+            str::from_utf8(header_bytes).map_err(|err| ParseError::Header(err.to_string()))?;
+        from_str(header_str).map_err(|err| ParseError::Header(err.to_string()))
+    }
+
+    /// parsed start_time of the recording, None if missing or malformed
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        let start_time = self.start_time.as_ref()?;
+        DateTime::parse_from_str(start_time, "%Y-%m-%d %H:%M:%S %z")
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
     }
 }
 
@@ -63,15 +112,21 @@ pub struct DDNetSequence {
     pub start_tick: i32,
     /// exclusive
     pub end_tick: Option<i32>,
-    pub player_name: Option<String>,
+    pub player_name: Option<Arc<str>>,
+    /// timeout code the client set via /timeout, identifies a client across name changes
+    pub timeout_code: Option<String>,
+    /// finish time from the PlayerFinish chunk, if the player finished during this sequence
+    pub finish_time: Option<i32>,
+    /// account the client was logged into during this sequence, see [`PlayerAuth`]
+    pub auth: Option<PlayerAuth>,
     #[derivative(Debug = "ignore")]
     pub input_vectors: Vec<[i32; 10]>,
     #[derivative(Debug = "ignore")]
     pub player_positions: Vec<(i32, i32)>,
-    pub map_name: Option<String>,
+    pub map_name: Option<Arc<str>>,
 
     /// path / name of the teehistorian file this ddnet sequence origins from
-    pub teehist_path: Option<String>,
+    pub teehist_path: Option<Arc<str>>,
 }
 
 impl DDNetSequence {
@@ -81,48 +136,475 @@ impl DDNetSequence {
             start_tick,
             end_tick: None,
             player_name: None,
+            timeout_code: None,
+            finish_time: None,
+            auth: None,
             input_vectors: Vec::new(),
             player_positions: Vec::new(),
             map_name: None,
             teehist_path: None,
         }
     }
+
+    /// approximate heap memory of the tick data in bytes
+    pub fn memory_bytes(&self) -> usize {
+        self.input_vectors.len() * std::mem::size_of::<[i32; 10]>()
+            + self.player_positions.len() * std::mem::size_of::<(i32, i32)>()
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ParserConfig {
     /// on player kill the current sequence is completed and a new one started
-    cut_kill: bool,
+    pub cut_kill: bool,
 
     /// on player rescue the current sequence is completed and a new one started
-    cut_rescue: bool,
+    pub cut_rescue: bool,
 
     /// maximum allowed speed for x and y individually. If this threshold is exceeded, it is
     /// considered unexpected movement and results in completed/new sequence (e.g. teleport)
-    max_speed: i32,
+    pub max_speed: i32,
+
+    /// Fill gaps of up to this many ticks without input vector in a sequence, e.g. of a client
+    /// timing out and reconnecting, with the last input before them. Longer gaps split the
+    /// sequence.
+    pub max_input_gap: usize,
+
+    /// set of exclusive player names, filter out all players that are NOT in this set!
+    /// Filtered out players are not tracked by the parser once their name is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_players: Option<HashSet<String>>,
+
+    /// set of player names to filter out, takes precedence over filter_players
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_players: Option<HashSet<String>>,
+
+    /// timeout code globs, sequences whose timeout code matches none of these are skipped
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::config::patterns"
+    )]
+    pub filter_timeout_codes: Option<Vec<Pattern>>,
+
+    /// timeout code globs, sequences whose timeout code matches any of these are skipped
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::config::patterns"
+    )]
+    pub exclude_timeout_codes: Option<Vec<Pattern>>,
+
+    /// skip sequences of clients that never set a timeout code, they are kept by default
+    pub require_timeout_code: bool,
+
+    /// map name globs, files whose map matches none of these are skipped
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::config::patterns"
+    )]
+    pub filter_maps: Option<Vec<Pattern>>,
+
+    /// map name globs, files whose map matches any of these are skipped
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "crate::config::patterns"
+    )]
+    pub exclude_maps: Option<Vec<Pattern>>,
+
+    /// clean up of player names, applied before the player filters
+    pub name_normalization: NameNormalization,
+
+    /// Memory map uncompressed files instead of reading them through a buffer.
+    /// Files must not be modified while they are parsed.
+    pub mmap: bool,
+}
 
-    /// vec of exclusive player names, filter out all players that are NOT in this vec!
-    filter_players: Option<Vec<String>>,
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            cut_kill: false,
+            cut_rescue: false,
+            max_speed: 100,
+            max_input_gap: 0,
+            filter_players: None,
+            exclude_players: None,
+            filter_timeout_codes: None,
+            exclude_timeout_codes: None,
+            require_timeout_code: false,
+            filter_maps: None,
+            exclude_maps: None,
+            name_normalization: NameNormalization::default(),
+            mmap: false,
+        }
+    }
 }
 
 impl ParserConfig {
-    pub fn new(
-        cut_kill: bool,
-        cut_rescue: bool,
-        max_speed: i32,
-        filter_players: Option<Vec<String>>,
-    ) -> ParserConfig {
-        ParserConfig {
-            cut_kill,
-            cut_rescue,
-            max_speed,
-            filter_players,
+    /// builder starting from the default config
+    pub fn builder() -> ParserConfigBuilder {
+        ParserConfigBuilder {
+            config: ParserConfig::default(),
+        }
+    }
+
+    /// check for values that would make parsing fail or drop all sequences
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_speed <= 0 {
+            return Err(ConfigError::Invalid(format!(
+                "max_speed must be positive, got {}",
+                self.max_speed
+            )));
+        }
+        if let (Some(filter_players), Some(exclude_players)) =
+            (&self.filter_players, &self.exclude_players)
+        {
+            if filter_players.is_subset(exclude_players) {
+                return Err(ConfigError::Invalid(
+                    "exclude_players removes every player of filter_players".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// check player name against include and exclude sets
+    pub fn is_player_included(&self, player_name: &str) -> bool {
+        if let Some(filter_players) = &self.filter_players {
+            if !filter_players.contains(player_name) {
+                return false;
+            }
+        }
+        if let Some(exclude_players) = &self.exclude_players {
+            if exclude_players.contains(player_name) {
+                return false;
+            }
         }
+        true
+    }
+
+    /// Check timeout code against include and exclude globs.
+    /// Sequences without timeout code never match, so they are dropped by an include filter.
+    /// Otherwise they are only dropped if require_timeout_code is set.
+    pub fn is_timeout_code_included(&self, timeout_code: Option<&str>) -> bool {
+        if self.require_timeout_code && timeout_code.is_none() {
+            return false;
+        }
+        let matches = |patterns: &Vec<Pattern>| {
+            timeout_code.is_some_and(|code| patterns.iter().any(|p| p.matches(code)))
+        };
+        if let Some(filter_timeout_codes) = &self.filter_timeout_codes {
+            if !matches(filter_timeout_codes) {
+                return false;
+            }
+        }
+        if let Some(exclude_timeout_codes) = &self.exclude_timeout_codes {
+            if matches(exclude_timeout_codes) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// check map name against include and exclude globs
+    pub fn is_map_included(&self, map_name: &str) -> bool {
+        if let Some(filter_maps) = &self.filter_maps {
+            if !filter_maps.iter().any(|pattern| pattern.matches(map_name)) {
+                return false;
+            }
+        }
+        if let Some(exclude_maps) = &self.exclude_maps {
+            if exclude_maps.iter().any(|pattern| pattern.matches(map_name)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Builder for [`ParserConfig`], unset values keep their defaults.
+/// Optional filters accept both plain values and Options.
+pub struct ParserConfigBuilder {
+    config: ParserConfig,
+}
+
+impl ParserConfigBuilder {
+    pub fn cut_kill(mut self, cut_kill: bool) -> Self {
+        self.config.cut_kill = cut_kill;
+        self
+    }
+
+    pub fn cut_rescue(mut self, cut_rescue: bool) -> Self {
+        self.config.cut_rescue = cut_rescue;
+        self
+    }
+
+    pub fn max_speed(mut self, max_speed: i32) -> Self {
+        self.config.max_speed = max_speed;
+        self
+    }
+
+    pub fn max_input_gap(mut self, max_input_gap: usize) -> Self {
+        self.config.max_input_gap = max_input_gap;
+        self
+    }
+
+    pub fn filter_players(mut self, players: impl Into<Option<HashSet<String>>>) -> Self {
+        self.config.filter_players = players.into();
+        self
+    }
+
+    pub fn exclude_players(mut self, players: impl Into<Option<HashSet<String>>>) -> Self {
+        self.config.exclude_players = players.into();
+        self
+    }
+
+    pub fn filter_timeout_codes(mut self, patterns: impl Into<Option<Vec<Pattern>>>) -> Self {
+        self.config.filter_timeout_codes = patterns.into();
+        self
+    }
+
+    pub fn exclude_timeout_codes(mut self, patterns: impl Into<Option<Vec<Pattern>>>) -> Self {
+        self.config.exclude_timeout_codes = patterns.into();
+        self
+    }
+
+    pub fn require_timeout_code(mut self, require_timeout_code: bool) -> Self {
+        self.config.require_timeout_code = require_timeout_code;
+        self
+    }
+
+    pub fn filter_maps(mut self, patterns: impl Into<Option<Vec<Pattern>>>) -> Self {
+        self.config.filter_maps = patterns.into();
+        self
+    }
+
+    pub fn exclude_maps(mut self, patterns: impl Into<Option<Vec<Pattern>>>) -> Self {
+        self.config.exclude_maps = patterns.into();
+        self
+    }
+
+    pub fn name_normalization(mut self, name_normalization: NameNormalization) -> Self {
+        self.config.name_normalization = name_normalization;
+        self
+    }
+
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.config.mmap = mmap;
+        self
+    }
+
+    /// validated config, see [`ParserConfig::validate`]
+    pub fn build(self) -> Result<ParserConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// player name as used in datasets, without quotation marks and duplicate-name prefix
+pub fn clean_player_name(raw_name: &[u8]) -> String {
+    String::from_utf8_lossy(raw_name)
+        .chars()
+        .filter(|c| *c != '"' && *c != '\'') // remove quotation marks
+        .collect::<String>()
+        .replace("(1)", "") // remove leading (1)
+        .trim()
+        .to_string()
+}
+
+/// Optional clean up of player names on top of [`clean_player_name`]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameNormalization {
+    /// remove control characters such as newlines, which would break meta.csv
+    pub strip_control: bool,
+
+    /// unicode NFKC normalization, e.g. fullwidth or styled letters to plain ones
+    pub unicode: bool,
+}
+
+impl NameNormalization {
+    /// all normalization steps
+    pub fn all() -> NameNormalization {
+        NameNormalization {
+            strip_control: true,
+            unicode: true,
+        }
+    }
+
+    /// normalized name, trimmed again as removed characters may expose whitespace
+    pub fn apply(&self, name: &str) -> String {
+        let mut name: String = if self.unicode {
+            name.nfkc().collect()
+        } else {
+            name.to_string()
+        };
+        if self.strip_control {
+            name.retain(|c| !c.is_control());
+        }
+        name.trim().to_string()
+    }
+}
+
+/// stand-in for player names that are empty after sanitizing
+pub const UNNAMED_PLAYER: &str = "(unnamed)";
+
+/// whether a player name can be exported as is, i.e. isn't empty and has no control characters
+pub fn is_valid_player_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(char::is_control)
+}
+
+/// valid player name with all normalization steps applied, see [`is_valid_player_name`]
+pub fn sanitize_player_name(name: &str) -> String {
+    let name = NameNormalization::all().apply(name);
+    if name.is_empty() {
+        UNNAMED_PLAYER.to_string()
+    } else {
+        name
+    }
+}
+
+/// player name of a StartInfo net message, None for all other messages
+pub fn start_info_name(net_msg: &NetMessage) -> Option<String> {
+    match net_msg::parse_net_msg(net_msg.msg, &mut net_msg::NetVersion::V06).ok()? {
+        net_msg::ClNetMessage::ClStartInfo(info) => Some(clean_player_name(info.name)),
+        _ => None,
+    }
+}
+
+/// Server account of a client from the AuthInit and AuthLogin chunks. Unlike player names the
+/// account name can't be chosen freely, so it identifies the person behind a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlayerAuth {
+    pub name: String,
+    /// authentication level, 1 helper, 2 moderator, 3 admin
+    pub level: i32,
+}
+
+/// a client connection, from its join (or first appearance) to its drop
+#[derive(Debug, Clone)]
+pub struct ClientSession {
+    pub cid: i32,
+    pub player_name: Option<String>,
+    pub join_tick: i32,
+    /// None if the client was still connected at the end of the recording
+    pub leave_tick: Option<i32>,
+}
+
+/// input vector and player position of a cid in a recorded tick
+type TickState = (Option<[i32; 10]>, Option<(i32, i32)>);
+
+/// ticks of a sequence with an input vector in each of them
+struct SequencePart {
+    /// ticks after the start of the sequence
+    offset: usize,
+    input_vectors: Vec<[i32; 10]>,
+    player_positions: Vec<Option<(i32, i32)>>,
+    /// ticks without input vector filled with the one before them
+    filled_ticks: usize,
+}
+
+/// Split the states of a sequence at ticks without input vector. Ticks before the first and
+/// after the last input are dropped, gaps of at most max_gap ticks in between are filled with
+/// the last input before them.
+fn fill_input_gaps(states: &[TickState], max_gap: usize) -> Vec<SequencePart> {
+    let mut parts: Vec<SequencePart> = Vec::new();
+    let mut gap_start = None;
+    for (i, &(input_vector, player_position)) in states.iter().enumerate() {
+        let Some(input_vector) = input_vector else {
+            gap_start.get_or_insert(i);
+            continue;
+        };
+        let gap = gap_start.take().unwrap_or(i)..i;
+        match parts.last_mut() {
+            Some(part) if gap.len() <= max_gap => {
+                let last_input = *part.input_vectors.last().expect("parts aren't empty");
+                part.filled_ticks += gap.len();
+                for &(_, gap_position) in &states[gap] {
+                    part.input_vectors.push(last_input);
+                    part.player_positions.push(gap_position);
+                }
+                part.input_vectors.push(input_vector);
+                part.player_positions.push(player_position);
+            }
+            _ => parts.push(SequencePart {
+                offset: i,
+                input_vectors: vec![input_vector],
+                player_positions: vec![player_position],
+                filled_ticks: 0,
+            }),
+        }
+    }
+    parts
+}
+
+/// unusual event encountered while parsing, e.g. a teleport that cut a sequence
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub tick: i32,
+    pub cid: Option<i32>,
+    pub description: String,
+}
+
+/// Callbacks for events encountered while parsing, all of them default to doing nothing.
+/// Register implementations with [`Parser::add_events`] to build custom aggregations in the
+/// same pass that extracts sequences.
+#[allow(unused_variables)]
+pub trait ParserEvents {
+    /// a tick was completed, state holds the inputs and positions of all players
+    fn on_tick(&mut self, tick: i32, state: &Tick) {}
+
+    /// a client connected
+    fn on_join(&mut self, tick: i32, cid: i32) {}
+
+    /// a client sent its player name
+    fn on_player_name(&mut self, tick: i32, cid: i32, name: &str) {}
+
+    /// a client disconnected
+    fn on_drop(&mut self, tick: i32, cid: i32, reason: &str) {}
+
+    /// the input of a player changed, input is the full input vector, see [`DDNetSequence`].
+    /// Not reported for players filtered out by the config, as their inputs aren't tracked.
+    fn on_input(&mut self, tick: i32, cid: i32, input: &[i32; 10]) {}
+
+    /// a player killed themselves
+    fn on_kill(&mut self, tick: i32, cid: i32) {}
+
+    /// a client executed a console command, e.g. "timeout" or "r"
+    fn on_console_command(&mut self, tick: i32, cid: i32, command: &str, args: &[String]) {}
+}
+
+impl<T: ParserEvents + ?Sized> ParserEvents for &mut T {
+    fn on_tick(&mut self, tick: i32, state: &Tick) {
+        (**self).on_tick(tick, state)
+    }
+
+    fn on_join(&mut self, tick: i32, cid: i32) {
+        (**self).on_join(tick, cid)
+    }
+
+    fn on_player_name(&mut self, tick: i32, cid: i32, name: &str) {
+        (**self).on_player_name(tick, cid, name)
+    }
+
+    fn on_drop(&mut self, tick: i32, cid: i32, reason: &str) {
+        (**self).on_drop(tick, cid, reason)
+    }
+
+    fn on_input(&mut self, tick: i32, cid: i32, input: &[i32; 10]) {
+        (**self).on_input(tick, cid, input)
+    }
+
+    fn on_kill(&mut self, tick: i32, cid: i32) {
+        (**self).on_kill(tick, cid)
+    }
+
+    fn on_console_command(&mut self, tick: i32, cid: i32, command: &str, args: &[String]) {
+        (**self).on_console_command(tick, cid, command, args)
     }
 }
 
 /// tracks state while parsing teehistorian file
-pub struct Parser {
+pub struct Parser<'a> {
     /// if end of stream (EOS) chunk has already been parsed
     pub finished: bool,
 
@@ -139,7 +621,7 @@ pub struct Parser {
     current_tick: Tick,
 
     /// all previous ticks
-    previous_ticks: Vec<Tick>,
+    previous_ticks: TickHistory,
 
     /// all active sequences
     active_sequences: HashMap<i32, DDNetSequence>,
@@ -148,55 +630,135 @@ pub struct Parser {
     pub completed_sequences: Vec<DDNetSequence>,
 
     /// player names
-    player_names: HashMap<i32, String>,
+    player_names: HashMap<i32, Arc<str>>,
+
+    /// timeout codes, cleared when a new client joins on the cid, so a dropped client keeps
+    /// its code for its last sequence
+    timeout_codes: HashMap<i32, String>,
+
+    /// accounts of logged in clients, cleared on logout and when a new client joins on the cid
+    auths: HashMap<i32, PlayerAuth>,
+
+    /// cids of players filtered out by the config or with corrupt data, their inputs and
+    /// positions aren't tracked. Cleared when a new client joins on the cid.
+    ignored_cids: HashSet<i32>,
 
     // game info such as map name
     game_info: Option<GameInfo>,
 
+    /// all client connections in order of joining
+    pub sessions: Vec<ClientSession>,
+
+    /// unusual events, e.g. teleports and untracked chunks
+    pub anomalies: Vec<Anomaly>,
+
+    /// registered event callbacks
+    events: Vec<Box<dyn ParserEvents + 'a>>,
+
     config: ParserConfig,
 }
 
-impl Parser {
-    pub fn new(config: ParserConfig) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(config: ParserConfig) -> Parser<'a> {
         Parser {
             finished: false,
             tick_index: 0,
             chunk_index: 0,
             last_cid: None,
             current_tick: Tick::new(),
-            previous_ticks: Vec::new(),
+            previous_ticks: TickHistory::new(),
             active_sequences: HashMap::new(),
             completed_sequences: Vec::new(),
             player_names: HashMap::new(),
+            timeout_codes: HashMap::new(),
+            auths: HashMap::new(),
+            ignored_cids: HashSet::new(),
             game_info: None,
+            sessions: Vec::new(),
+            anomalies: Vec::new(),
+            events: Vec::new(),
             config,
         }
     }
 
-    pub fn parse_header(&mut self, header_bytes: &[u8]) {
-        let game_info = GameInfo::from_header_bytes(header_bytes);
+    /// Register event callbacks, called in order of registration.
+    /// Pass `&mut events` to keep access to the callbacks after parsing.
+    pub fn add_events(&mut self, events: impl ParserEvents + 'a) {
+        self.events.push(Box::new(events));
+    }
+
+    /// open session of cid, a new one is started if there is none
+    fn session_mut(&mut self, cid: i32) -> &mut ClientSession {
+        let open_index = self
+            .sessions
+            .iter()
+            .rposition(|s| s.cid == cid && s.leave_tick.is_none());
+        let index = open_index.unwrap_or_else(|| {
+            self.sessions.push(ClientSession {
+                cid,
+                player_name: None,
+                join_tick: self.tick_index,
+                leave_tick: None,
+            });
+            self.sessions.len() - 1
+        });
+        &mut self.sessions[index]
+    }
+
+    pub fn parse_header(&mut self, header_bytes: &[u8]) -> Result<(), ParseError> {
+        let game_info = GameInfo::from_header_bytes(header_bytes)?;
         self.game_info = Some(game_info);
+        Ok(())
+    }
+
+    /// server name from parsed header, None if header wasn't parsed yet
+    pub fn server_name(&self) -> Option<&str> {
+        self.game_info.as_ref().map(|g| g.server_name.as_str())
+    }
+
+    /// start of the recording from the parsed header, see [`GameInfo::start_time`]
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        self.game_info.as_ref().and_then(GameInfo::start_time)
+    }
+
+    /// map name from parsed header, None if header wasn't parsed yet
+    pub fn map_name(&self) -> Option<&str> {
+        self.game_info.as_ref().map(|g| &*g.map_name)
     }
 
     pub fn parse_chunk(&mut self, chunk: Chunk) -> Result<(), ParseError> {
-        assert!(
-            !self.finished,
-            "parser already finished, EOS chunk was reached"
-        );
+        if self.finished {
+            return Err(ParseError::UnexpectedParserState(
+                "parser already finished, EOS chunk was reached".to_string(),
+            ));
+        }
 
         match chunk {
-            Chunk::TickSkip(skip) => self.handle_tick_skip(skip.dt, false),
-            Chunk::InputNew(inp_new) => self.handle_input_new(inp_new),
+            Chunk::TickSkip(skip) => self.handle_tick_skip(skip.dt, false)?,
+            Chunk::InputNew(inp_new) => self.handle_input_new(inp_new)?,
             Chunk::InputDiff(inp_diff) => self.handle_input_diff(inp_diff),
             Chunk::NetMessage(net_msg) => self.handle_net_message(net_msg)?,
             Chunk::PlayerDiff(player_diff) => self.handle_player_diff(player_diff)?,
             Chunk::Eos => self.handle_eos()?,
             Chunk::ConsoleCommand(command) => self.handle_console_command(command)?,
             Chunk::PlayerOld(player) => self.handle_player_old(player)?,
-            Chunk::PlayerNew(player) => self.handle_player_new(player),
+            Chunk::PlayerNew(player) => self.handle_player_new(player)?,
             Chunk::Drop(drop) => self.handle_drop(drop),
             Chunk::PlayerReady(rdy) => debug!("T={} {:?}", self.tick_index, rdy),
-            Chunk::Join(join) => debug!("T={} {:?}", self.tick_index, join),
+            Chunk::Join(join) => {
+                debug!("T={} {:?}", self.tick_index, join);
+                self.session_mut(join.cid);
+                self.ignored_cids.remove(&join.cid);
+                // kept after the drop of the previous client, for its last sequence
+                self.timeout_codes.remove(&join.cid);
+                self.auths.remove(&join.cid);
+                for events in self.events.iter_mut() {
+                    events.on_join(self.tick_index, join.cid);
+                }
+            }
+            Chunk::PlayerFinish(finish) => self.handle_player_finish(finish),
+            Chunk::AuthInit(auth) | Chunk::AuthLogin(auth) => self.handle_auth_login(auth),
+            Chunk::AuthLogout(logout) => self.handle_auth_logout(logout.cid),
             Chunk::PlayerSwap(_) => {
                 return Err(ParseError::UnhandledChunkError("Player Swap".to_string()))
             }
@@ -213,13 +775,17 @@ impl Parser {
             | Chunk::PlayerTeam(_)
             | Chunk::TeamPractice(_)
             | Chunk::DdnetVersionOld(_)
-            | Chunk::AuthInit(_)
             | Chunk::TeamSaveSuccess(_) => {}
             _ => {
                 warn!(
                     "chunk={}, tick={} -> Untracked Chunk Variant: {:?}",
                     self.chunk_index, self.tick_index, chunk
                 );
+                self.anomalies.push(Anomaly {
+                    tick: self.tick_index,
+                    cid: None,
+                    description: format!("untracked chunk {:?}", chunk),
+                });
             }
         }
 
@@ -228,7 +794,7 @@ impl Parser {
     }
 
     /// Skips dt+1 ticks. In the case of dt=0 this just "finalizes" the current tick
-    fn handle_tick_skip(&mut self, dt: i32, implicit: bool) {
+    fn handle_tick_skip(&mut self, dt: i32, implicit: bool) -> Result<(), ParseError> {
         trace!(
             "T={}\tSKIP dt={}{}",
             self.tick_index,
@@ -236,50 +802,96 @@ impl Parser {
             if implicit { " (implicit)" } else { "" }
         );
 
-        self.tick_index += 1 + dt;
-        for _ in 0..(dt + 1) {
-            self.previous_ticks.push(self.current_tick.clone());
+        let next_tick = (dt >= 0)
+            .then(|| self.tick_index.checked_add(dt)?.checked_add(1))
+            .flatten()
+            .ok_or_else(|| {
+                ParseError::OutOfRange(format!("tick skip dt={} at tick={}", dt, self.tick_index))
+            })?;
+        for tick in self.tick_index..next_tick {
+            for events in self.events.iter_mut() {
+                events.on_tick(tick, &self.current_tick);
+            }
         }
+        self.previous_ticks
+            .push(self.tick_index..next_tick, &self.current_tick);
+        self.tick_index = next_tick;
 
         // on explicit tick skip, clear last_cid so no unintended implicit skip follows
         if !implicit {
             self.last_cid = None
         }
+        Ok(())
     }
 
-    fn handle_input_new(&mut self, input_new: InputNew) {
+    fn handle_input_new(&mut self, input_new: InputNew) -> Result<(), ParseError> {
         debug!("T={} {:?}", self.tick_index, &input_new);
-        self.current_tick.add_init_input(input_new);
+        let cid = input_new.cid;
+        if self.ignored_cids.contains(&cid) {
+            return Ok(());
+        }
+        self.current_tick.add_init_input(input_new)?;
+        self.emit_input(cid);
+        Ok(())
     }
 
     fn handle_input_diff(&mut self, input_diff: InputDiff) {
         trace!("T={} {:?}", self.tick_index, &input_diff);
+        let cid = input_diff.cid;
+        if self.ignored_cids.contains(&cid) {
+            return;
+        }
         self.current_tick.apply_input_diff(input_diff);
+        self.emit_input(cid);
     }
 
-    fn handle_net_message(&mut self, net_msg: NetMessage) -> Result<(), ParseError> {
-        let res = net_msg::parse_net_msg(&net_msg.msg, &mut net_msg::NetVersion::V06).ok();
+    /// Stop tracking a player that is filtered out by the config or whose data is corrupt.
+    /// No sequences are built for it until another client joins on the cid.
+    fn ignore_cid(&mut self, cid: i32) {
+        debug!("T={} ignoring player cid={}", self.tick_index, cid);
+        self.ignored_cids.insert(cid);
+        self.active_sequences.remove(&cid);
+        self.current_tick.input_vectors.remove(&cid);
+        self.current_tick.player_positions.remove(&cid);
+    }
 
-        if res.is_none() {
-            return Err(ParseError::NetMsgParseError());
+    /// pass the current input vector of cid to all event callbacks
+    fn emit_input(&mut self, cid: i32) {
+        let Some(input) = self.current_tick.input_vectors.get(&cid) else {
+            return;
+        };
+        for events in self.events.iter_mut() {
+            events.on_input(self.tick_index, cid, input);
         }
+    }
 
-        match res.unwrap() {
+    fn handle_net_message(&mut self, net_msg: NetMessage) -> Result<(), ParseError> {
+        let Ok(res) = net_msg::parse_net_msg(net_msg.msg, &mut net_msg::NetVersion::V06) else {
+            return Err(ParseError::NetMsgParseError());
+        };
+
+        match res {
             net_msg::ClNetMessage::ClStartInfo(info) => {
-                let player_name = String::from_utf8_lossy(info.name).to_string();
-                let cleaned_name = player_name
-                    .chars()
-                    .filter(|c| *c != '"' && *c != '\'') // remove quotation marks
-                    .collect::<String>()
-                    .replace("(1)", "") // remove leading (1)
-                    .trim()
-                    .to_string();
+                let cleaned_name = self
+                    .config
+                    .name_normalization
+                    .apply(&clean_player_name(info.name));
                 debug!("StartInfo cid={} => name={}", net_msg.cid, cleaned_name);
-                self.player_names.insert(net_msg.cid, cleaned_name);
+                self.session_mut(net_msg.cid).player_name = Some(cleaned_name.clone());
+                for events in self.events.iter_mut() {
+                    events.on_player_name(self.tick_index, net_msg.cid, &cleaned_name);
+                }
+                if !self.config.is_player_included(&cleaned_name) {
+                    self.ignore_cid(net_msg.cid);
+                }
+                self.player_names.insert(net_msg.cid, cleaned_name.into());
             }
             net_msg::ClNetMessage::ClKill => {
                 debug!("tick={} cid={} KILL", self.tick_index, net_msg.cid);
-                if self.config.cut_kill {
+                for events in self.events.iter_mut() {
+                    events.on_kill(self.tick_index, net_msg.cid);
+                }
+                if self.config.cut_kill && !self.ignored_cids.contains(&net_msg.cid) {
                     self.complete_active_sequence(net_msg.cid, false)?;
                 }
             }
@@ -303,25 +915,52 @@ impl Parser {
         Ok(())
     }
 
-    fn handle_player_new(&mut self, player_new: PlayerNew) {
-        self.check_implicit_tick(player_new.cid);
+    fn handle_player_new(&mut self, player_new: PlayerNew) -> Result<(), ParseError> {
+        self.check_implicit_tick(player_new.cid)?;
         debug!("T={} {:?}", self.tick_index, &player_new);
+        if self.ignored_cids.contains(&player_new.cid) {
+            return Ok(());
+        }
         self.active_sequences.insert(
             player_new.cid,
             DDNetSequence::new(player_new.cid, self.tick_index),
         );
-        self.current_tick.add_init_position(player_new);
+        self.current_tick.add_init_position(player_new)
     }
 
     fn handle_player_diff(&mut self, player_diff: PlayerDiff) -> Result<(), ParseError> {
-        self.check_implicit_tick(player_diff.cid);
+        self.check_implicit_tick(player_diff.cid)?;
+        if self.ignored_cids.contains(&player_diff.cid) {
+            return Ok(());
+        }
+        // truncated or rotated files can contain diffs for players whose PlayerNew is missing.
+        // Without a start position, none of the player's positions can be reconstructed, so
+        // we drop its data instead of giving up on the whole file.
+        if !self
+            .current_tick
+            .player_positions
+            .contains_key(&player_diff.cid)
+        {
+            self.anomalies.push(Anomaly {
+                tick: self.tick_index,
+                cid: Some(player_diff.cid),
+                description: "player diff without player new".to_string(),
+            });
+            self.ignore_cid(player_diff.cid);
+            return Ok(());
+        }
         if player_diff.dx.abs() > self.config.max_speed
             || player_diff.dy.abs() > self.config.max_speed
         {
             let seq_start_tick = self
                 .active_sequences
                 .get(&player_diff.cid)
-                .unwrap()
+                .ok_or_else(|| {
+                    ParseError::UnexpectedParserState(format!(
+                        "no active sequence for cid={}",
+                        player_diff.cid
+                    ))
+                })?
                 .start_tick;
 
             // high player diffs can occur on kill/rescue outside of sequences, which are just
@@ -329,14 +968,17 @@ impl Parser {
             // check that we are currently in an active sequence, so we do not expect such high
             // player diffs. Most likely this is due to teleporters on maps.
             if self.tick_index >= seq_start_tick {
+                self.anomalies.push(Anomaly {
+                    tick: self.tick_index,
+                    cid: Some(player_diff.cid),
+                    description: format!("teleport dx={} dy={}", player_diff.dx, player_diff.dy),
+                });
                 self.complete_active_sequence(player_diff.cid, false)?;
             }
         }
 
         trace!("T={} {:?}", self.tick_index, &player_diff);
-        self.current_tick.apply_position_diff(player_diff);
-
-        Ok(())
+        self.current_tick.apply_position_diff(player_diff)
     }
 
     fn complete_active_sequence(&mut self, cid: i32, drop_player: bool) -> Result<(), ParseError> {
@@ -355,17 +997,18 @@ impl Parser {
         );
 
         if drop_player {
-            self.current_tick.remove_player_position(cid);
+            self.current_tick.remove_player_position(cid)?;
         } else {
             // we skip the start of following ddnet sequence by two ticks, as kill and position
             // reset (PlayerDiff) are sometimes over more than one tick..
+            let start_tick = self.tick_index.checked_add(2).ok_or_else(|| {
+                ParseError::OutOfRange(format!("sequence start after tick={}", self.tick_index))
+            })?;
             self.active_sequences
-                .insert(cid, DDNetSequence::new(cid, self.tick_index + 2));
+                .insert(cid, DDNetSequence::new(cid, start_tick));
             debug!(
                 "T={} initialized new sequence for cid={}, start_tick={}",
-                self.tick_index,
-                cid,
-                self.tick_index + 1
+                self.tick_index, cid, start_tick
             );
         }
 
@@ -375,88 +1018,114 @@ impl Parser {
             return Ok(());
         }
 
-        sequence.end_tick = Some(self.tick_index);
+        // only recorded ticks can be read from the history
+        if sequence.start_tick < 0 || self.tick_index > self.previous_ticks.end_tick() {
+            return Err(ParseError::OutOfRange(format!(
+                "ticks {}..{} of cid={} aren't recorded, recorded until {}",
+                sequence.start_tick,
+                self.tick_index,
+                cid,
+                self.previous_ticks.end_tick()
+            )));
+        }
 
-        sequence.player_name = Some(self.player_names.get(&cid).unwrap().clone());
+        let player_name = self.player_names.get(&cid).cloned().ok_or_else(|| {
+            ParseError::UnexpectedParserState(format!("no player name for cid={}", cid))
+        })?;
+        sequence.timeout_code = self.timeout_codes.get(&cid).cloned();
+        if let Some(auth) = self.auths.get(&cid) {
+            sequence.auth = Some(auth.clone());
+        }
         sequence.map_name = self.game_info.as_ref().map(|g| g.map_name.clone());
 
-        // if filter_players is defined and player_name not included, we skip this sequence
-        if let Some(filter_players) = &self.config.filter_players {
-            if !filter_players.contains(sequence.player_name.as_ref().unwrap()) {
-                return Ok(());
-            }
+        // if player or client is filtered out, we skip this sequence
+        let player_included = self.config.is_player_included(&player_name);
+        sequence.player_name = Some(player_name);
+        if !player_included {
+            return Ok(());
         }
-
-        self.previous_ticks
-            .iter()
-            .skip((sequence.start_tick) as usize)
-            .take((sequence.end_tick.unwrap() - sequence.start_tick) as usize)
-            .for_each(|tick| {
-                let input_vector = tick.input_vectors.get(&cid);
-
-                // after the first player/position event there can be a
-                // delay until the first actual inputs, so we just skip those
-                if input_vector.is_none() {
-                    sequence.start_tick += 1;
-                    return;
-                }
-
-                sequence.input_vectors.push(
-                    *tick
-                        .input_vectors
-                        .get(&cid)
-                        .expect("No input vector found for cid"),
-                );
-                sequence.player_positions.push(
-                    *tick
-                        .player_positions
-                        .get(&cid)
-                        .expect("No player position found for cid"),
-                );
-            });
-
-        // sanity check that no high velocities make it into final sequence
-        let max_vel_x = sequence
-            .player_positions
-            .windows(2)
-            .map(|w| w[1].0 - w[0].0)
-            .max()
-            .unwrap_or(0);
-        let max_vel_y = sequence
-            .player_positions
-            .windows(2)
-            .map(|w| w[1].1 - w[0].1)
-            .max()
-            .unwrap_or(0);
-        assert!(
-            max_vel_y.abs() <= self.config.max_speed && max_vel_x.abs() <= self.config.max_speed,
-            "max vel exceeded -> ({:},{:})",
-            max_vel_x,
-            max_vel_y
-        );
-
-        if sequence.input_vectors.len() < 3 {
+        if !self
+            .config
+            .is_timeout_code_included(sequence.timeout_code.as_deref())
+        {
             return Ok(());
         }
 
-        // let last_index = sequence.player_positions.len() - 1;
-        // let x_diff =
-        //     sequence.player_positions[last_index - 2].0 - sequence.player_positions[last_index].0;
-        // let y_diff =
-        //     sequence.player_positions[last_index - 2].1 - sequence.player_positions[last_index].1;
-        // if x_diff > 50 || y_diff > 50 {
-        //     return Err(ParseError::UnexpectedParserState(
-        //         "position diff too large!".to_string(),
-        //     ));
-        // }
+        let states: Vec<_> = self
+            .previous_ticks
+            .states(cid, sequence.start_tick, self.tick_index)
+            .collect();
+        let parts = fill_input_gaps(&states, self.config.max_input_gap);
+        let part_count = parts.len();
+        for (i, part) in parts.into_iter().enumerate() {
+            let start_tick = sequence.start_tick + part.offset as i32;
+            let ticks = part.input_vectors.len() as i32;
+            if part.filled_ticks > 0 {
+                self.anomalies.push(Anomaly {
+                    tick: start_tick,
+                    cid: Some(cid),
+                    description: format!("filled {} ticks without input", part.filled_ticks),
+                });
+            }
+            let player_positions = part
+                .player_positions
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    ParseError::UnexpectedParserState(format!("no player position for cid={}", cid))
+                })?;
+
+            // sanity check that no high velocities make it into final sequence
+            let max_vel_x = player_positions
+                .windows(2)
+                .map(|w| w[1].0 - w[0].0)
+                .max()
+                .unwrap_or(0);
+            let max_vel_y = player_positions
+                .windows(2)
+                .map(|w| w[1].1 - w[0].1)
+                .max()
+                .unwrap_or(0);
+            if max_vel_y.abs() > self.config.max_speed || max_vel_x.abs() > self.config.max_speed {
+                return Err(ParseError::UnexpectedParserState(format!(
+                    "max vel exceeded -> ({:},{:})",
+                    max_vel_x, max_vel_y
+                )));
+            }
 
-        self.completed_sequences.push(sequence);
+            if ticks < 3 {
+                continue;
+            }
+
+            // the finish belongs to the last part, the player can't finish during a gap
+            let finish_time = if i + 1 == part_count {
+                sequence.finish_time
+            } else {
+                None
+            };
+            self.completed_sequences.push(DDNetSequence {
+                cid,
+                start_tick,
+                end_tick: Some(start_tick + ticks),
+                player_name: sequence.player_name.clone(),
+                timeout_code: sequence.timeout_code.clone(),
+                finish_time,
+                auth: sequence.auth.clone(),
+                input_vectors: part.input_vectors,
+                player_positions,
+                map_name: sequence.map_name.clone(),
+                teehist_path: sequence.teehist_path.clone(),
+            });
+        }
         Ok(())
     }
 
     fn handle_player_old(&mut self, player_old: PlayerOld) -> Result<(), ParseError> {
-        self.check_implicit_tick(player_old.cid);
+        self.check_implicit_tick(player_old.cid)?;
         debug!("T={} {:?}", self.tick_index, &player_old);
+        if self.ignored_cids.contains(&player_old.cid) {
+            return Ok(());
+        }
         self.complete_active_sequence(player_old.cid, true)
     }
 
@@ -464,20 +1133,21 @@ impl Parser {
     // recorded using any of PLAYER_DIFF, PLAYER_NEW, PLAYER_OLD
     // source: https://ddnet.org/libtw2-doc/teehistorian/
     // INFO: i believe the docs are wrong, and its lower or equal(!) cid
-    fn check_implicit_tick(&mut self, cid: i32) {
+    fn check_implicit_tick(&mut self, cid: i32) -> Result<(), ParseError> {
         if let Some(last) = self.last_cid {
             if cid <= last {
-                self.handle_tick_skip(0, true);
+                self.handle_tick_skip(0, true)?;
             }
         }
         self.last_cid = Some(cid);
+        Ok(())
     }
 
     fn handle_console_command(&mut self, command: ConsoleCommand) -> Result<(), ParseError> {
         if command.cid == -1 {
             return Ok(()); // ignore server commands
         }
-        let cmd = String::from_utf8_lossy(&command.cmd);
+        let cmd = String::from_utf8_lossy(command.cmd);
         let args: Vec<String> = command
             .args
             .iter()
@@ -490,9 +1160,18 @@ impl Parser {
             cmd,
             args.join(" ")
         );
+        for events in self.events.iter_mut() {
+            events.on_console_command(self.tick_index, command.cid, &cmd, &args);
+        }
+
+        if cmd == "timeout" {
+            if let Some(timeout_code) = args.first() {
+                self.timeout_codes.insert(command.cid, timeout_code.clone());
+            }
+        }
 
         // handle rescue
-        if self.config.cut_rescue && cmd == "r" {
+        if self.config.cut_rescue && cmd == "r" && !self.ignored_cids.contains(&command.cid) {
             self.complete_active_sequence(command.cid, false)?;
         }
 
@@ -509,9 +1188,42 @@ impl Parser {
         Ok(())
     }
 
+    fn handle_player_finish(&mut self, finish: PlayerFinish) {
+        debug!("T={} {:?}", self.tick_index, &finish);
+        if let Some(sequence) = self.active_sequences.get_mut(&finish.cid) {
+            sequence.finish_time = Some(finish.time);
+        }
+    }
+
+    fn handle_auth_login(&mut self, auth: Auth) {
+        debug!("T={} {:?}", self.tick_index, &auth);
+        let player_auth = PlayerAuth {
+            name: String::from_utf8_lossy(auth.auth_name).into_owned(),
+            level: auth.level,
+        };
+        if let Some(sequence) = self.active_sequences.get_mut(&auth.cid) {
+            sequence.auth = Some(player_auth.clone());
+        }
+        self.auths.insert(auth.cid, player_auth);
+    }
+
+    /// the active sequence keeps the account it was logged into
+    fn handle_auth_logout(&mut self, cid: i32) {
+        debug!("T={} auth logout cid={}", self.tick_index, cid);
+        let auth = self.auths.remove(&cid);
+        if let Some(sequence) = self.active_sequences.get_mut(&cid) {
+            sequence.auth = sequence.auth.take().or(auth);
+        }
+    }
+
     fn handle_drop(&mut self, drop: Drop) {
         debug!("T={} {:?}", self.tick_index, &drop);
         self.current_tick.input_vectors.remove(&drop.cid);
+        self.session_mut(drop.cid).leave_tick = Some(self.tick_index);
+        let reason = String::from_utf8_lossy(drop.reason);
+        for events in self.events.iter_mut() {
+            events.on_drop(self.tick_index, drop.cid, &reason);
+        }
         // we dont clear player position, as this is handled by OldPlayer event
     }
 }
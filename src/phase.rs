@@ -0,0 +1,103 @@
+//! Heuristic labels of the coarse gameplay phase of each tick, exported as the categorical
+//! `phase` column with the index of the phase.
+
+use serde::{Deserialize, Serialize};
+
+use crate::extractor::Sequence;
+
+/// vertical speed in units per tick from which a player counts as falling, about a third of a
+/// tile, reached after 10 ticks of free fall
+pub const FALL_SPEED: i32 = 5;
+
+/// phases lasting fewer ticks are merged into the phase before them
+pub const MIN_PHASE_TICKS: usize = 5;
+
+/// Coarse gameplay phase, the discriminant is the exported value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// walking or jumping around, the default
+    Moving = 0,
+    /// holding the hook, e.g. swinging through a hook chain
+    Hooking = 1,
+    /// standing still without inputs
+    Waiting = 2,
+    /// frozen, only known with freeze detection, see [`crate::preprocess::frozen_ticks`]
+    Frozen = 3,
+    /// falling down fast without holding the hook
+    Falling = 4,
+}
+
+impl Phase {
+    /// all phases in order of their exported value
+    pub const ALL: [Phase; 5] = [
+        Phase::Moving,
+        Phase::Hooking,
+        Phase::Waiting,
+        Phase::Frozen,
+        Phase::Falling,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Moving => "moving",
+            Phase::Hooking => "hooking",
+            Phase::Waiting => "waiting",
+            Phase::Frozen => "frozen",
+            Phase::Falling => "falling",
+        }
+    }
+
+    /// names of all phases, indexed by their exported value
+    pub fn names() -> Vec<&'static str> {
+        Phase::ALL.iter().map(|phase| phase.name()).collect()
+    }
+
+    /// phase of a single tick, frozen takes precedence over hooking, falling and waiting
+    fn of_tick(sequence: &Sequence, tick: usize, vel: (i32, i32)) -> Phase {
+        if sequence.frozen.get(tick).copied().unwrap_or(false) {
+            Phase::Frozen
+        } else if sequence.hook[tick] {
+            Phase::Hooking
+        } else if vel.1 >= FALL_SPEED {
+            Phase::Falling
+        } else if vel == (0, 0) && sequence.move_dir[tick] == 0 && !sequence.jump[tick] {
+            Phase::Waiting
+        } else {
+            Phase::Moving
+        }
+    }
+}
+
+/// Phase of each tick of the sequence. The velocity of a tick is the movement to the next one,
+/// the last tick keeps the velocity before it. Phases shorter than [`MIN_PHASE_TICKS`] are
+/// merged into the one before them, so e.g. the top of a jump isn't labeled as waiting.
+pub fn phases(sequence: &Sequence) -> Vec<Phase> {
+    let ticks = sequence.tick_count;
+    let vel = |tick: usize| {
+        let tick = tick.min(ticks.saturating_sub(2));
+        if tick + 1 >= ticks {
+            return (0, 0);
+        }
+        (
+            sequence.pos_x[tick + 1] - sequence.pos_x[tick],
+            sequence.pos_y[tick + 1] - sequence.pos_y[tick],
+        )
+    };
+    let mut phases: Vec<Phase> = (0..ticks)
+        .map(|tick| Phase::of_tick(sequence, tick, vel(tick)))
+        .collect();
+
+    let mut run_start = 0;
+    for tick in 1..=ticks {
+        if tick < ticks && phases[tick] == phases[run_start] {
+            continue;
+        }
+        if run_start > 0 && tick - run_start < MIN_PHASE_TICKS {
+            let previous = phases[run_start - 1];
+            phases[run_start..tick].fill(previous);
+        }
+        run_start = tick;
+    }
+    phases
+}
@@ -0,0 +1,194 @@
+//! Per-player statistics of teehistorian files, independent of any export.
+
+use chrono::{DateTime, Duration as TimeDelta, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::error::Result;
+use crate::extractor::{Extractor, Sequence};
+use crate::parser::ParserConfig;
+use crate::preprocess::{ActivityInput, Duration};
+
+/// server ticks per second
+const TICK_RATE: f64 = 50.;
+
+/// aggregated statistics of a single player name
+#[derive(Debug, Clone, Default)]
+pub struct PlayerStats {
+    /// client connections with this name
+    pub sessions: usize,
+    /// ticks connected to a server
+    pub playtime_ticks: u64,
+    /// ticks spent alive in tracked sequences
+    pub alive_ticks: u64,
+    /// alive ticks outside of afk stretches
+    pub active_ticks: u64,
+    pub maps: BTreeSet<String>,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl PlayerStats {
+    pub fn merge(&mut self, other: PlayerStats) {
+        self.sessions += other.sessions;
+        self.playtime_ticks += other.playtime_ticks;
+        self.alive_ticks += other.alive_ticks;
+        self.active_ticks += other.active_ticks;
+        self.maps.extend(other.maps);
+        self.first_seen = self.first_seen.into_iter().chain(other.first_seen).min();
+        self.last_seen = self.last_seen.into_iter().chain(other.last_seen).max();
+    }
+
+    /// fraction of alive ticks the player wasn't afk, None without tracked sequences
+    pub fn active_ratio(&self) -> Option<f64> {
+        (self.alive_ticks > 0).then(|| self.active_ticks as f64 / self.alive_ticks as f64)
+    }
+
+    pub fn avg_session_ticks(&self) -> f64 {
+        if self.sessions == 0 {
+            0.
+        } else {
+            self.playtime_ticks as f64 / self.sessions as f64
+        }
+    }
+}
+
+/// Statistics of all named players of a single file.
+/// afk_ticks is the amount of ticks without changes of afk_inputs that count as afk, see
+/// [`Duration::get_non_afk_durations`]. If parsing fails midway, everything until the error
+/// is counted.
+pub fn file_player_stats(
+    path: &Path,
+    afk_ticks: usize,
+    afk_inputs: &[ActivityInput],
+) -> Result<HashMap<String, PlayerStats>> {
+    let parsed_file = Extractor::parse_file(path, &ParserConfig::default())?;
+    let game_info = Extractor::get_game_info(path);
+    let map_name = game_info.as_ref().map(|g| g.map_name.to_string());
+    let start_time = game_info.as_ref().and_then(|g| g.start_time());
+    let tick_time = |tick: i32| {
+        start_time.map(|t| t + TimeDelta::milliseconds((tick as f64 * 1000. / TICK_RATE) as i64))
+    };
+
+    let mut stats: HashMap<String, PlayerStats> = HashMap::new();
+    for session in parsed_file.sessions {
+        let Some(player_name) = session.player_name else {
+            continue;
+        };
+        let leave_tick = session.leave_tick.unwrap_or(parsed_file.ticks);
+        let player = stats.entry(player_name).or_default();
+        player.merge(PlayerStats {
+            sessions: 1,
+            playtime_ticks: leave_tick.saturating_sub(session.join_tick).max(0) as u64,
+            maps: map_name.iter().cloned().collect(),
+            first_seen: tick_time(session.join_tick),
+            last_seen: tick_time(leave_tick),
+            ..Default::default()
+        });
+    }
+
+    for ddnet_sequence in parsed_file.sequences.iter() {
+        let Some(player_name) = ddnet_sequence.player_name.as_deref() else {
+            continue;
+        };
+        let sequence = Sequence::from_ddnet_sequence(ddnet_sequence)?;
+        let active_ticks =
+            Duration::get_non_afk_durations(&sequence, afk_ticks, afk_inputs).tick_count();
+        let player = stats.entry(player_name.to_string()).or_default();
+        player.alive_ticks += sequence.tick_count as u64;
+        player.active_ticks += active_ticks as u64;
+    }
+    Ok(stats)
+}
+
+/// Report of a player, durations in hours and minutes
+#[derive(Debug, Serialize)]
+pub struct PlayerReport {
+    pub player: String,
+    pub sessions: usize,
+    pub playtime_hours: f64,
+    pub avg_session_minutes: f64,
+    pub active_ratio: Option<f64>,
+    pub maps_played: usize,
+    pub maps: Vec<String>,
+    /// RFC 3339 timestamps, None if no file had a start time
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+}
+
+impl PlayerReport {
+    pub fn new(player: String, stats: &PlayerStats) -> PlayerReport {
+        PlayerReport {
+            player,
+            sessions: stats.sessions,
+            playtime_hours: stats.playtime_ticks as f64 / (TICK_RATE * 60. * 60.),
+            avg_session_minutes: stats.avg_session_ticks() / (TICK_RATE * 60.),
+            active_ratio: stats.active_ratio(),
+            maps_played: stats.maps.len(),
+            maps: stats.maps.iter().cloned().collect(),
+            first_seen: stats.first_seen.map(|t| t.to_rfc3339()),
+            last_seen: stats.last_seen.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+/// Reports of all players, most playtime first
+pub fn player_reports(stats: HashMap<String, PlayerStats>) -> Vec<PlayerReport> {
+    let mut stats: Vec<_> = stats.into_iter().collect();
+    stats.sort_by(|a, b| {
+        b.1.playtime_ticks
+            .cmp(&a.1.playtime_ticks)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    stats
+        .into_iter()
+        .map(|(player, stats)| PlayerReport::new(player, &stats))
+        .collect()
+}
+
+pub fn write_json(reports: &[PlayerReport], writer: impl Write) -> Result<()> {
+    serde_json::to_writer_pretty(writer, reports)?;
+    Ok(())
+}
+
+/// one row per player, maps are separated by ';'
+pub fn write_csv(reports: &[PlayerReport], writer: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record([
+            "player",
+            "sessions",
+            "playtime_hours",
+            "avg_session_minutes",
+            "active_ratio",
+            "maps_played",
+            "maps",
+            "first_seen",
+            "last_seen",
+        ])
+        .map_err(io::Error::from)?;
+    for report in reports {
+        writer
+            .write_record([
+                report.player.clone(),
+                report.sessions.to_string(),
+                format!("{:.3}", report.playtime_hours),
+                format!("{:.2}", report.avg_session_minutes),
+                report
+                    .active_ratio
+                    .map(|ratio| format!("{:.4}", ratio))
+                    .unwrap_or_default(),
+                report.maps_played.to_string(),
+                report.maps.join(";"),
+                report.first_seen.clone().unwrap_or_default(),
+                report.last_seen.clone().unwrap_or_default(),
+            ])
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
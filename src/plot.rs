@@ -0,0 +1,260 @@
+//! Render single exported sequences, to look at odd training samples without extra tooling.
+//!
+//! Exports only contain velocities, so the path is rebuilt relative to the position at the
+//! start of the sequence.
+
+use ndarray::ArrayView2;
+use std::fmt::Write;
+
+use crate::error::{Error, Result};
+use crate::png::RgbImage;
+
+/// maximum width and height of the plotted path in pixels
+const PLOT_SIZE: f64 = 800.;
+const MARGIN: f64 = 20.;
+/// height of the title above svg plots
+const TITLE_HEIGHT: f64 = 30.;
+const MARKER_SIZE: f64 = 5.;
+
+/// viridis color scale, the path goes from purple at the start to yellow at the end
+const TIME_COLORS: [[u8; 3]; 5] = [
+    [68, 1, 84],
+    [59, 82, 139],
+    [33, 145, 140],
+    [94, 201, 98],
+    [253, 231, 37],
+];
+
+/// input pressed by the player, marked on the tick it started being held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Jump,
+    Fire,
+    Hook,
+}
+
+impl InputEvent {
+    const ALL: [InputEvent; 3] = [InputEvent::Jump, InputEvent::Fire, InputEvent::Hook];
+
+    fn column_name(self) -> &'static str {
+        match self {
+            InputEvent::Jump => "jump",
+            InputEvent::Fire => "fire",
+            InputEvent::Hook => "hook",
+        }
+    }
+
+    fn color(self) -> [u8; 3] {
+        match self {
+            InputEvent::Jump => [30, 100, 230],
+            InputEvent::Fire => [220, 30, 30],
+            InputEvent::Hook => [240, 140, 0],
+        }
+    }
+
+    /// lines of the marker centered at (x, y): triangle for jumps, cross for fire and
+    /// diamond for hooks
+    fn marker(self, (x, y): (f64, f64)) -> Vec<((f64, f64), (f64, f64))> {
+        let r = MARKER_SIZE;
+        let corners = match self {
+            InputEvent::Jump => vec![(x, y - r), (x + r, y + r), (x - r, y + r)],
+            InputEvent::Fire => {
+                return vec![
+                    ((x - r, y - r), (x + r, y + r)),
+                    ((x - r, y + r), (x + r, y - r)),
+                ]
+            }
+            InputEvent::Hook => vec![(x, y - r), (x + r, y), (x, y + r), (x - r, y)],
+        };
+        (0..corners.len())
+            .map(|i| (corners[i], corners[(i + 1) % corners.len()]))
+            .collect()
+    }
+}
+
+/// Path and input events of an exported sequence
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    /// position of each tick relative to the first one, one more than the exported ticks
+    pub positions: Vec<(f64, f64)>,
+    /// tick index and input, for every tick an input started being held
+    pub events: Vec<(usize, InputEvent)>,
+}
+
+impl Trajectory {
+    /// Rebuild the trajectory of exported features shaped (ticks, features).
+    /// Requires the velocity columns, input columns are optional.
+    pub fn from_features(column_names: &[String], features: ArrayView2<f32>) -> Result<Trajectory> {
+        let column = |name: &str| column_names.iter().position(|c| c == name);
+        let (Some(vel_x), Some(vel_y)) = (column("vel_x"), column("vel_y")) else {
+            return Err(Error::InvalidExport(
+                "sequences have no velocity columns to plot".to_string(),
+            ));
+        };
+        if column_names.len() != features.ncols() {
+            return Err(Error::InvalidExport(format!(
+                "{} column names for {} features",
+                column_names.len(),
+                features.ncols()
+            )));
+        }
+
+        let mut positions = vec![(0., 0.)];
+        for tick in features.rows() {
+            let &(x, y) = positions.last().unwrap();
+            positions.push((x + tick[vel_x] as f64, y + tick[vel_y] as f64));
+        }
+
+        let mut events = Vec::new();
+        for event in InputEvent::ALL {
+            let Some(index) = column(event.column_name()) else {
+                continue;
+            };
+            let mut held = false;
+            for (tick, &value) in features.column(index).iter().enumerate() {
+                if value > 0.5 && !held {
+                    events.push((tick, event));
+                }
+                held = value > 0.5;
+            }
+        }
+        events.sort_by_key(|&(tick, _)| tick);
+        Ok(Trajectory { positions, events })
+    }
+
+    /// Render as standalone svg document with the title above the plot
+    pub fn render_svg(&self, title: &str) -> String {
+        let canvas = Canvas::new(&self.positions, TITLE_HEIGHT);
+        let mut svg = String::new();
+        // writing into a String can't fail
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}">"#,
+            canvas.width, canvas.height, canvas.width, canvas.height
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="20" font-family="sans-serif" font-size="14">{}</text>"#,
+            MARGIN,
+            escape_xml(title)
+        );
+
+        let _ = writeln!(svg, r#"<g stroke-width="2" stroke-linecap="round">"#);
+        for (i, segment) in self.positions.windows(2).enumerate() {
+            let (from, to) = (canvas.project(segment[0]), canvas.project(segment[1]));
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}"/>"#,
+                from.0,
+                from.1,
+                to.0,
+                to.1,
+                hex_color(self.time_color(i))
+            );
+        }
+        let _ = writeln!(svg, "</g>");
+
+        let _ = writeln!(svg, r#"<g fill="none" stroke-width="1.5">"#);
+        for &(tick, event) in &self.events {
+            let mut path = String::new();
+            for (from, to) in event.marker(canvas.project(self.positions[tick])) {
+                let _ = write!(path, "M{:.1} {:.1}L{:.1} {:.1}", from.0, from.1, to.0, to.1);
+            }
+            let _ = writeln!(
+                svg,
+                r#"<path d="{}" stroke="{}"><title>{} at tick {}</title></path>"#,
+                path,
+                hex_color(event.color()),
+                event.column_name(),
+                tick
+            );
+        }
+        let _ = writeln!(svg, "</g>");
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Render as image, without title
+    pub fn render_png(&self) -> RgbImage {
+        let canvas = Canvas::new(&self.positions, 0.);
+        let mut image = RgbImage::new(
+            canvas.width as usize,
+            canvas.height as usize,
+            [255, 255, 255],
+        );
+        let pixel = |(x, y): (f64, f64)| (x.round() as i64, y.round() as i64);
+        for (i, segment) in self.positions.windows(2).enumerate() {
+            image.draw_line(
+                pixel(canvas.project(segment[0])),
+                pixel(canvas.project(segment[1])),
+                self.time_color(i),
+            );
+        }
+        for &(tick, event) in &self.events {
+            for (from, to) in event.marker(canvas.project(self.positions[tick])) {
+                image.draw_line(pixel(from), pixel(to), event.color());
+            }
+        }
+        image
+    }
+
+    /// color of the path segment starting at tick
+    fn time_color(&self, tick: usize) -> [u8; 3] {
+        let t = tick as f64 / (self.positions.len() - 1).max(1) as f64;
+        let scaled = t * (TIME_COLORS.len() - 1) as f64;
+        let i = (scaled.floor() as usize).min(TIME_COLORS.len() - 2);
+        let fraction = scaled - i as f64;
+        let (from, to) = (TIME_COLORS[i], TIME_COLORS[i + 1]);
+        [0, 1, 2].map(|c| (from[c] as f64 + (to[c] as f64 - from[c] as f64) * fraction) as u8)
+    }
+}
+
+/// Maps world positions to plot coordinates, keeping the aspect ratio
+struct Canvas {
+    min: (f64, f64),
+    scale: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Canvas {
+    fn new(positions: &[(f64, f64)], top: f64) -> Canvas {
+        let fold = |f: fn(f64, f64) -> f64, init: f64| {
+            positions
+                .iter()
+                .fold((init, init), |acc, p| (f(acc.0, p.0), f(acc.1, p.1)))
+        };
+        let min = fold(f64::min, f64::INFINITY);
+        let max = fold(f64::max, f64::NEG_INFINITY);
+        let extent = ((max.0 - min.0).max(1.), (max.1 - min.1).max(1.));
+        let scale = PLOT_SIZE / extent.0.max(extent.1);
+        Canvas {
+            min,
+            scale,
+            top,
+            width: extent.0 * scale + 2. * MARGIN,
+            height: extent.1 * scale + 2. * MARGIN + top,
+        }
+    }
+
+    /// world y points down like in svg and image coordinates
+    fn project(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            MARGIN + (x - self.min.0) * self.scale,
+            self.top + MARGIN + (y - self.min.1) * self.scale,
+        )
+    }
+}
+
+fn hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
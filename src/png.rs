@@ -0,0 +1,111 @@
+//! Minimal PNG encoder for 8-bit RGB images, enough for plots and heatmaps.
+
+use flate2::{write::ZlibEncoder, Compression};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// 8-bit RGB image, pixels are stored row by row
+#[derive(Debug, Clone)]
+pub struct RgbImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl RgbImage {
+    pub fn new(width: usize, height: usize, background: [u8; 3]) -> RgbImage {
+        RgbImage {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    /// pixel at (x, y), None if out of bounds
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut [u8; 3]> {
+        if x < self.width && y < self.height {
+            self.pixels.get_mut(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Draw a one pixel wide line, parts outside of the image are skipped
+    pub fn draw_line(&mut self, from: (i64, i64), to: (i64, i64), color: [u8; 3]) {
+        let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+        let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (mut x, mut y) = from;
+        let mut error = dx + dy;
+        loop {
+            if let (Ok(px), Ok(py)) = (usize::try_from(x), usize::try_from(y)) {
+                if let Some(pixel) = self.get_mut(px, py) {
+                    *pixel = color;
+                }
+            }
+            if (x, y) == to {
+                break;
+            }
+            let double_error = 2 * error;
+            if double_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if double_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    pub fn write_png(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.encode_png(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn encode_png(&self, writer: &mut impl Write) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid("png images can't be empty"));
+        }
+        let (Ok(width), Ok(height)) = (u32::try_from(self.width), u32::try_from(self.height))
+        else {
+            return Err(invalid("image too large for png"));
+        };
+
+        writer.write_all(&SIGNATURE)?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // bit depth 8, color type rgb, default compression, filter and no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(writer, b"IHDR", &header)?;
+
+        // every scanline starts with its filter type, 0 is none
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks(self.width) {
+            encoder.write_all(&[0])?;
+            encoder.write_all(row.as_flattened())?;
+        }
+        write_chunk(writer, b"IDAT", &encoder.finish()?)?;
+        write_chunk(writer, b"IEND", &[])
+    }
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "png chunk too large"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&crc.finalize().to_be_bytes())
+}
@@ -1,93 +1,170 @@
-use log::warn;
+use serde::{Deserialize, Serialize};
 
+use crate::error::{Error, Result};
 use crate::extractor::Sequence;
-use std::collections::HashMap;
+use crate::map_file::{
+    GameLayer, TILE_DEEP_FREEZE, TILE_DEEP_UNFREEZE, TILE_FREEZE, TILE_UNFREEZE,
+};
+use std::{collections::HashMap, fmt, str::FromStr};
 
-#[derive(Debug)]
+/// ticks a player stays frozen after leaving a freeze tile, the default sv_freeze_delay of
+/// 3 seconds
+pub const FREEZE_TICKS: usize = 150;
+
+/// Input whose changes count as activity for afk detection, see
+/// [`Duration::get_non_afk_durations`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityInput {
+    /// move direction (A/D)
+    Move,
+    Jump,
+    Fire,
+    Hook,
+    /// aim target, e.g. of players aiming and shooting without walking
+    Target,
+}
+
+impl ActivityInput {
+    pub const ALL: [ActivityInput; 5] = [
+        ActivityInput::Move,
+        ActivityInput::Jump,
+        ActivityInput::Fire,
+        ActivityInput::Hook,
+        ActivityInput::Target,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ActivityInput::Move => "move",
+            ActivityInput::Jump => "jump",
+            ActivityInput::Fire => "fire",
+            ActivityInput::Hook => "hook",
+            ActivityInput::Target => "target",
+        }
+    }
+
+    /// whether the input at tick differs from the tick before
+    fn changed(self, sequence: &Sequence, tick: usize) -> bool {
+        let changed = |values: &[bool]| values[tick] != values[tick - 1];
+        match self {
+            ActivityInput::Move => sequence.move_dir[tick] != sequence.move_dir[tick - 1],
+            ActivityInput::Jump => changed(&sequence.jump),
+            ActivityInput::Fire => changed(&sequence.fire),
+            ActivityInput::Hook => changed(&sequence.hook),
+            ActivityInput::Target => {
+                sequence.target_x[tick] != sequence.target_x[tick - 1]
+                    || sequence.target_y[tick] != sequence.target_y[tick - 1]
+            }
+        }
+    }
+}
+
+impl fmt::Display for ActivityInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ActivityInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ActivityInput::ALL
+            .into_iter()
+            .find(|input| input.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown input '{}', expected one of move, jump, fire, hook, target",
+                    s
+                )
+            })
+    }
+}
+
+/// Inclusive range of ticks, as indices into the ticks of a sequence, not server ticks.
+/// Holds at least one tick, start <= end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Duration {
     start: usize,
     end: usize,
 }
 
 impl Duration {
+    /// panics if start > end
     pub fn new(start: usize, end: usize) -> Duration {
-        if start >= end {
-            warn!("start >= end! {}>={}", start, end);
-        }
+        assert!(
+            start <= end,
+            "duration starts at {} after its end {}",
+            start,
+            end
+        );
         Duration { start, end }
     }
 
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
     /// end is inclusive
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
     pub fn tick_count(&self) -> usize {
         self.end - self.start + 1
     }
 
-    pub fn cut_duration(&self, target_length: usize) -> Vec<Duration> {
-        let sequence_count = self.tick_count() / target_length;
-        let mut durations = Vec::with_capacity(sequence_count);
+    /// Cut into consecutive durations of target_length ticks. Each one is extended by
+    /// extra_ticks, which overlap with the start of the next duration.
+    /// The remaining ticks are dropped, unless keep_tail is set: then a last duration ending
+    /// with self is added, overlapping the one before it.
+    pub fn cut_duration(
+        &self,
+        target_length: usize,
+        extra_ticks: usize,
+        keep_tail: bool,
+    ) -> Vec<Duration> {
+        let usable_ticks = self.tick_count().saturating_sub(extra_ticks);
+        let sequence_count = usable_ticks / target_length;
+        let mut durations = Vec::with_capacity(sequence_count + 1);
 
         for idx in 0..sequence_count {
             let start = self.start + (target_length * idx);
-            durations.push(Duration::new(start, start + target_length - 1));
+            durations.push(Duration::new(
+                start,
+                start + target_length + extra_ticks - 1,
+            ));
         }
 
-        durations
-    }
-
-    pub fn pad_durations(
-        durations: Vec<Duration>,
-        max_tick: usize,
-        margin: usize,
-    ) -> Vec<Duration> {
-        let mut adjusted_durations: Vec<Duration> = Vec::new();
-
-        for (i, duration) in durations.iter().enumerate() {
-            let mut start = if duration.start >= margin {
-                duration.start - margin
-            } else {
-                0
-            };
-            let mut end = if duration.end + margin <= max_tick {
-                duration.end + margin
-            } else {
-                max_tick
-            };
-
-            // Check if there is a next duration to avoid overlapping
-            if let Some(next_duration) = durations.get(i + 1) {
-                if end >= next_duration.start {
-                    end = next_duration.start - 1; // Limit padding to avoid overlap
-                }
-            }
-
-            // Check for overlap with the previous adjusted duration and limit the start
-            if let Some(prev_duration) = adjusted_durations.last() {
-                if start <= prev_duration.end {
-                    start = prev_duration.end + 1; // Limit start to avoid overlap
-                }
-            }
-
-            // Ensure valid interval after adjustments
-            if start <= end {
-                adjusted_durations.push(Duration::new(start, end));
-            }
+        if keep_tail && sequence_count > 0 && !usable_ticks.is_multiple_of(target_length) {
+            durations.push(Duration::new(
+                self.end + 1 - target_length - extra_ticks,
+                self.end,
+            ));
         }
 
-        adjusted_durations
+        durations
     }
 
-    pub fn get_non_afk_durations(sequence: &Sequence, tick_threshold: usize) -> Vec<Duration> {
+    /// Durations in which the player changed any of the inputs at least every tick_threshold
+    /// ticks. Holding an input doesn't count, e.g. so players afk while holding hook are
+    /// still cut out.
+    pub fn get_non_afk_durations(
+        sequence: &Sequence,
+        tick_threshold: usize,
+        inputs: &[ActivityInput],
+    ) -> Durations {
         let mut afk = true;
         let mut first_move_tick: Option<usize> = None;
         let mut last_move_tick: Option<usize> = None;
         let mut durations: Vec<Duration> = Vec::new();
 
         for current_tick in 0..sequence.move_dir.len() {
-            let player_moved = if current_tick == 0 {
-                false
-            } else {
-                sequence.move_dir[current_tick] != sequence.move_dir[current_tick - 1]
-            };
+            let player_moved = current_tick > 0
+                && inputs
+                    .iter()
+                    .any(|input| input.changed(sequence, current_tick));
 
             if player_moved {
                 last_move_tick = Some(current_tick);
@@ -120,38 +197,245 @@ impl Duration {
             }
         }
 
-        durations
+        Durations(durations)
+    }
+
+    /// Stretches in which the player was frozen for more than max_ticks in a row, e.g.
+    /// waiting in a freeze jail or deep freeze for teammates
+    pub fn get_freeze_durations(frozen: &[bool], max_ticks: usize) -> Durations {
+        let mut durations = Vec::new();
+        let mut first_frozen_tick = None;
+        // a trailing non-frozen tick closes a stretch reaching the end
+        for (tick, &is_frozen) in frozen.iter().chain([&false]).enumerate() {
+            match (is_frozen, first_frozen_tick) {
+                (true, None) => first_frozen_tick = Some(tick),
+                (false, Some(first_tick)) => {
+                    if tick - first_tick > max_ticks {
+                        durations.push(Duration::new(first_tick, tick - 1));
+                    }
+                    first_frozen_tick = None;
+                }
+                _ => {}
+            }
+        }
+        Durations(durations)
     }
 
-    pub fn extract_sub_sequences(sequence: &Sequence, durations: Vec<Duration>) -> Vec<Sequence> {
-        let mut sub_sequences = Vec::new();
+    /// Copy the ticks of each duration into a sequence of their own. The durations may
+    /// overlap, e.g. the extra ticks of [`Duration::cut_duration`].
+    pub fn extract_sub_sequences(
+        sequence: &Sequence,
+        durations: &[Duration],
+    ) -> Result<Vec<Sequence>> {
+        let mut sub_sequences = Vec::with_capacity(durations.len());
 
         for duration in durations {
-            assert!(duration.start < sequence.tick_count && duration.end < sequence.tick_count);
+            if duration.end >= sequence.tick_count {
+                return Err(Error::InvalidSequence(format!(
+                    "duration {}..={} out of bounds for {} ticks",
+                    duration.start, duration.end, sequence.tick_count
+                )));
+            }
 
+            let ticks = duration.start..=duration.end;
             let sub_sequence = Sequence {
                 start_tick: sequence.start_tick + duration.start,
-                tick_count: duration.end - duration.start + 1,
-                pos_x: sequence.pos_x[duration.start..=duration.end].to_vec(),
-                pos_y: sequence.pos_y[duration.start..=duration.end].to_vec(),
-                move_dir: sequence.move_dir[duration.start..=duration.end].to_vec(),
-                target_x: sequence.target_x[duration.start..=duration.end].to_vec(),
-                target_y: sequence.target_y[duration.start..=duration.end].to_vec(),
-                jump: sequence.jump[duration.start..=duration.end].to_vec(),
-                fire: sequence.fire[duration.start..=duration.end].to_vec(),
-                hook: sequence.hook[duration.start..=duration.end].to_vec(),
+                tick_count: duration.tick_count(),
+                cid: sequence.cid,
+                pos_x: sequence.pos_x[ticks.clone()].to_vec(),
+                pos_y: sequence.pos_y[ticks.clone()].to_vec(),
+                move_dir: sequence.move_dir[ticks.clone()].to_vec(),
+                target_x: sequence.target_x[ticks.clone()].to_vec(),
+                target_y: sequence.target_y[ticks.clone()].to_vec(),
+                jump: sequence.jump[ticks.clone()].to_vec(),
+                fire: sequence.fire[ticks.clone()].to_vec(),
+                hook: sequence.hook[ticks.clone()].to_vec(),
+                frozen: sequence
+                    .frozen
+                    .get(ticks.clone())
+                    .map(<[bool]>::to_vec)
+                    .unwrap_or_default(),
+                active: sequence
+                    .active
+                    .get(ticks)
+                    .map(<[bool]>::to_vec)
+                    .unwrap_or_default(),
                 player_name: sequence.player_name.clone(),
+                timeout_code: sequence.timeout_code.clone(),
+                auth: sequence.auth.clone(),
+                finish_time: sequence.finish_time,
                 map_name: sequence.map_name.clone(),
                 teehist_name: sequence.teehist_name.clone(),
+                session_id: sequence.session_id.clone(),
+                session_seq: sequence.session_seq,
             };
 
             sub_sequences.push(sub_sequence);
         }
 
-        sub_sequences
+        Ok(sub_sequences)
     }
 }
 
+/// Ordered durations without overlaps, every operation keeps them so. Neighbors may touch,
+/// they are kept apart.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Durations(Vec<Duration>);
+
+impl Durations {
+    /// Sort the durations and join the overlapping ones
+    pub fn new(mut durations: Vec<Duration>) -> Durations {
+        durations.sort_by_key(|duration| duration.start);
+        let mut joined: Vec<Duration> = Vec::with_capacity(durations.len());
+        for duration in durations {
+            match joined.last_mut() {
+                Some(last) if duration.start <= last.end => last.end = last.end.max(duration.end),
+                _ => joined.push(duration),
+            }
+        }
+        Durations(joined)
+    }
+
+    pub fn as_slice(&self) -> &[Duration] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// ticks covered by all durations
+    pub fn tick_count(&self) -> usize {
+        self.0.iter().map(Duration::tick_count).sum()
+    }
+
+    /// Join durations separated by fewer than max_gap ticks, e.g. gameplay with a short pause
+    /// just above the afk threshold
+    pub fn merge_gaps(self, max_gap: usize) -> Durations {
+        let mut merged: Vec<Duration> = Vec::with_capacity(self.0.len());
+        for duration in self.0 {
+            match merged.last_mut() {
+                Some(last) if duration.start - last.end - 1 < max_gap => last.end = duration.end,
+                _ => merged.push(duration),
+            }
+        }
+        Durations(merged)
+    }
+
+    /// Durations without the ticks of removed, they are split where a removed duration lies
+    /// within them
+    pub fn remove(self, removed: &Durations) -> Durations {
+        let mut kept = Vec::with_capacity(self.0.len());
+        for duration in self.0 {
+            let mut start = duration.start;
+            for cut in removed
+                .0
+                .iter()
+                .filter(|cut| cut.end >= duration.start && cut.start <= duration.end)
+            {
+                if cut.start > start {
+                    kept.push(Duration::new(start, cut.start - 1));
+                }
+                start = cut.end + 1;
+            }
+            if start <= duration.end {
+                kept.push(Duration::new(start, duration.end));
+            }
+        }
+        Durations(kept)
+    }
+
+    /// Extend each duration by margin ticks on both sides, within 0..=max_tick. Padding stops
+    /// at the neighbors, the original ticks of a duration are never taken by padding.
+    /// Ticks after max_tick are dropped.
+    pub fn pad(self, max_tick: usize, margin: usize) -> Durations {
+        let mut padded: Vec<Duration> = Vec::with_capacity(self.0.len());
+
+        for (i, duration) in self.0.iter().enumerate() {
+            if duration.start > max_tick {
+                break;
+            }
+            let mut start = duration.start.saturating_sub(margin);
+            let mut end = (duration.end + margin).min(max_tick);
+
+            if let Some(next_duration) = self.0.get(i + 1) {
+                end = end.min(next_duration.start - 1);
+            }
+            if let Some(prev_duration) = padded.last() {
+                start = start.max(prev_duration.end + 1);
+            }
+
+            padded.push(Duration::new(start, end));
+        }
+
+        Durations(padded)
+    }
+
+    /// Cut each duration, see [`Duration::cut_duration`]. The cuts may overlap by extra_ticks.
+    pub fn cut(&self, target_length: usize, extra_ticks: usize, keep_tail: bool) -> Vec<Duration> {
+        self.0
+            .iter()
+            .flat_map(|duration| duration.cut_duration(target_length, extra_ticks, keep_tail))
+            .collect()
+    }
+
+    /// per tick of tick_count whether it lies within one of the durations
+    pub fn mask(&self, tick_count: usize) -> Vec<bool> {
+        let mut mask = vec![false; tick_count];
+        for duration in &self.0 {
+            let end = duration.end.min(tick_count.saturating_sub(1));
+            if duration.start <= end {
+                mask[duration.start..=end].fill(true);
+            }
+        }
+        mask
+    }
+}
+
+/// Per tick whether the player was frozen, from the freeze tiles of the game layer at their
+/// position. Freeze tiles freeze until [`FREEZE_TICKS`] after leaving them or touching an
+/// unfreeze tile, deep freeze lasts until touching an undeep tile. Freeze tiles of the front
+/// layer, tuned freeze delays and freezing weapons aren't known, so this is an approximation.
+pub fn frozen_ticks(sequence: &Sequence, game_layer: &GameLayer) -> Vec<bool> {
+    let mut freeze_ticks_left = 0;
+    let mut deep_frozen = false;
+    sequence
+        .pos_x
+        .iter()
+        .zip(&sequence.pos_y)
+        .map(|(&x, &y)| {
+            match game_layer.tile_at(x, y) {
+                Some(TILE_FREEZE) => freeze_ticks_left = FREEZE_TICKS,
+                Some(TILE_UNFREEZE) => freeze_ticks_left = 0,
+                Some(TILE_DEEP_FREEZE) => deep_frozen = true,
+                Some(TILE_DEEP_UNFREEZE) => deep_frozen = false,
+                _ => {}
+            }
+            let frozen = deep_frozen || freeze_ticks_left > 0;
+            freeze_ticks_left = freeze_ticks_left.saturating_sub(1);
+            frozen
+        })
+        .collect()
+}
+
+/// fraction of ticks in which any of move_dir, jump, fire or hook is non-zero
+pub fn activity_ratio(sequence: &Sequence) -> f32 {
+    if sequence.tick_count == 0 {
+        return 0.0;
+    }
+
+    let active_ticks = (0..sequence.tick_count)
+        .filter(|&i| {
+            sequence.move_dir[i] != 0 || sequence.jump[i] || sequence.fire[i] || sequence.hook[i]
+        })
+        .count();
+    active_ticks as f32 / sequence.tick_count as f32
+}
+
 pub fn get_top_k_players(
     sequences: &[Sequence],
     k: usize,
@@ -0,0 +1,104 @@
+//! Optional perturbation of exported datasets for public releases.
+//!
+//! Positions and cursor targets of each exported sequence get Laplace noise with scale
+//! sensitivity / epsilon added per tick, and the recorded column of meta.csv is rounded down
+//! to a time resolution. The noise is drawn once per sequence and the feature columns,
+//! embeddings, anomaly scores and route cells are all computed from that noisy copy, so the
+//! release holds no second, independently noised view of the same path. This is local noise
+//! per value in the spirit of differential privacy, not a formal guarantee for whole
+//! sequences: neighboring ticks are correlated, so averaging over many ticks recovers much
+//! of the signal. Noise is drawn from an unseeded generator, so --seed can't be used to
+//! remove it again.
+//!
+//! Exports with [`ExportConfig::privacy`] record the settings and the noise scale of each
+//! perturbed value in manifest.json.
+//!
+//! [`ExportConfig::privacy`]: crate::export::ExportConfig::privacy
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::extractor::Sequence;
+
+/// values of a sequence that get noise with the change a single tick of input can cause,
+/// either by one tick of movement or by moving the cursor
+pub const SENSITIVITIES: [(&str, f64); 4] = [
+    ("pos_x", 2.0),
+    ("pos_y", 2.0),
+    ("target_x", 16.0),
+    ("target_y", 16.0),
+];
+
+/// Noise of a privacy-preserving export
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyNoise {
+    /// privacy budget per value, lower values mean more noise
+    pub epsilon: f64,
+    /// the recorded column of meta.csv is rounded down to multiples of this many seconds
+    pub time_resolution: u64,
+}
+
+impl PrivacyNoise {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.epsilon > 0.0 && self.epsilon.is_finite()) {
+            return Err(format!("epsilon={} must be positive", self.epsilon));
+        }
+        if self.time_resolution == 0 {
+            return Err("time_resolution must be positive".into());
+        }
+        Ok(())
+    }
+
+    /// scale of the Laplace noise added to a value of a sequence, None if it is left as is
+    pub fn noise_scale(&self, name: &str) -> Option<f64> {
+        SENSITIVITIES
+            .iter()
+            .find(|(value_name, _)| *value_name == name)
+            .map(|(_, sensitivity)| sensitivity / self.epsilon)
+    }
+
+    /// value name -> noise scale of all values that get noise
+    pub fn noise_scales(&self) -> BTreeMap<String, f64> {
+        SENSITIVITIES
+            .iter()
+            .map(|(name, sensitivity)| (name.to_string(), sensitivity / self.epsilon))
+            .collect()
+    }
+
+    /// Copy of a sequence with noise on its positions and cursor targets, everything exported
+    /// of the sequence is computed from it. Inputs are left as is.
+    pub fn perturb_sequence(&self, sequence: &Sequence, rng: &mut impl Rng) -> Sequence {
+        let scale = |name| self.noise_scale(name).unwrap_or_default();
+        Sequence {
+            pos_x: noisy(&sequence.pos_x, scale("pos_x"), rng),
+            pos_y: noisy(&sequence.pos_y, scale("pos_y"), rng),
+            target_x: noisy(&sequence.target_x, scale("target_x"), rng),
+            target_y: noisy(&sequence.target_y, scale("target_y"), rng),
+            ..sequence.clone()
+        }
+    }
+
+    /// time rounded down to a multiple of time_resolution since the unix epoch
+    pub fn coarsen(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = time.timestamp();
+        let coarse = seconds - seconds.rem_euclid(self.time_resolution as i64);
+        DateTime::from_timestamp(coarse, 0).unwrap_or(time)
+    }
+}
+
+/// values with Laplace noise of the given scale, rounded to whole units
+fn noisy(values: &[i32], scale: f64, rng: &mut impl Rng) -> Vec<i32> {
+    values
+        .iter()
+        .map(|&value| value.saturating_add(laplace(rng, scale).round() as i32))
+        .collect()
+}
+
+/// sample of the Laplace distribution centered at 0, by inverting its cdf
+fn laplace(rng: &mut impl Rng, scale: f64) -> f64 {
+    let u: f64 = rng.gen_range(-0.5..0.5);
+    // u = -0.5 would give an infinite sample
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+}
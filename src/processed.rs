@@ -0,0 +1,59 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::remote;
+
+/// file name of the log of ingested input files in the output folder
+pub const PROCESSED_FILE: &str = "processed.jsonl";
+
+/// a single line of processed.jsonl
+#[derive(Serialize, Deserialize)]
+pub struct ProcessedEntry {
+    pub hash: String,
+    pub path: PathBuf,
+    pub ticks: usize,
+}
+
+/// xxh3-128 hash of the file contents as hex string.
+/// Remote files are streamed, so hashing them downloads them once more.
+pub fn file_hash(path: &Path) -> io::Result<String> {
+    let mut file: Box<dyn Read> = match remote::is_remote(path) {
+        true => remote::open(path)?,
+        false => Box::new(File::open(path)?),
+    };
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read_bytes = file.read(&mut buffer)?;
+        if read_bytes == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read_bytes]);
+    }
+    Ok(format!("{:032x}", hasher.digest128()))
+}
+
+/// Hashes of all files recorded in processed.jsonl of folder_path.
+/// Empty if the folder has no processed.jsonl yet.
+pub fn load_processed_hashes(folder_path: &Path) -> HashSet<String> {
+    let Ok(content) = fs::read_to_string(folder_path.join(PROCESSED_FILE)) else {
+        return HashSet::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| match serde_json::from_str::<ProcessedEntry>(line) {
+            Ok(entry) => Some(entry.hash),
+            Err(err) => {
+                warn!("skipping invalid line in {}: {}", PROCESSED_FILE, err);
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+
+/// Terminal progress bars of a batched export.
+/// Log lines should go through [`indicatif_log_bridge::LogWrapper`] with the same
+/// [`MultiProgress`], otherwise they tear the bars apart.
+pub struct ExportProgress {
+    pub batches: ProgressBar,
+    pub files: ProgressBar,
+}
+
+impl ExportProgress {
+    pub fn new(multi: &MultiProgress, batch_count: usize, file_count: usize) -> ExportProgress {
+        let batches = multi.add(ProgressBar::new(batch_count as u64));
+        batches.set_style(
+            ProgressStyle::with_template("batches {pos}/{len} [{elapsed_precise}] {wide_msg}")
+                .unwrap(),
+        );
+
+        let files = multi.add(ProgressBar::new(file_count as u64));
+        files.set_style(
+            ProgressStyle::with_template(
+                "files   {pos}/{len} {bar:40} {per_sec} eta {eta_precise} {wide_msg}",
+            )
+            .unwrap(),
+        );
+
+        ExportProgress { batches, files }
+    }
+
+    /// show exported sequence count and dataset size next to the bars
+    pub fn set_exported(&self, sequence_count: usize, bytes_written: u64) {
+        self.files.set_message(format!(
+            "sequences={} written={}",
+            sequence_count,
+            HumanBytes(bytes_written)
+        ));
+    }
+
+    pub fn finish(&self) {
+        self.batches.finish();
+        self.files.finish();
+    }
+}
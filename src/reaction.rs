@@ -0,0 +1,325 @@
+//! Reaction times: latencies of inputs after salient events, aggregated per player.
+//!
+//! Only the latency distributions leave this module, so they can be shared for anticheat
+//! research without the tick data they were measured on. Events are inferred from positions
+//! and inputs alone:
+//! - a wall hit stops the player while they still move towards it, the reaction is letting go
+//!   of or reversing the direction
+//! - an aim snap turns the aim by a large angle in one tick, the reaction is the next fire or
+//!   hook press
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::error::Result;
+use crate::extractor::{Extractor, Sequence};
+use crate::parser::ParserConfig;
+
+/// milliseconds per server tick
+const TICK_MS: f64 = 20.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionEvent {
+    /// from a wall hit to releasing or reversing the move direction
+    WallReversal,
+    /// from an aim snap to the next fire press
+    SnapFire,
+    /// from an aim snap to the next hook press
+    SnapHook,
+}
+
+impl ReactionEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReactionEvent::WallReversal => "wall_reversal",
+            ReactionEvent::SnapFire => "snap_fire",
+            ReactionEvent::SnapHook => "snap_hook",
+        }
+    }
+}
+
+/// thresholds of the events
+#[derive(Debug, Clone)]
+pub struct ReactionConfig {
+    /// latencies above this many ticks don't count as reaction to the event
+    pub max_latency: usize,
+    /// horizontal speed in units per tick before a wall hit, walking is about 10
+    pub min_speed: i32,
+    /// aim angle change in degrees within one tick that counts as snap
+    pub snap_angle: f64,
+}
+
+impl Default for ReactionConfig {
+    fn default() -> Self {
+        ReactionConfig {
+            max_latency: 25,
+            min_speed: 5,
+            snap_angle: 45.,
+        }
+    }
+}
+
+/// Histogram of latencies in ticks, index is the latency
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    pub counts: Vec<u64>,
+    /// events without reaction within max_latency
+    pub unanswered: u64,
+}
+
+impl LatencyHistogram {
+    pub fn add(&mut self, latency: Option<usize>) {
+        match latency {
+            Some(latency) => {
+                if self.counts.len() <= latency {
+                    self.counts.resize(latency + 1, 0);
+                }
+                self.counts[latency] += 1;
+            }
+            None => self.unanswered += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: LatencyHistogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+        self.unanswered += other.unanswered;
+    }
+
+    /// amount of answered events
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| {
+            let sum: u64 = (0..).zip(&self.counts).map(|(tick, n)| tick * n).sum();
+            sum as f64 / count as f64
+        })
+    }
+
+    pub fn std(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance = (0..)
+            .zip(&self.counts)
+            .map(|(tick, &n)| (tick as f64 - mean).powi(2) * n as f64)
+            .sum::<f64>()
+            / self.count() as f64;
+        Some(variance.sqrt())
+    }
+
+    /// nearest-rank quantile q in 0..=1 of the latencies
+    pub fn quantile(&self, q: f64) -> Option<usize> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        self.counts.iter().position(|&n| {
+            seen += n;
+            seen >= rank
+        })
+    }
+}
+
+/// event -> latency histogram
+pub type Reactions = BTreeMap<ReactionEvent, LatencyHistogram>;
+
+fn merge_reactions(reactions: &mut Reactions, other: Reactions) {
+    for (event, histogram) in other {
+        reactions.entry(event).or_default().merge(histogram);
+    }
+}
+
+/// Latencies of the events of a sequence. Events too close to the end of the sequence to
+/// tell whether there was a reaction are skipped.
+pub fn sequence_reactions(sequence: &Sequence, config: &ReactionConfig) -> Reactions {
+    let mut reactions = Reactions::new();
+    let ticks = sequence.tick_count;
+    let velocity = |tick: usize| sequence.pos_x[tick] - sequence.pos_x[tick - 1];
+    // ticks from the event to the first tick at least min_latency after it that reacted
+    let latency = |event: usize, min_latency: usize, reacted: &dyn Fn(usize) -> bool| {
+        (event + min_latency..=event + config.max_latency)
+            .find(|&t| reacted(t))
+            .map(|t| t - event)
+    };
+
+    for tick in 2..ticks.saturating_sub(config.max_latency) {
+        let direction = velocity(tick - 1).signum();
+        let stopped = velocity(tick - 1).abs() >= config.min_speed
+            && velocity(tick) * direction <= 0
+            && velocity(tick).abs() < config.min_speed;
+        let pushing =
+            sequence.move_dir[tick - 1] == direction && sequence.move_dir[tick] == direction;
+        if stopped && pushing {
+            let released = latency(tick, 1, &|t| sequence.move_dir[t] != direction);
+            reactions
+                .entry(ReactionEvent::WallReversal)
+                .or_default()
+                .add(released);
+        }
+    }
+
+    let angle = |i: usize| {
+        (sequence.target_y[i] as f64)
+            .atan2(sequence.target_x[i] as f64)
+            .to_degrees()
+    };
+    let pressed = |buttons: &[bool], t: usize| buttons[t] && !buttons[t - 1];
+    for tick in 1..ticks.saturating_sub(config.max_latency) {
+        let delta = (angle(tick) - angle(tick - 1)).abs();
+        if delta.min(360. - delta) < config.snap_angle {
+            continue;
+        }
+        // a press in the snapping input itself has latency 0
+        for (event, buttons) in [
+            (ReactionEvent::SnapFire, &sequence.fire),
+            (ReactionEvent::SnapHook, &sequence.hook),
+        ] {
+            let press = latency(tick, 0, &|t| pressed(buttons, t));
+            reactions.entry(event).or_default().add(press);
+        }
+    }
+    reactions
+}
+
+/// Reactions of all named players of a single file.
+/// If parsing fails midway, everything until the error is counted.
+pub fn file_reactions(path: &Path, config: &ReactionConfig) -> Result<HashMap<String, Reactions>> {
+    let parsed_file = Extractor::parse_file(path, &ParserConfig::default())?;
+    let mut reactions: HashMap<String, Reactions> = HashMap::new();
+    for ddnet_sequence in parsed_file.sequences.iter() {
+        let Some(player_name) = ddnet_sequence.player_name.as_deref() else {
+            continue;
+        };
+        let sequence = Sequence::from_ddnet_sequence(ddnet_sequence)?;
+        merge_reactions(
+            reactions.entry(player_name.to_string()).or_default(),
+            sequence_reactions(&sequence, config),
+        );
+    }
+    Ok(reactions)
+}
+
+/// Merge the reactions of a file into those of all files
+pub fn merge_players(
+    reactions: &mut HashMap<String, Reactions>,
+    other: HashMap<String, Reactions>,
+) {
+    for (player, player_reactions) in other {
+        merge_reactions(reactions.entry(player).or_default(), player_reactions);
+    }
+}
+
+/// Latency distribution of a player for an event, in milliseconds
+#[derive(Debug, Serialize)]
+pub struct ReactionReport {
+    pub player: String,
+    pub event: ReactionEvent,
+    pub events: u64,
+    /// fraction of events without reaction within max_latency
+    pub unanswered_ratio: f64,
+    pub mean_ms: Option<f64>,
+    pub std_ms: Option<f64>,
+    pub p10_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p90_ms: Option<f64>,
+    /// answered events per latency in ticks
+    pub histogram: Vec<u64>,
+}
+
+impl ReactionReport {
+    pub fn new(player: String, event: ReactionEvent, histogram: &LatencyHistogram) -> Self {
+        let events = histogram.count() + histogram.unanswered;
+        let ms = |ticks: usize| ticks as f64 * TICK_MS;
+        ReactionReport {
+            player,
+            event,
+            events,
+            unanswered_ratio: if events > 0 {
+                histogram.unanswered as f64 / events as f64
+            } else {
+                0.
+            },
+            mean_ms: histogram.mean().map(|mean| mean * TICK_MS),
+            std_ms: histogram.std().map(|std| std * TICK_MS),
+            p10_ms: histogram.quantile(0.1).map(ms),
+            p50_ms: histogram.quantile(0.5).map(ms),
+            p90_ms: histogram.quantile(0.9).map(ms),
+            histogram: histogram.counts.clone(),
+        }
+    }
+}
+
+/// Reports of all players and events with at least min_events answered events, ordered by
+/// player and event
+pub fn reaction_reports(
+    reactions: HashMap<String, Reactions>,
+    min_events: u64,
+) -> Vec<ReactionReport> {
+    let mut reactions: Vec<_> = reactions.into_iter().collect();
+    reactions.sort_by(|a, b| a.0.cmp(&b.0));
+    reactions
+        .into_iter()
+        .flat_map(|(player, player_reactions)| {
+            player_reactions
+                .into_iter()
+                .filter(|(_, histogram)| histogram.count() >= min_events.max(1))
+                .map(move |(event, histogram)| {
+                    ReactionReport::new(player.clone(), event, &histogram)
+                })
+        })
+        .collect()
+}
+
+pub fn write_json(reports: &[ReactionReport], writer: impl Write) -> Result<()> {
+    serde_json::to_writer_pretty(writer, reports)?;
+    Ok(())
+}
+
+/// one row per player and event, the histogram is only part of the json report
+pub fn write_csv(reports: &[ReactionReport], writer: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record([
+            "player",
+            "event",
+            "events",
+            "unanswered_ratio",
+            "mean_ms",
+            "std_ms",
+            "p10_ms",
+            "p50_ms",
+            "p90_ms",
+        ])
+        .map_err(io::Error::from)?;
+    let optional = |value: Option<f64>| value.map(|v| format!("{:.1}", v)).unwrap_or_default();
+    for report in reports {
+        writer
+            .write_record([
+                report.player.clone(),
+                report.event.name().to_string(),
+                report.events.to_string(),
+                format!("{:.4}", report.unanswered_ratio),
+                optional(report.mean_ms),
+                optional(report.std_ms),
+                optional(report.p10_ms),
+                optional(report.p50_ms),
+                optional(report.p90_ms),
+            ])
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! Finish times of DDNet ranks dumps, joined into meta.csv per (player, map) so sequences
+//! can be weighted by the skill of their player on that map.
+//!
+//! Dumps are csv files with `Map`, `Name` and `Time` columns like the race table of the
+//! ddnet.org stats export, or sqlite databases with a `record_race` table as written by
+//! DDNet servers (requires the sqlite feature).
+
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+use crate::error::{Error, Result};
+
+/// best finish of a player on a map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerRecord {
+    /// best finish time in seconds
+    pub time: f32,
+    /// 1 for the fastest player of the map, players with equal times share a rank
+    pub rank: usize,
+    /// players with a finish on the map
+    pub finishers: usize,
+}
+
+/// row of a csv dump, further columns like Timestamp or Server are ignored
+#[derive(Deserialize)]
+struct FinishRow {
+    #[serde(rename = "Map", alias = "map")]
+    map: String,
+    #[serde(rename = "Name", alias = "name")]
+    name: String,
+    #[serde(rename = "Time", alias = "time")]
+    time: f32,
+}
+
+/// map -> player name -> best finish
+#[derive(Debug, Default)]
+pub struct RecordIndex {
+    maps: HashMap<String, HashMap<String, PlayerRecord>>,
+}
+
+impl RecordIndex {
+    /// Load a csv or sqlite dump, chosen by file extension
+    pub fn load(path: &Path) -> Result<RecordIndex> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => RecordIndex::load_csv(path),
+            #[cfg(feature = "sqlite")]
+            Some("sqlite" | "db") => RecordIndex::load_sqlite(path),
+            _ => Err(Error::InvalidRecords(format!(
+                "{:?}: unsupported ranks dump, expected .csv{}",
+                path,
+                if cfg!(feature = "sqlite") {
+                    ", .sqlite or .db"
+                } else {
+                    ""
+                }
+            ))),
+        }
+    }
+
+    pub fn load_csv(path: &Path) -> Result<RecordIndex> {
+        let invalid = |err: csv::Error| Error::InvalidRecords(format!("{:?}: {}", path, err));
+        let mut reader = csv::Reader::from_path(path).map_err(invalid)?;
+        let finishes = reader
+            .deserialize()
+            .map(|row| row.map(|row: FinishRow| (row.map, row.name, row.time)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        Ok(RecordIndex::from_finishes(finishes))
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn load_sqlite(path: &Path) -> Result<RecordIndex> {
+        let invalid = |err: rusqlite::Error| Error::InvalidRecords(format!("{:?}: {}", path, err));
+        let connection =
+            rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(invalid)?;
+        let mut statement = connection
+            .prepare("SELECT Map, Name, MIN(Time) FROM record_race GROUP BY Map, Name")
+            .map_err(invalid)?;
+        let finishes = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(invalid)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        Ok(RecordIndex::from_finishes(finishes))
+    }
+
+    /// Index of (map, player name, time in seconds) finishes, only the best finish of each
+    /// player is kept. Non-positive and non-finite times are ignored.
+    pub fn from_finishes(finishes: impl IntoIterator<Item = (String, String, f32)>) -> RecordIndex {
+        let mut best: HashMap<String, HashMap<String, f32>> = HashMap::new();
+        for (map, name, time) in finishes {
+            if !(time.is_finite() && time > 0.) {
+                continue;
+            }
+            let best_time = best.entry(map).or_default().entry(name).or_insert(time);
+            *best_time = best_time.min(time);
+        }
+
+        let maps = best
+            .into_iter()
+            .map(|(map, players)| {
+                let mut times: Vec<f32> = players.values().copied().collect();
+                times.sort_unstable_by(f32::total_cmp);
+                let finishers = times.len();
+                let records = players
+                    .into_iter()
+                    .map(|(name, time)| {
+                        // players with a faster time plus one
+                        let rank = times.partition_point(|&t| t < time) + 1;
+                        let record = PlayerRecord {
+                            time,
+                            rank,
+                            finishers,
+                        };
+                        (name, record)
+                    })
+                    .collect();
+                (map, records)
+            })
+            .collect();
+        RecordIndex { maps }
+    }
+
+    pub fn get(&self, map: &str, player: &str) -> Option<PlayerRecord> {
+        self.maps.get(map)?.get(player).copied()
+    }
+
+    /// amount of (map, player) records
+    pub fn len(&self) -> usize {
+        self.maps.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+}
@@ -0,0 +1,240 @@
+//! Player identities that persist across exports, so player_ids of different dataset versions
+//! can be compared. Each export writes its registry as players.json, which the next export
+//! can start from.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, File},
+    path::Path,
+};
+
+use crate::config::ConfigError;
+use crate::error::{Error, Result};
+
+/// file name of the registry in the output folder
+pub const REGISTRY_FILE: &str = "players.json";
+
+/// incremented on incompatible changes of players.json
+pub const REGISTRY_VERSION: u32 = 1;
+
+/// canonical name -> its aliases, as written in alias files
+pub type AliasMap = BTreeMap<String, Vec<String>>;
+
+/// Read an alias file (toml or yaml) mapping canonical names to lists of aliases, e.g.
+/// `amy = ["amy2", "Amy"]`
+pub fn load_alias_map(path: &Path) -> Result<AliasMap> {
+    let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+    let aliases = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(ConfigError::from)?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(ConfigError::from)?,
+        _ => return Err(ConfigError::UnsupportedFormat(path.into()).into()),
+    };
+    Ok(aliases)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PlayerIdentity {
+    pub player_id: usize,
+    pub timeout_codes: BTreeSet<String>,
+    /// server accounts the player logged into, unlike names they can't be taken by others
+    #[serde(default)]
+    pub auth_names: BTreeSet<String>,
+}
+
+/// player name -> identity, player_ids are never reused
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerRegistry {
+    pub version: u32,
+    /// id of the next new player
+    pub next_id: usize,
+    pub players: BTreeMap<String, PlayerIdentity>,
+    /// alias name -> canonical name, aliases share the identity of their canonical name
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for PlayerRegistry {
+    fn default() -> Self {
+        PlayerRegistry {
+            version: REGISTRY_VERSION,
+            next_id: 0,
+            players: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl PlayerRegistry {
+    pub fn load(path: &Path) -> Result<PlayerRegistry> {
+        let registry: PlayerRegistry = serde_json::from_reader(File::open(path)?)?;
+        if registry.version != REGISTRY_VERSION {
+            return Err(Error::InvalidExport(format!(
+                "player registry {:?} has version {}, expected {}",
+                path, registry.version, REGISTRY_VERSION
+            )));
+        }
+        Ok(registry)
+    }
+
+    /// Written to a temporary file first, so a kill mid-write keeps the previous registry
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("json.tmp");
+        serde_json::to_writer_pretty(File::create(&tmp_path)?, self)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// name whose identity is used for name, following chained aliases
+    pub fn canonical_name<'a>(&'a self, mut name: &'a str) -> &'a str {
+        // add_alias prevents cycles, the limit guards against edited files
+        for _ in 0..=self.aliases.len() {
+            match self.aliases.get(name) {
+                Some(canonical) => name = canonical,
+                None => break,
+            }
+        }
+        name
+    }
+
+    /// id of the player, a new one is assigned to names seen for the first time
+    pub fn player_id(&mut self, name: &str) -> usize {
+        let canonical = self.canonical_name(name).to_string();
+        if let Some(identity) = self.players.get(&canonical) {
+            return identity.player_id;
+        }
+        let player_id = self.next_id;
+        self.register(&canonical, player_id);
+        player_id
+    }
+
+    /// Add a player with a known id, e.g. from an export that didn't use a registry.
+    /// Names that are already registered keep their id.
+    pub fn register(&mut self, name: &str, player_id: usize) {
+        let canonical = self.canonical_name(name).to_string();
+        self.players
+            .entry(canonical)
+            .or_insert_with(|| PlayerIdentity {
+                player_id,
+                timeout_codes: BTreeSet::new(),
+                auth_names: BTreeSet::new(),
+            });
+        self.next_id = self.next_id.max(player_id + 1);
+    }
+
+    pub fn add_timeout_code(&mut self, name: &str, timeout_code: &str) {
+        let canonical = self.canonical_name(name).to_string();
+        if let Some(identity) = self.players.get_mut(&canonical) {
+            if !identity.timeout_codes.contains(timeout_code) {
+                identity.timeout_codes.insert(timeout_code.to_string());
+            }
+        }
+    }
+
+    pub fn add_auth_name(&mut self, name: &str, auth_name: &str) {
+        let canonical = self.canonical_name(name).to_string();
+        if let Some(identity) = self.players.get_mut(&canonical) {
+            if !identity.auth_names.contains(auth_name) {
+                identity.auth_names.insert(auth_name.to_string());
+            }
+        }
+    }
+
+    /// Record that alias is the same player as canonical. The identity of alias is merged
+    /// into the one of canonical and its player_id is retired, unless canonical has no
+    /// identity yet and takes it over.
+    pub fn add_alias(&mut self, alias: &str, canonical: &str) -> Result<()> {
+        let mut name = canonical;
+        for _ in 0..=self.aliases.len() {
+            if name == alias {
+                return Err(Error::InvalidExport(format!(
+                    "alias {} -> {} would form a cycle",
+                    alias, canonical
+                )));
+            }
+            match self.aliases.get(name) {
+                Some(next) => name = next,
+                None => break,
+            }
+        }
+
+        self.aliases
+            .insert(alias.to_string(), canonical.to_string());
+        if let Some(alias_identity) = self.players.remove(alias) {
+            let canonical = self.canonical_name(canonical).to_string();
+            match self.players.get_mut(&canonical) {
+                Some(identity) => {
+                    identity.timeout_codes.extend(alias_identity.timeout_codes);
+                    identity.auth_names.extend(alias_identity.auth_names);
+                }
+                None => {
+                    self.players.insert(canonical, alias_identity);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the players and aliases of another registry, e.g. of an export of another month.
+    /// Players known to both keep the id of self. Ids of other players are kept if self
+    /// never assigned them, as for players added by a later export starting from self,
+    /// otherwise they get a new id.
+    pub fn union(&mut self, other: &PlayerRegistry) -> Result<()> {
+        for (alias, canonical) in &other.aliases {
+            match self.aliases.get(alias) {
+                Some(existing) if existing == canonical => {}
+                Some(existing) => {
+                    return Err(Error::InvalidExport(format!(
+                        "{} is an alias of {} and {}",
+                        alias, existing, canonical
+                    )))
+                }
+                None => self.add_alias(alias, canonical)?,
+            }
+        }
+
+        let first_unassigned_id = self.next_id;
+        self.next_id = self.next_id.max(other.next_id);
+        // in order of their ids, so new ids keep the order of other
+        let mut players: Vec<_> = other.players.iter().collect();
+        players.sort_by_key(|(_, identity)| identity.player_id);
+        for (name, identity) in players {
+            let canonical = self.canonical_name(name).to_string();
+            if !self.players.contains_key(&canonical) {
+                let player_id = if identity.player_id >= first_unassigned_id {
+                    identity.player_id
+                } else {
+                    self.next_id += 1;
+                    self.next_id - 1
+                };
+                self.register(&canonical, player_id);
+            }
+            if let Some(merged) = self.players.get_mut(&canonical) {
+                merged
+                    .timeout_codes
+                    .extend(identity.timeout_codes.iter().cloned());
+                merged
+                    .auth_names
+                    .extend(identity.auth_names.iter().cloned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Record all aliases of the map, see [`PlayerRegistry::add_alias`]. Names listed as
+    /// alias of multiple canonical names are rejected.
+    pub fn apply_aliases(&mut self, aliases: &AliasMap) -> Result<()> {
+        let mut canonical_names: HashMap<&str, &str> = HashMap::new();
+        for (canonical, names) in aliases {
+            for alias in names {
+                if let Some(other) = canonical_names.insert(alias, canonical) {
+                    return Err(Error::InvalidExport(format!(
+                        "{} is listed as alias of {} and {}",
+                        alias, other, canonical
+                    )));
+                }
+                self.add_alias(alias, canonical)?;
+            }
+        }
+        Ok(())
+    }
+}
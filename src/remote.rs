@@ -0,0 +1,383 @@
+//! Read teehistorian files from object storage and web servers without downloading them first.
+//!
+//! Inputs like `s3://bucket/prefix` or `https://host/a.teehistorian.zst` are streamed through
+//! the same decompression and parsing as local files. S3 requests are signed with the usual
+//! `AWS_*` environment variables, without credentials buckets are read anonymously.
+//! Requests need the `remote` feature, without it remote inputs fail to open.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// sha256 of an empty request body
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Whether the input is an `s3://`, `http://` or `https://` url instead of a local path
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| {
+        ["s3://", "http://", "https://"]
+            .iter()
+            .any(|scheme| path.starts_with(scheme))
+    })
+}
+
+/// Bucket and key of an `s3://bucket/key` url, the key is empty for the whole bucket
+pub fn parse_s3_url(url: &str) -> Option<(&str, &str)> {
+    let location = url.strip_prefix("s3://")?;
+    let (bucket, key) = location.split_once('/').unwrap_or((location, ""));
+    (!bucket.is_empty()).then_some((bucket, key))
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// token of temporary credentials
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// e.g. https://s3.eu-central-1.amazonaws.com
+    pub endpoint: String,
+    /// Bucket as first path segment instead of part of the host name, needed by most
+    /// self-hosted storage like MinIO
+    pub path_style: bool,
+    pub region: String,
+    /// None sends unsigned requests, only public buckets can be read
+    pub credentials: Option<S3Credentials>,
+}
+
+impl S3Config {
+    /// Config from `AWS_REGION`, `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`. Setting `AWS_ENDPOINT_URL` switches to path style requests
+    /// against that endpoint.
+    pub fn from_env() -> S3Config {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let credentials = var("AWS_ACCESS_KEY_ID")
+            .zip(var("AWS_SECRET_ACCESS_KEY"))
+            .map(|(access_key_id, secret_access_key)| S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: var("AWS_SESSION_TOKEN"),
+            });
+        let custom_endpoint = var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL"));
+        S3Config {
+            path_style: custom_endpoint.is_some(),
+            endpoint: custom_endpoint
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region)),
+            region,
+            credentials,
+        }
+    }
+
+    /// Url and headers of a GET request for the key in the bucket, see [`S3Config::request`]
+    pub fn get_request(
+        &self,
+        bucket: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        time: DateTime<Utc>,
+    ) -> (String, Vec<(String, String)>) {
+        self.request("GET", bucket, key, query, EMPTY_SHA256, time)
+    }
+
+    /// Url and headers of a request for the key in the bucket, signed with AWS signature
+    /// version 4 if there are credentials. An empty key requests the bucket itself, e.g. to
+    /// list it. Storage rejects bodies that don't match payload_sha256, the hex sha256 of
+    /// the request body.
+    pub fn request(
+        &self,
+        method: &str,
+        bucket: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        payload_sha256: &str,
+        time: DateTime<Utc>,
+    ) -> (String, Vec<(String, String)>) {
+        let (scheme, endpoint_host) = self
+            .endpoint
+            .split_once("://")
+            .unwrap_or(("https", &self.endpoint));
+        let endpoint_host = endpoint_host.trim_end_matches('/');
+        let (host, path) = match self.path_style {
+            true if key.is_empty() => (endpoint_host.to_string(), format!("/{}", bucket)),
+            true => (endpoint_host.to_string(), format!("/{}/{}", bucket, key)),
+            false => (format!("{}.{}", bucket, endpoint_host), format!("/{}", key)),
+        };
+        let canonical_uri = uri_encode(&path, false);
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+        let url = match canonical_query.is_empty() {
+            true => format!("{}://{}{}", scheme, host, canonical_uri),
+            false => format!("{}://{}{}?{}", scheme, host, canonical_uri, canonical_query),
+        };
+
+        let Some(credentials) = &self.credentials else {
+            return (url, Vec::new());
+        };
+        let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = time.format("%Y%m%d").to_string();
+        // sorted by name, like the canonical request requires
+        let mut headers = vec![
+            ("host".to_string(), host),
+            (
+                "x-amz-content-sha256".to_string(),
+                payload_sha256.to_string(),
+            ),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            canonical_uri,
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            payload_sha256
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = hmac_sha256(
+            format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        // ureq sets the host header itself
+        headers.remove(0);
+        headers.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        (url, headers)
+    }
+}
+
+/// Keys and continuation token of a page of an S3 ListObjectsV2 response.
+/// The token is None on the last page.
+pub fn parse_list_page(xml: &str) -> (Vec<String>, Option<String>) {
+    let keys = xml_values(xml, "Key").map(xml_unescape).collect();
+    let token = xml_values(xml, "NextContinuationToken")
+        .next()
+        .map(xml_unescape);
+    (keys, token)
+}
+
+/// text of all elements with the tag name
+fn xml_values<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let len = rest[start..].find(&close)?;
+        let value = &rest[start..start + len];
+        rest = &rest[start + len + close.len()..];
+        Some(value)
+    })
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Percent-encode everything but unreserved characters, like AWS expects.
+/// Slashes are kept unless encode_slash is set.
+fn uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// hex sha256 of the file contents
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// HMAC-SHA256 (RFC 2104)
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn remote_url(path: &Path) -> io::Result<&str> {
+    path.to_str()
+        .filter(|_| is_remote(path))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a remote url"))
+}
+
+/// Stream the contents of an s3 object or http(s) url
+#[cfg(feature = "remote")]
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let url = remote_url(path)?;
+    let body = match parse_s3_url(url) {
+        Some((bucket, key)) => {
+            let (url, headers) = S3Config::from_env().get_request(bucket, key, &[], Utc::now());
+            get(&url, &headers)?
+        }
+        None => get(url, &[])?,
+    };
+    Ok(Box::new(body.into_reader()))
+}
+
+/// Stream the contents of an s3 object or http(s) url
+#[cfg(not(feature = "remote"))]
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: built without the remote feature", remote_url(path)?),
+    ))
+}
+
+/// All objects below the prefix of an `s3://bucket/prefix` url, as `s3://` urls
+#[cfg(feature = "remote")]
+pub fn list_s3(url: &str) -> io::Result<Vec<PathBuf>> {
+    let (bucket, prefix) = parse_s3_url(url)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an s3 url"))?;
+    let config = S3Config::from_env();
+    let mut paths = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+        if let Some(token) = &token {
+            query.push(("continuation-token", token.as_str()));
+        }
+        let (list_url, headers) = config.get_request(bucket, "", &query, Utc::now());
+        let xml = get(&list_url, &headers)?
+            .read_to_string()
+            .map_err(|err| io::Error::other(format!("{}: {}", url, err)))?;
+        let (keys, next_token) = parse_list_page(&xml);
+        paths.extend(
+            keys.iter()
+                .map(|key| PathBuf::from(format!("s3://{}/{}", bucket, key))),
+        );
+        match next_token {
+            Some(next_token) => token = Some(next_token),
+            None => return Ok(paths),
+        }
+    }
+}
+
+/// All objects below the prefix of an `s3://bucket/prefix` url, as `s3://` urls
+#[cfg(not(feature = "remote"))]
+pub fn list_s3(url: &str) -> io::Result<Vec<PathBuf>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: built without the remote feature", url),
+    ))
+}
+
+/// Upload a local file to an `s3://bucket/key` url. The sha256 of the contents is signed,
+/// so storage rejects uploads that were corrupted on the way. Returns the hex sha256.
+#[cfg(feature = "remote")]
+pub fn put_file(path: &Path, url: &str) -> io::Result<String> {
+    let (bucket, key) = parse_s3_url(url)
+        .filter(|(_, key)| !key.is_empty())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an s3 object url"))?;
+    let sha256 = file_sha256(path)?;
+    let (put_url, headers) =
+        S3Config::from_env().request("PUT", bucket, key, &[], &sha256, Utc::now());
+    let mut request = ureq::put(&put_url);
+    for (name, value) in &headers {
+        request = request.header(name, value);
+    }
+    request
+        .send(File::open(path)?)
+        .map_err(|err| io::Error::other(format!("{}: {}", url, err)))?;
+    Ok(sha256)
+}
+
+/// Upload a local file to an `s3://bucket/key` url. The sha256 of the contents is signed,
+/// so storage rejects uploads that were corrupted on the way. Returns the hex sha256.
+#[cfg(not(feature = "remote"))]
+pub fn put_file(_path: &Path, url: &str) -> io::Result<String> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: built without the remote feature", url),
+    ))
+}
+
+#[cfg(feature = "remote")]
+fn get(url: &str, headers: &[(String, String)]) -> io::Result<ureq::Body> {
+    let mut request = ureq::get(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request
+        .call()
+        .map(|response| response.into_body())
+        .map_err(|err| io::Error::other(format!("{}: {}", url, err)))
+}
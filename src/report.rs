@@ -0,0 +1,268 @@
+//! Self-contained HTML report of an export run, to share results without the log output.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::dataset::Dataset;
+use crate::error::Result;
+use crate::export::Exporter;
+use crate::parser::NameNormalization;
+use crate::plot::{escape_xml, Trajectory};
+use crate::sink::ExportSink;
+
+/// server ticks per second
+const TICK_RATE: f64 = 50.;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+th,td{border:1px solid #ccc;padding:3px 10px;text-align:left}\
+td.num{text-align:right}th{background:#eee}\
+.plots svg{max-width:100%;height:auto;border:1px solid #ccc;margin:0 1em 1em 0}";
+
+/// Groups of player names that are probably the same player, i.e. that only differ in case,
+/// unicode styling or punctuation. Groups and names are sorted.
+pub fn alias_candidates<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<Vec<String>> {
+    let normalization = NameNormalization::all();
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in names {
+        let key: String = normalization
+            .apply(name)
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect();
+        if !key.is_empty() {
+            groups.entry(key).or_default().push(name.to_string());
+        }
+    }
+    groups
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .map(|mut names| {
+            names.sort();
+            names
+        })
+        .collect()
+}
+
+/// Svg plots of count sequences spread evenly over an exported dataset
+pub fn sample_plots(folder_path: &Path, count: usize) -> Result<Vec<String>> {
+    let dataset = Dataset::open(folder_path)?;
+    let rows = dataset.meta.len().min(dataset.shape().0);
+    let mut plots = Vec::new();
+    for i in 0..count.min(rows) {
+        let row = i * rows / count.min(rows);
+        let sequences = dataset.read_sequences(row, row + 1)?;
+        let features = sequences.index_axis(ndarray::Axis(0), 0);
+        let trajectory = Trajectory::from_features(&dataset.column_names, features)?;
+        let meta = &dataset.meta[row];
+        let title = format!(
+            "seq {}: {} on {}, tick {} of {}",
+            meta.seq_id, meta.player, meta.map, meta.start, meta.teehist
+        );
+        plots.push(trajectory.render_svg(&title));
+    }
+    Ok(plots)
+}
+
+/// Render the report of a run, listing the top_k players. The sample plots are svg documents
+/// that are embedded as they are.
+pub fn render_html<S: ExportSink>(
+    exporter: &Exporter<S>,
+    elapsed: Duration,
+    top_k: usize,
+    sample_plots: &[String],
+) -> String {
+    let summary = &exporter.summary;
+    let exported_ticks: usize = exporter.file_ticks.values().sum();
+    let dropped: usize = summary.sequences_dropped.values().sum();
+    let failed_files: usize = summary.parse_errors.values().sum();
+
+    let mut html = String::new();
+    // writing into a String can't fail
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>teehistorian export report</title>\n<style>{}</style>\n</head>\n<body>",
+        STYLE
+    );
+    let _ = writeln!(html, "<h1>Export report</h1>");
+
+    let _ = writeln!(html, "<h2>Summary</h2>");
+    table(
+        &mut html,
+        &[],
+        [
+            ("files processed", summary.files_processed.to_string()),
+            ("files with parse errors", failed_files.to_string()),
+            (
+                "sequences converted",
+                summary.sequences_converted.to_string(),
+            ),
+            ("sequences dropped", dropped.to_string()),
+            ("sequences exported", exporter.sequence_count.to_string()),
+            (
+                "sequences flagged as anomalous",
+                summary.sequences_flagged.to_string(),
+            ),
+            ("players", exporter.players.len().to_string()),
+            (
+                "exported gameplay",
+                format!(
+                    "{} ticks, {:.1} hours",
+                    exported_ticks,
+                    exported_ticks as f64 / (TICK_RATE * 60. * 60.)
+                ),
+            ),
+            (
+                "duration",
+                humantime::format_duration(Duration::from_millis(elapsed.as_millis() as u64))
+                    .to_string(),
+            ),
+        ]
+        .iter()
+        .map(|(name, value)| vec![name.to_string(), value.clone()]),
+    );
+
+    let _ = writeln!(html, "<h2>Dropped sequences</h2>");
+    table(
+        &mut html,
+        &["reason", "sequences", "share"],
+        summary.sequences_dropped.iter().map(|(reason, &count)| {
+            vec![reason.clone(), count.to_string(), percent(count, dropped)]
+        }),
+    );
+
+    if !summary.parse_errors.is_empty() {
+        let _ = writeln!(html, "<h2>Parse errors</h2>");
+        table(
+            &mut html,
+            &["error", "files"],
+            summary
+                .parse_errors
+                .iter()
+                .map(|(kind, count)| vec![kind.clone(), count.to_string()]),
+        );
+    }
+
+    let mut players: Vec<_> = exporter.players.iter().collect();
+    players.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(b.0)));
+    let _ = writeln!(
+        html,
+        "<h2>Top {} of {} players</h2>",
+        top_k.min(players.len()),
+        players.len()
+    );
+    table(
+        &mut html,
+        &["player", "id", "sequences", "share"],
+        players.iter().take(top_k).map(|(name, &(id, count))| {
+            vec![
+                name.to_string(),
+                id.to_string(),
+                count.to_string(),
+                percent(count, exporter.sequence_count),
+            ]
+        }),
+    );
+
+    let _ = writeln!(html, "<h2>Maps</h2>");
+    table(
+        &mut html,
+        &["map", "sequences", "share"],
+        sorted_counts(&exporter.map_sequences)
+            .into_iter()
+            .map(|(map, count)| {
+                vec![
+                    map.to_string(),
+                    count.to_string(),
+                    percent(count, exporter.sequence_count),
+                ]
+            }),
+    );
+
+    // names that already share a player_id are merged aliases
+    let aliases: Vec<Vec<String>> =
+        alias_candidates(exporter.players.keys().map(|name| name.as_ref()))
+            .into_iter()
+            .filter(|names| {
+                let ids: HashSet<usize> = names
+                    .iter()
+                    .map(|name| exporter.players[name.as_str()].0)
+                    .collect();
+                ids.len() > 1
+            })
+            .collect();
+    let _ = writeln!(html, "<h2>Alias candidates</h2>");
+    let _ = writeln!(
+        html,
+        "<p>Player names with different player_ids that only differ in case, unicode styling \
+         or punctuation.</p>"
+    );
+    table(
+        &mut html,
+        &["names", "sequences"],
+        aliases.iter().map(|names| {
+            let count: usize = names
+                .iter()
+                .map(|name| exporter.players[name.as_str()].1)
+                .sum();
+            vec![names.join(", "), count.to_string()]
+        }),
+    );
+
+    if !sample_plots.is_empty() {
+        let _ = writeln!(html, "<h2>Sample sequences</h2>\n<div class=\"plots\">");
+        for plot in sample_plots {
+            html.push_str(plot);
+        }
+        let _ = writeln!(html, "</div>");
+    }
+    let _ = writeln!(html, "</body>\n</html>");
+    html
+}
+
+/// append a table, cells are escaped and all but the first column aligned right
+fn table(html: &mut String, header: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+    let mut rows = rows.peekable();
+    if rows.peek().is_none() {
+        html.push_str("<p>none</p>\n");
+        return;
+    }
+    html.push_str("<table>\n");
+    if header.iter().any(|h| !h.is_empty()) {
+        html.push_str("<tr>");
+        for cell in header {
+            let _ = write!(html, "<th>{}</th>", escape_xml(cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    for row in rows {
+        html.push_str("<tr>");
+        for (i, cell) in row.into_iter().enumerate() {
+            let class = if i > 0 { " class=\"num\"" } else { "" };
+            let _ = write!(html, "<td{}>{}</td>", class, escape_xml(&cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn percent(count: usize, total: usize) -> String {
+    format!("{:.1}%", count as f64 * 100. / total.max(1) as f64)
+}
+
+/// most frequent first, ties by name
+fn sorted_counts(counts: &HashMap<Arc<str>, usize>) -> Vec<(&str, usize)> {
+    let mut counts: Vec<_> = counts
+        .iter()
+        .map(|(name, &count)| (name.as_ref(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    counts
+}
@@ -0,0 +1,191 @@
+//! Routes: clusters of sequences that move through the same part of a map, to stratify
+//! training data and spot skips or unusual routes.
+//!
+//! Exports with [`ExportConfig::routes`] write the coarse path of each sequence through a
+//! grid of cell_size world units as the route_cells column of meta.csv. Once the export is
+//! finalized, the sequences of each map are clustered by the cells they visited and the
+//! route column holds the cluster, 0 being the most common route of the map. Like sample
+//! weights, routes describe the dataset they were computed for and need to be computed again
+//! after filter or merge.
+//!
+//! [`ExportConfig::routes`]: crate::export::ExportConfig::routes
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use crate::checksum;
+use crate::dataset::{read_meta, MetaRow, META_HEADER};
+use crate::error::{Error, Result};
+use crate::extractor::Sequence;
+
+/// cell of the route grid, (x, y) in cells
+pub type Cell = (i32, i32);
+
+/// Route clustering of an export
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// side of a grid cell in world units, a tile has 32
+    pub cell_size: i32,
+    /// sequences join a route if the jaccard distance of their visited cells to those of its
+    /// first sequence is at most this, from 0 (same cells) to 1 (any cells)
+    pub max_distance: f64,
+}
+
+impl RouteConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cell_size <= 0 {
+            return Err(format!("cell_size={} must be positive", self.cell_size));
+        }
+        validate_max_distance(self.max_distance)
+    }
+}
+
+fn validate_max_distance(max_distance: f64) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&max_distance) {
+        return Err(format!("max_distance={} not in [0, 1]", max_distance));
+    }
+    Ok(())
+}
+
+/// Cells visited in the first ticks of the sequence in order, staying in a cell counts once
+pub fn route_cells(sequence: &Sequence, ticks: usize, cell_size: i32) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::new();
+    for (&x, &y) in sequence.pos_x.iter().zip(&sequence.pos_y).take(ticks) {
+        let cell = (x.div_euclid(cell_size), y.div_euclid(cell_size));
+        if cells.last() != Some(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+}
+
+/// value of the route_cells column: cells as "x:y" joined by '|'
+pub fn format_route_cells(cells: &[Cell]) -> String {
+    cells
+        .iter()
+        .map(|(x, y)| format!("{}:{}", x, y))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Parse the route_cells column, None if it is malformed
+pub fn parse_route_cells(s: &str) -> Option<Vec<Cell>> {
+    s.split('|')
+        .map(|cell| {
+            let (x, y) = cell.split_once(':')?;
+            Some((x.parse().ok()?, y.parse().ok()?))
+        })
+        .collect()
+}
+
+/// 1 - shared cells / cells visited by either, 0 if both are empty
+pub fn jaccard_distance(a: &BTreeSet<Cell>, b: &BTreeSet<Cell>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - a.intersection(b).count() as f64 / union as f64
+}
+
+/// Route of each meta row among the rows of its map, None for rows without route cells.
+///
+/// Rows are visited in order and join the route whose first row is nearest, if that is
+/// within max_distance, or start a new route. Routes are numbered by size, so 0 is the most
+/// common route of each map.
+pub fn cluster_routes(meta: &[MetaRow], max_distance: f64) -> Vec<Option<usize>> {
+    let mut maps: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, row) in meta.iter().enumerate() {
+        maps.entry(&row.map).or_default().push(index);
+    }
+
+    let mut routes = vec![None; meta.len()];
+    for rows in maps.values() {
+        // visited cells of the first row and rows of each route
+        let mut clusters: Vec<(BTreeSet<Cell>, Vec<usize>)> = Vec::new();
+        for &index in rows {
+            let Some(cells) = meta[index]
+                .route_cells
+                .as_deref()
+                .and_then(parse_route_cells)
+            else {
+                continue;
+            };
+            let cells: BTreeSet<Cell> = cells.into_iter().collect();
+            let nearest = clusters
+                .iter()
+                .enumerate()
+                .map(|(cluster, (first, _))| (cluster, jaccard_distance(first, &cells)))
+                .filter(|&(_, distance)| distance <= max_distance)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            match nearest {
+                Some((cluster, _)) => clusters[cluster].1.push(index),
+                None => clusters.push((cells, vec![index])),
+            }
+        }
+        // stable, ties keep the order of their first rows
+        clusters.sort_by_key(|(_, members)| Reverse(members.len()));
+        for (route, (_, members)) in clusters.iter().enumerate() {
+            for &index in members {
+                routes[index] = Some(route);
+            }
+        }
+    }
+    routes
+}
+
+/// Fill the route column of meta.csv of the dataset in folder_path, see [`cluster_routes`].
+/// meta.csv is replaced at once. Returns the amount of rows with a route.
+pub fn write_routes(folder_path: &Path, max_distance: f64) -> Result<usize> {
+    let meta_path = folder_path.join("meta.csv");
+    let meta = read_meta(&meta_path)?;
+    let routes = cluster_routes(&meta, max_distance);
+
+    let tmp_meta_path = folder_path.join("meta.csv.tmp");
+    let mut meta_file = File::create(&tmp_meta_path)?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    for (row, route) in meta.iter().zip(&routes) {
+        let routed_row = MetaRow {
+            route: *route,
+            ..row.clone()
+        };
+        writeln!(meta_file, "{}", routed_row.to_csv())?;
+    }
+    meta_file.sync_all()?;
+    fs::rename(tmp_meta_path, meta_path)?;
+
+    let routed = routes.iter().flatten().count();
+    info!("wrote routes of {} sequences", routed);
+    Ok(routed)
+}
+
+/// Cluster the routes of an exported dataset again in place, e.g. after filter or merge,
+/// record max_distance in its manifest.json and update its checksums. The dataset needs to
+/// be exported with route cells.
+pub fn route_dataset(folder_path: &Path, max_distance: f64) -> Result<usize> {
+    validate_max_distance(max_distance).map_err(Error::InvalidExport)?;
+    let routed = write_routes(folder_path, max_distance)?;
+    if routed == 0 {
+        return Err(Error::InvalidExport(
+            "dataset has no route cells, export it with --route-cell-size".to_string(),
+        ));
+    }
+
+    let manifest_path = folder_path.join("manifest.json");
+    if manifest_path.is_file() {
+        let mut manifest: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_reader(File::open(&manifest_path)?)?;
+        if let Some(routes) = manifest.get_mut("routes").and_then(|r| r.as_object_mut()) {
+            routes.insert("max_distance".into(), max_distance.into());
+        }
+        serde_json::to_writer_pretty(File::create(manifest_path)?, &manifest)?;
+    }
+    checksum::write_checksums(folder_path)?;
+    Ok(routed)
+}
@@ -0,0 +1,54 @@
+//! Play sessions of the sequences of a file, exported as the session_id and session_seq
+//! columns of meta.csv, e.g. to model warm-up and fatigue.
+//!
+//! A session is a stretch of play of a client connection. It ends with the drop of the
+//! client or an absence of more than [`ExportConfig::session_gap`] ticks between two
+//! sequences, e.g. while spectating or afk.
+//!
+//! [`ExportConfig::session_gap`]: crate::export::ExportConfig::session_gap
+
+use std::sync::Arc;
+
+use crate::extractor::Sequence;
+use crate::parser::ClientSession;
+
+/// Id of a session, unique within a dataset: `<teehist>:<cid>:<start tick of the session>`
+pub fn session_id(sequence: &Sequence) -> String {
+    format!(
+        "{}:{}:{}",
+        sequence.teehist_name, sequence.cid, sequence.start_tick
+    )
+}
+
+/// Set session_id and session_seq of the sequences of a file, which must be ordered by cid and
+/// start tick. client_sessions are the join to drop connections of the file, sequences of a
+/// cid without one are split by gaps only.
+pub fn assign_sessions(sequences: &mut [Sequence], client_sessions: &[ClientSession], gap: usize) {
+    // cid, client session and end tick of the previous sequence
+    let mut previous: Option<(i32, Option<usize>, usize)> = None;
+    let mut session_seq = 0;
+    let mut current_id: Option<Arc<str>> = None;
+    for sequence in sequences {
+        let client_session = client_sessions.iter().rposition(|client| {
+            client.cid == sequence.cid && client.join_tick as i64 <= sequence.start_tick as i64
+        });
+        let continues = previous.is_some_and(|(cid, previous_session, end_tick)| {
+            cid == sequence.cid
+                && previous_session == client_session
+                && sequence.start_tick <= end_tick + gap
+        });
+        if continues {
+            session_seq += 1;
+        } else {
+            session_seq = 0;
+            current_id = Some(session_id(sequence).into());
+        }
+        sequence.session_id = current_id.clone();
+        sequence.session_seq = session_seq;
+        previous = Some((
+            sequence.cid,
+            client_session,
+            sequence.start_tick + sequence.tick_count,
+        ));
+    }
+}
@@ -0,0 +1,666 @@
+use hdf5_metno as hdf5;
+use ndarray::{ArrayView2, ArrayView3, ArrayViewMut1, ArrayViewMut2};
+use rayon::prelude::*;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::dataset::{
+    create_sequences_file, open_sequences_file, DatasetError, MetaRow, META_HEADER,
+};
+use crate::embedding::{create_embeddings_dataset, embed, EMBEDDINGS_DATASET, EMBEDDING_SIZE};
+use crate::error::{Error, Result};
+use crate::export::{ExportConfig, MAX_AIM_DISTANCE};
+use crate::extractor::Sequence;
+use crate::phase::phases;
+
+/// Storage backend of an [`crate::export::Exporter`].
+/// The exporter decides which sequences are kept and assigns their ids, a sink only stores
+/// them. Bookkeeping such as checkpoints and the ledger stays in the output folder.
+pub trait ExportSink {
+    /// Prepare the store for sequences of config.seq_length ticks.
+    /// When resuming, resume_count is the amount of sequences of the last checkpoint and
+    /// everything stored after them has to be discarded.
+    fn open(&mut self, config: &ExportConfig, resume_count: Option<usize>) -> Result<()>;
+
+    /// store a batch of sequences, meta[i] holds the ids assigned to sequences[i]
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()>;
+
+    /// persist everything written so far, called before each checkpoint
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// called once after the last batch
+    fn finalize(&mut self) -> Result<()>;
+
+    /// Check that exactly sequence_count sequences are stored, called after each flush and
+    /// after finalize. A mismatch aborts the export before the next checkpoint is written.
+    #[allow(unused_variables)]
+    fn check_count(&mut self, sequence_count: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// size of the stored data, used for max_dataset_bytes
+    fn bytes_written(&self) -> u64 {
+        0
+    }
+
+    /// batches passed to write_batch that aren't stored yet
+    fn queued_batches(&self) -> u64 {
+        0
+    }
+}
+
+/// sink chosen at runtime, e.g. by command line arguments
+impl ExportSink for Box<dyn ExportSink + Send> {
+    fn open(&mut self, config: &ExportConfig, resume_count: Option<usize>) -> Result<()> {
+        (**self).open(config, resume_count)
+    }
+
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()> {
+        (**self).write_batch(sequences, meta)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        (**self).finalize()
+    }
+
+    fn check_count(&mut self, sequence_count: usize) -> Result<()> {
+        (**self).check_count(sequence_count)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        (**self).bytes_written()
+    }
+
+    fn queued_batches(&self) -> u64 {
+        (**self).queued_batches()
+    }
+}
+
+fn bool_to_unit_f32(b: bool) -> f32 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Write the values into the next feature column
+fn fill_column<'a>(
+    columns: &mut impl Iterator<Item = ArrayViewMut1<'a, f32>>,
+    values: impl Iterator<Item = f32>,
+) -> Result<()> {
+    let mut column = columns.next().ok_or_else(|| {
+        Error::InvalidExport("config has more features than the output".to_string())
+    })?;
+    column
+        .iter_mut()
+        .zip(values)
+        .for_each(|(feature, value)| *feature = value);
+    Ok(())
+}
+
+/// Write the features of the first ticks of a sequence into out, shaped (ticks, features).
+/// The features have to match [`ExportConfig::column_names`], the velocity of the last tick
+/// needs one more tick of the sequence.
+pub fn write_features(
+    seq: &Sequence,
+    config: &ExportConfig,
+    mut out: ArrayViewMut2<f32>,
+) -> Result<()> {
+    let ticks = out.nrows();
+    let needed_ticks = if config.use_vel { ticks + 1 } else { ticks };
+    if seq.tick_count < needed_ticks {
+        return Err(Error::InvalidExport(format!(
+            "sequence has {} ticks, {} are needed",
+            seq.tick_count, needed_ticks
+        )));
+    }
+
+    let columns = &mut out.columns_mut().into_iter();
+    let targets = || seq.target_x[..ticks].iter().zip(&seq.target_y[..ticks]);
+    fill_column(columns, seq.move_dir[..ticks].iter().map(|&i| i as f32))?;
+    fill_column(
+        columns,
+        seq.jump[..ticks].iter().map(|&b| bool_to_unit_f32(b)),
+    )?;
+    fill_column(
+        columns,
+        seq.fire[..ticks].iter().map(|&b| bool_to_unit_f32(b)),
+    )?;
+    fill_column(
+        columns,
+        seq.hook[..ticks].iter().map(|&b| bool_to_unit_f32(b)),
+    )?;
+
+    if config.use_vel {
+        fill_column(
+            columns,
+            seq.pos_x[..=ticks].windows(2).map(|w| (w[1] - w[0]) as f32),
+        )?;
+        fill_column(
+            columns,
+            seq.pos_y[..=ticks].windows(2).map(|w| (w[1] - w[0]) as f32),
+        )?;
+    }
+
+    if config.use_rel_target {
+        fill_column(columns, targets().map(|(&x, _)| x as f32))?;
+        fill_column(columns, targets().map(|(_, &y)| y as f32))?;
+    }
+
+    if config.use_aim_angle {
+        fill_column(
+            columns,
+            targets().map(|(&x, &y)| (y as f32).atan2(x as f32).to_degrees()),
+        )?;
+    }
+
+    if config.use_aim_distance {
+        fill_column(
+            columns,
+            targets().map(|(&x, &y)| (x as f32).hypot(y as f32).min(MAX_AIM_DISTANCE)),
+        )?;
+    }
+
+    if config.freeze_mask {
+        fill_column(
+            columns,
+            (0..ticks).map(|i| bool_to_unit_f32(seq.frozen.get(i).copied().unwrap_or(false))),
+        )?;
+    }
+
+    if config.mask_inactive {
+        fill_column(
+            columns,
+            (0..ticks).map(|i| bool_to_unit_f32(seq.active.get(i).copied().unwrap_or(true))),
+        )?;
+    }
+
+    if config.phase_labels {
+        fill_column(
+            columns,
+            phases(seq)[..ticks].iter().map(|&phase| phase as u8 as f32),
+        )?;
+    }
+
+    if columns.next().is_some() {
+        return Err(Error::InvalidExport(
+            "output has more features than the config".to_string(),
+        ));
+    }
+
+    if !config.smoothing.is_empty() {
+        for (name, column) in config.column_names().iter().zip(out.columns_mut()) {
+            if let Some(smoothing) = config.smoothing.get(name) {
+                smoothing.apply(column, name == "aim_angle");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Default sink writing sequences.h5 and meta.csv into a folder.
+/// Sequences are buffered until a whole chunk of the dataset can be written, the dataset
+/// grows geometrically and is cut to the written sequences on flush.
+pub struct Hdf5Sink {
+    folder_path: PathBuf,
+    config: ExportConfig,
+    num_features: usize,
+    seq_dataset: Option<hdf5::Dataset>,
+    meta_file: Option<File>,
+
+    /// data rows of meta_file
+    meta_rows: usize,
+
+    /// sequences written to seq_dataset, it may be allocated larger
+    written: usize,
+
+    /// sequences per chunk of seq_dataset
+    chunk_sequences: usize,
+
+    /// ticks of sequences not written yet, (sequences, seq_length, features) in row-major order
+    pending: Vec<f32>,
+
+    /// embeddings dataset of sequences.h5 if the config writes them, kept at the size of
+    /// seq_dataset
+    embedding_dataset: Option<hdf5::Dataset>,
+
+    /// embeddings of the pending sequences, (sequences, EMBEDDING_SIZE) in row-major order
+    pending_embeddings: Vec<f32>,
+}
+
+impl Hdf5Sink {
+    pub fn new(folder_path: &Path) -> Hdf5Sink {
+        Hdf5Sink {
+            folder_path: folder_path.to_path_buf(),
+            config: ExportConfig::default(),
+            num_features: 0,
+            seq_dataset: None,
+            meta_file: None,
+            meta_rows: 0,
+            written: 0,
+            chunk_sequences: 1,
+            pending: Vec::new(),
+            embedding_dataset: None,
+            pending_embeddings: Vec::new(),
+        }
+    }
+
+    fn sequence_len(&self) -> usize {
+        self.config.seq_length * self.num_features
+    }
+
+    fn pending_sequences(&self) -> usize {
+        self.pending.len() / self.sequence_len().max(1)
+    }
+
+    /// Write pending sequences up to the last chunk boundary, or all of them.
+    /// The dataset is at least doubled when it has to grow.
+    fn write_pending(&mut self, all: bool) -> Result<()> {
+        let pending = self.pending_sequences();
+        let count = if all {
+            pending
+        } else {
+            let chunk_end = (self.written + pending) / self.chunk_sequences * self.chunk_sequences;
+            chunk_end.saturating_sub(self.written)
+        };
+        if count == 0 {
+            return Ok(());
+        }
+        let Some(seq_dataset) = &self.seq_dataset else {
+            return Err(Error::InvalidExport("sink was not opened".to_string()));
+        };
+
+        let shape = (self.config.seq_length, self.num_features);
+        let (start, end) = (self.written, self.written + count);
+        let capacity = seq_dataset.shape()[0];
+        if end > capacity {
+            let grown = end.max(capacity * 2).div_ceil(self.chunk_sequences) * self.chunk_sequences;
+            seq_dataset.resize((grown, shape.0, shape.1))?;
+        }
+
+        let values = count * self.sequence_len();
+        let tick_data = ArrayView3::from_shape((count, shape.0, shape.1), &self.pending[..values])?;
+        seq_dataset.write_slice(&tick_data, (start..end, .., ..))?;
+        self.pending.drain(..values);
+
+        if let Some(embedding_dataset) = &self.embedding_dataset {
+            let capacity = embedding_dataset.shape()[0];
+            if end > capacity {
+                embedding_dataset.resize((end.max(capacity * 2), EMBEDDING_SIZE))?;
+            }
+            let values = count * EMBEDDING_SIZE;
+            let embeddings = ArrayView2::from_shape(
+                (count, EMBEDDING_SIZE),
+                &self.pending_embeddings[..values],
+            )?;
+            embedding_dataset.write_slice(&embeddings, (start..end, ..))?;
+            self.pending_embeddings.drain(..values);
+        }
+        self.written = end;
+        Ok(())
+    }
+}
+
+impl ExportSink for Hdf5Sink {
+    fn open(&mut self, config: &ExportConfig, resume_count: Option<usize>) -> Result<()> {
+        let column_names = config.column_names();
+        let num_features = column_names.len();
+        let folder_path = &self.folder_path;
+
+        let (seq_dataset, embedding_dataset, meta_file) = if let Some(resume_count) = resume_count {
+            let seq_dataset = open_sequences_file(folder_path)?;
+            if seq_dataset.shape()[1..] != [config.seq_length, num_features] {
+                return Err(DatasetError::SchemaMismatch(format!(
+                    "sequences.h5 has shape {:?}, export config expects seq_length={} features={}",
+                    seq_dataset.shape(),
+                    config.seq_length,
+                    num_features
+                ))
+                .into());
+            }
+
+            // keep the rows up to the checkpoint, quoted values may span several lines
+            let meta_path = folder_path.join("meta.csv");
+            let meta: Vec<MetaRow> = csv::Reader::from_path(&meta_path)
+                .map_err(DatasetError::from)?
+                .deserialize()
+                .take(resume_count)
+                .collect::<Result<_, _>>()
+                .map_err(DatasetError::from)?;
+
+            // rows after the checkpoint can be discarded, missing ones can't be restored
+            let (sequences, meta_rows) = (seq_dataset.shape()[0], meta.len());
+            if sequences < resume_count || meta_rows < resume_count {
+                return Err(DatasetError::CountMismatch {
+                    sequences,
+                    meta: meta_rows,
+                    expected: resume_count,
+                }
+                .into());
+            }
+            seq_dataset.resize((resume_count, config.seq_length, num_features))?;
+            let embedding_dataset = if config.embeddings {
+                let seq_file = seq_dataset.file()?;
+                if !seq_file.link_exists(EMBEDDINGS_DATASET) {
+                    return Err(DatasetError::SchemaMismatch(
+                        "sequences.h5 has no embeddings, export config expects them".to_string(),
+                    )
+                    .into());
+                }
+                let embedding_dataset = seq_file.dataset(EMBEDDINGS_DATASET)?;
+                embedding_dataset.resize((resume_count, EMBEDDING_SIZE))?;
+                Some(embedding_dataset)
+            } else {
+                None
+            };
+            seq_dataset.file()?.flush()?;
+            // replaced at once, a crash while rewriting keeps the old rows
+            let tmp_meta_path = folder_path.join("meta.csv.tmp");
+            let mut rows = format!("{}\n", META_HEADER);
+            for row in &meta {
+                rows += &format!("{}\n", row.to_csv());
+            }
+            fs::write(&tmp_meta_path, rows)?;
+            fs::rename(&tmp_meta_path, &meta_path)?;
+            let meta_file = OpenOptions::new().append(true).open(&meta_path)?;
+
+            (seq_dataset, embedding_dataset, meta_file)
+        } else {
+            // initialize sequences hdf5 file
+            let seq_dataset = create_sequences_file(folder_path, config.seq_length, &column_names)?;
+            let embedding_dataset = if config.embeddings {
+                Some(create_embeddings_dataset(&seq_dataset.file()?)?)
+            } else {
+                None
+            };
+
+            // initialize meta
+            let mut meta_file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(folder_path.join("meta.csv"))?;
+            writeln!(meta_file, "{}", META_HEADER)?;
+
+            (seq_dataset, embedding_dataset, meta_file)
+        };
+
+        self.config = config.clone();
+        self.num_features = num_features;
+        self.written = seq_dataset.shape()[0];
+        self.meta_rows = self.written;
+        self.chunk_sequences = seq_dataset.chunk().map_or(1, |chunk| chunk[0].max(1));
+        self.pending.clear();
+        self.pending_embeddings.clear();
+        self.seq_dataset = Some(seq_dataset);
+        self.embedding_dataset = embedding_dataset;
+        self.meta_file = Some(meta_file);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()> {
+        let Some(meta_file) = &mut self.meta_file else {
+            return Err(Error::InvalidExport("sink was not opened".to_string()));
+        };
+        if meta.len() != sequences.len() {
+            return Err(Error::InvalidExport(format!(
+                "batch of {} sequences has {} meta rows",
+                sequences.len(),
+                meta.len()
+            )));
+        }
+        for meta_row in meta {
+            writeln!(meta_file, "{}", meta_row.to_csv())?;
+        }
+        self.meta_rows += meta.len();
+
+        // features are written directly into the pending buffer, one sequence per task
+        let (start, sequence_len) = (self.pending.len(), self.sequence_len());
+        self.pending
+            .resize(start + sequences.len() * sequence_len, 0.0);
+        let shape = (self.config.seq_length, self.num_features);
+        let config = &self.config;
+        let written = self.pending[start..]
+            .par_chunks_mut(sequence_len.max(1))
+            .zip(sequences.par_iter())
+            .try_for_each(|(out, seq)| {
+                write_features(seq, config, ArrayViewMut2::from_shape(shape, out)?)
+            });
+        if let Err(err) = written {
+            self.pending.truncate(start);
+            return Err(err);
+        }
+        if self.embedding_dataset.is_some() {
+            let embeddings: Vec<[f32; EMBEDDING_SIZE]> = sequences
+                .par_iter()
+                .map(|seq| embed(seq, config.seq_length))
+                .collect();
+            self.pending_embeddings.extend(embeddings.iter().flatten());
+        }
+        self.write_pending(false)
+    }
+
+    /// Writes all pending sequences, cuts the dataset to the written ones and syncs both
+    /// files to disk. Metadata of sequences.h5 is only written here, see
+    /// [`open_sequences_file`], so a crash after a flush leaves a readable file.
+    fn flush(&mut self) -> Result<()> {
+        self.write_pending(true)?;
+        if let Some(meta_file) = self.meta_file.as_mut() {
+            meta_file.flush()?;
+            meta_file.sync_data()?;
+        }
+        if let Some(seq_dataset) = self.seq_dataset.as_ref() {
+            if seq_dataset.shape()[0] > self.written {
+                seq_dataset.resize((self.written, self.config.seq_length, self.num_features))?;
+            }
+            if let Some(embedding_dataset) = self.embedding_dataset.as_ref() {
+                if embedding_dataset.shape()[0] > self.written {
+                    embedding_dataset.resize((self.written, EMBEDDING_SIZE))?;
+                }
+            }
+            seq_dataset.file()?.flush()?;
+            // H5Fflush only hands the data to the operating system
+            OpenOptions::new()
+                .write(true)
+                .open(self.folder_path.join("sequences.h5"))?
+                .sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    /// compares the flushed size of sequences.h5 and the rows written to meta.csv
+    fn check_count(&mut self, sequence_count: usize) -> Result<()> {
+        let Some(seq_dataset) = &self.seq_dataset else {
+            return Err(Error::InvalidExport("sink was not opened".to_string()));
+        };
+        let sequences = seq_dataset.shape()[0] + self.pending_sequences();
+        if sequences != sequence_count || self.meta_rows != sequence_count {
+            return Err(DatasetError::CountMismatch {
+                sequences,
+                meta: self.meta_rows,
+                expected: sequence_count,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> u64 {
+        let sequence_bytes = self.config.seq_length * self.num_features * size_of::<f32>();
+        let sequences = self.written + self.pending_sequences();
+        let meta_bytes = self
+            .meta_file
+            .as_ref()
+            .and_then(|f| f.metadata().ok())
+            .map(|m| m.len())
+            .unwrap_or(0);
+        (sequences * sequence_bytes) as u64 + meta_bytes
+    }
+}
+
+/// amount of batches a [`BackgroundSink`] queues before write_batch blocks
+const BACKGROUND_QUEUE_LEN: usize = 16;
+
+enum SinkCommand {
+    Write(Vec<Sequence>, Vec<MetaRow>),
+    Flush(SyncSender<Result<()>>),
+    Finalize(SyncSender<Result<()>>),
+    CheckCount(usize, SyncSender<Result<()>>),
+}
+
+/// Runs another sink on a dedicated writer thread, so writing overlaps with parsing.
+/// Batches are queued in a bounded channel. Write errors are reported by the next flush or
+/// finalize, which wait until everything queued before them is written.
+pub struct BackgroundSink<S: ExportSink + Send + 'static> {
+    inner: Option<S>,
+    sender: Option<SyncSender<SinkCommand>>,
+    writer: Option<JoinHandle<()>>,
+    bytes_written: Arc<AtomicU64>,
+    queued: Arc<AtomicU64>,
+}
+
+impl<S: ExportSink + Send + 'static> BackgroundSink<S> {
+    pub fn new(inner: S) -> BackgroundSink<S> {
+        BackgroundSink {
+            inner: Some(inner),
+            sender: None,
+            writer: None,
+            bytes_written: Arc::new(AtomicU64::new(0)),
+            queued: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// writer thread, the first write error skips all further writes until it is reported
+    fn run(
+        mut inner: S,
+        receiver: Receiver<SinkCommand>,
+        bytes_written: Arc<AtomicU64>,
+        queued: Arc<AtomicU64>,
+    ) {
+        let mut error = None;
+        for command in receiver {
+            match command {
+                SinkCommand::Write(sequences, meta) => {
+                    if error.is_none() {
+                        error = inner.write_batch(&sequences, &meta).err();
+                        bytes_written.store(inner.bytes_written(), Ordering::Relaxed);
+                    }
+                    queued.fetch_sub(1, Ordering::Relaxed);
+                }
+                SinkCommand::Flush(reply) => {
+                    let result = error.take().map_or_else(|| inner.flush(), Err);
+                    let _ = reply.send(result);
+                }
+                SinkCommand::Finalize(reply) => {
+                    let result = error.take().map_or_else(|| inner.finalize(), Err);
+                    let _ = reply.send(result);
+                }
+                SinkCommand::CheckCount(sequence_count, reply) => {
+                    let result = error
+                        .take()
+                        .map_or_else(|| inner.check_count(sequence_count), Err);
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    }
+
+    fn send(&self, command: SinkCommand) -> Result<()> {
+        let sender = self
+            .sender
+            .as_ref()
+            .ok_or_else(|| Error::InvalidExport("sink was not opened".to_string()))?;
+        sender
+            .send(command)
+            .map_err(|_| Error::InvalidExport("writer thread stopped".to_string()))
+    }
+
+    /// send a command and wait for the writer thread to handle it
+    fn request(&self, command: impl FnOnce(SyncSender<Result<()>>) -> SinkCommand) -> Result<()> {
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(command(reply))?;
+        response
+            .recv()
+            .map_err(|_| Error::InvalidExport("writer thread stopped".to_string()))?
+    }
+}
+
+impl<S: ExportSink + Send + 'static> ExportSink for BackgroundSink<S> {
+    fn open(&mut self, config: &ExportConfig, resume_count: Option<usize>) -> Result<()> {
+        let mut inner = self
+            .inner
+            .take()
+            .ok_or_else(|| Error::InvalidExport("sink was already opened".to_string()))?;
+        inner.open(config, resume_count)?;
+        self.bytes_written
+            .store(inner.bytes_written(), Ordering::Relaxed);
+
+        let (sender, receiver) = mpsc::sync_channel(BACKGROUND_QUEUE_LEN);
+        let bytes_written = self.bytes_written.clone();
+        let queued = self.queued.clone();
+        self.sender = Some(sender);
+        self.writer = Some(thread::spawn(move || {
+            BackgroundSink::run(inner, receiver, bytes_written, queued)
+        }));
+        Ok(())
+    }
+
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.send(SinkCommand::Write(sequences.to_vec(), meta.to_vec()))
+            .inspect_err(|_| {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+            })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.request(SinkCommand::Flush)
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.request(SinkCommand::Finalize)
+    }
+
+    fn check_count(&mut self, sequence_count: usize) -> Result<()> {
+        self.request(|reply| SinkCommand::CheckCount(sequence_count, reply))
+    }
+
+    /// size after the last written batch, queued batches are not included yet
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    fn queued_batches(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: ExportSink + Send + 'static> Drop for BackgroundSink<S> {
+    /// write everything still queued before the sink is closed
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Optional smoothing of the continuous feature columns. Positions are integers, so per tick
+//! velocities are quantized and jump between neighboring values.
+//!
+//! Both filters are causal, a smoothed tick only depends on itself and the ticks before it.
+
+use ndarray::ArrayViewMut1;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// feature columns that can be smoothed
+pub const SMOOTHABLE_COLUMNS: [&str; 6] = [
+    "vel_x",
+    "vel_y",
+    "target_rel_x",
+    "target_rel_y",
+    "aim_angle",
+    "aim_distance",
+];
+
+/// Filter applied to a feature column
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Smoothing {
+    /// mean of the tick and the ticks before it, window ticks in total
+    MovingAverage(usize),
+    /// exponential moving average, weight of the current tick from 0 (exclusive) to 1
+    Exponential(f64),
+}
+
+impl Smoothing {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Smoothing::MovingAverage(0) => Err("moving average window must be positive".into()),
+            Smoothing::Exponential(alpha) if !(alpha > 0.0 && alpha <= 1.0) => Err(format!(
+                "exponential smoothing alpha={} not in (0, 1]",
+                alpha
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Smooth the values in place. Angles in degrees are unwrapped before and wrapped into
+    /// (-180, 180] after, so aiming across the left side doesn't average to aiming right.
+    pub fn apply(&self, mut values: ArrayViewMut1<f32>, is_angle: bool) {
+        if is_angle {
+            unwrap_angles(values.view_mut());
+        }
+        match *self {
+            Smoothing::MovingAverage(window) => {
+                let original = values.to_vec();
+                let mut sum = 0.0;
+                for (i, value) in values.iter_mut().enumerate() {
+                    sum += original[i];
+                    if i >= window {
+                        sum -= original[i - window];
+                    }
+                    *value = sum / window.min(i + 1) as f32;
+                }
+            }
+            Smoothing::Exponential(alpha) => {
+                let alpha = alpha as f32;
+                let mut smoothed = None;
+                for value in values.iter_mut() {
+                    let next = match smoothed {
+                        None => *value,
+                        Some(previous) => alpha * *value + (1.0 - alpha) * previous,
+                    };
+                    smoothed = Some(next);
+                    *value = next;
+                }
+            }
+        }
+        if is_angle {
+            values.mapv_inplace(|angle| 180.0 - (180.0 - angle).rem_euclid(360.0));
+        }
+    }
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Smoothing::MovingAverage(window) => write!(f, "ma:{}", window),
+            Smoothing::Exponential(alpha) => write!(f, "ema:{}", alpha),
+        }
+    }
+}
+
+/// Parse `<column>=ma:<window>` or `<column>=ema:<alpha>`, e.g. `vel_x=ma:5`
+pub fn parse_column_smoothing(s: &str) -> Result<(String, Smoothing), String> {
+    let invalid = || {
+        format!(
+            "invalid smoothing '{}', expected <column>=ma:<window> or <column>=ema:<alpha>",
+            s
+        )
+    };
+    let (column, filter) = s.split_once('=').ok_or_else(invalid)?;
+    let smoothing = match filter.split_once(':') {
+        Some(("ma", window)) => Smoothing::MovingAverage(window.parse().map_err(|_| invalid())?),
+        Some(("ema", alpha)) => Smoothing::Exponential(alpha.parse().map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    };
+    smoothing.validate()?;
+    Ok((column.to_string(), smoothing))
+}
+
+/// shift angles by multiples of 360 degrees so consecutive ones differ by at most 180
+fn unwrap_angles(mut angles: ArrayViewMut1<f32>) {
+    let mut offset = 0.0;
+    let mut previous: Option<f32> = None;
+    for angle in angles.iter_mut() {
+        let raw = *angle;
+        if let Some(previous) = previous {
+            let delta = raw - previous;
+            if delta > 180.0 {
+                offset -= 360.0;
+            } else if delta < -180.0 {
+                offset += 360.0;
+            }
+        }
+        previous = Some(raw);
+        *angle = raw + offset;
+    }
+}
@@ -0,0 +1,89 @@
+//! Follow teehistorian files that a running server is still writing, like `tail -f`.
+//!
+//! Servers append chunk by chunk, so a read can end in the middle of a chunk. Instead of
+//! reporting the end of the file, the reader waits for the file to grow until the chunk is
+//! complete. See [`crate::extractor::Extractor::tail_sequences`].
+
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+use teehistorian::{ThBufRead, ThBufReader};
+
+use crate::cancel::CancellationToken;
+
+#[derive(Debug, Clone)]
+pub struct TailConfig {
+    /// time between checks for new data at the end of the file
+    pub poll_interval: Duration,
+
+    /// Stop once the file hasn't grown for this long, e.g. when the server crashed without
+    /// writing the end of stream chunk. None waits forever.
+    pub idle_timeout: Option<Duration>,
+
+    /// stop waiting for new data once cancelled
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for TailConfig {
+    fn default() -> Self {
+        TailConfig {
+            poll_interval: Duration::from_millis(100),
+            idle_timeout: None,
+            cancel: None,
+        }
+    }
+}
+
+/// Uncompressed teehistorian file that is still being written. Reaching the current end of
+/// the file blocks until it grows, the end of the file is only reported once the idle
+/// timeout passed or tailing was cancelled.
+pub(crate) struct GrowingFile {
+    reader: ThBufReader<File>,
+    config: TailConfig,
+}
+
+impl GrowingFile {
+    pub(crate) fn open(path: &Path, config: TailConfig) -> io::Result<GrowingFile> {
+        Ok(GrowingFile {
+            reader: ThBufReader::new(File::open(path)?),
+            config,
+        })
+    }
+
+    fn stopped(&self, idle_since: Instant) -> bool {
+        self.config
+            .cancel
+            .as_ref()
+            .is_some_and(|c| c.is_cancelled())
+            || self
+                .config
+                .idle_timeout
+                .is_some_and(|timeout| idle_since.elapsed() >= timeout)
+    }
+}
+
+impl ThBufRead for GrowingFile {
+    fn get_buf(&self) -> &[u8] {
+        self.reader.get_buf()
+    }
+
+    fn fill_buf(&mut self) -> io::Result<usize> {
+        let idle_since = Instant::now();
+        loop {
+            // reads past the end return 0 until the server appends more data
+            let read = self.reader.fill_buf()?;
+            if read > 0 || self.stopped(idle_since) {
+                return Ok(read);
+            }
+            thread::sleep(self.config.poll_interval);
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount)
+    }
+}
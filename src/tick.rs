@@ -1,11 +1,13 @@
-use log::{error, warn};
-use std::collections::HashMap;
+use log::error;
+use std::{collections::HashMap, ops::Range};
 use teehistorian::chunks::{InputDiff, InputNew, PlayerDiff, PlayerNew};
 
+use crate::parser::ParseError;
+
 /// A tick defines the input vectors and player positions for a timestep.
-/// With the exception of the first tick, the previous tick is copied during
-/// parsing and only the changes are applied. This means that after successful parsing,
-/// all implicit information is explicitly available for each tick.
+/// The parser keeps a single current tick and only applies the changes of each chunk.
+/// Finalized ticks are recorded in a [`TickHistory`], so all implicit information
+/// is available for each tick after parsing.
 #[derive(Clone, Debug)]
 pub struct Tick {
     /// tracks input vectors for each cid
@@ -15,6 +17,12 @@ pub struct Tick {
     pub player_positions: HashMap<i32, (i32, i32)>,
 }
 
+impl Default for Tick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Tick {
     /// initializes an empty tick struct
     pub fn new() -> Tick {
@@ -25,62 +33,161 @@ impl Tick {
     }
 
     /// Add inital player position based on PlayerNew chunk
-    pub fn add_init_position(&mut self, new_player: PlayerNew) {
-        assert!(!self.player_positions.contains_key(&new_player.cid));
+    pub fn add_init_position(&mut self, new_player: PlayerNew) -> Result<(), ParseError> {
+        if self.player_positions.contains_key(&new_player.cid) {
+            return Err(ParseError::UnexpectedParserState(format!(
+                "position for cid={} already exists",
+                new_player.cid
+            )));
+        }
         self.player_positions
             .insert(new_player.cid, (new_player.x, new_player.y));
+        Ok(())
     }
 
     /// Add initial player input based on PlayerNew chunk
-    pub fn add_init_input(&mut self, input_new: InputNew) {
-        if self.input_vectors.contains_key(&input_new.cid) {
-            warn!(
+    pub fn add_init_input(&mut self, input_new: InputNew) -> Result<(), ParseError> {
+        if let Some(input_vector) = self.input_vectors.get(&input_new.cid) {
+            return Err(ParseError::UnexpectedParserState(format!(
                 "OVERWRITE: for cid={} an input vector exists={:?}, new input vector={:?}",
-                &input_new.cid,
-                self.input_vectors.get(&input_new.cid).unwrap(),
-                input_new.input
-            );
-            panic!();
+                input_new.cid, input_vector, input_new.input
+            )));
         }
         self.input_vectors.insert(input_new.cid, input_new.input);
+        Ok(())
     }
 
     /// Update tick's input vector for some cid given a InputDiff
     pub fn apply_input_diff(&mut self, input_diff: InputDiff) {
-        if !self.input_vectors.contains_key(&input_diff.cid) {
+        let Some(input) = self.input_vectors.get_mut(&input_diff.cid) else {
             error!(
                 "expected input vector for cid={} -> {:?}",
                 &input_diff.cid, &input_diff.dinput
             );
             return;
-        }
-
-        let input = self
-            .input_vectors
-            .get_mut(&input_diff.cid)
-            .expect("no input vector for cid exists yet");
+        };
 
         // apply input diff to current input
-        for i in 0..10 {
-            input[i] = input[i].wrapping_add(input_diff.dinput[i]); // TODO: why wrapping?
+        for (value, diff) in input.iter_mut().zip(input_diff.dinput.iter()) {
+            *value = value.wrapping_add(*diff); // TODO: why wrapping?
         }
     }
 
     /// Update tick's position for some cid given a PlayerDiff
-    pub fn apply_position_diff(&mut self, player_diff: PlayerDiff) {
+    pub fn apply_position_diff(&mut self, player_diff: PlayerDiff) -> Result<(), ParseError> {
         let position = self
             .player_positions
             .get_mut(&player_diff.cid)
-            .expect("no position for cid exists yet");
+            .ok_or_else(|| {
+                ParseError::UnexpectedParserState(format!(
+                    "no position for cid={} exists yet",
+                    player_diff.cid
+                ))
+            })?;
 
-        position.0 += player_diff.dx;
-        position.1 += player_diff.dy;
+        let (Some(x), Some(y)) = (
+            position.0.checked_add(player_diff.dx),
+            position.1.checked_add(player_diff.dy),
+        ) else {
+            return Err(ParseError::OutOfRange(format!(
+                "position {:?} of cid={} moved by ({}, {})",
+                position, player_diff.cid, player_diff.dx, player_diff.dy
+            )));
+        };
+        *position = (x, y);
+        Ok(())
     }
 
     /// remove player position for PlayerOld events
-    pub fn remove_player_position(&mut self, cid: i32) {
+    pub fn remove_player_position(&mut self, cid: i32) -> Result<(), ParseError> {
         self.player_positions
             .remove(&cid)
-            .expect("no position for cid exists");
+            .map(|_| ())
+            .ok_or_else(|| {
+                ParseError::UnexpectedParserState(format!("no position for cid={} exists", cid))
+            })
+    }
+}
+
+/// State of a cid from start_tick until the start of the next run
+#[derive(Debug, PartialEq)]
+struct TickRun {
+    start_tick: i32,
+    input_vector: Option<[i32; 10]>,
+    player_position: Option<(i32, i32)>,
+}
+
+/// Input vectors and player positions of all finalized ticks.
+/// Stored per cid as runs of ticks with unchanged state, so skipped ticks and
+/// players without changes don't take additional memory.
+#[derive(Default, Debug)]
+pub struct TickHistory {
+    runs: HashMap<i32, Vec<TickRun>>,
+    /// first tick after the recorded ones
+    end_tick: i32,
+}
+
+impl TickHistory {
+    pub fn new() -> TickHistory {
+        TickHistory::default()
+    }
+
+    /// Record the state of tick, which holds for all ticks in the range.
+    /// Ranges have to be pushed in order.
+    pub fn push(&mut self, ticks: Range<i32>, tick: &Tick) {
+        debug_assert!(ticks.start >= self.end_tick, "ticks pushed out of order");
+        for cid in tick
+            .input_vectors
+            .keys()
+            .chain(tick.player_positions.keys())
+        {
+            self.runs.entry(*cid).or_default();
+        }
+
+        for (cid, runs) in self.runs.iter_mut() {
+            let run = TickRun {
+                start_tick: ticks.start,
+                input_vector: tick.input_vectors.get(cid).copied(),
+                player_position: tick.player_positions.get(cid).copied(),
+            };
+            let unchanged = runs.last().is_some_and(|last| {
+                last.input_vector == run.input_vector && last.player_position == run.player_position
+            });
+            if !unchanged {
+                runs.push(run);
+            }
+        }
+        self.end_tick = ticks.end;
+    }
+
+    /// first tick after the recorded ones, 0 if nothing was recorded yet
+    pub fn end_tick(&self) -> i32 {
+        self.end_tick
+    }
+
+    /// Input vector and player position of cid for each tick in start_tick..end_tick
+    pub fn states(
+        &self,
+        cid: i32,
+        start_tick: i32,
+        end_tick: i32,
+    ) -> impl Iterator<Item = (Option<[i32; 10]>, Option<(i32, i32)>)> + '_ {
+        let runs = self.runs.get(&cid).map_or(&[][..], |runs| runs.as_slice());
+        // last run that starts at or before start_tick
+        let mut run_index = runs
+            .partition_point(|run| run.start_tick <= start_tick)
+            .saturating_sub(1);
+        (start_tick..end_tick).map(move |tick| {
+            while runs
+                .get(run_index + 1)
+                .is_some_and(|next| next.start_tick <= tick)
+            {
+                run_index += 1;
+            }
+            match runs.get(run_index) {
+                Some(run) if run.start_tick <= tick => (run.input_vector, run.player_position),
+                _ => (None, None),
+            }
+        })
     }
 }
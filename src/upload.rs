@@ -0,0 +1,39 @@
+//! Upload a finished export to object storage, so datasets can be produced on machines whose
+//! disks are gone after the run.
+//!
+//! Any S3 compatible storage works, e.g. Google Cloud Storage with HMAC keys and
+//! `AWS_ENDPOINT_URL=https://storage.googleapis.com`, see [`crate::remote::S3Config`].
+
+use log::info;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::{save_checksums, Checksum, CHECKSUM_FILE};
+use crate::error::Result;
+use crate::export::export_files;
+use crate::remote;
+
+/// Upload the export in folder_path below an `s3://bucket/prefix` url.
+/// SHA256SUMS is written next to the dataset and uploaded last, so its presence marks a
+/// complete upload. Returns the uploaded files.
+pub fn upload_export(folder_path: &Path, url: &str) -> Result<Vec<PathBuf>> {
+    let prefix = url.trim_end_matches('/');
+    let mut uploaded = Vec::new();
+    let mut checksums = Vec::new();
+    for path in export_files(folder_path) {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let sha256 = remote::put_file(&path, &format!("{}/{}", prefix, file_name))?;
+        info!("uploaded {} ({})", file_name, sha256);
+        checksums.push(Checksum {
+            sha256,
+            file_name: file_name.into(),
+        });
+        uploaded.push(path);
+    }
+
+    // of the uploaded contents, in case files changed since the export wrote SHA256SUMS
+    save_checksums(folder_path, &checksums)?;
+    let checksum_path = folder_path.join(CHECKSUM_FILE);
+    remote::put_file(&checksum_path, &format!("{}/{}", prefix, CHECKSUM_FILE))?;
+    uploaded.push(checksum_path);
+    Ok(uploaded)
+}
@@ -0,0 +1,153 @@
+//! Per-sequence sample weights in the weight column of meta.csv, so training can re-balance
+//! players, maps and recording time without counting them again.
+//!
+//! The weight of a sequence is the product of the factors of all rules, normalized so the
+//! weights of a dataset have mean 1. Weights describe the dataset they were computed for,
+//! subsets from `filter` or combinations from `merge` need them computed again.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use chrono::DateTime;
+
+use crate::checksum;
+use crate::dataset::{counts_by, read_meta, MetaRow, META_HEADER};
+use crate::error::Result;
+
+/// factor of the sample weight of a sequence
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightRule {
+    /// 1 / sequences of the player
+    InversePlayer,
+    /// 1 / sequences on the map
+    InverseMap,
+    /// 0.5 ^ (days recorded before the newest sequence / half-life in days). Sequences
+    /// without recorded time get the factor of the oldest one.
+    Recency(f64),
+}
+
+impl WeightRule {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            WeightRule::Recency(half_life) if !(half_life > 0.0 && half_life.is_finite()) => Err(
+                format!("recency half-life of {} days is not positive", half_life),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse `player`, `map` or `recency:<half-life days>`, e.g. `recency:30`
+pub fn parse_weight_rule(s: &str) -> Result<WeightRule, String> {
+    let invalid = || {
+        format!(
+            "invalid weight rule '{}', expected player, map or recency:<half-life days>",
+            s
+        )
+    };
+    let rule = match s.split_once(':') {
+        None if s == "player" => WeightRule::InversePlayer,
+        None if s == "map" => WeightRule::InverseMap,
+        Some(("recency", half_life)) => {
+            WeightRule::Recency(half_life.parse().map_err(|_| invalid())?)
+        }
+        _ => return Err(invalid()),
+    };
+    rule.validate()?;
+    Ok(rule)
+}
+
+/// Sample weight of each meta row by the rules, with mean 1. Without rules all weights are 1.
+pub fn sample_weights(meta: &[MetaRow], rules: &[WeightRule]) -> Vec<f32> {
+    let mut weights = vec![1.0f64; meta.len()];
+    for rule in rules {
+        match *rule {
+            WeightRule::InversePlayer | WeightRule::InverseMap => {
+                let key: fn(&MetaRow) -> &str = if *rule == WeightRule::InversePlayer {
+                    |row| &row.player
+                } else {
+                    |row| &row.map
+                };
+                let counts = counts_by(meta, key);
+                for (weight, row) in weights.iter_mut().zip(meta) {
+                    let (sequences, _) = counts[key(row)];
+                    *weight /= sequences as f64;
+                }
+            }
+            WeightRule::Recency(half_life) => {
+                let recorded: Vec<Option<i64>> = meta
+                    .iter()
+                    .map(|row| {
+                        let recorded = DateTime::parse_from_rfc3339(row.recorded.as_deref()?);
+                        recorded.ok().map(|recorded| recorded.timestamp())
+                    })
+                    .collect();
+                let (Some(&oldest), Some(&newest)) = (
+                    recorded.iter().flatten().min(),
+                    recorded.iter().flatten().max(),
+                ) else {
+                    continue;
+                };
+                for (weight, recorded) in weights.iter_mut().zip(&recorded) {
+                    let age_days = (newest - recorded.unwrap_or(oldest)) as f64 / 86_400.0;
+                    *weight *= 0.5f64.powf(age_days / half_life);
+                }
+            }
+        }
+    }
+
+    let mean = weights.iter().sum::<f64>() / weights.len().max(1) as f64;
+    if mean <= 0.0 {
+        return vec![1.0; weights.len()];
+    }
+    weights
+        .iter()
+        .map(|weight| (weight / mean) as f32)
+        .collect()
+}
+
+/// Fill the weight column of meta.csv of the dataset in folder_path by the rules, see
+/// [`sample_weights`]. meta.csv is replaced at once. Returns the amount of rows.
+pub fn write_sample_weights(folder_path: &Path, rules: &[WeightRule]) -> Result<usize> {
+    let meta_path = folder_path.join("meta.csv");
+    let meta = read_meta(&meta_path)?;
+    let weights = sample_weights(&meta, rules);
+
+    let tmp_meta_path = folder_path.join("meta.csv.tmp");
+    let mut meta_file = File::create(&tmp_meta_path)?;
+    writeln!(meta_file, "{}", META_HEADER)?;
+    for (row, weight) in meta.iter().zip(weights) {
+        let weighted_row = MetaRow {
+            weight: Some(weight),
+            ..row.clone()
+        };
+        writeln!(meta_file, "{}", weighted_row.to_csv())?;
+    }
+    meta_file.sync_all()?;
+    fs::rename(tmp_meta_path, meta_path)?;
+
+    info!("wrote sample weights of {} sequences", meta.len());
+    Ok(meta.len())
+}
+
+/// Fill the weight column of an exported dataset in place, record the rules in its
+/// manifest.json and update its checksums
+pub fn weight_dataset(folder_path: &Path, rules: &[WeightRule]) -> Result<usize> {
+    let weighted = write_sample_weights(folder_path, rules)?;
+
+    let manifest_path = folder_path.join("manifest.json");
+    if manifest_path.is_file() {
+        let mut manifest: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_reader(File::open(&manifest_path)?)?;
+        manifest.insert("sample_weights".into(), serde_json::to_value(rules)?);
+        serde_json::to_writer_pretty(File::create(manifest_path)?, &manifest)?;
+    }
+    checksum::write_checksums(folder_path)?;
+    Ok(weighted)
+}
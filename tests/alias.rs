@@ -0,0 +1,57 @@
+mod support;
+
+use support::{temp_dir, ThBuilder};
+use teehistorian_extractor::alias::{file_fingerprints, rank_aliases, AliasWeights};
+
+#[test]
+fn similar_players_rank_first() {
+    let dir = temp_dir("alias");
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").join(1, "amy2").join(2, "zed");
+    th.console(0, "timeout", &["code1"]);
+    th.console(1, "timeout", &["code1"]);
+    th.console(2, "timeout", &["code2"]);
+    th.spawn(0, 0, 0).spawn(1, 0, 0).spawn(2, 0, 0);
+    for tick in 0..1000 {
+        let toggle = |period: i32| match tick % (2 * period) {
+            0 => 1,
+            t if t == period => -1,
+            _ => 0,
+        };
+        for cid in 0..3 {
+            let mut dinput = [0; 10];
+            if cid < 2 {
+                // slow aim, changes direction every 10 ticks
+                dinput[0] = toggle(10);
+                dinput[1] = 1;
+            } else {
+                // fast aim, changes direction every tick
+                dinput[0] = toggle(1);
+                dinput[1] = 40;
+            }
+            dinput[2] = if tick == 0 { 100 } else { 0 };
+            th.diff(cid, 1, 0).input(cid, dinput);
+        }
+    }
+    th.despawn(0).despawn(1).despawn(2).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+
+    let fingerprints = file_fingerprints(&path).unwrap();
+    assert_eq!(fingerprints.len(), 3);
+    assert_eq!(fingerprints["amy"].ticks, 1001);
+    assert!(fingerprints["amy"].timeout_codes.contains("code1"));
+
+    let candidates = rank_aliases(&fingerprints, &AliasWeights::default(), 0, 0.);
+    assert_eq!(candidates.len(), 3);
+    let best = &candidates[0];
+    assert_eq!((best.player.as_str(), best.alias.as_str()), ("amy", "amy2"));
+    assert_eq!(best.timeout_codes, Some(1.));
+    assert!(best.score > 0.99);
+    for other in &candidates[1..] {
+        assert_eq!(other.timeout_codes, Some(0.));
+        assert!(other.score < 0.5);
+    }
+
+    // players below min_ticks aren't compared
+    assert!(rank_aliases(&fingerprints, &AliasWeights::default(), 2000, 0.).is_empty());
+}
@@ -0,0 +1,71 @@
+mod support;
+
+use support::sequence;
+use teehistorian_extractor::anomaly::AnomalyScore;
+use teehistorian_extractor::extractor::Sequence;
+
+/// 500 ticks of standing still, aiming right
+fn idle_sequence() -> Sequence {
+    sequence(&[(0, 0); 500])
+}
+
+/// deterministic noise in 0..n
+fn noise(seed: &mut u64, n: u64) -> u64 {
+    *seed = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    (*seed >> 33) % n
+}
+
+#[test]
+fn human_inputs_are_not_flagged() {
+    let mut sequence = idle_sequence();
+    let mut seed = 1;
+    let (mut direction, mut hold) = (1, 0);
+    for i in 1..sequence.tick_count {
+        if hold == 0 {
+            direction = [-1, 0, 1][noise(&mut seed, 3) as usize];
+            hold = 5 + noise(&mut seed, 40);
+        }
+        hold -= 1;
+        sequence.move_dir[i] = direction;
+        sequence.jump[i] = noise(&mut seed, 30) == 0;
+        sequence.target_x[i] = sequence.target_x[i - 1] + noise(&mut seed, 21) as i32 - 10;
+        sequence.target_y[i] = sequence.target_y[i - 1] + noise(&mut seed, 21) as i32 - 10;
+    }
+
+    let anomaly = AnomalyScore::new(&sequence);
+    assert!(anomaly.score() < 0.5, "{:?}", anomaly);
+    assert_eq!(anomaly.flags(), "");
+    assert_eq!(AnomalyScore::new(&idle_sequence()), AnomalyScore::default());
+}
+
+#[test]
+fn interpolated_aim_is_flagged() {
+    let mut sequence = idle_sequence();
+    for i in 0..sequence.tick_count {
+        // aim moves in straight steps towards a new target every 50 ticks
+        let step = (i % 50) as i32;
+        sequence.target_x[i] = 100 + 8 * step;
+        sequence.target_y[i] = -3 * step;
+    }
+
+    let anomaly = AnomalyScore::new(&sequence);
+    assert!(anomaly.linear_aim > 0.9, "{:?}", anomaly);
+    assert_eq!(anomaly.reaction_time, 0.);
+    assert_eq!(anomaly.flags(), "linear_aim");
+}
+
+#[test]
+fn scripted_inputs_are_flagged() {
+    let mut sequence = idle_sequence();
+    for i in 0..sequence.tick_count {
+        sequence.move_dir[i] = if i % 2 == 0 { 1 } else { -1 };
+    }
+
+    let anomaly = AnomalyScore::new(&sequence);
+    assert_eq!(anomaly.reaction_time, 1.);
+    assert_eq!(anomaly.periodic_inputs, 1.);
+    assert_eq!(anomaly.score(), 1.);
+    assert_eq!(anomaly.flags(), "reaction_time|periodic_inputs");
+}
@@ -0,0 +1,58 @@
+mod support;
+
+use std::fs;
+use support::temp_dir;
+use teehistorian_extractor::checksum::{
+    parse_checksums, verify, write_checksums, Checksum, CHECKSUM_FILE,
+};
+
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+#[test]
+fn parses_text_and_binary_mode_lines() {
+    let content = format!(
+        "{}  meta.csv\n\n{} *sequences.h5\n",
+        EMPTY_SHA256, EMPTY_SHA256
+    );
+    let checksums = parse_checksums(&content).unwrap();
+    assert_eq!(
+        checksums,
+        vec![
+            Checksum {
+                sha256: EMPTY_SHA256.into(),
+                file_name: "meta.csv".into(),
+            },
+            Checksum {
+                sha256: EMPTY_SHA256.into(),
+                file_name: "sequences.h5".into(),
+            },
+        ]
+    );
+    assert_eq!(
+        checksums[0].to_line(),
+        format!("{}  meta.csv", EMPTY_SHA256)
+    );
+    assert!(parse_checksums("abc  meta.csv").is_err());
+    assert!(parse_checksums(EMPTY_SHA256).is_err());
+}
+
+#[test]
+fn verify_finds_modified_missing_and_unlisted_files() {
+    let dir = temp_dir("checksum_verify");
+    fs::write(dir.join("meta.csv"), "seq_id\n0\n").unwrap();
+    fs::write(dir.join("manifest.json"), "{}").unwrap();
+    fs::write(dir.join("unrelated.txt"), "not part of the dataset").unwrap();
+    let checksums = write_checksums(&dir).unwrap();
+    assert_eq!(checksums.len(), 2);
+    assert!(dir.join(CHECKSUM_FILE).is_file());
+    assert!(verify(&dir).unwrap().is_empty());
+
+    fs::write(dir.join("meta.csv"), "seq_id\n1\n").unwrap();
+    fs::remove_file(dir.join("manifest.json")).unwrap();
+    fs::write(dir.join("summary.json"), "{}").unwrap();
+    let issues = verify(&dir).unwrap();
+    assert_eq!(issues.len(), 3, "{:?}", issues);
+    assert!(issues[0].starts_with("meta.csv has sha256"));
+    assert_eq!(issues[1], "manifest.json is missing");
+    assert_eq!(issues[2], "summary.json is not listed in SHA256SUMS");
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use teehistorian_extractor::compare::{
+    ks_statistic, schema_differences, CompositionDrift, FeatureDrift, HISTOGRAM_BINS,
+};
+
+#[test]
+fn ks_statistic_of_samples() {
+    let a: Vec<f32> = (0..100).map(|i| i as f32).collect();
+    assert_eq!(ks_statistic(&a, &a), 0.);
+    let shifted: Vec<f32> = (50..150).map(|i| i as f32).collect();
+    assert!((ks_statistic(&a, &shifted) - 0.5).abs() < 1e-9);
+    let disjoint: Vec<f32> = (200..210).map(|i| i as f32).collect();
+    assert_eq!(ks_statistic(&a, &disjoint), 1.);
+    // ties move both distribution functions at once
+    assert_eq!(ks_statistic(&[0., 0., 1.], &[0., 1., 1.]), 1. / 3.);
+    assert_eq!(ks_statistic(&[], &[]), 0.);
+    assert_eq!(ks_statistic(&a, &[]), 1.);
+}
+
+#[test]
+fn feature_histograms_share_bins() {
+    let drift = FeatureDrift::new(
+        "vel_x",
+        vec![0., 0., 10., f32::NAN],
+        vec![10., 10., 20., 20.],
+    );
+    assert_eq!((drift.min, drift.max), (0., 20.));
+    assert_eq!(drift.mean.old, 10. / 3.);
+    assert_eq!(drift.mean.new, 15.);
+    assert_eq!(drift.histogram.old.len(), HISTOGRAM_BINS);
+    assert!((drift.histogram.old[0] - 2. / 3.).abs() < 1e-9);
+    assert!((drift.histogram.old[HISTOGRAM_BINS / 2] - 1. / 3.).abs() < 1e-9);
+    assert_eq!(drift.histogram.new[HISTOGRAM_BINS / 2], 0.5);
+    assert_eq!(drift.histogram.new[HISTOGRAM_BINS - 1], 0.5);
+    assert!((drift.ks_statistic - 2. / 3.).abs() < 1e-9);
+}
+
+#[test]
+fn composition_changes_by_tick_share() {
+    let old: HashMap<&str, (usize, usize)> = [("a", (1, 300)), ("b", (1, 100))].into();
+    let new: HashMap<&str, (usize, usize)> = [("a", (2, 100)), ("c", (1, 100))].into();
+    let drift = CompositionDrift::new(&old, &new);
+
+    let changes: Vec<(&str, f64, f64)> = drift
+        .changes
+        .iter()
+        .map(|c| (c.name.as_str(), c.share.old, c.share.new))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![("c", 0., 0.5), ("a", 0.75, 0.5), ("b", 0.25, 0.)]
+    );
+    assert_eq!(drift.distance, 0.5);
+    assert_eq!(CompositionDrift::new(&old, &old).distance, 0.);
+}
+
+#[test]
+fn schema_changes_are_listed() {
+    let columns = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    let old = columns(&["vel_x", "vel_y", "jump"]);
+    assert!(schema_differences((&old, 100), (&old, 100)).is_empty());
+
+    let new = columns(&["vel_y", "vel_x", "fire"]);
+    let differences = schema_differences((&old, 100), (&new, 200));
+    assert_eq!(
+        differences,
+        vec![
+            "seq_length changed from 100 to 200",
+            "removed columns [\"jump\"]",
+            "added columns [\"fire\"]",
+            "column order changed from [\"vel_x\", \"vel_y\"] to [\"vel_y\", \"vel_x\"]",
+        ]
+    );
+}
@@ -0,0 +1,89 @@
+mod support;
+
+use std::path::{Path, PathBuf};
+use support::{temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    dedup::DedupMode,
+    export::{ExportConfig, Exporter, RunSummary},
+    parser::ParserConfig,
+};
+
+/// amy walks, bob copies her inputs and movement while aiming differently if aim_offset
+/// isn't 0
+fn amy_and_dummy(path: &Path, aim_offset: i32) -> PathBuf {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy")
+        .join(1, "bob")
+        .spawn(0, 0, 0)
+        .spawn(1, 64, 0);
+    // inputs and speed change irregularly, so no two sequences of a player are equal
+    let mut move_dir: i32 = 0;
+    for tick in 0..100 {
+        let options = match move_dir {
+            -1 => [0, 1],
+            0 => [-1, 1],
+            _ => [-1, 0],
+        };
+        let next_move_dir = options[(tick * tick + tick / 7) as usize % 2];
+        let mut dinput = [0; 10];
+        dinput[0] = next_move_dir - move_dir;
+        move_dir = next_move_dir;
+        let dx = (tick * 7) % 5;
+        th.diff(0, dx, 0).input(0, dinput);
+        dinput[1] = if tick == 0 { aim_offset } else { 0 };
+        th.diff(1, dx, 0).input(1, dinput);
+    }
+    th.despawn(0).despawn(1).eos();
+    th.write(path)
+}
+
+fn export(out: &Path, paths: &[PathBuf], dedup: Option<DedupMode>) -> (usize, RunSummary) {
+    let config = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .dedup(dedup)
+        .build()
+        .unwrap();
+    let sink = MemorySink::default();
+    let mut exporter =
+        Exporter::with_sink(&out.to_path_buf(), config.clone(), sink.clone()).unwrap();
+    exporter
+        .handle_batch(paths, &ParserConfig::default(), &config)
+        .unwrap();
+    let count = sink.stored.borrow().len();
+    (count, exporter.summary)
+}
+
+#[test]
+fn copies_of_files_and_dummies_are_dropped() {
+    let dir = temp_dir("dedup_exact");
+    let first = amy_and_dummy(&dir.join("a.teehistorian"), 0);
+    let copy = dir.join("copy.teehistorian");
+    std::fs::copy(&first, &copy).unwrap();
+    let paths = [first, copy];
+
+    let (all, _) = export(&dir.join("all"), &paths, None);
+    let (unique, summary) = export(&dir.join("exact"), &paths, Some(DedupMode::Exact));
+    // a quarter of the sequences are amy's in the first file
+    assert_eq!(unique * 4, all);
+    // bob copies amy in both files
+    assert_eq!(summary.duplicates_other_player, 2 * unique);
+    assert_eq!(summary.duplicates_same_player, unique);
+    assert_eq!(
+        summary.sequences_dropped.get("duplicate"),
+        Some(&(3 * unique))
+    );
+}
+
+#[test]
+fn near_duplicates_ignore_the_aim() {
+    let dir = temp_dir("dedup_near");
+    let paths = [amy_and_dummy(&dir.join("a.teehistorian"), 50)];
+
+    let (all, _) = export(&dir.join("all"), &paths, None);
+    let (exact, _) = export(&dir.join("exact"), &paths, Some(DedupMode::Exact));
+    assert_eq!(exact, all);
+    let (near, summary) = export(&dir.join("near"), &paths, Some(DedupMode::Near));
+    assert_eq!(near * 2, all);
+    assert_eq!(summary.duplicates_other_player, near);
+}
@@ -0,0 +1,150 @@
+mod support;
+
+use std::{fs::File, path::Path, thread};
+use support::{temp_dir, ThBuilder};
+use teehistorian_extractor::demo::{self, DemoFilter};
+use teehistorian_extractor::parser::{GameInfo, ParserConfig};
+use twsnap::compat::ddnet::{DemoChunk, DemoMapHash, DemoReader};
+use twsnap::Snap;
+
+/// two players spawn apart and walk right for 100 ticks
+fn two_players() -> ThBuilder {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 320, 64);
+    for tick in 0..100 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        dinput[1] = 100;
+        th.diff(0, 1, 0).input(0, dinput);
+        th.diff(1, 2, 0).input(1, dinput);
+    }
+    th
+}
+
+/// tick and (name, x, y) of the players with a tee
+type Snapshot = (i32, Vec<(String, i32, i32)>);
+
+/// every snapshot of a demo. Like writing, reading needs more stack than test threads have
+/// in debug builds.
+fn read_demo(path: &Path) -> Vec<Snapshot> {
+    let path = path.to_path_buf();
+    thread::Builder::new()
+        .stack_size(16 * 1024 * 1024)
+        .spawn(move || read_snapshots(&path))
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+fn read_snapshots(path: &Path) -> Vec<Snapshot> {
+    let mut reader = DemoReader::new(File::open(path).unwrap()).unwrap();
+    let mut snap = Snap::default();
+    let mut snapshots = Vec::new();
+    while let Some(chunk) = reader.next_chunk(&mut snap).unwrap() {
+        if let DemoChunk::Snapshot(tick) = chunk {
+            let players = snap
+                .players
+                .values()
+                .filter_map(|player| {
+                    let tee = player.tee.as_ref()?;
+                    Some((
+                        player.name.to_string(),
+                        tee.pos.x.to_bits(),
+                        tee.pos.y.to_bits(),
+                    ))
+                })
+                .collect();
+            snapshots.push((tick, players));
+        }
+    }
+    snapshots
+}
+
+#[test]
+fn demo_replays_all_sequences_of_a_file() {
+    let dir = temp_dir("demo_all");
+    let mut th = two_players();
+    th.eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let output = dir.join("a.demo");
+
+    let count = demo::convert_file(
+        &path,
+        &DemoFilter::default(),
+        &ParserConfig::default(),
+        None,
+        &output,
+    )
+    .unwrap();
+    assert_eq!(count, 2);
+
+    let snapshots = read_demo(&output);
+    assert!(!snapshots.is_empty());
+    assert!(snapshots.windows(2).all(|w| w[1].0 == w[0].0 + 1));
+    let (_, players) = snapshots.last().unwrap();
+    let mut players = players.clone();
+    players.sort();
+    let last_x = |name: &str| players.iter().find(|p| p.0 == name).unwrap().1;
+    assert_eq!(players.len(), 2);
+    assert!(last_x("bob") - 320 > last_x("amy"));
+    assert_eq!(players[1].2, 64);
+}
+
+#[test]
+fn demo_filter_selects_a_single_player() {
+    let dir = temp_dir("demo_filter");
+    let mut th = two_players();
+    th.eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let output = dir.join("a.demo");
+    let filter = DemoFilter {
+        player: Some("bob".to_string()),
+        start_tick: None,
+    };
+
+    let count =
+        demo::convert_file(&path, &filter, &ParserConfig::default(), None, &output).unwrap();
+    assert_eq!(count, 1);
+    // the reader only fills in players it has seen in a previous snapshot, so the first
+    // snapshot is skipped
+    let snapshots = &read_demo(&output)[1..];
+    assert!(snapshots
+        .iter()
+        .all(|(_, players)| players.len() == 1 && players[0].0 == "bob"));
+    // positions are the recorded ones, 2 units per tick
+    let (_, first) = &snapshots[0];
+    let (_, second) = &snapshots[1];
+    assert_eq!(first[0].1, 322);
+    assert_eq!(second[0].1 - first[0].1, 2);
+
+    let filter = DemoFilter {
+        player: Some("nobody".to_string()),
+        start_tick: None,
+    };
+    let err =
+        demo::convert_file(&path, &filter, &ParserConfig::default(), None, &output).unwrap_err();
+    assert_eq!(err.kind(), "demo");
+}
+
+#[test]
+fn map_hash_prefers_map_data_over_header() {
+    let header = br#"{"server_name":"s","map_name":"m","map_crc":"0000abcd","map_sha256":"00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff"}"#;
+    let game_info = GameInfo::from_header_bytes(header).unwrap();
+
+    match demo::map_hash(&game_info, None) {
+        DemoMapHash::Sha256(sha256) => assert_eq!(sha256[..3], [0x00, 0x11, 0x22]),
+        DemoMapHash::Crc(_) => panic!("expected the header sha256"),
+    }
+    match demo::map_hash(&game_info, Some(b"map")) {
+        DemoMapHash::Sha256(sha256) => assert_ne!(sha256[..3], [0x00, 0x11, 0x22]),
+        DemoMapHash::Crc(_) => panic!("expected the sha256 of the map data"),
+    }
+
+    let old_header = br#"{"server_name":"s","map_name":"m","map_crc":"0000abcd"}"#;
+    let game_info = GameInfo::from_header_bytes(old_header).unwrap();
+    assert!(matches!(
+        demo::map_hash(&game_info, None),
+        DemoMapHash::Crc(0xabcd)
+    ));
+}
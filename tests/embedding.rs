@@ -0,0 +1,59 @@
+mod support;
+
+use teehistorian_extractor::embedding::{embed, embedding_names, EMBEDDING_SIZE};
+use teehistorian_extractor::extractor::Sequence;
+
+/// player running right and aiming right, pressing jump every 10 ticks
+fn sequence(ticks: usize) -> Sequence {
+    let positions: Vec<_> = (0..ticks as i32).map(|tick| (tick * 10, 0)).collect();
+    let mut sequence = support::sequence(&positions);
+    sequence.move_dir.fill(1);
+    sequence.jump = (0..ticks).map(|tick| tick % 10 == 5).collect();
+    sequence
+}
+
+fn value(embedding: &[f32], name: &str) -> f32 {
+    let index = embedding_names()
+        .iter()
+        .position(|n| n == name)
+        .unwrap_or_else(|| panic!("no embedding value {}", name));
+    embedding[index]
+}
+
+#[test]
+fn names_cover_the_embedding() {
+    let names = embedding_names();
+    assert_eq!(names.len(), EMBEDDING_SIZE);
+    assert_eq!(names[0], "move_dir_0");
+    assert_eq!(names.last().map(String::as_str), Some("speed_5"));
+}
+
+#[test]
+fn embeddings_describe_the_inputs() {
+    let embedding = embed(&sequence(100), 100);
+    assert!(embedding.iter().all(|value| value.is_finite()));
+    assert_eq!(value(&embedding, "move_dir_2"), 1.);
+    assert_eq!(value(&embedding, "held_0"), 0.1);
+    // 10 jump presses in 2 seconds
+    assert_eq!(value(&embedding, "presses_0"), 5.);
+    assert_eq!(value(&embedding, "presses_1"), 0.);
+    assert_eq!(value(&embedding, "turns_0"), 0.);
+    // aim never moves, pointing right at a distance of 100
+    assert!((0..9).all(|bin| value(&embedding, &format!("aim_speed_{}", bin)) == 0.));
+    assert_eq!(value(&embedding, "aim_distance_0"), 1.);
+    assert_eq!(value(&embedding, "aim_sector_0"), 1.);
+    // jump is released 1 tick after each press and pressed again 9 ticks later
+    assert_eq!(value(&embedding, "input_interval_0"), 10. / 19.);
+    assert_eq!(value(&embedding, "input_interval_5"), 9. / 19.);
+    assert_eq!(value(&embedding, "speed_3"), 1.);
+}
+
+#[test]
+fn only_the_first_ticks_are_embedded() {
+    let mut long = sequence(200);
+    for tick in 100..200 {
+        long.move_dir[tick] = -1;
+    }
+    assert_eq!(embed(&long, 100), embed(&sequence(100), 100));
+    assert_eq!(embed(&sequence(50), 100), embed(&sequence(50), 50));
+}
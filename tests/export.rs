@@ -0,0 +1,837 @@
+mod support;
+
+use std::path::{Path, PathBuf};
+use support::{map_bytes, meta_row, temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    dataset::{read_meta, MetaRow, META_HEADER},
+    dedup::DedupMode,
+    export::{ExportConfig, Exporter, Progress, PROGRESS_FILE},
+    labels::LabelIndex,
+    map_info::MapCatalog,
+    parser::ParserConfig,
+    preprocess::ActivityInput,
+    records::RecordIndex,
+    registry::{self, PlayerRegistry, REGISTRY_FILE},
+    report,
+};
+
+/// players spawn together and walk right for the given ticks, changing their move direction
+/// every tick so nothing counts as afk
+fn walking_players(players: &[(i32, &str)], ticks: i32) -> ThBuilder {
+    let mut th = ThBuilder::new();
+    for &(cid, name) in players {
+        th.join(cid, name);
+    }
+    for &(cid, _) in players {
+        th.spawn(cid, 0, 0);
+    }
+    for tick in 0..ticks {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        for &(cid, _) in players {
+            th.diff(cid, 1, 0).input(cid, dinput);
+        }
+    }
+    th
+}
+
+/// short sequences keep the fixtures small
+fn short_config() -> ExportConfig {
+    ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .build()
+        .unwrap()
+}
+
+fn export(
+    out: &Path,
+    paths: &[PathBuf],
+    config: ExportConfig,
+) -> (MemorySink, Exporter<MemorySink>) {
+    let sink = MemorySink::default();
+    let mut exporter =
+        Exporter::with_sink(&out.to_path_buf(), config.clone(), sink.clone()).unwrap();
+    exporter
+        .handle_batch(paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(paths).unwrap();
+    (sink, exporter)
+}
+
+#[test]
+fn exports_sequences_ordered_by_cid() {
+    let dir = temp_dir("export_order");
+    let mut th = walking_players(&[(0, "zed"), (1, "amy")], 100);
+    th.despawn(1).despawn(0).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let config = short_config();
+    let (sink, exporter) = export(&dir.join("out"), &[path], config);
+
+    // 101 ticks hold 5 sequences of 20 ticks plus the tick for the last velocity
+    let stored = sink.stored.borrow();
+    assert_eq!(stored.len(), 10);
+    assert_eq!(exporter.summary.sequences_kept, 10);
+    for (seq_id, stored) in stored.iter().enumerate() {
+        assert_eq!(stored.meta.seq_id, seq_id);
+        assert_eq!(stored.meta.ticks, 20);
+        assert_eq!(&*stored.meta.map, "Synthetic");
+        assert_eq!(&*stored.meta.teehist, "a");
+    }
+    let players: Vec<(&str, usize)> = stored
+        .iter()
+        .map(|s| (&*s.meta.player, s.meta.start))
+        .collect();
+    assert_eq!(
+        players,
+        vec![
+            ("zed", 0),
+            ("zed", 20),
+            ("zed", 40),
+            ("zed", 60),
+            ("zed", 80),
+            ("amy", 0),
+            ("amy", 20),
+            ("amy", 40),
+            ("amy", 60),
+            ("amy", 80),
+        ]
+    );
+}
+
+#[test]
+fn sequences_record_their_start_time() {
+    let dir = temp_dir("export_recorded");
+    let mut th = walking_players(&[(0, "amy")], 100);
+    th.despawn(0).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let (sink, _) = export(&dir.join("out"), &[path], short_config());
+
+    // the header starts at 18:23:05 +0200, 50 ticks per second
+    let stored = sink.stored.borrow();
+    let recorded: Vec<(usize, Option<&str>)> = stored[..4]
+        .iter()
+        .map(|s| (s.meta.start, s.meta.recorded.as_deref()))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            (0, Some("2024-10-01T16:23:05Z")),
+            (20, Some("2024-10-01T16:23:05Z")),
+            (40, Some("2024-10-01T16:23:05Z")),
+            (60, Some("2024-10-01T16:23:06Z")),
+        ]
+    );
+}
+
+#[test]
+fn player_ids_span_files() {
+    let dir = temp_dir("export_player_ids");
+    let mut paths = Vec::new();
+    for (file, name) in ["amy", "zed", "amy"].iter().enumerate() {
+        let mut th = walking_players(&[(0, name)], 50);
+        th.despawn(0).eos();
+        paths.push(th.write(&dir.join(format!("{}.teehistorian", file))));
+    }
+    let config = short_config();
+    let (sink, _) = export(&dir.join("out"), &paths, config);
+
+    let player_ids: Vec<usize> = sink
+        .stored
+        .borrow()
+        .iter()
+        .map(|s| s.meta.player_id)
+        .collect();
+    assert_eq!(player_ids, vec![0, 0, 1, 1, 0, 0]);
+}
+
+#[test]
+fn each_batch_writes_a_progress_marker() {
+    let dir = temp_dir("export_progress");
+    let mut paths = Vec::new();
+    for (file, name) in ["amy", "zed"].iter().enumerate() {
+        let mut th = walking_players(&[(0, name)], 50);
+        th.despawn(0).eos();
+        paths.push(th.write(&dir.join(format!("{}.teehistorian", file))));
+    }
+    let config = short_config();
+    let out = dir.join("out");
+    let mut exporter = Exporter::with_sink(&out, config.clone(), MemorySink::default()).unwrap();
+    let read_progress = || -> Progress {
+        serde_json::from_slice(&std::fs::read(out.join(PROGRESS_FILE)).unwrap()).unwrap()
+    };
+    for (batch, path) in paths.iter().enumerate() {
+        exporter
+            .handle_batch(&[path.clone()], &ParserConfig::default(), &config)
+            .unwrap();
+        let progress = read_progress();
+        assert_eq!(progress.batches, batch + 1);
+        assert_eq!(progress.processed_files, batch + 1);
+        assert_eq!(progress.sequence_count, exporter.sequence_count);
+    }
+    assert_eq!(read_progress().sequence_count, 4);
+    assert!(!out.join("progress.json.tmp").exists());
+}
+
+#[test]
+fn short_sequences_are_dropped() {
+    let dir = temp_dir("export_short");
+    let mut th = walking_players(&[(0, "amy")], 100);
+    // zed only plays 10 ticks, too short for a single sequence
+    th.join(1, "zed").spawn(1, 0, 0).walk(1, 10, 1, 0);
+    th.despawn(0).despawn(1).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let config = short_config();
+    let (sink, exporter) = export(&dir.join("out"), &[path], config);
+
+    assert!(sink
+        .stored
+        .borrow()
+        .iter()
+        .all(|s| &*s.meta.player == "amy"));
+    assert_eq!(
+        exporter.summary.sequences_dropped.get("too_short"),
+        Some(&1)
+    );
+}
+
+#[test]
+fn aiming_counts_as_activity_if_configured() {
+    let dir = temp_dir("export_afk_inputs");
+    // a turret player standing still while aiming around
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..100 {
+        let mut dinput = [0; 10];
+        dinput[1] = 100 + tick % 7;
+        dinput[2] = -50;
+        th.diff(0, 0, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let (sink, _) = export(&dir.join("move"), &paths, short_config());
+    assert!(sink.stored.borrow().is_empty());
+
+    let config = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .afk_inputs(vec![ActivityInput::Move, ActivityInput::Target])
+        .build()
+        .unwrap();
+    let (sink, _) = export(&dir.join("target"), &paths, config);
+    assert!(sink.stored.borrow().len() >= 4);
+}
+
+/// two short runs of amy with a pause of 500 ticks between them
+fn paused_runs(dir: &Path) -> PathBuf {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..620 {
+        let mut dinput = [0; 10];
+        if !(60..560).contains(&tick) {
+            dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        }
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    th.write(&dir.join("a.teehistorian"))
+}
+
+#[test]
+fn nearby_durations_are_merged() {
+    let dir = temp_dir("export_merge_gap");
+    let paths = [paused_runs(&dir)];
+
+    let config = |merge_gap| {
+        ExportConfig::builder()
+            .seq_length(100)
+            .afk_ticks(450)
+            .afk_padding(2)
+            .merge_gap(merge_gap)
+            .build()
+            .unwrap()
+    };
+    let (sink, _) = export(&dir.join("apart"), &paths, config(0));
+    assert!(sink.stored.borrow().is_empty());
+    let (sink, _) = export(&dir.join("merged"), &paths, config(600));
+    assert_eq!(sink.stored.borrow().len(), 6);
+}
+
+#[test]
+fn sessions_end_after_long_absences_and_reconnects() {
+    let dir = temp_dir("export_sessions");
+    let mut reconnect = walking_players(&[(0, "amy")], 60);
+    reconnect.drop(0, "").join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..60 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        reconnect.diff(0, 1, 0).input(0, dinput);
+    }
+    reconnect.despawn(0).eos();
+    let paths = [
+        paused_runs(&dir),
+        reconnect.write(&dir.join("b.teehistorian")),
+    ];
+
+    let config = |session_gap| {
+        ExportConfig::builder()
+            .seq_length(20)
+            .afk_ticks(100)
+            .afk_padding(2)
+            .session_gap(session_gap)
+            .build()
+            .unwrap()
+    };
+    let sessions = |out: &str, session_gap| -> Vec<(String, String, usize)> {
+        let (sink, _) = export(&dir.join(out), &paths, config(session_gap));
+        let stored = sink.stored.borrow();
+        stored
+            .iter()
+            .map(|s| {
+                (
+                    s.meta.teehist.to_string(),
+                    s.meta.session_id.clone().unwrap(),
+                    s.meta.session_seq.unwrap(),
+                )
+            })
+            .collect()
+    };
+    let session_count = |sessions: &[(String, String, usize)], teehist: &str| {
+        sessions
+            .iter()
+            .filter(|(file, _, seq)| file == teehist && *seq == 0)
+            .count()
+    };
+
+    // the afk pause of paused_runs is shorter than the default gap
+    let joined = sessions("joined", ExportConfig::default().session_gap);
+    assert_eq!(session_count(&joined, "a"), 1);
+    assert_eq!(session_count(&joined, "b"), 2);
+    let (_, first_id, _) = &joined[0];
+    assert!(first_id.starts_with("a:0:"));
+    let a_sequences: Vec<_> = joined.iter().filter(|(file, ..)| file == "a").collect();
+    for (seq, (_, id, session_seq)) in a_sequences.iter().enumerate() {
+        assert_eq!(id, first_id);
+        assert_eq!(*session_seq, seq);
+    }
+
+    let split = sessions("split", 100);
+    assert_eq!(session_count(&split, "a"), 2);
+    assert_eq!(session_count(&split, "b"), 2);
+}
+
+#[test]
+fn afk_ticks_are_masked_instead_of_cut() {
+    let dir = temp_dir("export_mask_inactive");
+    let paths = [paused_runs(&dir)];
+    let config = ExportConfig::builder()
+        .seq_length(100)
+        .afk_ticks(450)
+        .afk_padding(2)
+        .mask_inactive(true)
+        .build()
+        .unwrap();
+    assert_eq!(config.column_names().last().unwrap(), "active");
+
+    let (sink, exporter) = export(&dir.join("out"), &paths, config);
+    // the first and last window hold gameplay, the windows of the pause are dropped
+    let starts: Vec<_> = sink.stored.borrow().iter().map(|s| s.meta.start).collect();
+    assert_eq!(starts.len(), 2);
+    assert_eq!(starts[1] - starts[0], 500);
+    assert_eq!(exporter.summary.sequences_dropped.get("inactive"), Some(&4));
+}
+
+/// amy mashes the move keys for 100 ticks, then for 300 ticks in deep freeze and 100 more
+/// ticks after leaving it
+fn freeze_jail(dir: &Path) -> PathBuf {
+    // air, deep freeze and undeep tiles
+    std::fs::write(dir.join("Synthetic.map"), map_bytes(3, 1, &[0, 12, 13])).unwrap();
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 16, 16);
+    for (tile, ticks) in [100, 300, 100].into_iter().enumerate() {
+        for tick in 0..ticks {
+            let mut dinput = [0; 10];
+            dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+            // step onto the next tile
+            let dx = if tick == 0 && tile > 0 { 32 } else { 0 };
+            th.diff(0, dx, 0).input(0, dinput);
+        }
+    }
+    th.despawn(0).eos();
+    th.write(&dir.join("a.teehistorian"))
+}
+
+fn export_with_maps(dir: &Path, out: &str, paths: &[PathBuf], config: ExportConfig) -> MemorySink {
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join(out), config.clone(), sink.clone()).unwrap();
+    exporter.maps = MapCatalog::new(Some(dir));
+    exporter
+        .handle_batch(paths, &ParserConfig::default(), &config)
+        .unwrap();
+    sink
+}
+
+#[test]
+fn long_freeze_is_cut_out() {
+    let dir = temp_dir("export_freeze");
+    let paths = [freeze_jail(&dir)];
+
+    let masked = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .freeze_mask(true)
+        .build()
+        .unwrap();
+    assert_eq!(masked.column_names().last().unwrap(), "frozen");
+    let sink = export_with_maps(&dir, "masked", &paths, masked);
+    let stored = sink.stored.borrow();
+    let frozen_sequences = stored.iter().filter(|s| s.frozen_ticks > 0).count();
+    assert!(frozen_sequences >= 14, "{}", frozen_sequences);
+    let all_count = stored.len();
+    drop(stored);
+
+    let cut = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .max_freeze_ticks(200)
+        .freeze_mask(true)
+        .build()
+        .unwrap();
+    let sink = export_with_maps(&dir, "cut", &paths, cut);
+    let stored = sink.stored.borrow();
+    assert!(stored.len() <= all_count - 14);
+    assert!(stored.len() >= 8);
+    // only the padding reaches into the freeze
+    assert!(stored.iter().all(|s| s.frozen_ticks <= 2));
+}
+
+#[test]
+fn short_freeze_is_kept() {
+    let dir = temp_dir("export_short_freeze");
+    let paths = [freeze_jail(&dir)];
+    let config = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .max_freeze_ticks(400)
+        .freeze_mask(true)
+        .build()
+        .unwrap();
+    let sink = export_with_maps(&dir, "out", &paths, config);
+    assert!(sink.stored.borrow().iter().any(|s| s.frozen_ticks == 21));
+}
+
+#[test]
+fn sequences_before_parse_error_are_kept() {
+    let dir = temp_dir("export_parse_error");
+    let mut th = walking_players(&[(0, "amy")], 50);
+    th.despawn(0);
+    th.spawn(0, 0, 0).swap(0, 1).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let config = short_config();
+    let (sink, exporter) = export(&dir.join("out"), &[path], config);
+
+    assert_eq!(sink.stored.borrow().len(), 2);
+    assert_eq!(
+        exporter.summary.parse_errors.get("unhandled_chunk"),
+        Some(&1)
+    );
+}
+
+#[test]
+fn truncated_files_are_reported() {
+    let dir = temp_dir("export_truncated");
+    let mut th = walking_players(&[(0, "amy")], 50);
+    th.despawn(0).spawn(0, 0, 0);
+    for _ in 0..30 {
+        th.diff(0, 1, 0);
+    }
+    th.eos();
+    // the recording was cut in the middle of a chunk
+    let bytes = th.finish();
+    let path = dir.join("a.teehistorian");
+    std::fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    let config = short_config();
+    let (sink, exporter) = export(&dir.join("out"), &[path], config);
+
+    assert_eq!(sink.stored.borrow().len(), 2);
+    assert_eq!(exporter.summary.parse_errors.get("teehistorian"), Some(&1));
+}
+
+#[test]
+fn tails_overlap_the_last_sequence() {
+    let dir = temp_dir("export_tails");
+    let mut th = walking_players(&[(0, "amy")], 110);
+    th.despawn(0).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+
+    let starts = |keep_tails: bool, out: &str| -> Vec<usize> {
+        let config = ExportConfig::builder()
+            .seq_length(20)
+            .afk_padding(2)
+            .keep_tails(keep_tails)
+            .build()
+            .unwrap();
+        let (sink, _) = export(&dir.join(out), std::slice::from_ref(&path), config);
+        let starts = sink.stored.borrow().iter().map(|s| s.meta.start).collect();
+        starts
+    };
+    // 111 ticks, the last one is only used for the velocity
+    assert_eq!(starts(false, "out"), vec![0, 20, 40, 60, 80]);
+    assert_eq!(starts(true, "out_tails"), vec![0, 20, 40, 60, 80, 90]);
+}
+
+#[test]
+fn files_cut_by_the_size_quota_stay_pending() {
+    let dir = temp_dir("export_size_quota");
+    let mut paths = Vec::new();
+    for file in 0..3 {
+        let mut th = walking_players(&[(0, "amy")], 50);
+        th.despawn(0).eos();
+        paths.push(th.write(&dir.join(format!("{}.teehistorian", file))));
+    }
+    let config = short_config();
+    let sequence_bytes =
+        config.seq_length * config.column_names().len() * std::mem::size_of::<f32>();
+    // room for the two sequences of the first file, but not for those of the second
+    let config = ExportConfig {
+        max_dataset_bytes: Some(3 * sequence_bytes as u64),
+        ..config
+    };
+    let (sink, exporter) = export(&dir.join("out"), &paths, config);
+
+    assert_eq!(sink.stored.borrow().len(), 2);
+    assert_eq!(exporter.processed_files, paths[..1]);
+    assert_eq!(
+        exporter.summary.sequences_dropped.get("size_quota"),
+        Some(&2)
+    );
+}
+
+#[test]
+fn files_cut_by_the_size_quota_are_no_duplicates_when_resumed() {
+    let dir = temp_dir("export_size_quota_dedup");
+    let mut paths = Vec::new();
+    for file in 0..2 {
+        // speeds differ between the files and grow within them, so no two sequences are equal
+        let mut th = ThBuilder::new();
+        th.join(0, "amy").spawn(0, 0, 0);
+        for tick in 0..50 {
+            let mut dinput = [0; 10];
+            dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+            th.diff(0, tick / 10 + 1 + 10 * file, 0).input(0, dinput);
+        }
+        th.despawn(0).eos();
+        paths.push(th.write(&dir.join(format!("{}.teehistorian", file))));
+    }
+    let config = ExportConfig {
+        dedup: Some(DedupMode::Exact),
+        ..short_config()
+    };
+    let sequence_bytes =
+        config.seq_length * config.column_names().len() * std::mem::size_of::<f32>();
+    let cut_config = ExportConfig {
+        max_dataset_bytes: Some(3 * sequence_bytes as u64),
+        ..config.clone()
+    };
+    let out = dir.join("out");
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&out, cut_config.clone(), sink.clone()).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &cut_config)
+        .unwrap();
+    assert!(exporter.quota_reached);
+    assert_eq!(exporter.processed_files, paths[..1]);
+    assert_eq!(sink.stored.borrow().len(), 2);
+
+    // the fingerprints of the cut file weren't recorded in the checkpoint
+    let mut exporter = Exporter::resume_with_sink(&out, config.clone(), sink.clone()).unwrap();
+    exporter
+        .handle_batch(&paths[1..], &ParserConfig::default(), &config)
+        .unwrap();
+    assert_eq!(sink.stored.borrow().len(), 4);
+    assert_eq!(exporter.summary.sequences_dropped.get("duplicate"), None);
+    let teehists: Vec<String> = sink
+        .stored
+        .borrow()
+        .iter()
+        .map(|stored| stored.meta.teehist.to_string())
+        .collect();
+    assert_eq!(teehists, ["0", "0", "1", "1"]);
+}
+
+#[test]
+fn resolutions_cut_the_same_files_into_other_seq_lengths() {
+    let dir = temp_dir("export_resolutions");
+    let mut th = walking_players(&[(0, "amy")], 110);
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let config = short_config();
+    let long_config = ExportConfig {
+        seq_length: 50,
+        ..config.clone()
+    };
+    let sink = MemorySink::default();
+    let long_sink = MemorySink::default();
+    let mut exporter =
+        Exporter::with_sink(&dir.join("out_20"), config.clone(), sink.clone()).unwrap();
+    exporter
+        .resolutions
+        .push(Exporter::with_sink(&dir.join("out_50"), long_config, long_sink.clone()).unwrap());
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    let starts = |sink: &MemorySink| -> Vec<usize> {
+        sink.stored.borrow().iter().map(|s| s.meta.start).collect()
+    };
+    assert_eq!(starts(&sink), vec![0, 20, 40, 60, 80]);
+    assert_eq!(starts(&long_sink), vec![0, 50]);
+    assert_eq!(exporter.resolutions[0].processed_files, paths);
+    assert!(dir.join("out_50").join("manifest.json").is_file());
+}
+
+#[test]
+fn html_report_lists_players_and_aliases() {
+    let dir = temp_dir("export_report");
+    let mut th = walking_players(&[(0, "Amy"), (1, "amy."), (2, "<b>")], 100);
+    th.despawn(0).despawn(1).despawn(2).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let (_, exporter) = export(&dir.join("out"), &[path], short_config());
+
+    let html = report::render_html(&exporter, std::time::Duration::from_secs(3), 2, &[]);
+    assert!(html.contains("<h2>Top 2 of 3 players</h2>"));
+    assert!(html.contains("<td>Amy, amy.</td><td class=\"num\">10</td>"));
+    assert!(html.contains("&lt;b&gt;"));
+    assert!(!html.contains("<b>"));
+    assert!(!html.contains("Sample sequences"));
+}
+
+#[test]
+fn registry_keeps_player_ids_across_exports() {
+    let dir = temp_dir("export_registry");
+    let mut th = walking_players(&[(0, "amy"), (1, "zed")], 100);
+    th.despawn(0).despawn(1).eos();
+    let first = th.write(&dir.join("a.teehistorian"));
+    let mut th = walking_players(&[(0, "bob"), (1, "zed")], 100);
+    th.despawn(0).despawn(1).eos();
+    let second = th.write(&dir.join("b.teehistorian"));
+
+    let (_, exporter) = export(&dir.join("first"), &[first], short_config());
+    assert_eq!(exporter.players["zed"].0, 1);
+
+    let config = short_config();
+    let mut exporter =
+        Exporter::with_sink(&dir.join("second"), config.clone(), MemorySink::default()).unwrap();
+    exporter.registry = PlayerRegistry::load(&dir.join("first").join(REGISTRY_FILE)).unwrap();
+    let paths = [second];
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    // zed keeps its id, bob doesn't reuse the one of amy
+    assert_eq!(exporter.players["zed"].0, 1);
+    assert_eq!(exporter.players["bob"].0, 2);
+    let registry = PlayerRegistry::load(&dir.join("second").join(REGISTRY_FILE)).unwrap();
+    assert_eq!(registry.players.len(), 3);
+    assert_eq!(registry.next_id, 3);
+}
+
+#[test]
+fn aliases_share_player_ids() {
+    let dir = temp_dir("export_aliases");
+    let mut th = walking_players(&[(0, "amy"), (1, "Amy2"), (2, "zed")], 100);
+    th.despawn(0).despawn(1).despawn(2).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+    let aliases_path = dir.join("aliases.toml");
+    std::fs::write(&aliases_path, "amy = [\"Amy2\"]\n").unwrap();
+
+    let config = short_config();
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join("out"), config.clone(), sink.clone()).unwrap();
+    let aliases = registry::load_alias_map(&aliases_path).unwrap();
+    exporter.registry.apply_aliases(&aliases).unwrap();
+    let paths = [path];
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    assert_eq!(exporter.players["amy"].0, exporter.players["Amy2"].0);
+    assert_ne!(exporter.players["amy"].0, exporter.players["zed"].0);
+    assert_eq!(exporter.player_count, 2);
+    let stored = sink.stored.borrow();
+    let amy_ids: Vec<usize> = stored
+        .iter()
+        .filter(|s| s.meta.player.starts_with(['a', 'A']))
+        .map(|s| s.meta.player_id)
+        .collect();
+    assert_eq!(amy_ids.len(), 10);
+    assert!(amy_ids.iter().all(|&id| id == amy_ids[0]));
+
+    // merged aliases are no candidates anymore
+    let html = report::render_html(&exporter, std::time::Duration::ZERO, 10, &[]);
+    assert!(!html.contains("<td>Amy2, amy</td>"));
+}
+
+#[test]
+fn meta_rows_hold_anomaly_scores() {
+    let dir = temp_dir("export_anomaly");
+    let mut th = walking_players(&[(0, "amy")], 100);
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let config = short_config();
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join("out"), config.clone(), sink.clone()).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+
+    // inputs are diffs, so the fixture taps right every other tick like a script would
+    let stored = sink.stored.borrow();
+    assert!(!stored.is_empty());
+    for sequence in stored.iter() {
+        assert_eq!(sequence.meta.anomaly_score, Some(1.));
+        // followed by the recorded time, weight, labels, account, session and route
+        let csv = sequence.meta.to_csv();
+        let csv = csv.rsplitn(10, ',').last().unwrap();
+        assert!(csv.ends_with(",1.000,periodic_inputs,,,,,,,,,,"), "{}", csv);
+    }
+    assert_eq!(exporter.summary.sequences_flagged, stored.len());
+}
+
+#[test]
+fn meta_rows_hold_labels_of_overlapping_annotations() {
+    let dir = temp_dir("export_labels");
+    let mut th = walking_players(&[(0, "amy"), (1, "zed")], 100);
+    th.despawn(0).despawn(1).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+    std::fs::write(
+        dir.join("labels.csv"),
+        "teehist,cid,player,start_tick,end_tick,label\n\
+         a.teehistorian,0,,,,reviewed\n\
+         a,,amy,25,30,cheat\n\
+         ,,zed,,,banned\n\
+         b,0,,,,reviewed\n",
+    )
+    .unwrap();
+
+    let config = short_config();
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join("out"), config.clone(), sink.clone()).unwrap();
+    exporter.labels = LabelIndex::load(&dir.join("labels.csv")).unwrap();
+    assert_eq!(exporter.labels.len(), 4);
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+
+    let labels = |player: &str| -> Vec<Option<String>> {
+        let stored = sink.stored.borrow();
+        stored
+            .iter()
+            .filter(|s| &*s.meta.player == player)
+            .map(|s| s.meta.labels.clone())
+            .collect()
+    };
+    let amy = labels("amy");
+    assert_eq!(amy.len(), 5);
+    assert_eq!(amy[0].as_deref(), Some("reviewed"));
+    assert_eq!(amy[1].as_deref(), Some("cheat|reviewed"));
+    assert_eq!(amy[2].as_deref(), Some("reviewed"));
+    assert!(labels("zed")
+        .iter()
+        .all(|labels| labels.as_deref() == Some("banned")));
+}
+
+#[test]
+fn labels_with_separators_round_trip_through_meta_csv() {
+    let dir = temp_dir("export_label_escaping");
+    let mut th = walking_players(&[(0, "amy")], 30);
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+    std::fs::write(
+        dir.join("labels.csv"),
+        "teehist,cid,player,start_tick,end_tick,label\n\
+         a,0,,,,\"fast, \"\"clean\"\"\nrun\"\n",
+    )
+    .unwrap();
+
+    let config = short_config();
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join("out"), config.clone(), sink.clone()).unwrap();
+    exporter.labels = LabelIndex::load(&dir.join("labels.csv")).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+
+    let stored = sink.stored.borrow();
+    assert!(!stored.is_empty());
+    let csv: String = stored
+        .iter()
+        .map(|sequence| format!("{}\n", sequence.meta.to_csv()))
+        .collect();
+    std::fs::write(dir.join("meta.csv"), format!("{}\n{}", META_HEADER, csv)).unwrap();
+    let meta = read_meta(&dir.join("meta.csv")).unwrap();
+    assert_eq!(meta.len(), stored.len());
+    assert!(meta
+        .iter()
+        .all(|row| row.labels.as_deref() == Some("fast, \"clean\"\nrun")));
+}
+
+#[test]
+fn meta_rows_hold_map_info_and_records() {
+    let dir = temp_dir("export_map_info");
+    let mut th = walking_players(&[(0, "amy")], 100);
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+    std::fs::write(dir.join("Synthetic.map"), map_bytes(2, 2, &[0, 0, 192, 0])).unwrap();
+
+    let config = short_config();
+    let sink = MemorySink::default();
+    let mut exporter = Exporter::with_sink(&dir.join("out"), config.clone(), sink.clone()).unwrap();
+    exporter.maps = MapCatalog::new(Some(&dir));
+    exporter.records =
+        RecordIndex::from_finishes([("Synthetic".to_string(), "amy".to_string(), 12.5)]);
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+
+    let stored = sink.stored.borrow();
+    assert!(!stored.is_empty());
+    for sequence in stored.iter() {
+        assert_eq!(sequence.meta.map_width, Some(2));
+        assert_eq!(sequence.meta.map_height, Some(2));
+        assert_eq!(sequence.meta.map_stars, None);
+        assert_eq!(sequence.meta.record_rank, Some(1));
+        let csv = sequence.meta.to_csv();
+        let (csv, _) = csv.rsplit_once(',').unwrap();
+        assert!(csv.ends_with(",2,2,,16:48,,,,12.5,1,1"), "{}", csv);
+    }
+}
+
+#[test]
+fn string_columns_round_trip_through_meta_csv() {
+    let dir = temp_dir("export_meta_escaping");
+    // timeout codes and names are typed by players
+    let row = MetaRow {
+        map: "Kobra, 1".into(),
+        timeout: Some("a,b\"c".into()),
+        auth_name: Some("line\nbreak".into()),
+        ..meta_row("\"amy\"", 0, 100)
+    };
+    let csv = row.to_csv();
+    assert!(csv.starts_with("0,0,\"\"\"amy\"\"\",0,100,\"Kobra, 1\",a,\"a,b\"\"c\","));
+    std::fs::write(dir.join("meta.csv"), format!("{}\n{}\n", META_HEADER, csv)).unwrap();
+
+    let meta = read_meta(&dir.join("meta.csv")).unwrap();
+    assert_eq!(meta.len(), 1);
+    assert_eq!(&*meta[0].player, "\"amy\"");
+    assert_eq!(&*meta[0].map, "Kobra, 1");
+    assert_eq!(meta[0].timeout.as_deref(), Some("a,b\"c"));
+    assert_eq!(meta[0].auth_name.as_deref(), Some("line\nbreak"));
+    assert_eq!(meta[0].route, None);
+}
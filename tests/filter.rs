@@ -0,0 +1,69 @@
+mod support;
+
+use chrono::{NaiveDate, Utc};
+use glob::Pattern;
+use support::meta_row;
+use teehistorian_extractor::dataset::{DatasetFilter, MetaRow};
+
+fn row(player: &str, map: &str, ticks: usize, recorded: Option<&str>) -> MetaRow {
+    MetaRow {
+        map: map.into(),
+        recorded: recorded.map(str::to_string),
+        ..meta_row(player, 0, ticks)
+    }
+}
+
+fn date(day: u32) -> chrono::DateTime<Utc> {
+    NaiveDate::from_ymd_opt(2024, 10, day)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+}
+
+#[test]
+fn empty_filter_keeps_everything() {
+    assert!(DatasetFilter::default().matches(&row("amy", "Kobra 1", 1, None)));
+}
+
+#[test]
+fn excluded_players_and_maps_take_precedence() {
+    let filter = DatasetFilter {
+        filter_players: Some(["amy".to_string(), "zed".to_string()].into()),
+        exclude_players: Some(["zed".to_string()].into()),
+        filter_maps: Some(vec![Pattern::new("Kobra*").unwrap()]),
+        exclude_maps: Some(vec![Pattern::new("Kobra 2").unwrap()]),
+        ..Default::default()
+    };
+    assert!(filter.matches(&row("amy", "Kobra 1", 100, None)));
+    assert!(!filter.matches(&row("zed", "Kobra 1", 100, None)));
+    assert!(!filter.matches(&row("bob", "Kobra 1", 100, None)));
+    assert!(!filter.matches(&row("amy", "Kobra 2", 100, None)));
+    assert!(!filter.matches(&row("amy", "Tutorial", 100, None)));
+}
+
+#[test]
+fn min_ticks_drops_short_sequences() {
+    let filter = DatasetFilter {
+        min_ticks: Some(100),
+        ..Default::default()
+    };
+    assert!(filter.matches(&row("amy", "Kobra 1", 100, None)));
+    assert!(!filter.matches(&row("amy", "Kobra 1", 99, None)));
+}
+
+#[test]
+fn dates_filter_by_recording_time() {
+    let filter = DatasetFilter {
+        since: Some(date(2)),
+        until: Some(date(3)),
+        ..Default::default()
+    };
+    let recorded = |recorded| filter.matches(&row("amy", "Kobra 1", 100, recorded));
+    assert!(recorded(Some("2024-10-02T00:00:00Z")));
+    assert!(recorded(Some("2024-10-02T23:59:59Z")));
+    assert!(!recorded(Some("2024-10-01T23:59:59Z")));
+    assert!(!recorded(Some("2024-10-03T00:00:00Z")));
+    // exports of older versions without recorded column
+    assert!(!recorded(None));
+}
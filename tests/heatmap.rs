@@ -0,0 +1,47 @@
+mod support;
+
+use support::{map_bytes, temp_dir, ThBuilder};
+use teehistorian_extractor::heatmap::file_heatmap;
+use teehistorian_extractor::map_file::{GameLayer, TILE_SOLID};
+
+#[test]
+fn positions_are_counted_per_cell() {
+    let dir = temp_dir("heatmap");
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, -1, 64);
+    for _ in 0..63 {
+        th.diff(0, 1, 0).diff(1, 0, 0);
+    }
+    th.despawn(0).despawn(1).eos();
+    let path = th.write(&dir.join("a.teehistorian"));
+
+    let (map_name, heatmap) = file_heatmap(&path, 32).unwrap().unwrap();
+    assert_eq!(map_name, "Synthetic");
+    assert_eq!(heatmap.total_ticks(), 128);
+    assert_eq!(heatmap.cells[&(0, 0)], 32);
+    assert_eq!(heatmap.cells[&(1, 0)], 32);
+    // negative positions belong to the cell left of the origin
+    assert_eq!(heatmap.cells[&(-1, 2)], 64);
+
+    let image = heatmap.render(None).unwrap();
+    assert_eq!((image.width, image.height), (3, 3));
+    let mut png = Vec::new();
+    image.encode_png(&mut png).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+}
+
+#[test]
+fn game_layer_is_read_from_map() {
+    let tiles = [0, 0, TILE_SOLID, 0, TILE_SOLID, 0];
+    let layer = GameLayer::from_bytes(&map_bytes(3, 2, &tiles)).unwrap();
+    assert_eq!((layer.width, layer.height), (3, 2));
+    assert_eq!(layer.tile_at(64, 0), Some(TILE_SOLID));
+    assert_eq!(layer.tile_at(40, 40), Some(TILE_SOLID));
+    assert_eq!(layer.tile_at(0, 0), Some(0));
+    assert_eq!(layer.tile_at(-1, 0), None);
+    assert_eq!(layer.tile_at(96, 0), None);
+
+    assert!(GameLayer::from_bytes(b"not a map").is_err());
+    assert!(GameLayer::from_bytes(&map_bytes(3, 3, &tiles)).is_err());
+}
@@ -0,0 +1,106 @@
+mod support;
+
+use arrow::array::{Array, AsArray};
+use arrow::datatypes::Float32Type;
+use ndarray::Array3;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fs::File;
+use support::{meta_row, temp_dir};
+use teehistorian_extractor::dataset::MetaRow;
+use teehistorian_extractor::huggingface::{
+    base64, commit_payload, dataset_card, shard_path, write_shard, HubFile,
+};
+
+#[test]
+fn shards_hold_a_row_per_sequence() {
+    let dir = temp_dir("huggingface_shard");
+    let column_names = vec!["move_dir".to_string(), "vel_x".to_string()];
+    let meta = [
+        meta_row("amy", 0, 3),
+        MetaRow {
+            seq_id: 1,
+            finish_time: Some(1234),
+            ..meta_row("zed", 3, 3)
+        },
+    ];
+    let data = Array3::from_shape_fn((2, 3, 2), |(sequence, tick, feature)| {
+        (sequence * 100 + tick * 10 + feature) as f32
+    });
+    let path = dir.join("shard.parquet");
+    write_shard(&path, &column_names, &meta, data.view()).unwrap();
+
+    let batches: Vec<_> = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 2);
+    assert!(batch.schema().field_with_name("timeout").is_err());
+
+    let players = batch.column_by_name("player").unwrap().as_string::<i32>();
+    assert_eq!((players.value(0), players.value(1)), ("amy", "zed"));
+    let finish_times = batch
+        .column_by_name("finish_time")
+        .unwrap()
+        .as_primitive::<arrow::datatypes::Int32Type>();
+    assert!(finish_times.is_null(0));
+    assert_eq!(finish_times.value(1), 1234);
+
+    let vel_x = batch.column_by_name("vel_x").unwrap().as_fixed_size_list();
+    assert_eq!(vel_x.value_length(), 3);
+    let second = vel_x.value(1);
+    assert_eq!(
+        second.as_primitive::<Float32Type>().values().to_vec(),
+        vec![101., 111., 121.]
+    );
+}
+
+#[test]
+fn card_describes_features_and_splits() {
+    let card = dataset_card("runs", &["move_dir".to_string()], 1000, 42);
+    assert!(card.starts_with("---\nconfigs:\n"));
+    assert!(card.contains("    path: data/train-*\n"));
+    assert!(card.contains("  - name: player\n    dtype: string\n"));
+    assert!(card.contains("  - name: move_dir\n    sequence: float32\n    length: 1000\n"));
+    assert!(card.contains("    num_examples: 42\n"));
+    assert!(card.contains("\n# runs\n"));
+    assert_eq!(shard_path(3, 12), "data/train-00003-of-00012.parquet");
+}
+
+#[test]
+fn base64_pads_partial_groups() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64(&[0xff, 0xfe]), "//4=");
+}
+
+#[test]
+fn commit_references_shards_by_sha256() {
+    let shards = [HubFile {
+        path: shard_path(0, 1),
+        sha256: "ab".repeat(32),
+        size: 7,
+    }];
+    let payload = commit_payload("upload", b"card", &shards);
+    let lines: Vec<serde_json::Value> = payload
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let keys: Vec<_> = lines
+        .iter()
+        .map(|line| line["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(keys, ["header", "deletedFolder", "file", "lfsFile"]);
+    assert_eq!(lines[2]["value"]["content"], "Y2FyZA==");
+    assert_eq!(lines[3]["value"]["oid"], shards[0].sha256.as_str());
+    assert_eq!(
+        lines[3]["value"]["path"],
+        "data/train-00000-of-00001.parquet"
+    );
+}
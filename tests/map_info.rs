@@ -0,0 +1,94 @@
+mod support;
+
+use std::{fs, sync::Arc};
+use support::{map_bytes, temp_dir};
+use teehistorian_extractor::map_file::{GameLayer, TILE_SOLID, TILE_SPAWN, TILE_SPAWN_RED};
+use teehistorian_extractor::map_info::{parse_ddnet_map_info, MapCatalog, MapInfo};
+
+#[test]
+fn spawn_points_are_tile_centers() {
+    let tiles = [TILE_SPAWN, TILE_SOLID, 0, 0, 0, TILE_SPAWN_RED];
+    let layer = GameLayer::from_bytes(&map_bytes(3, 2, &tiles)).unwrap();
+    assert_eq!(layer.spawn_points(), vec![(16, 16), (80, 48)]);
+}
+
+#[test]
+fn maps_are_resolved_by_name() {
+    let dir = temp_dir("map_info");
+    fs::write(
+        dir.join("Kobra.map"),
+        map_bytes(3, 2, &[0, 0, 0, TILE_SPAWN, 0, 0]),
+    )
+    .unwrap();
+    fs::write(dir.join("Broken.map"), b"not a map").unwrap();
+    let info_path = dir.join("maps.csv");
+    fs::write(
+        &info_path,
+        "map,server,stars\nKobra,Novice,2\nUnknown,Brutal,5\nBroken,Solo,\n",
+    )
+    .unwrap();
+
+    let mut catalog = MapCatalog::new(Some(&dir));
+    catalog.load_stars(&info_path).unwrap();
+
+    let kobra = catalog.get(&Arc::from("Kobra"));
+    assert_eq!(kobra.size, Some((3, 2)));
+    assert_eq!(kobra.spawn_points_csv(), "16:48");
+    assert_eq!(kobra.stars, Some(2));
+    assert_eq!(
+        *catalog.get(&Arc::from("Unknown")),
+        MapInfo {
+            stars: Some(5),
+            ..Default::default()
+        }
+    );
+    assert_eq!(*catalog.get(&Arc::from("Broken")), MapInfo::default());
+
+    fs::write(&info_path, "map,stars\nKobra,6\n").unwrap();
+    assert!(MapCatalog::new(None).load_stars(&info_path).is_err());
+}
+
+#[test]
+fn ddnet_map_info_is_merged() {
+    let dir = temp_dir("ddnet_map_info");
+    let dump_path = dir.join("maps.json");
+    fs::write(
+        &dump_path,
+        r#"[
+            {"name": "Kobra", "type": "Novice", "points": 5, "difficulty": 1,
+             "release": 1374616800.0, "mapper": "Rusk"},
+            {"name": "Grandma", "type": "Brutal", "points": 40, "difficulty": 3,
+             "release": "2015-06-01 20:00"},
+            {"name": "Old", "type": "Oldschool"}
+        ]"#,
+    )
+    .unwrap();
+    let stars_path = dir.join("stars.csv");
+    fs::write(&stars_path, "map,stars\nGrandma,4\n").unwrap();
+
+    let mut catalog = MapCatalog::new(None);
+    catalog.load_ddnet_info(&dump_path).unwrap();
+    catalog.load_stars(&stars_path).unwrap();
+
+    let kobra = catalog.get(&Arc::from("Kobra"));
+    assert_eq!(kobra.category.as_deref(), Some("Novice"));
+    assert_eq!(kobra.points, Some(5));
+    assert_eq!(kobra.stars, Some(1));
+    assert_eq!(
+        kobra.release.unwrap().format("%Y-%m-%d %H:%M").to_string(),
+        "2013-07-23 22:00"
+    );
+    // stars of the info file take precedence
+    let grandma = catalog.get(&Arc::from("Grandma"));
+    assert_eq!(grandma.stars, Some(4));
+    assert_eq!(
+        grandma.release.unwrap().to_rfc3339(),
+        "2015-06-01T20:00:00+00:00"
+    );
+    let old = catalog.get(&Arc::from("Old"));
+    assert_eq!((old.points, old.release), (None, None));
+
+    let single = parse_ddnet_map_info(r#"{"name": "Kobra", "release": "2013-07-23"}"#).unwrap();
+    assert_eq!(single.len(), 1);
+    assert!(parse_ddnet_map_info(r#"[{"name": "Kobra", "release": "soon"}]"#).is_err());
+}
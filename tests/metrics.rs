@@ -0,0 +1,89 @@
+mod support;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{atomic::Ordering, Arc},
+};
+use support::{temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    export::{ExportConfig, Exporter},
+    metrics::{self, Metrics},
+    parser::ParserConfig,
+};
+
+fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn metrics_are_served_in_prometheus_format() {
+    let metrics = Arc::new(Metrics::default());
+    let addr = metrics::serve("127.0.0.1:0", metrics.clone()).unwrap();
+    metrics.files_processed.store(3, Ordering::Relaxed);
+    metrics.add_parse_error("unhandled_chunk");
+    metrics.add_parse_error("unhandled_chunk");
+
+    let response = get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("# TYPE teehistorian_files_processed_total counter\n"));
+    assert!(response.contains("\nteehistorian_files_processed_total 3\n"));
+    assert!(response.contains("\nteehistorian_parse_errors_total{kind=\"unhandled_chunk\"} 2\n"));
+
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+/// amy walks for 50 ticks and despawns
+fn walking() -> ThBuilder {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..50 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0);
+    th
+}
+
+#[test]
+fn exporter_counts_files_and_sequences() {
+    let dir = temp_dir("metrics_export");
+    let mut th = walking();
+    th.eos();
+    let complete = th.write(&dir.join("a.teehistorian"));
+    // the second file stops with a parse error after the same gameplay
+    let mut th = walking();
+    th.spawn(0, 0, 0).swap(0, 1).eos();
+    let broken = th.write(&dir.join("b.teehistorian"));
+    let paths = [complete, broken];
+
+    let config = ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .build()
+        .unwrap();
+    let mut exporter =
+        Exporter::with_sink(&dir.join("out"), config.clone(), MemorySink::default()).unwrap();
+    let metrics = Arc::new(Metrics::default());
+    metrics.files_pending.store(2, Ordering::Relaxed);
+    exporter.metrics = Some(metrics.clone());
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+
+    assert_eq!(metrics.files_processed.load(Ordering::Relaxed), 2);
+    assert_eq!(metrics.files_pending.load(Ordering::Relaxed), 0);
+    assert_eq!(
+        metrics.sequences_exported.load(Ordering::Relaxed),
+        exporter.sequence_count as u64
+    );
+    assert!(metrics.ticks_parsed.load(Ordering::Relaxed) > 100);
+    assert!(metrics
+        .render()
+        .contains("teehistorian_parse_errors_total{kind=\"unhandled_chunk\"} 1\n"));
+}
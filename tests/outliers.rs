@@ -0,0 +1,109 @@
+mod support;
+
+use support::{sequence, temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    export::{ExportConfig, Exporter},
+    extractor::Sequence,
+    outliers::OutlierBounds,
+    parser::ParserConfig,
+};
+
+/// sequence of 21 ticks walking right with the given speed, aiming at (100, 0)
+fn walking(speed: i32) -> Sequence {
+    let positions: Vec<_> = (0..21).map(|tick| (tick * speed, 0)).collect();
+    let mut sequence = sequence(&positions);
+    sequence.move_dir.fill(1);
+    sequence
+}
+
+fn config() -> ExportConfig {
+    ExportConfig::builder()
+        .seq_length(20)
+        .afk_padding(2)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn bounds_are_quantiles_of_the_sequence_extremes() {
+    let mut sample: Vec<Sequence> = (1..=100).map(|speed| walking(speed % 10)).collect();
+    sample.push(walking(500));
+    let bounds = OutlierBounds::from_sample(&sample, &config(), 0.01);
+    assert_eq!(bounds.sample_size, 101);
+    assert_eq!(bounds.bounds["vel_x"], (0.0, 9.0));
+    assert_eq!(bounds.bounds["vel_y"], (0.0, 0.0));
+    assert_eq!(bounds.bounds["aim_distance"], (100.0, 100.0));
+    // relative targets aren't exported by default
+    assert!(!bounds.bounds.contains_key("target_rel_x"));
+
+    assert!(bounds.is_outlier(&sample[100], &config()));
+    assert!(!bounds.is_outlier(&sample[0], &config()));
+}
+
+#[test]
+fn clipping_moves_later_positions_along() {
+    let sample: Vec<Sequence> = (0..10).map(|_| walking(3)).collect();
+    let bounds = OutlierBounds::from_sample(&sample, &config(), 0.1);
+
+    let mut sequence = walking(3);
+    sequence.pos_x[5] += 100;
+    sequence.target_x[2] = 1000;
+    assert!(bounds.clip(&mut sequence, &config()));
+    assert_eq!(sequence.pos_x, walking(3).pos_x);
+    assert_eq!(sequence.target_x[2], 100);
+    assert!(!bounds.is_outlier(&sequence, &config()));
+    assert!(!bounds.clip(&mut sequence, &config()));
+}
+
+/// amy and bob walk, bob dashes once below the max speed of the parser
+fn walking_and_dashing(dir: &std::path::Path) -> std::path::PathBuf {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy")
+        .join(1, "bob")
+        .spawn(0, 0, 0)
+        .spawn(1, 0, 0);
+    for tick in 0..200 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+        let dx = if tick == 150 { 60 } else { 1 };
+        th.diff(1, dx, 0).input(1, dinput);
+    }
+    th.despawn(0).despawn(1).eos();
+    th.write(&dir.join("a.teehistorian"))
+}
+
+#[test]
+fn exporter_drops_or_clips_outliers() {
+    let dir = temp_dir("outliers_export");
+    let paths = [walking_and_dashing(&dir)];
+
+    let export = |name: &str, clip: bool| {
+        let config = ExportConfig::builder()
+            .seq_length(20)
+            .afk_padding(2)
+            .outlier_quantile(0.05)
+            .clip_outliers(clip)
+            .build()
+            .unwrap();
+        let sink = MemorySink::default();
+        let mut exporter =
+            Exporter::with_sink(&dir.join(name), config.clone(), sink.clone()).unwrap();
+        exporter
+            .handle_batch(&paths, &ParserConfig::default(), &config)
+            .unwrap();
+        exporter.finalize(&paths).unwrap();
+        let count = sink.stored.borrow().len();
+        (count, exporter)
+    };
+
+    let (dropped_count, exporter) = export("drop", false);
+    assert_eq!(exporter.summary.sequences_dropped.get("outlier"), Some(&1));
+    assert_eq!(exporter.outlier_bounds.unwrap().bounds["vel_x"], (1.0, 1.0));
+
+    let (clipped_count, exporter) = export("clip", true);
+    assert_eq!(exporter.summary.sequences_clipped, 1);
+    assert_eq!(clipped_count, dropped_count + 1);
+    let summary = std::fs::read_to_string(dir.join("clip").join("summary.json")).unwrap();
+    assert!(summary.contains("\"outlier_bounds\""));
+}
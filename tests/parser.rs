@@ -0,0 +1,325 @@
+mod support;
+
+use support::{parse, ThBuilder};
+use teehistorian_extractor::parser::{ParseError, ParserConfig, PlayerAuth};
+
+fn cut_config() -> ParserConfig {
+    ParserConfig {
+        cut_kill: true,
+        cut_rescue: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn single_player_sequence() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 100, 200).walk(0, 100, 1, -1);
+    th.despawn(0).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 1);
+    let seq = &parsed.sequences[0];
+    assert_eq!(seq.player_name.as_deref(), Some("alice"));
+    assert_eq!(seq.map_name.as_deref(), Some("Synthetic"));
+    assert_eq!((seq.start_tick, seq.end_tick), (0, Some(101)));
+    assert_eq!(seq.player_positions.len(), 101);
+    assert_eq!(seq.input_vectors.len(), 101);
+    assert_eq!(seq.player_positions[0], (100, 200));
+    assert_eq!(seq.player_positions[100], (200, 100));
+}
+
+#[test]
+fn implicit_ticks_with_multiple_players() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 0, 0);
+    for _ in 0..50 {
+        th.diff(0, 1, 0).diff(1, 0, 1);
+    }
+    th.despawn(0).despawn(1).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.ticks, 51);
+    assert_eq!(parsed.sequences.len(), 2);
+    for seq in &parsed.sequences {
+        assert_eq!(seq.player_positions.len(), 51);
+    }
+    let bob = parsed.sequences.iter().find(|s| s.cid == 1).unwrap();
+    assert_eq!(bob.player_positions[50], (0, 50));
+}
+
+#[test]
+fn explicit_tick_skip() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 0, 0).walk(0, 10, 1, 0);
+    th.tick_skip(5).walk(0, 10, 1, 0).despawn(0).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    let seq = &parsed.sequences[0];
+    assert_eq!(seq.player_positions.len(), 26);
+    // the player doesn't move during the skipped ticks
+    assert_eq!(seq.player_positions[10], (10, 0));
+    assert_eq!(seq.player_positions[15], (10, 0));
+    assert_eq!(seq.player_positions[16], (11, 0));
+}
+
+#[test]
+fn kill_cuts_sequence() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 0, 0).walk(0, 50, 1, 0);
+    th.kill(0).walk(0, 50, 1, 0).despawn(0).eos();
+    let bytes = th.finish();
+
+    let parsed = parse(&bytes, &ParserConfig::default());
+    assert_eq!(parsed.sequences.len(), 1);
+
+    let parsed = parse(&bytes, &cut_config());
+    assert!(parsed.error.is_none());
+    let ticks: Vec<_> = parsed
+        .sequences
+        .iter()
+        .map(|s| (s.start_tick, s.end_tick))
+        .collect();
+    // the ticks right after the kill are skipped, as the respawn spans multiple ticks
+    assert_eq!(ticks, vec![(0, Some(50)), (52, Some(101))]);
+}
+
+#[test]
+fn rescue_cuts_sequence() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 0, 0).walk(0, 50, 1, 0);
+    th.console(0, "r", &[]).walk(0, 50, 1, 0).despawn(0).eos();
+    let parsed = parse(&th.finish(), &cut_config());
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 2);
+}
+
+#[test]
+fn teleport_cuts_sequence() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 0, 0).walk(0, 50, 1, 0);
+    th.diff(0, 5000, 0).walk(0, 50, 1, 0).despawn(0).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 2);
+    assert!(parsed
+        .anomalies
+        .iter()
+        .any(|a| a.cid == Some(0) && a.description.starts_with("teleport")));
+    // no sequence contains the teleport itself
+    for seq in &parsed.sequences {
+        assert!(seq
+            .player_positions
+            .windows(2)
+            .all(|w| w[1].0 - w[0].0 <= 1));
+    }
+}
+
+#[test]
+fn mid_tick_drop() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 0, 0);
+    for _ in 0..30 {
+        th.diff(0, 1, 0).diff(1, 1, 0);
+    }
+    // bob leaves in the middle of a tick, alice keeps playing
+    th.diff(0, 1, 0).drop(1, "timeout").despawn(1);
+    th.walk(0, 30, 1, 0).despawn(0).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    let alice = parsed.sequences.iter().find(|s| s.cid == 0).unwrap();
+    let bob = parsed.sequences.iter().find(|s| s.cid == 1).unwrap();
+    assert_eq!(bob.end_tick, Some(31));
+    assert_eq!(bob.player_positions.len(), 31);
+    assert_eq!(alice.end_tick, Some(62));
+    assert_eq!(alice.player_positions[61], (61, 0));
+}
+
+#[test]
+fn timeout_code_is_recorded() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").console(0, "timeout", &["code123"]);
+    th.join(1, "bob").console(1, "timeout", &["code456"]);
+    th.spawn(0, 0, 0).spawn(1, 0, 0).walk(0, 10, 1, 0);
+    // the drop is recorded before the end of the last sequence
+    th.despawn(0).drop(1, "timeout").despawn(1).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    let timeout_code = |cid: i32| {
+        parsed
+            .sequences
+            .iter()
+            .find(|sequence| sequence.cid == cid)
+            .and_then(|sequence| sequence.timeout_code.as_deref())
+    };
+    assert_eq!(timeout_code(0), Some("code123"));
+    assert_eq!(timeout_code(1), Some("code456"));
+}
+
+#[test]
+fn auth_is_kept_until_the_sequence_ends() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob").login(0, "alice_acc", 2);
+    th.login(1, "bob_acc", 1);
+    th.spawn(0, 0, 0).spawn(1, 0, 0).walk(0, 10, 1, 0);
+    // logged out during the sequence, it still belongs to the account
+    th.logout(0).walk(0, 10, 1, 0).despawn(0);
+    th.spawn(0, 0, 0)
+        .walk(0, 10, 1, 0)
+        .despawn(0)
+        .drop(1, "leaving")
+        .despawn(1)
+        .eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    let auths: Vec<_> = parsed
+        .sequences
+        .iter()
+        .map(|sequence| (sequence.cid, sequence.auth.clone()))
+        .collect();
+    let alice_auth = PlayerAuth {
+        name: "alice_acc".to_string(),
+        level: 2,
+    };
+    assert!(auths.contains(&(0, Some(alice_auth))));
+    assert!(auths.contains(&(0, None)));
+    // the drop is recorded before the end of the last sequence
+    let bob_auth = PlayerAuth {
+        name: "bob_acc".to_string(),
+        level: 1,
+    };
+    assert!(auths.contains(&(1, Some(bob_auth))));
+}
+
+#[test]
+fn player_swap_is_unhandled() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 0, 0).swap(0, 1).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(matches!(
+        parsed.error,
+        Some(ParseError::UnhandledChunkError(_))
+    ));
+}
+
+#[test]
+fn diff_before_spawn_drops_player() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).diff(1, 1, 0);
+    for _ in 0..30 {
+        th.diff(0, 1, 0).diff(1, 1, 0);
+    }
+    th.spawn(1, 0, 0).walk(0, 10, 1, 0);
+    th.despawn(0).despawn(1).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 1);
+    assert_eq!(parsed.sequences[0].cid, 0);
+    assert!(parsed.anomalies.iter().any(|a| a.cid == Some(1)));
+}
+
+#[test]
+fn filtered_players_are_ignored() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 0, 0);
+    for _ in 0..30 {
+        th.diff(0, 1, 0).diff(1, 1, 0);
+    }
+    th.despawn(0).despawn(1).eos();
+    let config = ParserConfig {
+        exclude_players: Some(["bob".to_string()].into()),
+        ..Default::default()
+    };
+    let parsed = parse(&th.finish(), &config);
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 1);
+    assert_eq!(parsed.sequences[0].player_name.as_deref(), Some("alice"));
+}
+
+#[test]
+fn position_overflow_is_an_error() {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice")
+        .spawn(0, i32::MAX - 1, 0)
+        .walk(0, 3, 1, 0);
+    th.despawn(0).eos();
+    let parsed = parse(&th.finish(), &ParserConfig::default());
+
+    assert!(matches!(parsed.error, Some(ParseError::OutOfRange(_))));
+}
+
+#[test]
+fn invalid_tick_skips_are_errors() {
+    for dt in [-5, i32::MAX] {
+        let mut th = ThBuilder::new();
+        th.join(0, "alice").spawn(0, 0, 0).walk(0, 10, 1, 0);
+        th.tick_skip(dt).walk(0, 10, 1, 0).despawn(0).eos();
+        let parsed = parse(&th.finish(), &ParserConfig::default());
+
+        assert!(matches!(parsed.error, Some(ParseError::OutOfRange(_))));
+        assert_eq!(parsed.ticks, 10);
+    }
+}
+
+/// alice moves right with a timeout and reconnect of 3 ticks without inputs in between
+fn reconnecting_player() -> Vec<u8> {
+    let mut th = ThBuilder::new();
+    th.join(0, "alice").spawn(0, 0, 0).walk(0, 20, 1, 0);
+    let mut dinput = [0; 10];
+    dinput[0] = 1;
+    th.diff(0, 1, 0).input(0, dinput);
+    th.diff(0, 1, 0).drop(0, "timeout");
+    th.walk(0, 3, 1, 0).join(0, "alice");
+    th.walk(0, 20, 1, 0).despawn(0).eos();
+    th.finish()
+}
+
+#[test]
+fn input_gaps_split_sequences() {
+    let parsed = parse(&reconnecting_player(), &ParserConfig::default());
+
+    assert!(parsed.error.is_none());
+    let bounds: Vec<_> = parsed
+        .sequences
+        .iter()
+        .map(|seq| (seq.start_tick, seq.end_tick))
+        .collect();
+    assert_eq!(bounds, [(0, Some(22)), (25, Some(46))]);
+    // positions stay aligned with their ticks
+    assert_eq!(parsed.sequences[1].player_positions[0], (25, 0));
+}
+
+#[test]
+fn short_input_gaps_are_filled() {
+    let config = ParserConfig {
+        max_input_gap: 3,
+        ..Default::default()
+    };
+    let parsed = parse(&reconnecting_player(), &config);
+
+    assert!(parsed.error.is_none());
+    assert_eq!(parsed.sequences.len(), 1);
+    let seq = &parsed.sequences[0];
+    assert_eq!((seq.start_tick, seq.end_tick), (0, Some(46)));
+    assert_eq!(seq.player_positions[30], (30, 0));
+    assert!(seq.input_vectors[22..25].iter().all(|input| input[0] == 1));
+    assert_eq!(seq.input_vectors[25][0], 0);
+    assert!(parsed
+        .anomalies
+        .iter()
+        .any(|a| a.cid == Some(0) && a.description == "filled 3 ticks without input"));
+}
@@ -0,0 +1,104 @@
+mod support;
+
+use ndarray::Array2;
+use support::{sequence, temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    export::{ExportConfig, Exporter},
+    extractor::Sequence,
+    parser::ParserConfig,
+    phase::{phases, Phase},
+    sink::write_features,
+};
+
+/// standing for 10 ticks, falling for 10, then walking right for 10
+fn stand_fall_walk() -> Sequence {
+    let mut positions = vec![(0, 0); 10];
+    positions.extend((1..=10).map(|tick| (0, 10 * tick)));
+    positions.extend((1..=10).map(|tick| (tick, 100)));
+    let mut sequence = sequence(&positions);
+    sequence.move_dir[20..].fill(1);
+    sequence
+}
+
+#[test]
+fn ticks_are_labeled_by_movement() {
+    let phases = phases(&stand_fall_walk());
+    assert_eq!(phases.len(), 30);
+    // the movement of the last standing tick leads into the fall
+    assert!(phases[..9].iter().all(|&phase| phase == Phase::Waiting));
+    assert!(phases[9..19].iter().all(|&phase| phase == Phase::Falling));
+    assert!(phases[20..].iter().all(|&phase| phase == Phase::Moving));
+}
+
+#[test]
+fn hook_and_freeze_take_precedence() {
+    let mut sequence = stand_fall_walk();
+    sequence.hook[10..20].fill(true);
+    sequence.frozen = vec![false; 30];
+    sequence.frozen[..10].fill(true);
+    let phases = phases(&sequence);
+    assert!(phases[..10].iter().all(|&phase| phase == Phase::Frozen));
+    assert!(phases[10..20].iter().all(|&phase| phase == Phase::Hooking));
+}
+
+#[test]
+fn short_phases_are_merged_into_the_one_before() {
+    let mut sequence = stand_fall_walk();
+    // a short hook while walking
+    sequence.hook[24..26].fill(true);
+    let phases = phases(&sequence);
+    assert!(phases[20..].iter().all(|&phase| phase == Phase::Moving));
+}
+
+#[test]
+fn phase_column_holds_the_phase_index() {
+    let config = ExportConfig::builder()
+        .seq_length(25)
+        .afk_padding(2)
+        .phase_labels(true)
+        .build()
+        .unwrap();
+    let columns = config.column_names();
+    assert_eq!(columns.last().map(String::as_str), Some("phase"));
+
+    let mut out = Array2::zeros((25, columns.len()));
+    write_features(&stand_fall_walk(), &config, out.view_mut()).unwrap();
+    let phase = out.column(columns.len() - 1);
+    assert_eq!(phase[0], Phase::Waiting as u8 as f32);
+    assert_eq!(phase[12], Phase::Falling as u8 as f32);
+    assert_eq!(phase[24], Phase::Moving as u8 as f32);
+}
+
+#[test]
+fn phase_names_are_recorded_in_the_manifest() {
+    let dir = temp_dir("phase_manifest");
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..30 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let config = ExportConfig::builder()
+        .seq_length(10)
+        .afk_padding(2)
+        .phase_labels(true)
+        .build()
+        .unwrap();
+    let mut exporter =
+        Exporter::with_sink(&dir.join("out"), config.clone(), MemorySink::default()).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    let manifest = std::fs::read_to_string(dir.join("out").join("manifest.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(
+        manifest["phases"],
+        serde_json::json!(["moving", "hooking", "waiting", "frozen", "falling"])
+    );
+}
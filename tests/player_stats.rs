@@ -0,0 +1,40 @@
+mod support;
+
+use std::collections::HashMap;
+use support::{temp_dir, ThBuilder};
+use teehistorian_extractor::player_stats::{file_player_stats, player_reports, PlayerStats};
+use teehistorian_extractor::preprocess::ActivityInput;
+
+#[test]
+fn stats_are_merged_across_files() {
+    let dir = temp_dir("player_stats");
+    let mut stats: HashMap<String, PlayerStats> = HashMap::new();
+    for (file, map) in ["Kobra", "Tutorial"].iter().enumerate() {
+        let mut th = ThBuilder::with_map(map);
+        th.join(0, "amy").join(1, "zed").spawn(0, 0, 0);
+        th.walk(0, 100, 1, 0).drop(1, "timeout").walk(0, 100, 1, 0);
+        th.despawn(0).eos();
+        let path = th.write(&dir.join(format!("{}.teehistorian", file)));
+        for (player, player_stats) in file_player_stats(&path, 500, &[ActivityInput::Move]).unwrap()
+        {
+            stats.entry(player).or_default().merge(player_stats);
+        }
+    }
+
+    let reports = player_reports(stats);
+    let players: Vec<&str> = reports.iter().map(|r| r.player.as_str()).collect();
+    assert_eq!(players, vec!["amy", "zed"]);
+
+    let amy = &reports[0];
+    assert_eq!(amy.sessions, 2);
+    assert_eq!(amy.maps, vec!["Kobra", "Tutorial"]);
+    // connected for 201 ticks in each file
+    assert!((amy.avg_session_minutes - 201. / 50. / 60.).abs() < 1e-9);
+    assert_eq!(amy.first_seen.as_deref(), Some("2024-10-01T16:23:05+00:00"));
+
+    let zed = &reports[1];
+    assert_eq!(zed.sessions, 2);
+    assert!((zed.avg_session_minutes - 100. / 50. / 60.).abs() < 1e-9);
+    // zed never spawned
+    assert_eq!(zed.active_ratio, None);
+}
@@ -0,0 +1,66 @@
+use ndarray::Array2;
+use teehistorian_extractor::export::ExportConfig;
+use teehistorian_extractor::plot::{InputEvent, Trajectory};
+
+/// 100 ticks of moving right, then 50 down, jumping twice and holding fire
+fn features() -> (Vec<String>, Array2<f32>) {
+    let column_names = ExportConfig::default().column_names();
+    let column = |name: &str| column_names.iter().position(|c| c == name).unwrap();
+    let mut features = Array2::zeros((150, column_names.len()));
+    for tick in 0..150 {
+        let (vel_x, vel_y) = if tick < 100 { (2., 0.) } else { (0., 4.) };
+        features[[tick, column("vel_x")]] = vel_x;
+        features[[tick, column("vel_y")]] = vel_y;
+        features[[tick, column("jump")]] = if tick % 60 < 3 { 1. } else { 0. };
+        features[[tick, column("fire")]] = if tick >= 120 { 1. } else { 0. };
+    }
+    (column_names, features)
+}
+
+#[test]
+fn path_is_rebuilt_from_velocities() {
+    let (column_names, features) = features();
+    let trajectory = Trajectory::from_features(&column_names, features.view()).unwrap();
+
+    assert_eq!(trajectory.positions.len(), 151);
+    assert_eq!(trajectory.positions[0], (0., 0.));
+    assert_eq!(trajectory.positions[100], (200., 0.));
+    assert_eq!(trajectory.positions[150], (200., 200.));
+    // held inputs are only marked once
+    assert_eq!(
+        trajectory.events,
+        vec![
+            (0, InputEvent::Jump),
+            (60, InputEvent::Jump),
+            (120, InputEvent::Jump),
+            (120, InputEvent::Fire)
+        ]
+    );
+}
+
+#[test]
+fn plots_are_rendered() {
+    let (column_names, features) = features();
+    let trajectory = Trajectory::from_features(&column_names, features.view()).unwrap();
+
+    let svg = trajectory.render_svg("seq 0: <player> on map");
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("&lt;player&gt;"));
+    assert_eq!(svg.matches("<line").count(), 150);
+    assert_eq!(svg.matches("<path").count(), 4);
+
+    // the longer side is scaled to 800 pixels, plus margins
+    let image = trajectory.render_png();
+    assert_eq!((image.width, image.height), (840, 840));
+}
+
+#[test]
+fn velocities_are_required() {
+    let config = ExportConfig {
+        use_vel: false,
+        ..Default::default()
+    };
+    let column_names = config.column_names();
+    let features = Array2::zeros((10, column_names.len()));
+    assert!(Trajectory::from_features(&column_names, features.view()).is_err());
+}
@@ -0,0 +1,221 @@
+mod support;
+
+use proptest::prelude::*;
+use teehistorian_extractor::extractor::Sequence;
+use teehistorian_extractor::preprocess::{ActivityInput, Duration, Durations};
+
+/// sequence starting at server tick 1000 whose x position is the index of the tick
+fn sequence(move_dir: Vec<i32>) -> Sequence {
+    let positions: Vec<_> = (0..move_dir.len() as i32).map(|tick| (tick, 0)).collect();
+    Sequence {
+        start_tick: 1000,
+        move_dir,
+        ..support::sequence(&positions)
+    }
+}
+
+fn bounds(durations: &Durations) -> Vec<(usize, usize)> {
+    durations
+        .as_slice()
+        .iter()
+        .map(|duration| (duration.start(), duration.end()))
+        .collect()
+}
+
+fn assert_ordered_without_overlaps(durations: &Durations) {
+    for pair in durations.as_slice().windows(2) {
+        assert!(pair[0].end() < pair[1].start(), "{:?}", durations);
+    }
+}
+
+/// possibly overlapping durations within the first 400 ticks
+fn durations() -> impl Strategy<Value = Vec<Duration>> {
+    prop::collection::vec((0..350usize, 1..50usize), 0..12).prop_map(|bounds| {
+        bounds
+            .into_iter()
+            .map(|(start, ticks)| Duration::new(start, start + ticks - 1))
+            .collect()
+    })
+}
+
+#[test]
+fn single_tick_durations_are_valid() {
+    let duration = Duration::new(5, 5);
+    assert_eq!(duration.tick_count(), 1);
+    assert_eq!(duration.cut_duration(1, 0, false), [duration]);
+}
+
+#[test]
+#[should_panic]
+fn durations_can_not_end_before_their_start() {
+    Duration::new(6, 5);
+}
+
+#[test]
+fn padding_stops_at_neighbors() {
+    let durations = Durations::new(vec![Duration::new(10, 20), Duration::new(24, 30)]);
+    assert_eq!(bounds(&durations.pad(32, 5)), [(5, 23), (24, 32)]);
+}
+
+#[test]
+fn touching_durations_are_kept_apart() {
+    let durations = Durations::new(vec![Duration::new(4, 9), Duration::new(0, 3)]);
+    assert_eq!(bounds(&durations), [(0, 3), (4, 9)]);
+    assert_eq!(bounds(&durations.merge_gaps(1)), [(0, 9)]);
+}
+
+#[test]
+fn sub_sequences_start_at_their_duration() {
+    let sequence = sequence(vec![0; 10]);
+    let sub_sequences =
+        Duration::extract_sub_sequences(&sequence, &[Duration::new(0, 3), Duration::new(3, 9)])
+            .unwrap();
+    assert_eq!(sub_sequences[0].start_tick, 1000);
+    assert_eq!(sub_sequences[1].start_tick, 1003);
+    assert_eq!(sub_sequences[1].pos_x, [3, 4, 5, 6, 7, 8, 9]);
+
+    let err = Duration::extract_sub_sequences(&sequence, &[Duration::new(5, 10)]).unwrap_err();
+    assert_eq!(err.kind(), "invalid_sequence");
+}
+
+proptest! {
+    #[test]
+    fn joined_durations_cover_the_same_ticks(durations in durations()) {
+        let mut mask = vec![false; 400];
+        for duration in &durations {
+            mask[duration.start()..=duration.end()].fill(true);
+        }
+        let joined = Durations::new(durations);
+        assert_ordered_without_overlaps(&joined);
+        prop_assert_eq!(joined.mask(400), mask);
+    }
+
+    #[test]
+    fn merging_fills_only_short_gaps(durations in durations(), max_gap in 0..30usize) {
+        let durations = Durations::new(durations);
+        let merged = durations.clone().merge_gaps(max_gap);
+        assert_ordered_without_overlaps(&merged);
+        for pair in merged.as_slice().windows(2) {
+            prop_assert!(pair[1].start() - pair[0].end() > max_gap);
+        }
+        let (before, after) = (durations.mask(400), merged.mask(400));
+        prop_assert!(before.iter().zip(&after).all(|(&b, &a)| !b || a));
+        if max_gap == 0 {
+            prop_assert_eq!(merged, durations);
+        }
+    }
+
+    #[test]
+    fn removed_ticks_are_gone(durations in durations(), removed in durations()) {
+        let durations = Durations::new(durations);
+        let removed = Durations::new(removed);
+        let kept = durations.clone().remove(&removed);
+        assert_ordered_without_overlaps(&kept);
+        let expected: Vec<bool> = durations
+            .mask(400)
+            .iter()
+            .zip(removed.mask(400))
+            .map(|(&tick, removed)| tick && !removed)
+            .collect();
+        prop_assert_eq!(kept.mask(400), expected);
+    }
+
+    #[test]
+    fn padding_keeps_ticks_and_bounds(
+        durations in durations(),
+        max_tick in 0..400usize,
+        margin in 0..20usize,
+    ) {
+        let durations = Durations::new(durations);
+        let padded = durations.clone().pad(max_tick, margin);
+        assert_ordered_without_overlaps(&padded);
+        prop_assert!(padded.as_slice().iter().all(|d| d.end() <= max_tick));
+
+        let original = durations.mask(max_tick + 1);
+        let padded_mask = padded.mask(max_tick + 1);
+        for tick in 0..=max_tick {
+            // ticks of the durations stay, padding reaches at most margin ticks further
+            prop_assert!(!original[tick] || padded_mask[tick]);
+            let near = original[tick.saturating_sub(margin)..=(tick + margin).min(max_tick)]
+                .contains(&true);
+            prop_assert!(!padded_mask[tick] || near);
+        }
+        // every padded duration holds ticks of exactly one duration
+        for duration in padded.as_slice() {
+            let starts = durations
+                .as_slice()
+                .iter()
+                .filter(|d| d.start() >= duration.start() && d.start() <= duration.end())
+                .count();
+            prop_assert_eq!(starts, 1);
+        }
+    }
+
+    #[test]
+    fn cuts_lie_within_their_duration(
+        durations in durations(),
+        target_length in 1..30usize,
+        extra_ticks in 0..2usize,
+        keep_tail: bool,
+    ) {
+        let durations = Durations::new(durations);
+        for duration in durations.as_slice() {
+            let cuts = duration.cut_duration(target_length, extra_ticks, keep_tail);
+            let full_cuts = (duration.tick_count().saturating_sub(extra_ticks)) / target_length;
+            prop_assert!(cuts.len() == full_cuts || (keep_tail && cuts.len() == full_cuts + 1));
+            for (i, cut) in cuts.iter().enumerate() {
+                prop_assert_eq!(cut.tick_count(), target_length + extra_ticks);
+                prop_assert!(cut.start() >= duration.start() && cut.end() <= duration.end());
+                if i < full_cuts {
+                    prop_assert_eq!(cut.start(), duration.start() + i * target_length);
+                } else {
+                    prop_assert_eq!(cut.end(), duration.end());
+                }
+            }
+        }
+        let cut_count: usize = durations
+            .as_slice()
+            .iter()
+            .map(|d| d.cut_duration(target_length, extra_ticks, keep_tail).len())
+            .sum();
+        prop_assert_eq!(durations.cut(target_length, extra_ticks, keep_tail).len(), cut_count);
+    }
+
+    #[test]
+    fn sub_sequences_hold_the_ticks_of_their_duration(
+        move_dir in prop::collection::vec(-1..=1i32, 1..300),
+        afk_ticks in 1..40usize,
+        target_length in 1..20usize,
+    ) {
+        let sequence = sequence(move_dir);
+        let durations =
+            Duration::get_non_afk_durations(&sequence, afk_ticks, &[ActivityInput::Move]);
+        assert_ordered_without_overlaps(&durations);
+        let windows = durations.pad(sequence.tick_count - 1, 3).cut(target_length, 1, true);
+        let sub_sequences = Duration::extract_sub_sequences(&sequence, &windows).unwrap();
+        for (window, sub_sequence) in windows.iter().zip(&sub_sequences) {
+            prop_assert_eq!(sub_sequence.start_tick, 1000 + window.start());
+            prop_assert_eq!(sub_sequence.tick_count, window.tick_count());
+            prop_assert_eq!(sub_sequence.pos_x[0], window.start() as i32);
+            prop_assert_eq!(
+                &sub_sequence.move_dir[..],
+                &sequence.move_dir[window.start()..=window.end()]
+            );
+        }
+    }
+
+    #[test]
+    fn freeze_durations_are_long_frozen_stretches(
+        frozen in prop::collection::vec(any::<bool>(), 0..200),
+        max_ticks in 0..5usize,
+    ) {
+        let durations = Duration::get_freeze_durations(&frozen, max_ticks);
+        assert_ordered_without_overlaps(&durations);
+        for duration in durations.as_slice() {
+            prop_assert!(duration.tick_count() > max_ticks);
+            prop_assert!(frozen[duration.start()..=duration.end()].iter().all(|&f| f));
+            prop_assert!(duration.start() == 0 || !frozen[duration.start() - 1]);
+            prop_assert!(frozen.get(duration.end() + 1) != Some(&true));
+        }
+    }
+}
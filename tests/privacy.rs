@@ -0,0 +1,175 @@
+mod support;
+
+use chrono::{DateTime, Utc};
+use ndarray::Array2;
+use rand::{rngs::StdRng, SeedableRng};
+use support::{sequence, temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    embedding::embed,
+    export::{ExportConfig, Exporter},
+    parser::ParserConfig,
+    privacy::PrivacyNoise,
+    route::{format_route_cells, route_cells, RouteConfig},
+    sink::write_features,
+};
+
+fn noise(epsilon: f64) -> PrivacyNoise {
+    PrivacyNoise {
+        epsilon,
+        time_resolution: 3600,
+    }
+}
+
+#[test]
+fn noise_scales_with_the_budget() {
+    let scales = noise(0.5).noise_scales();
+    assert_eq!(scales.len(), 4);
+    assert_eq!(scales["pos_x"], 4.0);
+    assert_eq!(scales["target_y"], 32.0);
+    assert_eq!(noise(2.0).noise_scale("pos_y"), Some(1.0));
+    assert_eq!(noise(2.0).noise_scale("jump"), None);
+}
+
+#[test]
+fn sequences_get_noise_on_positions_and_targets() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut exact = sequence(&vec![(0, 0); 10_000]);
+    exact.jump[5] = true;
+    let noisy = noise(0.1).perturb_sequence(&exact, &mut rng);
+    assert_eq!(noisy.tick_count, exact.tick_count);
+    assert_eq!(noisy.jump, exact.jump);
+    assert_eq!(noisy.move_dir, exact.move_dir);
+
+    // mean absolute deviation of Laplace noise is its scale
+    let deviation = |values: &[i32], exact: &[i32]| {
+        let total: i64 = values
+            .iter()
+            .zip(exact)
+            .map(|(value, exact)| (value - exact).abs() as i64)
+            .sum();
+        total as f64 / values.len() as f64
+    };
+    let position = deviation(&noisy.pos_x, &exact.pos_x);
+    assert!((position - 20.0).abs() < 1.0, "deviation {}", position);
+    let target = deviation(&noisy.target_y, &exact.target_y);
+    assert!((target - 160.0).abs() < 8.0, "deviation {}", target);
+}
+
+#[test]
+fn recording_times_are_rounded_down() {
+    let time: DateTime<Utc> = "2024-03-05T13:47:12Z".parse().unwrap();
+    let coarse = noise(1.0).coarsen(time);
+    assert_eq!(coarse.to_rfc3339(), "2024-03-05T13:00:00+00:00");
+    let daily = PrivacyNoise {
+        epsilon: 1.0,
+        time_resolution: 86400,
+    };
+    assert_eq!(
+        daily.coarsen(time).to_rfc3339(),
+        "2024-03-05T00:00:00+00:00"
+    );
+}
+
+#[test]
+fn privacy_noise_is_validated() {
+    let build = |privacy: PrivacyNoise| ExportConfig::builder().privacy(privacy).build();
+    assert!(build(noise(1.0)).is_ok());
+    assert!(build(noise(0.0)).is_err());
+    assert!(build(noise(f64::INFINITY)).is_err());
+    assert!(build(PrivacyNoise {
+        epsilon: 1.0,
+        time_resolution: 0,
+    })
+    .is_err());
+}
+
+#[test]
+fn privacy_noise_is_recorded_in_the_manifest() {
+    let dir = temp_dir("privacy_manifest");
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..30 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let config = ExportConfig::builder()
+        .seq_length(4)
+        .afk_padding(1)
+        .privacy(noise(0.5))
+        .build()
+        .unwrap();
+    let mut exporter =
+        Exporter::with_sink(&dir.join("out"), config.clone(), MemorySink::default()).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    let manifest = std::fs::read_to_string(dir.join("out").join("manifest.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest["privacy"]["epsilon"], 0.5);
+    assert_eq!(manifest["privacy"]["time_resolution"], 3600);
+    assert_eq!(manifest["noise_scales"]["pos_x"], 4.0);
+    assert_eq!(manifest["noise_scales"]["target_x"], 32.0);
+    assert!(manifest["noise_scales"].get("move_dir").is_none());
+}
+
+#[test]
+fn exports_are_computed_from_one_noisy_copy_of_each_sequence() {
+    let dir = temp_dir("privacy_export");
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..30 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let export = |name: &str, privacy: Option<PrivacyNoise>| {
+        let config = ExportConfig::builder()
+            .seq_length(4)
+            .afk_padding(1)
+            .routes(RouteConfig {
+                cell_size: 1,
+                max_distance: 0.5,
+            })
+            .privacy(privacy)
+            .build()
+            .unwrap();
+        let sink = MemorySink::default();
+        let mut exporter =
+            Exporter::with_sink(&dir.join(name), config.clone(), sink.clone()).unwrap();
+        exporter
+            .handle_batch(&paths, &ParserConfig::default(), &config)
+            .unwrap();
+        let stored = sink.stored.borrow().clone();
+        (stored, config)
+    };
+    let (exact, _) = export("exact", None);
+    let (noisy, config) = export("noisy", Some(noise(0.5)));
+    assert_eq!(noisy.len(), exact.len());
+    assert!(!noisy.is_empty());
+
+    let columns = config.column_names();
+    let vel_x = columns.iter().position(|name| name == "vel_x").unwrap();
+    for (noisy, exact) in noisy.iter().zip(&exact) {
+        // sinks get the noisy copy, so features and embeddings are computed from it
+        assert_eq!(noisy.sequence.move_dir, exact.sequence.move_dir);
+        assert_ne!(noisy.sequence.pos_x, exact.sequence.pos_x);
+        assert_ne!(embed(&noisy.sequence, 4), embed(&exact.sequence, 4));
+        let mut features = Array2::zeros((4, columns.len()));
+        write_features(&noisy.sequence, &config, features.view_mut()).unwrap();
+        let pos_x = &noisy.sequence.pos_x;
+        assert_eq!(features[(0, vel_x)], (pos_x[1] - pos_x[0]) as f32);
+
+        // the meta row is computed from the same copy
+        let cells = route_cells(&noisy.sequence, noisy.meta.ticks, 1);
+        assert_eq!(noisy.meta.route_cells, Some(format_route_cells(&cells)));
+    }
+}
@@ -0,0 +1,84 @@
+mod support;
+
+use std::collections::HashMap;
+use teehistorian_extractor::extractor::Sequence;
+use teehistorian_extractor::reaction::{
+    reaction_reports, sequence_reactions, LatencyHistogram, ReactionConfig, ReactionEvent,
+};
+
+/// player aiming right and standing still for the given ticks
+fn sequence(ticks: usize) -> Sequence {
+    support::sequence(&vec![(0, 0); ticks])
+}
+
+#[test]
+fn wall_hits_are_answered_by_releasing_the_direction() {
+    let mut sequence = sequence(100);
+    // runs right until a wall stops it at tick 20, lets go at tick 26
+    for tick in 0..26 {
+        sequence.move_dir[tick] = 1;
+    }
+    for tick in 1..100 {
+        sequence.pos_x[tick] = sequence.pos_x[tick - 1] + if tick < 20 { 10 } else { 0 };
+    }
+
+    let reactions = sequence_reactions(&sequence, &ReactionConfig::default());
+    let wall = &reactions[&ReactionEvent::WallReversal];
+    assert_eq!(wall.count(), 1);
+    assert_eq!(wall.quantile(0.5), Some(6));
+    assert!(!reactions.contains_key(&ReactionEvent::SnapFire));
+}
+
+#[test]
+fn aim_snaps_are_answered_by_presses() {
+    let mut sequence = sequence(100);
+    // flicks up at tick 10 and fires 3 ticks later, but never hooks
+    for tick in 10..100 {
+        sequence.target_x[tick] = 0;
+        sequence.target_y[tick] = -100;
+    }
+    sequence.fire[13] = true;
+    sequence.fire[14] = true;
+
+    let reactions = sequence_reactions(&sequence, &ReactionConfig::default());
+    let fire = &reactions[&ReactionEvent::SnapFire];
+    assert_eq!(fire.counts, vec![0, 0, 0, 1]);
+    assert_eq!(fire.unanswered, 0);
+    let hook = &reactions[&ReactionEvent::SnapHook];
+    assert_eq!(hook.count(), 0);
+    assert_eq!(hook.unanswered, 1);
+
+    // too close to the end to tell
+    let late = sequence_reactions(
+        &sequence,
+        &ReactionConfig {
+            max_latency: 95,
+            ..Default::default()
+        },
+    );
+    assert!(late.is_empty());
+}
+
+#[test]
+fn reports_leave_out_rare_events() {
+    let mut histogram = LatencyHistogram::default();
+    for latency in [10, 10, 12, 14] {
+        histogram.add(Some(latency));
+    }
+    histogram.add(None);
+    let reactions = HashMap::from([(
+        "amy".to_string(),
+        [(ReactionEvent::SnapFire, histogram)].into(),
+    )]);
+
+    let reports = reaction_reports(reactions.clone(), 4);
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+    assert_eq!(report.events, 5);
+    assert!((report.unanswered_ratio - 0.2).abs() < 1e-9);
+    assert_eq!(report.mean_ms, Some(230.));
+    assert_eq!(report.p50_ms, Some(200.));
+    assert_eq!(report.p90_ms, Some(280.));
+
+    assert!(reaction_reports(reactions, 5).is_empty());
+}
@@ -0,0 +1,80 @@
+mod support;
+
+use std::fs;
+use support::temp_dir;
+use teehistorian_extractor::records::{PlayerRecord, RecordIndex};
+
+#[test]
+fn best_times_are_ranked_per_map() {
+    let finishes = [
+        ("Kobra", "amy", 62.5),
+        ("Kobra", "amy", 60.),
+        ("Kobra", "bob", 70.),
+        ("Kobra", "cid", 60.),
+        ("Kobra", "dan", 0.),
+        ("Grandma", "bob", 3600.),
+    ];
+    let records = RecordIndex::from_finishes(
+        finishes.map(|(map, name, time)| (map.to_string(), name.to_string(), time)),
+    );
+
+    assert_eq!(records.len(), 4);
+    // equal times share a rank
+    assert_eq!(
+        records.get("Kobra", "amy"),
+        Some(PlayerRecord {
+            time: 60.,
+            rank: 1,
+            finishers: 3
+        })
+    );
+    assert_eq!(records.get("Kobra", "cid").unwrap().rank, 1);
+    assert_eq!(records.get("Kobra", "bob").unwrap().rank, 3);
+    assert_eq!(records.get("Grandma", "bob").unwrap().finishers, 1);
+    assert_eq!(records.get("Kobra", "dan"), None);
+    assert_eq!(records.get("Grandma", "amy"), None);
+}
+
+#[test]
+fn csv_dumps_are_loaded() {
+    let dir = temp_dir("records");
+    let path = dir.join("race.csv");
+    fs::write(
+        &path,
+        "Map,Name,Time,Timestamp,Server\n\
+         Kobra,amy,61.2,2020-01-01 12:00:00,GER\n\
+         Kobra,\"bob, the builder\",75.0,2020-01-02 12:00:00,USA\n",
+    )
+    .unwrap();
+    let records = RecordIndex::load(&path).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records.get("Kobra", "bob, the builder").unwrap().rank, 2);
+
+    fs::write(&path, "Map,Name,Time\nKobra,amy,fast\n").unwrap();
+    assert!(RecordIndex::load(&path).is_err());
+    assert!(RecordIndex::load(&dir.join("race.txt")).is_err());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_databases_are_loaded() {
+    let dir = temp_dir("records_sqlite");
+    let path = dir.join("ddnet-server.sqlite");
+    let _ = fs::remove_file(&path);
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE record_race (Map VARCHAR(128), Name VARCHAR(16), Timestamp TIMESTAMP, \
+             Time FLOAT, Server CHAR(4));
+             INSERT INTO record_race VALUES ('Kobra', 'amy', '2020-01-01', 61.5, 'GER');
+             INSERT INTO record_race VALUES ('Kobra', 'amy', '2020-01-02', 59.5, 'GER');
+             INSERT INTO record_race VALUES ('Kobra', 'bob', '2020-01-02', 58.0, 'GER');",
+        )
+        .unwrap();
+    drop(connection);
+
+    let records = RecordIndex::load(&path).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records.get("Kobra", "amy").unwrap().time, 59.5);
+    assert_eq!(records.get("Kobra", "amy").unwrap().rank, 2);
+}
@@ -0,0 +1,90 @@
+use teehistorian_extractor::registry::PlayerRegistry;
+
+#[test]
+fn ids_are_stable_and_never_reused() {
+    let mut registry = PlayerRegistry::default();
+    assert_eq!(registry.player_id("amy"), 0);
+    assert_eq!(registry.player_id("zed"), 1);
+    assert_eq!(registry.player_id("amy"), 0);
+
+    registry.register("old", 7);
+    assert_eq!(registry.player_id("new"), 8);
+    // registered names keep their id
+    registry.register("amy", 3);
+    assert_eq!(registry.player_id("amy"), 0);
+}
+
+#[test]
+fn aliases_share_the_canonical_identity() {
+    let mut registry = PlayerRegistry::default();
+    registry.player_id("amy");
+    registry.player_id("amy2");
+    registry.add_timeout_code("amy2", "code1");
+
+    registry.add_alias("amy2", "amy").unwrap();
+    assert_eq!(registry.player_id("amy2"), 0);
+    assert!(registry.players["amy"].timeout_codes.contains("code1"));
+    assert!(!registry.players.contains_key("amy2"));
+
+    // chained aliases resolve to the end of the chain
+    registry.add_alias("amy3", "amy2").unwrap();
+    assert_eq!(registry.canonical_name("amy3"), "amy");
+    assert_eq!(registry.player_id("amy3"), 0);
+    assert!(registry.add_alias("amy", "amy3").is_err());
+    assert!(registry.add_alias("amy2", "amy3").is_err());
+
+    // a canonical name without identity takes over the one of its alias
+    registry.player_id("bob_");
+    registry.add_alias("bob_", "bob").unwrap();
+    // the retired id of amy2 isn't reused
+    assert_eq!(registry.player_id("bob"), 2);
+    assert_eq!(registry.next_id, 3);
+}
+
+#[test]
+fn conflicting_alias_maps_are_rejected() {
+    let mut registry = PlayerRegistry::default();
+    let aliases = [
+        ("amy".to_string(), vec!["a".to_string()]),
+        ("bob".to_string(), vec!["a".to_string()]),
+    ]
+    .into();
+    assert!(registry.apply_aliases(&aliases).is_err());
+
+    let mut registry = PlayerRegistry::default();
+    let aliases = [("amy".to_string(), vec!["amy".to_string()])].into();
+    assert!(registry.apply_aliases(&aliases).is_err());
+}
+
+#[test]
+fn union_keeps_ids_of_consecutive_exports() {
+    let mut january = PlayerRegistry::default();
+    january.player_id("amy");
+    january.player_id("bob");
+    january.add_timeout_code("amy", "code1");
+
+    // february starts from the registry of january
+    let mut february = january.clone();
+    february.player_id("cat");
+    february.add_alias("bob2", "bob").unwrap();
+    february.add_timeout_code("amy", "code2");
+
+    // an unrelated export assigned the same ids to other players
+    let mut other = PlayerRegistry::default();
+    other.player_id("dan");
+    other.player_id("amy");
+
+    let mut merged = january.clone();
+    merged.union(&february).unwrap();
+    merged.union(&other).unwrap();
+    assert_eq!(merged.player_id("amy"), 0);
+    assert_eq!(merged.player_id("bob2"), 1);
+    assert_eq!(merged.player_id("cat"), 2);
+    assert_eq!(merged.player_id("dan"), 3);
+    assert_eq!(merged.next_id, 4);
+    assert_eq!(merged.players["amy"].timeout_codes.len(), 2);
+
+    let mut conflicting = PlayerRegistry::default();
+    conflicting.add_alias("bob2", "amy").unwrap();
+    assert!(merged.union(&conflicting).is_err());
+}
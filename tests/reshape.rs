@@ -0,0 +1,44 @@
+mod support;
+
+use ndarray::{Array3, Axis};
+use support::meta_row;
+use teehistorian_extractor::dataset::{concat_run, continuous_runs, MetaRow};
+
+#[test]
+fn runs_join_sequences_continuing_each_other() {
+    let meta = [
+        meta_row("amy", 0, 10),
+        meta_row("amy", 10, 10),
+        // kept tail overlapping the sequence before
+        meta_row("amy", 15, 10),
+        // a dropped sequence before it
+        meta_row("amy", 35, 10),
+        meta_row("zed", 45, 10),
+        MetaRow {
+            teehist: "b".into(),
+            ..meta_row("zed", 55, 10)
+        },
+    ];
+    assert_eq!(continuous_runs(&meta), vec![0..3, 3..4, 4..5, 5..6]);
+    assert!(continuous_runs(&[]).is_empty());
+}
+
+#[test]
+fn overlapping_ticks_are_taken_once() {
+    let meta = [
+        meta_row("amy", 0, 4),
+        meta_row("amy", 4, 4),
+        meta_row("amy", 6, 4),
+    ];
+    // the value of each tick is its tick index
+    let data = Array3::from_shape_fn((3, 4, 2), |(sequence, tick, _)| {
+        (meta[sequence].start + tick) as f32
+    });
+    let (ticks, offsets) = concat_run(&meta, data.view());
+    assert_eq!(offsets, vec![0, 4, 8]);
+    let tick_values: Vec<f32> = ticks.index_axis(Axis(1), 0).to_vec();
+    assert_eq!(
+        tick_values,
+        (0..10).map(|tick| tick as f32).collect::<Vec<_>>()
+    );
+}
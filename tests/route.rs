@@ -0,0 +1,77 @@
+mod support;
+
+use std::fs;
+use support::{meta_row, sequence, temp_dir};
+use teehistorian_extractor::dataset::{read_meta, MetaRow, META_HEADER};
+use teehistorian_extractor::route::{
+    cluster_routes, format_route_cells, parse_route_cells, route_cells, route_dataset,
+};
+
+fn row(map: &str, cells: &str) -> MetaRow {
+    MetaRow {
+        map: map.into(),
+        route_cells: Some(cells.to_string()),
+        ..meta_row("amy", 0, 100)
+    }
+}
+
+#[test]
+fn route_cells_follow_the_path() {
+    let sequence = sequence(&[
+        (10, 10),
+        (90, 10),
+        (110, 10),
+        (110, -10),
+        (10, 10),
+        (300, 0),
+    ]);
+    let cells = route_cells(&sequence, 5, 100);
+    assert_eq!(cells, [(0, 0), (1, 0), (1, -1), (0, 0)]);
+    let formatted = format_route_cells(&cells);
+    assert_eq!(formatted, "0:0|1:0|1:-1|0:0");
+    assert_eq!(parse_route_cells(&formatted), Some(cells));
+    assert_eq!(parse_route_cells("0:0|1"), None);
+}
+
+#[test]
+fn routes_are_clustered_per_map_by_size() {
+    let meta = [
+        row("Kobra 1", "0:0|1:0|2:0"),
+        row("Kobra 1", "0:0|0:1|0:2|0:3"),
+        row("Kobra 1", "0:0|1:0|2:0|3:0"),
+        row("Kobra 2", "0:0|1:0|2:0"),
+        row("Kobra 1", "1:0|2:0|0:0"),
+        MetaRow {
+            map: "Kobra 1".into(),
+            ..meta_row("amy", 0, 100)
+        },
+    ];
+    let routes = cluster_routes(&meta, 0.5);
+    assert_eq!(routes, [Some(0), Some(1), Some(0), Some(0), Some(0), None]);
+
+    // only identical cells share a route
+    let routes = cluster_routes(&meta, 0.0);
+    assert_eq!(routes, [Some(0), Some(1), Some(2), Some(0), Some(0), None]);
+}
+
+#[test]
+fn routes_are_written_into_meta_csv() {
+    let dir = temp_dir("route_dataset");
+    let mut csv = format!("{}\n", META_HEADER);
+    for (seq_id, cells) in ["0:0|1:0", "5:5|6:5", "0:0|1:0"].iter().enumerate() {
+        let row = MetaRow {
+            seq_id,
+            ..row("Kobra 1", cells)
+        };
+        csv += &format!("{}\n", row.to_csv());
+    }
+    fs::write(dir.join("meta.csv"), csv).unwrap();
+
+    assert_eq!(route_dataset(&dir, 0.5).unwrap(), 3);
+    let meta = read_meta(&dir.join("meta.csv")).unwrap();
+    let routes: Vec<_> = meta.iter().map(|row| row.route).collect();
+    assert_eq!(routes, [Some(0), Some(1), Some(0)]);
+    assert_eq!(meta[1].route_cells.as_deref(), Some("5:5|6:5"));
+
+    assert!(route_dataset(&dir, 1.5).is_err());
+}
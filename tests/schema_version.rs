@@ -0,0 +1,25 @@
+use serde_json::json;
+use teehistorian_extractor::dataset::{schema_version, DatasetError, DATASET_VERSION};
+
+#[test]
+fn datasets_without_version_are_version_0() {
+    assert_eq!(schema_version(&serde_json::Value::Null).unwrap(), 0);
+    assert_eq!(schema_version(&json!({ "sequence_count": 3 })).unwrap(), 0);
+    assert_eq!(
+        schema_version(&json!({ "schema_version": DATASET_VERSION })).unwrap(),
+        DATASET_VERSION
+    );
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let newer = DATASET_VERSION + 1;
+    assert!(matches!(
+        schema_version(&json!({ "schema_version": newer })),
+        Err(DatasetError::UnsupportedVersion(version)) if version == newer
+    ));
+    assert!(matches!(
+        schema_version(&json!({ "schema_version": u64::MAX })),
+        Err(DatasetError::UnsupportedVersion(u32::MAX))
+    ));
+}
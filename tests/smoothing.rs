@@ -0,0 +1,138 @@
+mod support;
+
+use ndarray::{arr1, Array2};
+use std::collections::BTreeMap;
+use support::{sequence, temp_dir, MemorySink, ThBuilder};
+use teehistorian_extractor::{
+    export::{ExportConfig, Exporter},
+    parser::ParserConfig,
+    sink::write_features,
+    smoothing::{parse_column_smoothing, Smoothing},
+};
+
+fn smoothed(values: &[f32], smoothing: Smoothing, is_angle: bool) -> Vec<f32> {
+    let mut values = arr1(values);
+    smoothing.apply(values.view_mut(), is_angle);
+    values.to_vec()
+}
+
+fn config(smoothing: &[(&str, Smoothing)]) -> ExportConfig {
+    ExportConfig::builder()
+        .seq_length(4)
+        .afk_padding(1)
+        .smoothing(
+            smoothing
+                .iter()
+                .map(|&(column, smoothing)| (column.to_string(), smoothing))
+                .collect(),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn moving_average_uses_the_ticks_before() {
+    let values = [0.0, 2.0, 4.0, 0.0, 2.0];
+    assert_eq!(
+        smoothed(&values, Smoothing::MovingAverage(2), false),
+        [0.0, 1.0, 3.0, 2.0, 1.0]
+    );
+    assert_eq!(
+        smoothed(&values, Smoothing::MovingAverage(1), false),
+        values
+    );
+}
+
+#[test]
+fn exponential_smoothing_starts_at_the_first_tick() {
+    assert_eq!(
+        smoothed(&[4.0, 0.0, 0.0, 8.0], Smoothing::Exponential(0.5), false),
+        [4.0, 2.0, 1.0, 4.5]
+    );
+}
+
+#[test]
+fn angles_are_smoothed_across_the_wrap() {
+    // aiming left, slightly above and below
+    let angles = smoothed(&[170.0, -170.0, 170.0], Smoothing::MovingAverage(2), true);
+    assert_eq!(angles, [170.0, 180.0, 180.0]);
+    let angles = smoothed(&[-170.0, 170.0], Smoothing::Exponential(0.25), true);
+    assert_eq!(angles, [-170.0, -175.0]);
+}
+
+#[test]
+fn only_configured_columns_are_smoothed() {
+    let mut sequence = sequence(&[(0, 0), (2, 2), (2, 2), (4, 4), (4, 4)]);
+    sequence.move_dir.fill(1);
+    let config = config(&[("vel_x", Smoothing::MovingAverage(2))]);
+    let columns = config.column_names();
+    let mut out = Array2::zeros((4, columns.len()));
+    write_features(&sequence, &config, out.view_mut()).unwrap();
+
+    let column = |name: &str| {
+        out.column(columns.iter().position(|c| c == name).unwrap())
+            .to_vec()
+    };
+    assert_eq!(column("vel_x"), [2.0, 1.0, 1.0, 1.0]);
+    assert_eq!(column("vel_y"), [2.0, 0.0, 2.0, 0.0]);
+}
+
+#[test]
+fn smoothing_is_validated() {
+    let build = |column: &str, smoothing| {
+        ExportConfig::builder()
+            .smoothing(BTreeMap::from([(column.to_string(), smoothing)]))
+            .build()
+    };
+    assert!(build("aim_angle", Smoothing::Exponential(1.0)).is_ok());
+    assert!(build("aim_angle", Smoothing::Exponential(0.0)).is_err());
+    assert!(build("vel_x", Smoothing::MovingAverage(0)).is_err());
+    assert!(build("move_dir", Smoothing::MovingAverage(3)).is_err());
+    // relative targets aren't exported by default
+    assert!(build("target_rel_x", Smoothing::MovingAverage(3)).is_err());
+}
+
+#[test]
+fn smoothing_arguments_are_parsed() {
+    assert_eq!(
+        parse_column_smoothing("vel_x=ma:5"),
+        Ok(("vel_x".to_string(), Smoothing::MovingAverage(5)))
+    );
+    assert_eq!(
+        parse_column_smoothing("aim_angle=ema:0.3"),
+        Ok(("aim_angle".to_string(), Smoothing::Exponential(0.3)))
+    );
+    assert!(parse_column_smoothing("vel_x").is_err());
+    assert!(parse_column_smoothing("vel_x=median:3").is_err());
+    assert!(parse_column_smoothing("vel_x=ema:2").is_err());
+}
+
+#[test]
+fn smoothing_is_recorded_in_the_manifest() {
+    let dir = temp_dir("smoothing_manifest");
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").spawn(0, 0, 0);
+    for tick in 0..30 {
+        let mut dinput = [0; 10];
+        dinput[0] = if tick % 2 == 0 { 1 } else { -1 };
+        th.diff(0, 1, 0).input(0, dinput);
+    }
+    th.despawn(0).eos();
+    let paths = [th.write(&dir.join("a.teehistorian"))];
+
+    let config = config(&[
+        ("vel_x", Smoothing::MovingAverage(3)),
+        ("aim_angle", Smoothing::Exponential(0.5)),
+    ]);
+    let mut exporter =
+        Exporter::with_sink(&dir.join("out"), config.clone(), MemorySink::default()).unwrap();
+    exporter
+        .handle_batch(&paths, &ParserConfig::default(), &config)
+        .unwrap();
+    exporter.finalize(&paths).unwrap();
+
+    let manifest = std::fs::read_to_string(dir.join("out").join("manifest.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest["smoothing"]["vel_x"]["moving_average"], 3);
+    assert_eq!(manifest["smoothing"]["aim_angle"]["exponential"], 0.5);
+}
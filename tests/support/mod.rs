@@ -0,0 +1,352 @@
+//! Small synthetic teehistorian files for deterministic integration tests.
+//!
+//! Ticks are implicit like in real recordings: a player chunk with a lower or equal cid than
+//! the previous one starts a new tick, so moving a single player for n ticks takes n diffs.
+#![allow(dead_code)]
+
+use flate2::{write::ZlibEncoder, Compression};
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use teehistorian::{
+    chunks::{
+        Auth, AuthLogout, ConsoleCommand, Drop, InputDiff, InputNew, Join, NetMessage, PlayerDiff,
+        PlayerNew, PlayerOld, PlayerSwap, TickSkip,
+    },
+    Chunk, Th, ThWriter,
+};
+use teehistorian_extractor::{
+    dataset::MetaRow,
+    error::Result,
+    export::ExportConfig,
+    extractor::Sequence,
+    parser::{Anomaly, DDNetSequence, ParseError, Parser, ParserConfig},
+    sink::ExportSink,
+};
+
+/// message ids of the 0.6 protocol, packed with the system flag unset
+const CL_START_INFO: u8 = 20 << 1;
+const CL_KILL: u8 = 22 << 1;
+
+pub struct ThBuilder {
+    writer: ThWriter<Vec<u8>>,
+}
+
+impl ThBuilder {
+    pub fn new() -> ThBuilder {
+        ThBuilder::with_map("Synthetic")
+    }
+
+    pub fn with_map(map_name: &str) -> ThBuilder {
+        let header = format!(
+            r#"{{"server_name":"synthetic","map_name":"{}","start_time":"2024-10-01 18:23:05 +0200"}}"#,
+            map_name
+        );
+        ThBuilder {
+            writer: ThWriter::new(Vec::new(), &header).expect("writing to a vec can't fail"),
+        }
+    }
+
+    fn add(&mut self, chunk: Chunk) -> &mut Self {
+        self.writer
+            .add_chunk(&chunk)
+            .expect("writing to a vec can't fail");
+        self
+    }
+
+    fn net_message(&mut self, cid: i32, msg: &[u8]) -> &mut Self {
+        self.add(Chunk::NetMessage(NetMessage {
+            cid,
+            msg_size: msg.len() as i32,
+            msg,
+        }))
+    }
+
+    /// client connects, sends its name and an initial input
+    pub fn join(&mut self, cid: i32, name: &str) -> &mut Self {
+        self.add(Chunk::Join(Join { cid }));
+        self.name(cid, name);
+        self.add(Chunk::InputNew(InputNew {
+            cid,
+            input: [0; 10],
+        }))
+    }
+
+    /// ClStartInfo net message: name, clan, country, skin, use_custom_color, color_body,
+    /// color_feet
+    pub fn name(&mut self, cid: i32, name: &str) -> &mut Self {
+        let mut msg = vec![CL_START_INFO];
+        msg.extend_from_slice(name.as_bytes());
+        msg.extend_from_slice(&[0, 0, 0]); // name terminator, empty clan, country
+        msg.extend_from_slice(b"default\0");
+        msg.extend_from_slice(&[0, 0, 0]);
+        self.net_message(cid, &msg)
+    }
+
+    pub fn spawn(&mut self, cid: i32, x: i32, y: i32) -> &mut Self {
+        self.add(Chunk::PlayerNew(PlayerNew { cid, x, y }))
+    }
+
+    pub fn diff(&mut self, cid: i32, dx: i32, dy: i32) -> &mut Self {
+        self.add(Chunk::PlayerDiff(PlayerDiff { cid, dx, dy }))
+    }
+
+    /// move a single player by (dx, dy) in each of the next ticks
+    pub fn walk(&mut self, cid: i32, ticks: i32, dx: i32, dy: i32) -> &mut Self {
+        for _ in 0..ticks {
+            self.diff(cid, dx, dy);
+        }
+        self
+    }
+
+    pub fn input(&mut self, cid: i32, dinput: [i32; 10]) -> &mut Self {
+        self.add(Chunk::InputDiff(InputDiff { cid, dinput }))
+    }
+
+    /// finish the current tick and skip dt more
+    pub fn tick_skip(&mut self, dt: i32) -> &mut Self {
+        self.add(Chunk::TickSkip(TickSkip { dt }))
+    }
+
+    pub fn kill(&mut self, cid: i32) -> &mut Self {
+        self.net_message(cid, &[CL_KILL])
+    }
+
+    pub fn despawn(&mut self, cid: i32) -> &mut Self {
+        self.add(Chunk::PlayerOld(PlayerOld { cid }))
+    }
+
+    pub fn drop(&mut self, cid: i32, reason: &str) -> &mut Self {
+        self.add(Chunk::Drop(Drop {
+            cid,
+            reason: reason.as_bytes(),
+        }))
+    }
+
+    pub fn swap(&mut self, cid1: i32, cid2: i32) -> &mut Self {
+        self.add(Chunk::PlayerSwap(PlayerSwap { cid1, cid2 }))
+    }
+
+    pub fn login(&mut self, cid: i32, auth_name: &str, level: i32) -> &mut Self {
+        self.add(Chunk::AuthLogin(Auth {
+            cid,
+            level,
+            auth_name: auth_name.as_bytes(),
+        }))
+    }
+
+    pub fn logout(&mut self, cid: i32) -> &mut Self {
+        self.add(Chunk::AuthLogout(AuthLogout { cid }))
+    }
+
+    pub fn console(&mut self, cid: i32, cmd: &str, args: &[&str]) -> &mut Self {
+        self.add(Chunk::ConsoleCommand(ConsoleCommand {
+            cid,
+            flags: 0,
+            cmd: cmd.as_bytes(),
+            num_args: args.len() as i32,
+            args: args.iter().map(|arg| arg.as_bytes()).collect(),
+        }))
+    }
+
+    pub fn eos(&mut self) -> &mut Self {
+        self.add(Chunk::Eos)
+    }
+
+    /// uncompressed file contents
+    pub fn finish(self) -> Vec<u8> {
+        self.writer.into_inner()
+    }
+
+    pub fn write(self, path: &Path) -> PathBuf {
+        fs::write(path, self.finish()).expect("couldn't write teehistorian file");
+        path.to_path_buf()
+    }
+}
+
+/// what the parser produced for a file, parsing stops at the first error
+pub struct Parsed {
+    pub sequences: Vec<DDNetSequence>,
+    pub anomalies: Vec<Anomaly>,
+    pub error: Option<ParseError>,
+    pub ticks: i32,
+}
+
+pub fn parse(bytes: &[u8], config: &ParserConfig) -> Parsed {
+    let mut th = Th::parse(bytes).expect("invalid teehistorian header");
+    let mut parser = Parser::new(config.clone());
+    parser
+        .parse_header(th.header().expect("invalid teehistorian header"))
+        .expect("invalid teehistorian header");
+    let mut error = None;
+    while let Ok(chunk) = th.next_chunk() {
+        if let Err(err) = parser.parse_chunk(chunk) {
+            error = Some(err);
+            break;
+        }
+    }
+    Parsed {
+        sequences: parser.completed_sequences,
+        anomalies: parser.anomalies,
+        error,
+        ticks: parser.tick_index,
+    }
+}
+
+/// empty folder in the system temp dir, unique per test name
+pub fn temp_dir(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!(
+        "teehistorian_extractor_test_{}_{}",
+        name,
+        std::process::id()
+    ));
+    if path.exists() {
+        fs::remove_dir_all(&path).expect("couldn't clear temp dir");
+    }
+    fs::create_dir_all(&path).expect("couldn't create temp dir");
+    path
+}
+
+/// meta.csv row of a sequence of the player in file "a" on map "Synthetic", without optional
+/// columns
+pub fn meta_row(player: &str, start: usize, ticks: usize) -> MetaRow {
+    MetaRow {
+        seq_id: 0,
+        player_id: 0,
+        player: player.into(),
+        start,
+        ticks,
+        map: "Synthetic".into(),
+        teehist: "a".into(),
+        timeout: None,
+        finish_time: None,
+        anomaly_score: None,
+        anomaly_flags: None,
+        map_width: None,
+        map_height: None,
+        map_stars: None,
+        map_spawns: None,
+        map_category: None,
+        map_points: None,
+        map_release: None,
+        record_time: None,
+        record_rank: None,
+        record_finishers: None,
+        recorded: None,
+        weight: None,
+        labels: None,
+        auth_name: None,
+        auth_level: None,
+        session_id: None,
+        session_seq: None,
+        route_cells: None,
+        route: None,
+    }
+}
+
+/// sequence of amy in file "a" on map "map" along the given positions, without move
+/// direction or buttons and aiming at (100, 0)
+pub fn sequence(positions: &[(i32, i32)]) -> Sequence {
+    let ticks = positions.len();
+    Sequence {
+        start_tick: 0,
+        tick_count: ticks,
+        cid: 0,
+        player_name: "amy".into(),
+        timeout_code: None,
+        auth: None,
+        finish_time: None,
+        map_name: "map".into(),
+        teehist_name: "a".into(),
+        session_id: None,
+        session_seq: 0,
+        pos_x: positions.iter().map(|&(x, _)| x).collect(),
+        pos_y: positions.iter().map(|&(_, y)| y).collect(),
+        move_dir: vec![0; ticks],
+        target_x: vec![100; ticks],
+        target_y: vec![0; ticks],
+        jump: vec![false; ticks],
+        fire: vec![false; ticks],
+        hook: vec![false; ticks],
+        frozen: Vec::new(),
+        active: Vec::new(),
+    }
+}
+
+/// exported sequence, as seen by a sink
+#[derive(Debug, Clone)]
+pub struct Stored {
+    pub meta: MetaRow,
+    pub sequence: Sequence,
+    pub tick_count: usize,
+    /// ticks in which the player was frozen, 0 without freeze detection
+    pub frozen_ticks: usize,
+}
+
+/// [`ExportSink`] keeping the exported sequences in memory, clones share the storage
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    pub stored: Rc<RefCell<Vec<Stored>>>,
+}
+
+impl ExportSink for MemorySink {
+    fn open(&mut self, _config: &ExportConfig, resume_count: Option<usize>) -> Result<()> {
+        self.stored.borrow_mut().truncate(resume_count.unwrap_or(0));
+        Ok(())
+    }
+
+    fn write_batch(&mut self, sequences: &[Sequence], meta: &[MetaRow]) -> Result<()> {
+        let mut stored = self.stored.borrow_mut();
+        for (seq, meta) in sequences.iter().zip(meta) {
+            stored.push(Stored {
+                meta: meta.clone(),
+                sequence: seq.clone(),
+                tick_count: seq.tick_count,
+                frozen_ticks: seq.frozen.iter().filter(|&&frozen| frozen).count(),
+            });
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn check_count(&mut self, sequence_count: usize) -> Result<()> {
+        assert_eq!(self.stored.borrow().len(), sequence_count);
+        Ok(())
+    }
+}
+
+/// datafile with a single game layer, tiles are stored without skip compression
+pub fn map_bytes(width: i32, height: i32, tiles: &[u8]) -> Vec<u8> {
+    let mut tile_data = Vec::new();
+    for &tile in tiles {
+        tile_data.extend_from_slice(&[tile, 0, 0, 0]);
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&tile_data).unwrap();
+    let data = encoder.finish().unwrap();
+
+    // layer type tiles, tilemap version 3, game flag, color, envelope, image and data index
+    let layer = [
+        0, 2, 0, 3, width, height, 1, 255, 255, 255, 255, -1, 0, -1, 0,
+    ];
+    let mut item = vec![5 << 16, layer.len() as i32 * 4];
+    item.extend(layer);
+
+    // item types (type, start, num), item offsets, data offsets and data sizes
+    let mut ints = vec![4, 0, 0, 1, 1, 1, item.len() as i32 * 4, data.len() as i32];
+    ints.extend([5, 0, 1, 0, 0, tile_data.len() as i32]);
+    ints.extend(item);
+    let mut bytes = b"DATA".to_vec();
+    for int in ints {
+        bytes.extend_from_slice(&int.to_le_bytes());
+    }
+    bytes.extend(data);
+    bytes
+}
@@ -0,0 +1,92 @@
+mod support;
+
+use std::{fs, io::Write, thread, time::Duration};
+use support::{temp_dir, ThBuilder};
+use teehistorian_extractor::extractor::Extractor;
+use teehistorian_extractor::parser::ParserConfig;
+use teehistorian_extractor::tail::TailConfig;
+
+/// amy walks and despawns, bob keeps walking until the end of the recording
+fn recording() -> ThBuilder {
+    let mut th = ThBuilder::new();
+    th.join(0, "amy").join(1, "bob");
+    th.spawn(0, 0, 0).spawn(1, 0, 0);
+    for _ in 0..50 {
+        th.diff(0, 1, 0).diff(1, 2, 0);
+    }
+    th.despawn(0);
+    for _ in 0..50 {
+        th.diff(1, 2, 0);
+    }
+    th
+}
+
+fn fast_polling(idle_timeout: Duration) -> TailConfig {
+    TailConfig {
+        poll_interval: Duration::from_millis(2),
+        idle_timeout: Some(idle_timeout),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tailing_waits_for_partially_written_chunks() {
+    let dir = temp_dir("tail_partial");
+    let path = dir.join("live.teehistorian");
+    let mut th = recording();
+    th.eos();
+    let bytes = th.finish();
+    fs::write(&path, &bytes[..7]).unwrap();
+
+    // the server appends in pieces that end in the middle of chunks
+    let writer_path = path.clone();
+    let writer_bytes = bytes.clone();
+    let writer = thread::spawn(move || {
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(writer_path)
+            .unwrap();
+        for piece in writer_bytes[7..].chunks(13) {
+            thread::sleep(Duration::from_millis(1));
+            file.write_all(piece).unwrap();
+            file.flush().unwrap();
+        }
+    });
+
+    let config = ParserConfig::default();
+    let tailed: Vec<_> =
+        Extractor::tail_sequences(&path, &config, fast_polling(Duration::from_secs(10)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+    writer.join().unwrap();
+
+    let parsed: Vec<_> = Extractor::sequence_iter(&path, &config)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(tailed.len(), 2);
+    assert_eq!(tailed.len(), parsed.len());
+    for (tailed, parsed) in tailed.iter().zip(&parsed) {
+        assert_eq!(tailed.player_name, parsed.player_name);
+        assert_eq!(tailed.start_tick, parsed.start_tick);
+        assert_eq!(tailed.pos_x, parsed.pos_x);
+    }
+}
+
+#[test]
+fn tailing_stops_after_idle_timeout() {
+    let dir = temp_dir("tail_idle");
+    // the server stopped writing without an end of stream chunk
+    let path = recording().write(&dir.join("live.teehistorian"));
+
+    let sequences: Vec<_> = Extractor::tail_sequences(
+        &path,
+        &ParserConfig::default(),
+        fast_polling(Duration::from_millis(50)),
+    )
+    .collect::<Result<_, _>>()
+    .unwrap();
+
+    // bob's sequence is still active and dropped, like in truncated files
+    assert_eq!(sequences.len(), 1);
+    assert_eq!(&*sequences[0].player_name, "amy");
+}
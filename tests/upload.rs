@@ -0,0 +1,131 @@
+mod support;
+
+use std::{fs, path::PathBuf};
+use support::temp_dir;
+use teehistorian_extractor::export::export_files;
+
+/// files of a finished export, with a leftover temporary file and an unrelated file
+fn fake_export(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    for (file, contents) in [
+        ("sequences.h5", "sequences"),
+        ("meta.csv", "player_name\namy\n"),
+        ("manifest.json", "{}"),
+        ("players.json.tmp", "{}"),
+        ("notes.txt", "not part of the export"),
+    ] {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn export_files_skip_temporary_and_foreign_files() {
+    let dir = fake_export("upload_files");
+    let names: Vec<_> = export_files(&dir)
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, ["sequences.h5", "meta.csv", "manifest.json"]);
+}
+
+#[cfg(not(feature = "remote"))]
+#[test]
+fn upload_needs_the_remote_feature() {
+    let dir = fake_export("upload_unsupported");
+    let err = teehistorian_extractor::upload::upload_export(&dir, "s3://bucket/run").unwrap_err();
+    assert_eq!(err.kind(), "io");
+    assert!(!dir.join("SHA256SUMS").exists());
+}
+
+/// method, path, x-amz-content-sha256 header and body of a request
+#[cfg(feature = "remote")]
+type Request = (String, String, String, Vec<u8>);
+
+/// Endpoint answering every request with 200, and the requests it received
+#[cfg(feature = "remote")]
+fn fake_s3() -> (String, std::sync::mpsc::Receiver<Request>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = BufReader::new(stream.unwrap());
+            let sender = sender.clone();
+            std::thread::spawn(move || loop {
+                let mut request_line = String::new();
+                if stream.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap().to_string();
+                let path = parts.next().unwrap().to_string();
+                let (mut length, mut sha256) = (0, String::new());
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    match name.to_lowercase().as_str() {
+                        "content-length" => length = value.parse().unwrap(),
+                        "x-amz-content-sha256" => sha256 = value.to_string(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+                sender.send((method, path, sha256, body)).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+            });
+        }
+    });
+    (endpoint, receiver)
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn upload_puts_files_and_checksums() {
+    use teehistorian_extractor::remote::file_sha256;
+
+    let (endpoint, requests) = fake_s3();
+    std::env::set_var("AWS_ENDPOINT_URL", &endpoint);
+    std::env::set_var("AWS_ACCESS_KEY_ID", "key");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "secret");
+    let dir = fake_export("upload_put");
+
+    let uploaded =
+        teehistorian_extractor::upload::upload_export(&dir, "s3://bucket/runs/1/").unwrap();
+    assert_eq!(uploaded.len(), 4);
+    let requests: Vec<_> = requests.try_iter().collect();
+    let paths: Vec<_> = requests.iter().map(|r| r.1.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "/bucket/runs/1/sequences.h5",
+            "/bucket/runs/1/meta.csv",
+            "/bucket/runs/1/manifest.json",
+            "/bucket/runs/1/SHA256SUMS",
+        ]
+    );
+    for (method, _, sha256, body) in &requests {
+        assert_eq!(method, "PUT");
+        let body_path = dir.join("body");
+        fs::write(&body_path, body).unwrap();
+        assert_eq!(*sha256, file_sha256(&body_path).unwrap());
+    }
+
+    let checksums = String::from_utf8(requests[3].3.clone()).unwrap();
+    let meta_sha256 = file_sha256(&dir.join("meta.csv")).unwrap();
+    assert!(checksums.contains(&format!("{}  meta.csv\n", meta_sha256)));
+    assert_eq!(
+        checksums,
+        fs::read_to_string(dir.join("SHA256SUMS")).unwrap()
+    );
+}
@@ -0,0 +1,102 @@
+mod support;
+
+use support::meta_row;
+use teehistorian_extractor::dataset::{MetaRow, META_HEADER};
+use teehistorian_extractor::weights::{parse_weight_rule, sample_weights, WeightRule};
+
+fn row(player: &str, map: &str, recorded: Option<&str>) -> MetaRow {
+    MetaRow {
+        map: map.into(),
+        recorded: recorded.map(str::to_string),
+        ..meta_row(player, 0, 100)
+    }
+}
+
+fn assert_close(weights: &[f32], expected: &[f32]) {
+    assert_eq!(weights.len(), expected.len());
+    for (weight, expected) in weights.iter().zip(expected) {
+        assert!(
+            (weight - expected).abs() < 1e-5,
+            "{:?} != {:?}",
+            weights,
+            expected
+        );
+    }
+}
+
+#[test]
+fn rules_are_parsed() {
+    assert_eq!(parse_weight_rule("player"), Ok(WeightRule::InversePlayer));
+    assert_eq!(parse_weight_rule("map"), Ok(WeightRule::InverseMap));
+    assert_eq!(
+        parse_weight_rule("recency:30"),
+        Ok(WeightRule::Recency(30.0))
+    );
+    assert!(parse_weight_rule("recency").is_err());
+    assert!(parse_weight_rule("recency:0").is_err());
+    assert!(parse_weight_rule("recency:abc").is_err());
+    assert!(parse_weight_rule("players").is_err());
+}
+
+#[test]
+fn inverse_frequencies_balance_players_and_maps() {
+    let meta = [
+        row("amy", "Kobra 1", None),
+        row("amy", "Kobra 1", None),
+        row("amy", "Kobra 2", None),
+        row("zed", "Kobra 2", None),
+    ];
+    // amy 1/3, zed 1, mean 1/2
+    assert_close(
+        &sample_weights(&meta, &[WeightRule::InversePlayer]),
+        &[2. / 3., 2. / 3., 2. / 3., 2.],
+    );
+    // every map has 2 sequences
+    assert_close(
+        &sample_weights(&meta, &[WeightRule::InverseMap]),
+        &[1., 1., 1., 1.],
+    );
+    // 1/6, 1/6, 1/6, 1/2 with mean 1/4
+    assert_close(
+        &sample_weights(&meta, &[WeightRule::InversePlayer, WeightRule::InverseMap]),
+        &[2. / 3., 2. / 3., 2. / 3., 2.],
+    );
+    assert_close(&sample_weights(&meta, &[]), &[1., 1., 1., 1.]);
+    assert!(sample_weights(&[], &[WeightRule::InversePlayer]).is_empty());
+}
+
+#[test]
+fn recency_halves_per_half_life() {
+    let meta = [
+        row("amy", "Kobra 1", Some("2024-10-21T00:00:00Z")),
+        row("amy", "Kobra 1", Some("2024-10-11T00:00:00Z")),
+        row("amy", "Kobra 1", Some("2024-10-01T00:00:00Z")),
+        // treated as the oldest
+        row("amy", "Kobra 1", None),
+    ];
+    // 1, 1/2, 1/4, 1/4 with mean 1/2
+    assert_close(
+        &sample_weights(&meta, &[WeightRule::Recency(10.0)]),
+        &[2., 1., 0.5, 0.5],
+    );
+
+    let unrecorded = [row("amy", "Kobra 1", None), row("zed", "Kobra 1", None)];
+    assert_close(
+        &sample_weights(&unrecorded, &[WeightRule::Recency(10.0)]),
+        &[1., 1.],
+    );
+}
+
+#[test]
+fn weights_round_trip_through_meta_csv() {
+    let weighted = MetaRow {
+        weight: Some(0.25),
+        ..meta_row("amy", 0, 100)
+    };
+    let csv = format!("{}\n{}\n", META_HEADER, weighted.to_csv());
+    let rows: Vec<MetaRow> = csv::Reader::from_reader(csv.as_bytes())
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows[0].weight, Some(0.25));
+}